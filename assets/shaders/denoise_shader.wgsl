@@ -0,0 +1,309 @@
+// Spatio-temporal variance-guided (SVGF) denoiser
+
+#import rt/utils.wgsl as Utils
+
+// ========= Uniforms =========
+
+// TAA resource
+@group(0) @binding(0)
+var history_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var history_sampler: sampler;
+
+@group(0) @binding(2)
+var<storage, read_write> velocity_buffer: array<vec4<f32>>;
+
+@group(0) @binding(3)
+var<uniform> taa_config: Utils::TaaConfig;
+
+@group(0) @binding(4)
+var<storage, read> history_depth_buffer: array<f32>;
+
+// Tracer resource
+@group(1) @binding(0)
+var<uniform> camera: Utils::Camera;
+
+@group(1) @binding(1)
+var<storage, read_write> color_buffer: array<vec4<f32>>;
+
+@group(1) @binding(2)
+var<storage, read_write> background_buffer: array<vec4<f32>>;
+
+@group(1) @binding(3)
+var<storage, read_write> normal_buffer: array<vec4<f32>>;
+
+@group(1) @binding(4)
+var<storage, read_write> depth_buffer: array<f32>;
+
+@group(1) @binding(5)
+var<storage, read_write> depth2_buffer: array<f32>;
+
+@group(1) @binding(6)
+var<storage, read> palettes_buffer: array<vec4<f32>>;
+
+@group(1) @binding(7)
+var chunks: texture_3d<u32>;
+
+@group(1) @binding(8)
+var chunks_sampler: sampler;
+
+@group(1) @binding(9)
+var<uniform> accumulation: Utils::Accumulation;
+
+// Denoiser resource
+@group(2) @binding(0)
+var<uniform> denoiser_config: Utils::DenoiserConfig;
+
+@group(2) @binding(1)
+var<storage, read_write> history_buffer: array<vec4<f32>>;
+
+@group(2) @binding(2)
+var<storage, read_write> moments_buffer: array<vec4<f32>>;
+
+@group(2) @binding(3)
+var<storage, read_write> prev_moments_buffer: array<vec4<f32>>;
+
+@group(2) @binding(4)
+var<storage, read_write> prev_gbuffer: array<vec4<f32>>;
+
+@group(2) @binding(5)
+var<storage, read_write> ping_buffer: array<vec4<f32>>;
+
+@group(2) @binding(6)
+var<storage, read_write> pong_buffer: array<vec4<f32>>;
+
+// Push Constants
+var<push_constant> denoise_pass: Utils::DenoisePass;
+
+// ========= Constants =========
+
+const PASS_TEMPORAL: u32 = 0u;
+const PASS_ATROUS: u32 = 1u;
+const PASS_RESOLVE: u32 = 2u;
+
+const NORMAL_THRESHOLD: f32 = 0.9;
+const DEPTH_THRESHOLD: f32 = 0.1;
+const MIN_HISTORY_FOR_TEMPORAL_VARIANCE: f32 = 4.0;
+
+// ========= Helpers =========
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn in_bounds(pos: vec2<i32>) -> bool {
+    return pos.x >= 0 
+        && pos.y >= 0 
+        && pos.x < i32(denoiser_config.canvas_width) 
+        && pos.y < i32(denoiser_config.canvas_height);
+}
+
+fn pixel_index(pos: vec2<i32>) -> u32 {
+    return u32(pos.x) + u32(pos.y) * denoiser_config.canvas_width;
+}
+
+fn read_src(index: u32) -> vec4<f32> {
+    if denoise_pass.flip == 0u {
+        return ping_buffer[index];
+    }
+
+    return pong_buffer[index];
+}
+
+fn write_dst(index: u32, value: vec4<f32>) {
+    if denoise_pass.flip == 0u {
+        pong_buffer[index] = value;
+    } else {
+        ping_buffer[index] = value;
+    }
+}
+
+// B3-spline weights of the 5x5 à-trous kernel
+fn kernel_weight(offset: i32) -> f32 {
+    switch abs(offset) {
+        case 0: {
+            return 3.0 / 8.0;
+        }
+        case 1: {
+            return 1.0 / 4.0;
+        }
+        default: {
+            return 1.0 / 16.0;
+        }
+    }
+}
+
+// ========= Passes =========
+
+fn temporal(pos: vec2<i32>) {
+    let index = pixel_index(pos);
+    let color = color_buffer[index].rgb;
+    let normal = normal_buffer[index].xyz;
+    let depth = depth_buffer[index];
+    let lum = luminance(color);
+
+    let size = vec2<f32>(f32(denoiser_config.canvas_width), f32(denoiser_config.canvas_height));
+    let uv = (vec2<f32>(pos) + 0.5) / size;
+    let prev_pos = vec2<i32>(floor((uv - velocity_buffer[index].xy) * size));
+
+    // The history is discarded after resizes and resets
+    var history_valid = false;
+    if denoiser_config.history_valid != 0u && in_bounds(prev_pos) {
+        let prev = prev_gbuffer[pixel_index(prev_pos)];
+        history_valid = dot(prev.xyz, normal) > NORMAL_THRESHOLD
+            && abs(prev.w - depth) <= DEPTH_THRESHOLD * max(depth, 1.0);
+    }
+
+    var moments = vec2<f32>(lum, lum * lum);
+    var history_length = 1.0;
+    var result = color;
+
+    if history_valid {
+        let prev_index = pixel_index(prev_pos);
+        let prev_moments = prev_moments_buffer[prev_index];
+
+        history_length = min(prev_moments.z + 1.0, f32(denoiser_config.max_history));
+
+        let color_alpha = max(denoiser_config.color_alpha, 1.0 / history_length);
+        let moments_alpha = max(denoiser_config.moments_alpha, 1.0 / history_length);
+
+        moments = mix(prev_moments.xy, moments, moments_alpha);
+        result = mix(history_buffer[prev_index].rgb, color, color_alpha);
+    }
+
+    var variance = max(moments.y - moments.x * moments.x, 0.0);
+
+    // Temporal moments are unreliable right after disocclusion, estimate variance spatially
+    if history_length < MIN_HISTORY_FOR_TEMPORAL_VARIANCE {
+        var spatial_moments = vec2<f32>(0.0);
+        var weight_sum = 0.0;
+
+        for (var y = -1; y <= 1; y++) {
+            for (var x = -1; x <= 1; x++) {
+                let sample_pos = pos + vec2<i32>(x, y);
+                if !in_bounds(sample_pos) {
+                    continue;
+                }
+
+                let sample_lum = luminance(color_buffer[pixel_index(sample_pos)].rgb);
+                spatial_moments += vec2<f32>(sample_lum, sample_lum * sample_lum);
+                weight_sum += 1.0;
+            }
+        }
+
+        spatial_moments /= weight_sum;
+        variance = max(spatial_moments.y - spatial_moments.x * spatial_moments.x, 0.0);
+    }
+
+    moments_buffer[index] = vec4<f32>(moments, history_length, 0.0);
+    write_dst(index, vec4<f32>(result, variance));
+}
+
+fn filtered_variance(pos: vec2<i32>) -> f32 {
+    var variance = 0.0;
+    var weight_sum = 0.0;
+
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let sample_pos = pos + vec2<i32>(x, y);
+            if !in_bounds(sample_pos) {
+                continue;
+            }
+
+            let weight = kernel_weight(x) * kernel_weight(y);
+            variance += weight * read_src(pixel_index(sample_pos)).w;
+            weight_sum += weight;
+        }
+    }
+
+    return variance / weight_sum;
+}
+
+fn atrous(pos: vec2<i32>) {
+    let index = pixel_index(pos);
+    let center = read_src(index);
+    let normal = normal_buffer[index].xyz;
+    let depth = depth_buffer[index];
+    let lum = luminance(center.rgb);
+
+    let sigma_lum = denoiser_config.phi_color * sqrt(filtered_variance(pos)) + 1e-6;
+    let step_size = i32(denoise_pass.step_size);
+
+    let center_weight = kernel_weight(0) * kernel_weight(0);
+    var color_sum = center.rgb * center_weight;
+    var variance_sum = center.w * center_weight * center_weight;
+    var weight_sum = center_weight;
+
+    for (var y = -2; y <= 2; y++) {
+        for (var x = -2; x <= 2; x++) {
+            if x == 0 && y == 0 {
+                continue;
+            }
+
+            let offset = vec2<i32>(x, y) * step_size;
+            let sample_pos = pos + offset;
+            if !in_bounds(sample_pos) {
+                continue;
+            }
+
+            let sample_index = pixel_index(sample_pos);
+            let sample = read_src(sample_index);
+
+            let normal_weight = pow(max(dot(normal, normal_buffer[sample_index].xyz), 0.0), denoiser_config.phi_normal);
+            let depth_weight = exp(
+                -abs(depth - depth_buffer[sample_index]) 
+                / (denoiser_config.phi_depth * length(vec2<f32>(offset)) + 1e-6)
+            );
+            let lum_weight = exp(-abs(lum - luminance(sample.rgb)) / sigma_lum);
+
+            let weight = kernel_weight(x) * kernel_weight(y) * normal_weight * depth_weight * lum_weight;
+
+            color_sum += sample.rgb * weight;
+            variance_sum += sample.w * weight * weight;
+            weight_sum += weight;
+        }
+    }
+
+    let result = vec4<f32>(color_sum / weight_sum, variance_sum / (weight_sum * weight_sum));
+
+    // The first iteration output becomes the temporal history, as in SVGF
+    if denoise_pass.iteration == 0u {
+        history_buffer[index] = result;
+    }
+
+    write_dst(index, result);
+}
+
+fn resolve(pos: vec2<i32>) {
+    let index = pixel_index(pos);
+    let result = read_src(index);
+
+    if denoiser_config.iterations == 0u {
+        history_buffer[index] = result;
+    }
+
+    color_buffer[index] = vec4<f32>(result.rgb, 1.0);
+    prev_gbuffer[index] = vec4<f32>(normal_buffer[index].xyz, depth_buffer[index]);
+    prev_moments_buffer[index] = moments_buffer[index];
+}
+
+@compute @workgroup_size(1)
+fn cs_main(
+    @builtin(global_invocation_id) id: vec3<u32>
+) {
+    let pos = vec2<i32>(id.xy);
+
+    switch denoise_pass.kind {
+        case PASS_TEMPORAL: {
+            temporal(pos);
+        }
+        case PASS_ATROUS: {
+            atrous(pos);
+        }
+        case PASS_RESOLVE: {
+            resolve(pos);
+        }
+        default: {}
+    }
+}
@@ -3,6 +3,8 @@ struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) normal: vec3<f32>,
     @location(2) color: vec3<f32>,
+    @location(3) uv: vec2<f32>,
+    @location(4) texture: u32,
 }
 
 struct VertexOutput {
@@ -11,6 +13,8 @@ struct VertexOutput {
     @location(1) frag_pos: vec3<f32>,
     @location(2) normal: vec3<f32>,
     @location(3) camera_pos: vec3<f32>,
+    @location(4) uv: vec2<f32>,
+    @location(5) @interpolate(flat) texture: u32,
 };
 
 // Camera
@@ -22,22 +26,36 @@ struct CameraUniform {
 @group(0) @binding(0)
 var<uniform> camera: CameraUniform;
 
+// Block textures
+const UNTEXTURED: u32 = 0xFFFFFFFFu;
+
+@group(1) @binding(0)
+var block_textures: texture_2d_array<f32>;
+
+@group(1) @binding(1)
+var block_textures_sampler: sampler;
+
 // Transform
 struct TransformUniform {
     transform_matrix: mat4x4<f32>,
     inverse_matrix: mat4x4<f32>,
 };
 
-var<push_constant> transform: TransformUniform;
+@group(2) @binding(0)
+var<storage, read> instances: array<TransformUniform>;
 
 // Entries
 @vertex
 fn vs_main(
     input: VertexInput,
+    @builtin(instance_index) instance: u32,
 ) -> VertexOutput {
     var out: VertexOutput;
+    let transform = instances[instance];
 
     out.color = input.color;
+    out.uv = input.uv;
+    out.texture = input.texture;
     out.frag_pos = vec3<f32>((transform.transform_matrix * vec4<f32>(input.position, 1.0)).xyz);
 
     var transp = transpose(transform.inverse_matrix);
@@ -49,12 +67,17 @@ fn vs_main(
 
 @fragment
 fn fs_main(output: VertexOutput) -> @location(0) vec4<f32> {
+    // Sampled in uniform control flow, and replaced by the vertex color if untextured
+    let untextured = output.texture == UNTEXTURED;
+    let texel = textureSample(block_textures, block_textures_sampler, output.uv, select(output.texture, 0u, untextured));
+    let color = select(texel.rgb, output.color, untextured);
+
     let light_pos = vec3(-64.0, 64.0, 64.0);
     let light_color = vec3<f32>(1.0, 1.0, 1.0);
 
     // ambient
     let ambient_strength = 0.1;
-    let ambient = ambient_strength * output.color;
+    let ambient = ambient_strength * color;
   	
     // diffuse 
     let norm = normalize(output.normal);
@@ -69,7 +92,7 @@ fn fs_main(output: VertexOutput) -> @location(0) vec4<f32> {
     let spec = pow(max(dot(view_dir, reflect_dir), 0.0), 32.0);
     let specular = specular_strength * spec * light_color;  
         
-    let result = (ambient + diffuse + specular) * output.color;
+    let result = (ambient + diffuse + specular) * color;
 
     return vec4<f32>(result, 1.0);
 }
\ No newline at end of file
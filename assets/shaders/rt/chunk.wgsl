@@ -15,6 +15,46 @@ var chunks: texture_3d<u32>;
 @group(1) @binding(8)
 var chunks_sampler: sampler;
 
+// Top, side and bottom texture layers of block types, and whether they are textured
+@group(1) @binding(10)
+var<storage, read> block_faces: array<vec4<u32>>;
+
+@group(1) @binding(11)
+var block_textures: texture_2d_array<f32>;
+
+@group(1) @binding(12)
+var block_textures_sampler: sampler;
+
+// Color of the hit point on a face of a voxel, from the block texture or the palette
+fn voxel_color(voxel: Voxel::Voxel, pos: vec3<f32>, hit_pos: vec3<f32>, axis: i32, normal: vec3<f32>) -> vec4<f32> {
+    let faces = block_faces[voxel.color_id];
+
+    if faces.w == 0u {
+        return palettes_buffer[voxel.color_id];
+    }
+
+    let local = clamp(hit_pos - pos, vec3<f32>(0.0), vec3<f32>(1.0));
+
+    // Side textures are upright, as rows of textures go downwards
+    var uv = vec2<f32>(local.x, 1.0 - local.y);
+    var layer = faces.y;
+
+    switch axis {
+        case 0: {
+            uv = vec2<f32>(local.z, 1.0 - local.y);
+        }
+        case 1: {
+            uv = local.xz;
+            layer = select(faces.z, faces.x, normal.y > 0.0);
+        }
+        default: {}
+    }
+
+    let texel = textureSampleLevel(block_textures, block_textures_sampler, uv, i32(layer), 0.0);
+
+    return vec4<f32>(texel.rgb, 1.0);
+}
+
 fn hit(
     ray: Ray::Ray, 
     box_t_min: f32, 
@@ -29,14 +69,18 @@ fn hit(
         return false;
     }
 
-    let voxels_per_unit = (Constants::CHUNK_MAX - Constants::CHUNK_MIN) * f32(Constants::CHUNK_SIZE);
+    // Chunks are written at their level of detail, stored in every texel
+    let lod = textureLoad(chunks, vec3<i32>(0), 0).z;
+    let grid_size = f32(Constants::CHUNK_SIZE >> lod);
+
+    let voxels_per_unit = (Constants::CHUNK_MAX - Constants::CHUNK_MIN) * grid_size;
     
     var entry_pos = ((ray.origin + ray.direction * (grid_record.t + 0.0001)) - Constants::CHUNK_MIN) * voxels_per_unit;
 
     let step = Utils::vec_sign(ray.direction);
     let t_delta = abs(1.0 / ray.direction);
 
-    var pos = clamp(floor(entry_pos), vec3<f32>(0.0), vec3<f32>(f32(Constants::CHUNK_SIZE)));
+    var pos = clamp(floor(entry_pos), vec3<f32>(0.0), vec3<f32>(grid_size));
     var t_max = (pos - entry_pos + max(step, vec3<f32>(0.0))) / ray.direction;
 
     var voxel = Voxel::Voxel(false, 0);
@@ -66,7 +110,8 @@ fn hit(
                 (*record).normal = -(*record).normal;
             }
 
-            (*record).voxel_color = palettes_buffer[voxel.color_id];
+            let hit_pos = ((*record).p - Constants::CHUNK_MIN) * voxels_per_unit;
+            (*record).voxel_color = voxel_color(voxel, pos, hit_pos, axis, (*record).normal);
 
             return true;
         } 
@@ -74,13 +119,13 @@ fn hit(
         if t_max.x < t_max.y { 
             if t_max.x < t_max.z {
                 pos.x += step.x;
-                if pos.x < 0.0 || pos.x >= f32(Constants::CHUNK_SIZE) { return false; }
+                if pos.x < 0.0 || pos.x >= grid_size { return false; }
 
                 axis = 0;
                 t_max.x += t_delta.x;
             } else {
                 pos.z += step.z; 
-                if pos.z < 0.0 || pos.z >= f32(Constants::CHUNK_SIZE) { return false; }
+                if pos.z < 0.0 || pos.z >= grid_size { return false; }
 
                 axis = 2;
                 t_max.z += t_delta.z;
@@ -88,13 +133,13 @@ fn hit(
         } else { 
             if t_max.y < t_max.z { 
                 pos.y += step.y; 
-                if pos.y < 0.0 || pos.y >= f32(Constants::CHUNK_SIZE) { return false; } 
+                if pos.y < 0.0 || pos.y >= grid_size { return false; } 
 
                 axis = 1;
                 t_max.y += t_delta.y; 
             } else { 
                 pos.z += step.z; 
-                if pos.z < 0.0 || pos.z >= f32(Constants::CHUNK_SIZE) { return false; } 
+                if pos.z < 0.0 || pos.z >= grid_size { return false; } 
 
                 axis = 2;
                 t_max.z += t_delta.z; 
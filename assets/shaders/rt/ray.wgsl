@@ -9,15 +9,27 @@ struct Ray {
 
 fn on_coords(
     pos: vec2<u32>, 
+    jitter: vec2<f32>,
+    seed: f32,
     camera: Utils::Camera,
 ) -> Ray {
     let pixel_sample = camera.first_pixel
-        + (f32(pos.x) * camera.pixel_delta_u)
-        + (f32(pos.y) * camera.pixel_delta_v);
+        + ((f32(pos.x) + jitter.x) * camera.pixel_delta_u)
+        + ((f32(pos.y) + jitter.y) * camera.pixel_delta_v);
 
-    let ray_direction = pixel_sample - camera.center;
+    if camera.orthographic != 0u {
+        return Ray(pixel_sample, camera.forward);
+    }
+
+    var origin = camera.center;
+
+    // Thin lens: rays start on the aperture disk and converge on the focus plane
+    if camera.lens_radius > 0.0 {
+        let lens_sample = Utils::random_vec_in_unit_disk(vec2<f32>(pos), seed);
+        origin += lens_sample.x * camera.defocus_disk_u + lens_sample.y * camera.defocus_disk_v;
+    }
 
-    return Ray(camera.center, ray_direction);
+    return Ray(origin, pixel_sample - origin);
 }
 
 fn at(ray: Ray, t: f32) -> vec3<f32> {
@@ -3,22 +3,62 @@
 struct TaaConfig {
     canvas_width: u32,
     canvas_height: u32,
-    jitter: f32,
+    jitter: vec2<f32>,
+    seed: f32,
+};
+
+struct TaaPass {
+    present: u32,
 };
 
 struct Transform {
     inverse_matrix: mat4x4<f32>,
-    prev_matrix: mat4x4<f32>,
+    prev_transform_matrix: mat4x4<f32>,
+};
+
+struct Accumulation {
+    frame_count: u32,
+    enabled: u32,
+    converged: u32,
+};
+
+struct DenoiserConfig {
+    canvas_width: u32,
+    canvas_height: u32,
+    iterations: u32,
+    max_history: u32,
+    phi_color: f32,
+    phi_normal: f32,
+    phi_depth: f32,
+    color_alpha: f32,
+    moments_alpha: f32,
+    history_valid: u32,
+};
+
+struct DenoisePass {
+    kind: u32,
+    iteration: u32,
+    step_size: u32,
+    flip: u32,
 };
 
 struct Camera {
     image_width: u32,
     image_height: u32,
+    scan_depth: u32,
+    orthographic: u32,
     center: vec3<f32>,
+    lens_radius: f32,
     first_pixel: vec3<f32>,
+    near: f32,
     pixel_delta_u: vec3<f32>,
+    far: f32,
     pixel_delta_v: vec3<f32>,
-    scan_depth: u32,
+    defocus_disk_u: vec3<f32>,
+    defocus_disk_v: vec3<f32>,
+    forward: vec3<f32>,
+    view_projection: mat4x4<f32>,
+    prev_view_projection: mat4x4<f32>,
 };
 
 fn random_vec_in_unit_sphere(co: vec2<f32>, jitter: f32) -> vec3<f32> {
@@ -32,6 +72,17 @@ fn random_vec_in_unit_sphere(co: vec2<f32>, jitter: f32) -> vec3<f32> {
     return vec3<f32>(0.0);
 }
 
+fn random_vec_in_unit_disk(co: vec2<f32>, jitter: f32) -> vec2<f32> {
+    for (var j = 0.0; j < 1.0; j += 0.1) {
+        let vector = random_vec_range(co, -1.0, 1.0, jitter + j).xy;
+        if dot(vector, vector) < 1.0 {
+            return vector;
+        }
+    }
+
+    return vec2<f32>(0.0);
+}
+
 fn random_vec_range(co: vec2<f32>, min: f32, max: f32, jitter: f32) -> vec3<f32> {
     return vec3<f32>(
         rand_range(co - 1.0, min, max, jitter),
@@ -52,21 +103,17 @@ fn rand_range(co: vec2<f32>, min: f32, max: f32, jitter: f32) -> f32 {
     return min + (max - min) * rand(co, jitter);
 }
 
-fn calc_velocity(new_pos: vec4<f32>, old_pos: vec4<f32>) -> vec2<f32> {
-    var new_pos2 = new_pos;
-    var old_pos2 = old_pos;
-
-    old_pos2 /= old_pos2.w;
-    old_pos2.x = (old_pos2.x+1.0)/2.0;
-    old_pos2.y = (old_pos2.y+1.0)/2.0;
-    old_pos2.y = 1 - old_pos2.y;
-    
-    new_pos2 /= new_pos2.w;
-    new_pos2.x = (new_pos2.x+1)/2.0;
-    new_pos2.y = (new_pos2.y+1)/2.0;
-    new_pos2.y = 1 - new_pos2.y;
-    
-    return (new_pos2 - old_pos2).xy;
+// Screen-space motion of a point between the previous and the current frame, in UV units
+fn calc_velocity(current_uv: vec2<f32>, position: vec4<f32>, prev_view_projection: mat4x4<f32>) -> vec2<f32> {
+    let prev_clip = prev_view_projection * position;
+    if prev_clip.w <= 0.0 {
+        return vec2<f32>(0.0);
+    }
+
+    let prev_ndc = prev_clip.xy / prev_clip.w;
+    let prev_uv = vec2<f32>(prev_ndc.x * 0.5 + 0.5, 0.5 - prev_ndc.y * 0.5);
+
+    return current_uv - prev_uv;
 }
 
 fn vec_sign(vector: vec3<f32>) -> vec3<f32> {
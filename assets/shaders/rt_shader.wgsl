@@ -22,6 +22,9 @@ var<storage, read_write> velocity_buffer: array<vec4<f32>>;
 @group(0) @binding(3)
 var<uniform> taa_config: Utils::TaaConfig;
 
+@group(0) @binding(4)
+var<storage, read> history_depth_buffer: array<f32>;
+
 // Tracer resource
 @group(1) @binding(0)
 var<uniform> camera: Utils::Camera;
@@ -50,6 +53,9 @@ var chunks: texture_3d<u32>;
 @group(1) @binding(8)
 var chunks_sampler: sampler;
 
+@group(1) @binding(9)
+var<uniform> accumulation: Utils::Accumulation;
+
 // Push Constants
 var<push_constant> tmp_transform: Utils::Transform;
 
@@ -102,8 +108,18 @@ fn box_array_hit(
     return hit_anything;
 }
 
-fn render(ray: Ray::Ray, co: vec2<u32>, scan_depth: u32) {
+fn write_color(index: u32, color: vec4<f32>) {
+    if accumulation.enabled != 0u && accumulation.frame_count > 0u {
+        let weight = 1.0 / f32(accumulation.frame_count + 1u);
+        color_buffer[index] = mix(color_buffer[index], color, weight);
+    } else {
+        color_buffer[index] = color;
+    }
+}
+
+fn render(ray: Ray::Ray, world_direction: vec3<f32>, co: vec2<u32>, scan_depth: u32, t_range: vec2<f32>) {
     var current_ray = ray;
+    var current_t_range = t_range;
     var current_depth = scan_depth;
     var attenuation = 1.0;
 
@@ -116,7 +132,7 @@ fn render(ray: Ray::Ray, co: vec2<u32>, scan_depth: u32) {
 
     loop {
         if current_depth == 0u {
-            color_buffer[index] = vec4<f32>(vec3<f32>(0.0), 1.0);
+            write_color(index, vec4<f32>(vec3<f32>(0.0), 1.0));
             return;
         }
 
@@ -126,41 +142,58 @@ fn render(ray: Ray::Ray, co: vec2<u32>, scan_depth: u32) {
         // if box_array_hit(current_ray, 0.001, 3.40282347e+38, &hit_record) {
 
         // For voxel tracing
-        if Chunk::hit(current_ray, 0.001, 3.40282347e+38, &hit_record) {   
-
-            let direction = hit_record.normal + Utils::random_vec_in_unit_sphere(vec2<f32>(co), taa_config.jitter);
+        if Chunk::hit(current_ray, current_t_range.x, current_t_range.y, &hit_record) {   
+            if current_depth == scan_depth {
+                normal_buffer[index] = vec4<f32>(hit_record.normal, 1.0);
+                depth_buffer[index] = hit_record.t;
+                velocity_buffer[index] = vec4<f32>(pixel_velocity(co, tmp_transform.prev_transform_matrix * vec4<f32>(hit_record.p, 1.0)), 0.0, 1.0);
+            }
+
+            let direction = hit_record.normal + Utils::random_vec_in_unit_sphere(vec2<f32>(co), taa_config.seed);
             current_ray = Ray::Ray(hit_record.p, direction);
+            // Only primary rays are clipped by the near and far planes
+            current_t_range = vec2<f32>(0.001, 3.40282347e+38);
             attenuation *= 0.5;
             current_depth -= 1u;
         } else {
-            color_buffer[index] = vec4<f32>(background_color  * attenuation, 1.0);
+            if current_depth == scan_depth {
+                normal_buffer[index] = vec4<f32>(0.0);
+                depth_buffer[index] = 0.0;
+                velocity_buffer[index] = vec4<f32>(pixel_velocity(co, vec4<f32>(world_direction, 0.0)), 0.0, 1.0);
+            }
+
+            write_color(index, vec4<f32>(background_color  * attenuation, 1.0));
             return;
         }
     }
 }
 
-fn calc_velocity(coord : vec2<f32>, depth_sample: f32) -> vec4<f32> {
-    if (depth_sample >= 1.0) {
-        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
-    }
-
-    let pos_clip = vec4(coord.x * 2.0 - 1.0, (1.0 - coord.y) * 2.0 - 1.0, depth_sample, 1.0);
-    let pos_world_w = tmp_transform.inverse_matrix * pos_clip;
-    let pos_world = pos_world_w / pos_world_w.w;
+// Velocity of the traced point, where `position` is either a world-space hit point 
+// at its previous location (w = 1) or a ray direction towards the background (w = 0)
+fn pixel_velocity(co: vec2<u32>, position: vec4<f32>) -> vec2<f32> {
+    let canvas_size = vec2<f32>(f32(taa_config.canvas_width), f32(taa_config.canvas_height));
+    let current_uv = (vec2<f32>(co) + 0.5 + taa_config.jitter) / canvas_size;
 
-    let current_pos = pos_clip;
-    let previous_pos = tmp_transform.prev_matrix * pos_world;
-    let velocity = (current_pos - previous_pos) * 0.5;
-    return vec4<f32>(velocity.x, velocity.y, 0.0, 1.0);
+    return Utils::calc_velocity(current_uv, position, camera.prev_view_projection);
 }
 
 @compute @workgroup_size(1)
 fn cs_main(
     @builtin(global_invocation_id) id: vec3<u32>
 ) {
-    var current_ray = Ray::on_coords(id.xy, camera);
-    current_ray.origin = (tmp_transform.inverse_matrix * vec4<f32>(current_ray.origin, 1.0)).xyz;
-    current_ray.direction = (tmp_transform.inverse_matrix * vec4<f32>(current_ray.direction, 0.0)).xyz;
+    if accumulation.enabled != 0u && accumulation.converged != 0u {
+        return;
+    }
+
+    let world_ray = Ray::on_coords(id.xy, taa_config.jitter, taa_config.seed, camera);
+
+    var current_ray = world_ray;
+    current_ray.origin = (tmp_transform.inverse_matrix * vec4<f32>(world_ray.origin, 1.0)).xyz;
+    current_ray.direction = (tmp_transform.inverse_matrix * vec4<f32>(world_ray.direction, 0.0)).xyz;
+
+    // The near and far planes bound the view depth, which is `t` times the depth of the ray direction
+    let direction_depth = dot(world_ray.direction, camera.forward);
+    let t_range = vec2<f32>(camera.near, camera.far) / direction_depth;
 
-    render(current_ray, id.xy, camera.scan_depth);
+    render(current_ray, world_ray.direction, id.xy, camera.scan_depth, t_range);
 }
\ No newline at end of file
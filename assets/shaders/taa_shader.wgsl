@@ -20,6 +20,9 @@ var<storage, read_write> velocity_buffer: array<vec4<f32>>;
 @group(0) @binding(3)
 var<uniform> taa_config: Utils::TaaConfig;
 
+@group(0) @binding(4)
+var<storage, read> history_depth_buffer: array<f32>;
+
 // Tracer resource
 @group(1) @binding(0)
 var<uniform> camera: Utils::Camera;
@@ -49,14 +52,25 @@ var chunks: texture_3d<u32>;
 var chunks_sampler: sampler;
 
 // Push Constants
-var<push_constant> tmp_transform: Utils::Transform;
+var<push_constant> taa_pass: Utils::TaaPass;
+
+// ========= Constants =========
+
+const MODULATION_FACTOR: f32 = 0.9;
+
+const DISOCCLUSION_THRESHOLD: f32 = 0.1;
 
 // ========= Render =========
 
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) @interpolate(flat) present: u32,
+};
+
 @vertex
 fn vs_main(
     @builtin(vertex_index) vertex_index: u32
-) -> @builtin(position) vec4<f32> {
+) -> VertexOutput {
     var x = 0.0;
     var y = 0.0;
 
@@ -88,55 +102,67 @@ fn vs_main(
         default: {}
     };
 
-    return vec4<f32>(
-        x + (taa_config.jitter / 1280.0), 
-        y + (taa_config.jitter / 720.0), 
-        0.0, 
-        1.0
-    );
+    var out: VertexOutput;
+    out.position = vec4<f32>(x, y, 0.0, 1.0);
+    out.present = taa_pass.present;
+    return out;
 }
 
-@fragment
-fn fs_main(
-    @builtin(position) frag_pos: vec4<f32>,
-) -> @location(0) vec4<f32> {
-
-    let color_pos = vec2<u32>(
-        u32(frag_pos.x + 1.0),
-        u32(frag_pos.y + 1.0),
+fn pixel_index(pos: vec2<i32>) -> u32 {
+    let clamped = clamp(
+        pos, 
+        vec2<i32>(0), 
+        vec2<i32>(i32(taa_config.canvas_width) - 1, i32(taa_config.canvas_height) - 1),
     );
 
-    let history_pos = vec2<f32>(
-        frag_pos.x / f32(taa_config.canvas_width),
-        frag_pos.y / f32(taa_config.canvas_height),
-    );
-
-    let depth = depth_buffer[color_pos.x + color_pos.y * taa_config.canvas_width];
-    let depth2 = depth2_buffer[color_pos.x + color_pos.y * taa_config.canvas_width];
-    let normal = normal_buffer[color_pos.x + color_pos.y * taa_config.canvas_width];
-    let velocity = velocity_buffer[color_pos.x + color_pos.y * taa_config.canvas_width].xy;
-    let background_color = background_buffer[color_pos.x + color_pos.y * taa_config.canvas_width];
-    let previous_pixel_pos = history_pos - velocity;
-
-    let current_color = color_buffer[color_pos.x + color_pos.y * taa_config.canvas_width];
-    var history_color = textureSample(history_texture, history_sampler, previous_pixel_pos);
-
-    let near_color0 = color_buffer[(color_pos.x + 1) + color_pos.y * taa_config.canvas_width];
-    let near_color1 = color_buffer[color_pos.x + (color_pos.y + 1) * taa_config.canvas_width];
-    let near_color2 = color_buffer[(color_pos.x - 1) + color_pos.y * taa_config.canvas_width];
-    let near_color3 = color_buffer[color_pos.x + (color_pos.y - 1) * taa_config.canvas_width];
+    return u32(clamped.x) + u32(clamped.y) * taa_config.canvas_width;
+}
 
-    let box_min = min(current_color, min(near_color0, min(near_color1, min(near_color2, near_color3))));
-    let box_max = max(current_color, max(near_color0, max(near_color1, max(near_color2, near_color3))));
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let canvas_size = vec2<f32>(f32(taa_config.canvas_width), f32(taa_config.canvas_height));
+    let uv = input.position.xy / canvas_size;
+
+    // History already holds the resolved frame
+    if input.present != 0u {
+        return textureSampleLevel(history_texture, history_sampler, uv, 0.0);
+    }
+
+    let pos = vec2<i32>(input.position.xy);
+    let index = pixel_index(pos);
+
+    let current_color = color_buffer[index];
+    let velocity = velocity_buffer[index].xy;
+    let depth = depth_buffer[index];
+    let previous_uv = uv - velocity;
+
+    // Disocclusion detection
+    if any(previous_uv < vec2<f32>(0.0)) || any(previous_uv >= vec2<f32>(1.0)) {
+        return current_color;
+    }
+
+    let previous_depth = history_depth_buffer[pixel_index(vec2<i32>(previous_uv * canvas_size))];
+    if abs(previous_depth - depth) > DISOCCLUSION_THRESHOLD * max(depth, 1.0) {
+        return current_color;
+    }
+
+    // Neighbourhood clamping
+    var box_min = current_color;
+    var box_max = current_color;
+
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let near_color = color_buffer[pixel_index(pos + vec2<i32>(x, y))];
+            box_min = min(box_min, near_color);
+            box_max = max(box_max, near_color);
+        }
+    }
 
+    var history_color = textureSampleLevel(history_texture, history_sampler, previous_uv, 0.0);
     history_color = clamp(history_color, box_min, box_max);
 
-    let modulation_factor = 0.9;
-
-    // return mix(current_color, history_color, modulation_factor);
-    return current_color;
+    return mix(current_color, history_color, MODULATION_FACTOR);
     // return vec4<f32>(velocity.x, velocity.y, 0.0, 1.0);
     // return vec4<f32>(depth, 0.0, 0.0, 1.0);
-    // return vec4<f32>(depth2, 0.0, 0.0, 1.0);
-    // return normal;
+    // return normal_buffer[index];
 }
@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use tracengine::renderer::hal::{reflection::ShaderReflection, shader::load_wgsl};
+
+/// Command-line arguments for the application.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = "Validates WGSL shaders and lists their bindings without a GPU")]
+pub struct Args {
+    /// Shader directory; every `.wgsl` file in it, excluding subdirectories of imported modules, is validated.
+    #[arg(short, long, default_value = "../assets/shaders")]
+    path: PathBuf,
+}
+
+/// Entry point of the application.
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let mut shaders = std::fs::read_dir(&args.path)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wgsl"))
+        .collect::<Vec<_>>();
+    shaders.sort();
+
+    let mut failed = 0;
+
+    for path in shaders {
+        match load_wgsl(&path).and_then(|shader| ShaderReflection::new(&shader)) {
+            Ok(reflection) => {
+                println!("{}: ok", path.display());
+
+                for binding in reflection.bindings {
+                    println!(
+                        "  @group({}) @binding({}) {}: {}", 
+                        binding.group, 
+                        binding.binding, 
+                        binding.name.as_deref().unwrap_or("_"), 
+                        binding.kind,
+                    );
+                }
+            },
+            Err(e) => {
+                println!("{}: {e}", path.display());
+                failed += 1;
+            },
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{failed} shader(s) failed to validate");
+    }
+
+    Ok(())
+}
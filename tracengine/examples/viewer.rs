@@ -1,42 +1,71 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use clap::Parser;
 use tracengine::{
-    engine::Engine, event::{MouseScrollDelta, WindowEvent}, glm, include_wgsl, renderer::{
-        error::RenderError, hal::{
+    engine::{
+        controller::{CameraController, CameraControllerPlugin, ControllerMode}, 
+        events::{EventReader, Events},
+        hot_reload::{ShaderChanged, ShaderHotReloadPlugin, ShaderWatcher},
+        resources::Resources, 
+        Engine,
+    }, event::WindowEvent, glm, include_wgsl, renderer::{
+        config::RendererConfig, error::RenderError, hal::{
             buffer::{Buffer, BufferResourceDescriptor}, 
-            pipeline::{Pipeline, ShaderResource}
+            pipeline::{Pipeline, ShaderResource},
+            texture::{TextureResourceDescriptor, TextureResourceUsage},
         }, pbr::{
+            batch::DrawBatch,
             camera::{Camera, CameraType, CameraUniform},
-            transform::Transform
+            mesh::Vertex,
+            transform::{Transform, TransformUniform}
         }, types::*, voxel::{
+            atlas::BlockAtlas,
             chunk::Chunk,
+            lod::LodSettings,
             model::VoxelModel
         }, Drawable, Renderer
     }, 
     Game, PhysicalSize, WindowBuilder, World
 };
 
-/// Configuration for the camera, including rotation limits and target positions.
-#[derive(Clone, Default, Debug)]
-struct CameraConfiguration {
-    limit: (f32, f32),
-    target_x: f32,
-    target_y: f32,
-    latest_pos: glm::Vec2,
-}
-
 /// Engine implementation for viewing voxel models.
 #[derive(Default)]
 struct VoxelViewer {
     camera_buffer: Option<Buffer<CameraUniform>>,
     shader_resource: Option<ShaderResource>,
+    atlas_resource: Option<ShaderResource>,
     pipeline: Option<Pipeline>,
-    camera_config: CameraConfiguration,
+    batch: Option<DrawBatch<TransformUniform>>,
+    lod_settings: LodSettings,
     model_path: PathBuf,
+    textures_path: Option<PathBuf>,
+    shader_changed_reader: EventReader<ShaderChanged>,
+}
+
+impl VoxelViewer {
+    /// Recompiles the pipeline from the watched shader directory, keeping the current
+    /// pipeline if the shader fails to compile.
+    fn reload_shaders(&mut self, renderer: &Renderer, dir: &Path) {
+        let pipeline = Pipeline::load_builder(dir.join("main_shader.wgsl")).and_then(|mut builder| {
+            builder
+                .set_label("Viewer".to_owned())
+                .add_bindings(&[
+                    self.shader_resource.as_ref().unwrap(),
+                    self.atlas_resource.as_ref().unwrap(),
+                    self.batch.as_ref().unwrap().shader_resource(),
+                ])
+                .add_vertex_layout(Vertex::vertex_buffer_layout())
+                .try_build(renderer)
+        });
+
+        match pipeline {
+            Ok(pipeline) => self.pipeline = Some(pipeline),
+            Err(e) => eprintln!("{e}"),
+        }
+    }
 }
 
 impl Engine for VoxelViewer {
-    fn init(&mut self, world: &mut World, renderer: &mut Renderer) {
+    fn init(&mut self, world: &mut World, renderer: &mut Renderer, _resources: &mut Resources) -> Result<(), RenderError> {
         self.camera_buffer = Some(Buffer::new(renderer, 1, BufferUsages::UNIFORM | BufferUsages::COPY_DST));
 
         self.shader_resource = Some(
@@ -48,21 +77,42 @@ impl Engine for VoxelViewer {
                 .build(renderer)
         );
 
+        let atlas = match &self.textures_path {
+            Some(path) => BlockAtlas::load_dir(renderer, path)?,
+            None => BlockAtlas::placeholder(renderer)?,
+        };
+
+        self.atlas_resource = Some(
+            ShaderResource::builder()
+                .add_texture(atlas.texture(), &TextureResourceDescriptor {
+                    usage: TextureResourceUsage::TEXTURE | TextureResourceUsage::SAMPLER,
+                    sample_type: Some(TextureSampleType::Float { filterable: true }),
+                    storage_access: None,
+                })
+                .build(renderer)
+        );
+
+        let batch = DrawBatch::new(renderer, 64);
+
         self.pipeline = Some(Pipeline::new_render(
             renderer, 
             include_wgsl!("../../assets/shaders/main_shader.wgsl"),
-            &[self.shader_resource.as_ref().unwrap()],
+            &[
+                self.shader_resource.as_ref().unwrap(),
+                self.atlas_resource.as_ref().unwrap(),
+                batch.shader_resource(),
+            ],
             "Viewer",
             true,
         ));
+        self.batch = Some(batch);
 
-        let models = VoxelModel::load_vox(&self.model_path).unwrap_or_else(|e| {
-            panic!("Cannot load model `{}: {}", &self.model_path.to_str().unwrap(), e);
-        });
+        let models = VoxelModel::load_vox(&self.model_path)?;
 
         for model in models {
             for mut chunk_bundle in model.into_chunks().into_iter() {
-                chunk_bundle.chunk.update(renderer);
+                chunk_bundle.chunk.set_textures(Some(atlas.textures().clone()));
+                chunk_bundle.chunk.update(renderer)?;
                 world.spawn(chunk_bundle);
             }
         }
@@ -74,81 +124,74 @@ impl Engine for VoxelViewer {
             ),
             Transform::new_from_translation(glm::vec3(0.0, 0.0, -75.0)),
         ));
+
+        Ok(())
     }
 
-    fn update(&mut self, _world: &mut World) {}
-
-    fn input(&mut self, event: &WindowEvent, world: &mut World) -> bool {
-        for (_, (_, camera_transform)) in &mut world.query::<(&Camera, &mut Transform)>() {
-            match event {
-                WindowEvent::CursorMoved { position, .. } => {
-                    let (delta_x, delta_y) = {
-                        if self.camera_config.latest_pos == glm::Vec2::default() {
-                            (0.0, 0.0)
-                        } else {
-                            (
-                                position.x as f32 - self.camera_config.latest_pos.x,
-                                position.y as f32 - self.camera_config.latest_pos.y,
-                            )
-                        }
-                    };
-
-                    self.camera_config.latest_pos = glm::vec2(position.x as f32, position.y as f32);
-
-                    let local_x = camera_transform.local_x();
-                    let (tx, ty) = (self.camera_config.target_x, self.camera_config.target_y);
-
-                    self.camera_config.target_x += delta_y * 0.005;
-                    self.camera_config.target_y -= delta_x * 0.005;
-
-                    self.camera_config.target_x = self.camera_config.target_x.clamp(
-                        self.camera_config.limit.0.to_radians(),
-                        self.camera_config.limit.1.to_radians(),
-                    );
-
-                    camera_transform.rotation *=
-                        glm::quat_angle_axis(self.camera_config.target_x - tx, &local_x) *
-                        glm::quat_angle_axis(self.camera_config.target_y - ty, &glm::Vec3::y());
-                },
-                WindowEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(_, delta), .. } => {
-                    camera_transform.translation.z = (camera_transform.translation.z + delta).min(-16.0);
-                },
-                _ => return false,
-            }
-        }
+    fn update(&mut self, _world: &mut World, _resources: &mut Resources) {}
 
-        true
+    fn input(&mut self, _event: &WindowEvent, _world: &mut World, _resources: &mut Resources) -> bool {
+        false
     }
 
     fn render(
         &mut self,
         world: &mut World,
         renderer: &mut Renderer,
+        resources: &mut Resources,
     ) -> Result<(), RenderError> {
+        let shaders_changed = resources.get::<Events<ShaderChanged>>()
+            .is_ok_and(|events| self.shader_changed_reader.read(&events).count() > 0);
+
+        if shaders_changed {
+            if let Ok(watcher) = resources.get::<ShaderWatcher>() {
+                self.reload_shaders(renderer, watcher.root());
+            }
+        }
+
+        let batch = self.batch.as_mut().unwrap();
+
+        for (_, (camera, transform)) in &mut world.query::<(&Camera, &Transform)>() {
+            self.camera_buffer
+                .as_ref()
+                .unwrap()
+                .fill_exact(renderer, 0, &[CameraUniform::new(camera, transform)])?;
+            batch.begin(camera, transform);
+        }
+
+        for (_, (chunk, transform)) in &mut world.query::<(&mut Chunk, &Transform)>() {
+            let uniform = TransformUniform::new(transform);
+            let bounds = |chunk: &Chunk| chunk.aabb().map(|aabb| aabb.transformed(&uniform.transform_matrix));
+
+            // Distant chunks are meshed again at a coarser level of detail
+            let Some(aabb) = bounds(chunk) else { continue };
+            let level = self.lod_settings.select(aabb.distance(&batch.eye()), chunk.lod());
+            if chunk.set_lod(level) {
+                chunk.update(renderer)?;
+            }
+
+            let Some(aabb) = bounds(chunk) else { continue };
+            batch.push(0, chunk, &aabb, uniform)?;
+        }
+
+        batch.prepare(renderer)?;
+
         let canvas = renderer.canvas()?;
         let mut ctx = renderer.draw_ctx();
 
         {
             let mut render_pass = ctx.render_pass(&canvas, renderer.depth_texture());
 
-            for (_, (camera, transform)) in &mut world.query::<(&Camera, &Transform)>() {
-                self.camera_buffer
-                    .as_ref()
-                    .unwrap()
-                    .fill_exact(renderer, 0, &[CameraUniform::new(camera, transform)]).unwrap();
-            }
-
-            for (_, (chunk, transform)) in &mut world.query::<(&Chunk, &mut Transform)>() {
-                render_pass.draw(
-                    renderer, 
-                    Some(chunk),
-                    Some(transform), 
-                    self.pipeline.as_ref().unwrap(),
-                    &[
-                        self.shader_resource.as_ref().unwrap()
-                    ],
-                );
-            }
+            render_pass.draw_batch(
+                renderer,
+                batch,
+                &[self.pipeline.as_ref().unwrap()],
+                &[
+                    self.shader_resource.as_ref().unwrap(),
+                    self.atlas_resource.as_ref().unwrap(),
+                    batch.shader_resource(),
+                ],
+            )?;
         }
 
         ctx.apply(canvas, renderer);
@@ -163,6 +206,14 @@ impl Engine for VoxelViewer {
 pub struct Args {
     #[arg(short, long)]
     path: PathBuf,
+
+    /// Directory of block textures, named after the color ids of the model
+    #[arg(short, long)]
+    textures: Option<PathBuf>,
+
+    /// Shader directory to reload `main_shader.wgsl` from when it is modified, e.g. `../assets/shaders`
+    #[arg(short, long)]
+    shaders: Option<PathBuf>,
 }
 
 /// Entry point of the application.
@@ -173,13 +224,18 @@ fn main() -> anyhow::Result<()> {
         WindowBuilder::new()
             .with_title("Magica Voxel Model Viewer")
             .with_inner_size(PhysicalSize::new(800, 600)),
+        RendererConfig::default(),
     )?;
+    let mut controller = CameraController::new(ControllerMode::Orbit);
+    controller.distance_limit = 16.0..1000.0;
+
+    game.add_plugin(CameraControllerPlugin::<Camera>::new(controller));
+    if let Some(shaders) = args.shaders {
+        game.add_plugin(ShaderHotReloadPlugin::new(shaders));
+    }
     game.set_engine(VoxelViewer {
-        camera_config: CameraConfiguration {
-            limit: (-85.0, 85.0),
-            ..Default::default()
-        },
         model_path: args.path,
+        textures_path: args.textures,
         ..Default::default()
     });
     game.run()?;
@@ -0,0 +1,401 @@
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use game_loop::winit::{
+    event::MouseButton,
+    keyboard::KeyCode,
+    window::{CursorGrabMode, Window},
+};
+
+use crate::{glm, Game};
+use crate::renderer::pbr::{
+    camera::{CameraProjection, CameraType},
+    transform::Transform,
+};
+use super::{
+    input::{Input, InputBindings},
+    plugin::Plugin,
+    schedule::{Stage, SystemContext},
+    time::Time,
+};
+
+/// Movement model of the [`CameraController`].
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum ControllerMode {
+    /// Free 6-DoF flight: forward/backward move along the view direction,
+    /// left/right strafe, up/down move vertically.
+    #[default]
+    Fly,
+    /// Like [`ControllerMode::Fly`], but forward movement stays in the horizontal plane.
+    Walk,
+    /// Rotates around a target point; movement actions pan the target
+    /// and the mouse wheel changes the distance.
+    Orbit,
+}
+
+/// A first-person fly/walk and orbit camera controller.
+///
+/// The controller reads the [`Input`] state through the actions listed in
+/// [`CameraController::bind_defaults`] and writes the resulting position and
+/// orientation to the camera's `Transform`, respecting its [`CameraType`].
+/// On the first [`CameraController::update`] its state is taken from the transform,
+/// so the camera keeps its initial placement.
+///
+/// In fly and walk modes, the grab action grabs the cursor and the release action
+/// releases it; the camera only looks around while the cursor is grabbed.
+/// In orbit mode, the camera rotates while the grab action is held.
+#[derive(Debug, Clone)]
+pub struct CameraController {
+    /// Movement model of the controller.
+    pub mode: ControllerMode,
+    /// Movement speed in world units per second.
+    pub speed: f32,
+    /// Speed multiplier applied while the fast action is held.
+    pub fast_multiplier: f32,
+    /// Speed multiplier applied while the slow action is held.
+    pub slow_multiplier: f32,
+    /// Rotation in radians per pixel of mouse movement.
+    pub sensitivity: f32,
+    /// Allowed pitch range in degrees.
+    pub pitch_limit: Range<f32>,
+    /// Allowed orbit distance range.
+    pub distance_limit: Range<f32>,
+    /// Orbit distance change per mouse wheel line.
+    pub zoom_speed: f32,
+
+    position: glm::Vec3,
+    target: glm::Vec3,
+    distance: f32,
+    yaw: f32,
+    pitch: f32,
+    initialized: bool,
+
+    cursor_grabbed: bool,
+    cursor_grab_request: Option<bool>,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        CameraController {
+            mode: ControllerMode::default(),
+            speed: 8.0,
+            fast_multiplier: 4.0,
+            slow_multiplier: 0.25,
+            sensitivity: 0.005,
+            pitch_limit: -85.0..85.0,
+            distance_limit: 1.0..1000.0,
+            zoom_speed: 1.0,
+            position: glm::Vec3::zeros(),
+            target: glm::Vec3::zeros(),
+            distance: 1.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            initialized: false,
+            cursor_grabbed: false,
+            cursor_grab_request: None,
+        }
+    }
+}
+
+impl CameraController {
+    pub const MOVE_FORWARD: &'static str = "move_forward";
+    pub const MOVE_BACKWARD: &'static str = "move_backward";
+    pub const MOVE_LEFT: &'static str = "move_left";
+    pub const MOVE_RIGHT: &'static str = "move_right";
+    pub const MOVE_UP: &'static str = "move_up";
+    pub const MOVE_DOWN: &'static str = "move_down";
+    pub const MOVE_FAST: &'static str = "move_fast";
+    pub const MOVE_SLOW: &'static str = "move_slow";
+    pub const GRAB_CURSOR: &'static str = "grab_cursor";
+    pub const RELEASE_CURSOR: &'static str = "release_cursor";
+
+    /// Creates a new camera controller.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The movement model of the controller.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `CameraController` with default speed and sensitivity.
+    pub fn new(mode: ControllerMode) -> CameraController {
+        CameraController {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    /// Checks if the cursor is currently grabbed by the controller.
+    pub fn is_cursor_grabbed(&self) -> bool {
+        self.cursor_grabbed
+    }
+
+    /// Requests grabbing or releasing the cursor. Applied on the next [`CameraController::update_cursor`].
+    ///
+    /// # Arguments
+    ///
+    /// * `grab` - Whether the cursor should be grabbed.
+    pub fn grab_cursor(&mut self, grab: bool) {
+        self.cursor_grab_request = Some(grab);
+    }
+
+    /// Adds the default bindings of the controller actions which are not bound yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `bindings` - The bindings to extend.
+    pub fn bind_defaults(bindings: &mut InputBindings) {
+        bindings
+            .bind_default(Self::MOVE_FORWARD, KeyCode::KeyW)
+            .bind_default(Self::MOVE_BACKWARD, KeyCode::KeyS)
+            .bind_default(Self::MOVE_LEFT, KeyCode::KeyA)
+            .bind_default(Self::MOVE_RIGHT, KeyCode::KeyD)
+            .bind_default(Self::MOVE_UP, KeyCode::Space)
+            .bind_default(Self::MOVE_DOWN, KeyCode::ShiftLeft)
+            .bind_default(Self::MOVE_FAST, KeyCode::ControlLeft)
+            .bind_default(Self::MOVE_SLOW, KeyCode::AltLeft)
+            .bind_default(Self::GRAB_CURSOR, MouseButton::Left)
+            .bind_default(Self::RELEASE_CURSOR, KeyCode::Escape);
+    }
+
+    /// Applies pending cursor grab requests to the window.
+    ///
+    /// Platforms that cannot confine the cursor fall back to locking it in place.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - The window whose cursor is controlled.
+    pub fn update_cursor(&mut self, window: &Window) {
+        let Some(grab) = self.cursor_grab_request.take() else {
+            return;
+        };
+
+        let result = if grab {
+            window.set_cursor_grab(CursorGrabMode::Confined)
+                .or_else(|_| window.set_cursor_grab(CursorGrabMode::Locked))
+        } else {
+            window.set_cursor_grab(CursorGrabMode::None)
+        };
+
+        match result {
+            Ok(_) => {
+                window.set_cursor_visible(!grab);
+                self.cursor_grabbed = grab;
+            },
+            Err(e) => eprintln!("Cannot grab cursor: {e}"),
+        }
+    }
+
+    /// Moves and rotates the camera according to the input collected since the previous update.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The current input state.
+    /// * `camera` - The camera, defining how the transform is interpreted.
+    /// * `transform` - The transform of the camera.
+    /// * `delta` - The time elapsed since the previous update in seconds.
+    pub fn update(
+        &mut self,
+        input: &Input,
+        camera: &impl CameraProjection,
+        transform: &mut Transform,
+        delta: f32,
+    ) {
+        if !self.initialized {
+            self.sync(camera, transform);
+        }
+
+        // Cursor grab
+        if self.mode != ControllerMode::Orbit {
+            if !self.cursor_grabbed && input.action_just_pressed(Self::GRAB_CURSOR) {
+                self.grab_cursor(true);
+            }
+
+            if self.cursor_grabbed && (input.action_just_pressed(Self::RELEASE_CURSOR) || !input.is_focused()) {
+                self.grab_cursor(false);
+            }
+        }
+
+        // Rotation
+        let looking = match self.mode {
+            ControllerMode::Orbit => input.action_pressed(Self::GRAB_CURSOR),
+            ControllerMode::Fly | ControllerMode::Walk => self.cursor_grabbed,
+        };
+        let mouse_delta = if looking { input.mouse_delta() } else { glm::Vec2::zeros() };
+
+        self.yaw += mouse_delta.x * self.sensitivity;
+        self.pitch = (self.pitch + mouse_delta.y * self.sensitivity).clamp(
+            self.pitch_limit.start.to_radians(),
+            self.pitch_limit.end.to_radians(),
+        );
+
+        // Translation
+        let forward = self.forward();
+        let right = glm::vec3(self.yaw.cos(), 0.0, self.yaw.sin());
+        let up = glm::Vec3::y();
+
+        let forward = match self.mode {
+            ControllerMode::Walk => glm::vec3(forward.x, 0.0, forward.z).normalize(),
+            ControllerMode::Fly | ControllerMode::Orbit => forward,
+        };
+
+        let mut direction = glm::Vec3::zeros();
+        for (action, axis) in [
+            (Self::MOVE_FORWARD, forward),
+            (Self::MOVE_BACKWARD, -forward),
+            (Self::MOVE_RIGHT, right),
+            (Self::MOVE_LEFT, -right),
+            (Self::MOVE_UP, up),
+            (Self::MOVE_DOWN, -up),
+        ] {
+            if input.action_pressed(action) {
+                direction += axis;
+            }
+        }
+
+        let mut speed = self.speed;
+        if input.action_pressed(Self::MOVE_FAST) {
+            speed *= self.fast_multiplier;
+        }
+        if input.action_pressed(Self::MOVE_SLOW) {
+            speed *= self.slow_multiplier;
+        }
+
+        let offset = if direction != glm::Vec3::zeros() {
+            direction.normalize() * speed * delta
+        } else {
+            glm::Vec3::zeros()
+        };
+
+        let scroll_delta = input.scroll_delta();
+
+        match self.mode {
+            ControllerMode::Fly | ControllerMode::Walk => {
+                self.position += offset;
+                self.speed = (self.speed * 1.1f32.powf(scroll_delta)).max(f32::EPSILON);
+            },
+            ControllerMode::Orbit => {
+                self.target += offset;
+                self.distance = (self.distance - scroll_delta * self.zoom_speed).clamp(
+                    self.distance_limit.start,
+                    self.distance_limit.end,
+                );
+                self.position = self.target - self.forward() * self.distance;
+            },
+        }
+
+        self.write_transform(camera.camera_type(), transform);
+    }
+
+    /// Takes the position and orientation of the controller from the camera's transform.
+    ///
+    /// # Arguments
+    ///
+    /// * `camera` - The camera, defining how the transform is interpreted.
+    /// * `transform` - The transform of the camera.
+    pub fn sync(&mut self, camera: &impl CameraProjection, transform: &Transform) {
+        let camera_to_world = camera.view(transform)
+            .try_inverse()
+            .unwrap_or_else(glm::Mat4::identity);
+
+        let position = (camera_to_world * glm::vec4(0.0, 0.0, 0.0, 1.0)).xyz();
+        let forward = -(camera_to_world * glm::vec4(0.0, 0.0, 1.0, 0.0)).xyz().normalize();
+
+        self.position = position;
+        self.pitch = (-forward.y).clamp(-1.0, 1.0).asin();
+        self.yaw = forward.x.atan2(-forward.z);
+
+        // Orbit around the point of the view ray closest to the world origin
+        self.distance = glm::dot(&-position, &forward).clamp(
+            self.distance_limit.start,
+            self.distance_limit.end,
+        );
+        self.target = position + forward * self.distance;
+
+        self.initialized = true;
+    }
+
+    fn forward(&self) -> glm::Vec3 {
+        glm::vec3(
+            self.pitch.cos() * self.yaw.sin(),
+            -self.pitch.sin(),
+            -self.pitch.cos() * self.yaw.cos(),
+        )
+    }
+
+    fn write_transform(&self, camera_type: CameraType, transform: &mut Transform) {
+        let rotation =
+            glm::quat_angle_axis(self.pitch, &glm::Vec3::x()) *
+            glm::quat_angle_axis(self.yaw, &glm::Vec3::y());
+
+        transform.rotation = rotation;
+        transform.translation = match camera_type {
+            CameraType::FirstPerson => -self.position,
+            CameraType::LookAt => -glm::quat_rotate_vec3(&rotation, &self.position),
+        };
+    }
+}
+
+/// Plugin which drives every entity with a camera of type `C` and a `Transform`
+/// by a [`CameraController`] in the [`Stage::Update`] stage.
+///
+/// The controller state is stored per entity as a `CameraController` component, so
+/// several cameras move independently. Entities without one get a clone of the
+/// controller passed to the plugin, while entities spawned with their own component keep it.
+pub struct CameraControllerPlugin<C> {
+    controller: CameraController,
+    _camera: PhantomData<fn() -> C>,
+}
+
+impl<C> CameraControllerPlugin<C> {
+    /// The name of the controller system.
+    pub const SYSTEM: &'static str = "camera_controller";
+
+    /// Creates a new camera controller plugin.
+    ///
+    /// # Arguments
+    ///
+    /// * `controller` - The controller cloned into every camera without its own controller.
+    pub fn new(controller: CameraController) -> CameraControllerPlugin<C> {
+        CameraControllerPlugin {
+            controller,
+            _camera: PhantomData,
+        }
+    }
+}
+
+impl<C: CameraProjection + hecs::Component> Plugin for CameraControllerPlugin<C> {
+    fn build(&self, game: &mut Game) {
+        if let Ok(input) = game.resources_mut().get_exclusive::<Input>() {
+            CameraController::bind_defaults(input.bindings_mut());
+        }
+
+        let template = self.controller.clone();
+
+        game.add_system(Stage::Update, Self::SYSTEM, move |ctx: &mut SystemContext<'_>| {
+            let uncontrolled = ctx.world
+                .query_mut::<(&C, &Transform)>()
+                .without::<&CameraController>()
+                .into_iter()
+                .map(|(entity, _)| entity)
+                .collect::<Vec<_>>();
+
+            for entity in uncontrolled {
+                ctx.world.insert_one(entity, template.clone())?;
+            }
+
+            let input = ctx.resources.get::<Input>()?;
+            let delta = ctx.resources.get::<Time>()?.unscaled_fixed_delta();
+            let window = ctx.renderer.window();
+
+            for (_, (camera, transform, controller)) in
+                ctx.world.query_mut::<(&C, &mut Transform, &mut CameraController)>()
+            {
+                controller.update_cursor(&window);
+                controller.update(&input, camera, transform, delta);
+            }
+
+            Ok(())
+        });
+    }
+}
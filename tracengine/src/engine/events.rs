@@ -0,0 +1,303 @@
+use std::marker::PhantomData;
+
+use crate::renderer::voxel::block::Block;
+use super::resources::Resources;
+
+/// A double-buffered queue of events of type `T`, stored in `Resources`.
+///
+/// Events stay readable until the buffers have been swapped twice. `Game` swaps them
+/// after a rendered frame, but only once a fixed update has run since the previous swap,
+/// so every event is seen by readers in fixed update stages as well as by readers in
+/// frame stages, whichever of the tick and frame rates is higher.
+/// Each consumer keeps its own [`EventReader`], so several subsystems can react
+/// to the same events independently.
+#[derive(Debug, Clone)]
+pub struct Events<T> {
+    previous: Vec<(usize, T)>,
+    current: Vec<(usize, T)>,
+    event_count: usize,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Events {
+            previous: vec![],
+            current: vec![],
+            event_count: 0,
+        }
+    }
+}
+
+impl<T: 'static> Events<T> {
+    /// Creates an empty event queue.
+    pub fn new() -> Events<T> {
+        Events::default()
+    }
+
+    /// Sends an event, making it readable by all readers.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to send.
+    pub fn send(&mut self, event: T) {
+        self.current.push((self.event_count, event));
+        self.event_count += 1;
+    }
+
+    /// Sends several events at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The events to send.
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = T>) {
+        for event in events {
+            self.send(event);
+        }
+    }
+
+    /// Creates a reader which only reads events sent after its creation.
+    pub fn reader(&self) -> EventReader<T> {
+        EventReader {
+            last_event_count: self.event_count,
+            _event: PhantomData,
+        }
+    }
+
+    /// Iterates over all buffered events, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.previous.iter().chain(self.current.iter()).map(|(_, event)| event)
+    }
+
+    /// Retrieves the number of buffered events.
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    /// Checks if there are no buffered events.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops all buffered events.
+    pub fn clear(&mut self) {
+        self.previous.clear();
+        self.current.clear();
+    }
+
+    /// Swaps the buffers, dropping the events sent before the previous swap.
+    /// Called by `Game` at the end of frames in which at least one fixed update has run.
+    pub fn update(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    pub(crate) fn update_resource(resources: &mut Resources) {
+        if let Ok(events) = resources.get_exclusive::<Events<T>>() {
+            events.update();
+        }
+    }
+}
+
+/// Decides when `Game` swaps the event buffers.
+///
+/// Buffers are swapped at the end of a frame if a fixed update has run since the
+/// previous swap. An event therefore survives until a fixed update and a frame
+/// have both run after it was sent.
+#[derive(Debug, Default)]
+pub(crate) struct EventSwap {
+    ticked: bool,
+}
+
+impl EventSwap {
+    /// Records a fixed update.
+    pub(crate) fn tick(&mut self) {
+        self.ticked = true;
+    }
+
+    /// Records the end of a rendered frame.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the event buffers must be swapped.
+    pub(crate) fn frame(&mut self) -> bool {
+        std::mem::take(&mut self.ticked)
+    }
+}
+
+/// A cursor into an [`Events`] queue, remembering which events were already read.
+#[derive(Debug)]
+pub struct EventReader<T> {
+    last_event_count: usize,
+    _event: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        EventReader {
+            last_event_count: 0,
+            _event: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for EventReader<T> {
+    fn clone(&self) -> Self {
+        EventReader {
+            last_event_count: self.last_event_count,
+            _event: PhantomData,
+        }
+    }
+}
+
+impl<T: 'static> EventReader<T> {
+    /// Reads the events sent since the previous call.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The event queue to read.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the unread events, oldest first.
+    pub fn read<'a>(&mut self, events: &'a Events<T>) -> impl Iterator<Item = &'a T> + 'a {
+        let last_event_count = self.last_event_count;
+        self.last_event_count = events.event_count;
+
+        events.previous.iter()
+            .chain(events.current.iter())
+            .filter(move |(id, _)| *id >= last_event_count)
+            .map(|(_, event)| event)
+    }
+
+    /// Marks all sent events as read.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The event queue to read.
+    pub fn clear(&mut self, events: &Events<T>) {
+        self.last_event_count = events.event_count;
+    }
+}
+
+/// Sent when the window has been resized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowResized {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Sent when the render surface has been lost and recreated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SurfaceLost;
+
+/// Sent when a chunk has been loaded into the chunk storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkLoaded {
+    /// The index of the chunk in the chunk storage.
+    pub index: u64,
+}
+
+/// Sent when a chunk has been removed from the chunk storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkUnloaded {
+    /// The index of the chunk in the chunk storage.
+    pub index: u64,
+}
+
+/// Sent when a single block of a loaded chunk has been changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockChanged {
+    /// The index of the chunk in the chunk storage.
+    pub chunk: u64,
+    /// The coordinates of the block within the chunk.
+    pub position: [usize; 3],
+    /// The new block.
+    pub block: Block,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `frames` frames with `ticks_per_frame` fixed updates before each of them,
+    /// sending one event from every update and frame, and returns what a tick reader
+    /// and a frame reader have read.
+    fn simulate(frames: usize, ticks_per_frame: f32) -> (Vec<usize>, Vec<usize>) {
+        let mut events = Events::new();
+        let mut swap = EventSwap::default();
+        let (mut tick_reader, mut frame_reader) = (events.reader(), events.reader());
+        let (mut tick_read, mut frame_read) = (vec![], vec![]);
+        let mut sent = 0;
+        let mut ticks = 0.0;
+
+        for _ in 0..frames {
+            ticks += ticks_per_frame;
+
+            while ticks >= 1.0 {
+                ticks -= 1.0;
+                tick_read.extend(tick_reader.read(&events).copied());
+                events.send(sent);
+                sent += 1;
+                swap.tick();
+            }
+
+            frame_read.extend(frame_reader.read(&events).copied());
+            events.send(sent);
+            sent += 1;
+
+            if swap.frame() {
+                events.update();
+            }
+        }
+
+        (tick_read, frame_read)
+    }
+
+    /// Checks that the readers have read the events in order, missing at most the last ones.
+    fn assert_read_all(read: &[usize], sent: usize) {
+        assert!(read.len() + 4 >= sent, "Read {} of {sent} events", read.len());
+        assert!(read.iter().enumerate().all(|(i, event)| i == *event));
+    }
+
+    #[test]
+    fn no_events_missed_at_high_frame_rate() {
+        let (tick_read, frame_read) = simulate(100, 0.25);
+
+        assert_read_all(&tick_read, 125);
+        assert_read_all(&frame_read, 125);
+    }
+
+    #[test]
+    fn no_events_missed_at_high_tick_rate() {
+        let (tick_read, frame_read) = simulate(100, 3.0);
+
+        assert_read_all(&tick_read, 400);
+        assert_read_all(&frame_read, 400);
+    }
+
+    #[test]
+    fn events_dropped_after_two_swaps() {
+        let mut events = Events::new();
+        let mut reader = events.reader();
+
+        events.send(1);
+        events.update();
+        assert_eq!(events.len(), 1);
+
+        events.update();
+        assert!(events.is_empty());
+        assert_eq!(reader.read(&events).count(), 0);
+    }
+
+    #[test]
+    fn readers_are_independent() {
+        let mut events = Events::new();
+        let (mut first, mut second) = (events.reader(), events.reader());
+
+        events.send_batch([1, 2]);
+        assert_eq!(first.read(&events).copied().collect::<Vec<_>>(), [1, 2]);
+
+        events.send(3);
+        assert_eq!(first.read(&events).copied().collect::<Vec<_>>(), [3]);
+        assert_eq!(second.read(&events).copied().collect::<Vec<_>>(), [1, 2, 3]);
+    }
+}
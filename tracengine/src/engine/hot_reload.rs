@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::Game;
+use super::{
+    events::Events,
+    plugin::Plugin,
+    schedule::{Stage, SystemContext},
+};
+
+/// Sent when shader files in the watched directory have been modified, created or removed.
+///
+/// Pipelines using the shaders can be recreated from disk with
+/// [`Pipeline::load_compute`](crate::renderer::hal::pipeline::Pipeline::load_compute) or
+/// [`Pipeline::load_builder`](crate::renderer::hal::pipeline::Pipeline::load_builder)
+/// and swapped in place, keeping the old pipeline if the new one fails to compile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderChanged {
+    /// The changed files.
+    pub paths: Vec<PathBuf>,
+}
+
+/// Polls a directory for changes of `.wgsl` files by their modification time.
+#[derive(Debug)]
+pub struct ShaderWatcher {
+    root: PathBuf,
+    interval: Duration,
+    last_poll: Instant,
+    modified: HashMap<PathBuf, SystemTime>,
+}
+
+impl ShaderWatcher {
+    /// Default time between two scans of the directory.
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Creates a watcher of a shader directory, recording the current state of its files.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The directory to watch, including subdirectories.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `ShaderWatcher`.
+    pub fn new(root: impl Into<PathBuf>) -> ShaderWatcher {
+        let root = root.into();
+        let modified = ShaderWatcher::scan(&root);
+
+        ShaderWatcher {
+            root,
+            interval: ShaderWatcher::DEFAULT_INTERVAL,
+            last_poll: Instant::now(),
+            modified,
+        }
+    }
+
+    /// Retrieves the watched directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Sets the time between two scans of the directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The minimum time between scans.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Scans the directory if the interval has elapsed since the last scan.
+    ///
+    /// # Returns
+    ///
+    /// The files modified, created or removed since the last scan.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < self.interval {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+
+        let modified = ShaderWatcher::scan(&self.root);
+
+        let mut changed = modified
+            .iter()
+            .filter(|(path, time)| self.modified.get(*path) != Some(time))
+            .map(|(path, _)| path.clone())
+            .chain(self.modified.keys().filter(|path| !modified.contains_key(*path)).cloned())
+            .collect::<Vec<_>>();
+        changed.sort();
+
+        self.modified = modified;
+
+        changed
+    }
+
+    fn scan(root: &Path) -> HashMap<PathBuf, SystemTime> {
+        let mut modified = HashMap::new();
+        let mut dirs = vec![root.to_owned()];
+
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+
+            for entry in entries.flatten() {
+                let path = entry.path();
+
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if path.extension().is_some_and(|ext| ext == "wgsl") {
+                    if let Ok(time) = entry.metadata().and_then(|m| m.modified()) {
+                        modified.insert(path, time);
+                    }
+                }
+            }
+        }
+
+        modified
+    }
+}
+
+/// Watches a shader directory during development, sending [`ShaderChanged`] events
+/// before rendering a frame.
+pub struct ShaderHotReloadPlugin {
+    root: PathBuf,
+}
+
+impl ShaderHotReloadPlugin {
+    /// Name of the system polling the shader directory.
+    pub const SYSTEM: &'static str = "shader_hot_reload";
+
+    /// Creates a plugin watching the given shader directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The shader directory, e.g. `assets/shaders`.
+    pub fn new(root: impl Into<PathBuf>) -> ShaderHotReloadPlugin {
+        ShaderHotReloadPlugin { root: root.into() }
+    }
+}
+
+impl Plugin for ShaderHotReloadPlugin {
+    fn build(&self, game: &mut Game) {
+        game.add_event::<ShaderChanged>()
+            .insert_resource(ShaderWatcher::new(&self.root));
+
+        game.add_system(Stage::PreRender, ShaderHotReloadPlugin::SYSTEM, |ctx: &mut SystemContext<'_>| {
+            let paths = ctx.resources.get_mut::<ShaderWatcher>()?.poll();
+
+            if !paths.is_empty() {
+                ctx.resources.get_mut::<Events<ShaderChanged>>()?.send(ShaderChanged { paths });
+            }
+
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,451 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use game_loop::winit::{
+    event::{DeviceEvent, ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::glm;
+
+/// A physical button which can be bound to an action.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputButton {
+    /// A keyboard key, identified by its physical location.
+    Key(KeyCode),
+    /// A mouse button.
+    Mouse(MouseButton),
+}
+
+impl From<KeyCode> for InputButton {
+    fn from(value: KeyCode) -> Self {
+        InputButton::Key(value)
+    }
+}
+
+impl From<MouseButton> for InputButton {
+    fn from(value: MouseButton) -> Self {
+        InputButton::Mouse(value)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum InputBindingsError {
+    #[error("Cannot access input bindings file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Cannot parse input bindings: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("Cannot serialize input bindings: {0}")]
+    Serialize(#[from] ron::Error),
+}
+
+/// Mapping of named actions to the buttons which trigger them.
+///
+/// Bindings are stored in RON files as a map of action names to button lists, e.g.
+/// `{ "move_forward": [Key(KeyW), Key(ArrowUp)], "grab": [Mouse(Left)] }`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct InputBindings {
+    actions: BTreeMap<String, Vec<InputButton>>,
+}
+
+impl InputBindings {
+    /// Creates an empty set of bindings.
+    pub fn new() -> InputBindings {
+        InputBindings::default()
+    }
+
+    /// Loads bindings from a RON file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the bindings file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the loaded bindings or an error.
+    pub fn load(path: impl AsRef<Path>) -> Result<InputBindings, InputBindingsError> {
+        let source = std::fs::read_to_string(path)?;
+
+        Ok(ron::from_str(&source)?)
+    }
+
+    /// Saves bindings to a RON file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the bindings file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), InputBindingsError> {
+        let source = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(path, source)?;
+
+        Ok(())
+    }
+
+    /// Binds a button to an action, keeping its existing bindings.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The name of the action.
+    /// * `button` - The button to bind.
+    pub fn bind(&mut self, action: &str, button: impl Into<InputButton>) -> &mut Self {
+        let button = button.into();
+        let buttons = self.actions.entry(action.to_owned()).or_default();
+
+        if !buttons.contains(&button) {
+            buttons.push(button);
+        }
+
+        self
+    }
+
+    /// Binds a button to an action only if the action has no bindings yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The name of the action.
+    /// * `button` - The default button of the action.
+    pub fn bind_default(&mut self, action: &str, button: impl Into<InputButton>) -> &mut Self {
+        if !self.actions.contains_key(action) {
+            self.bind(action, button);
+        }
+
+        self
+    }
+
+    /// Removes all bindings of an action.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The name of the action.
+    pub fn unbind(&mut self, action: &str) -> &mut Self {
+        self.actions.remove(action);
+        self
+    }
+
+    /// Retrieves the buttons bound to an action.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The name of the action.
+    ///
+    /// # Returns
+    ///
+    /// The bound buttons, empty if the action is unknown.
+    pub fn buttons(&self, action: &str) -> &[InputButton] {
+        self.actions.get(action).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+/// Input state collected from window and device events.
+///
+/// Button states are kept between updates, while "just pressed"/"just released"
+/// flags and mouse deltas are accumulated until [`Input::clear`] is called
+/// after each `Engine::update`, so that no fixed update misses them.
+#[derive(Debug, Clone, Default)]
+pub struct Input {
+    pressed: HashSet<InputButton>,
+    just_pressed: HashSet<InputButton>,
+    just_released: HashSet<InputButton>,
+    mouse_delta: glm::Vec2,
+    scroll_delta: f32,
+    cursor_position: Option<glm::Vec2>,
+    unfocused: bool,
+    bindings: InputBindings,
+}
+
+impl Input {
+    /// Creates a new input state with the given bindings.
+    ///
+    /// # Arguments
+    ///
+    /// * `bindings` - The action bindings.
+    pub fn new(bindings: InputBindings) -> Input {
+        Input {
+            bindings,
+            ..Default::default()
+        }
+    }
+
+    /// Retrieves the action bindings.
+    pub fn bindings(&self) -> &InputBindings {
+        &self.bindings
+    }
+
+    /// Retrieves the action bindings for modification.
+    pub fn bindings_mut(&mut self) -> &mut InputBindings {
+        &mut self.bindings
+    }
+
+    /// Updates the state with a window event.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - A reference to the window event.
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { physical_key, state, repeat, .. },
+                ..
+            } => {
+                self.handle_key(*physical_key, *state, *repeat);
+            },
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.set_button(InputButton::Mouse(*button), *state);
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = Some(glm::vec2(position.x as f32, position.y as f32));
+            },
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor_position = None;
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll_delta += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 16.0,
+                };
+            },
+            WindowEvent::Focused(focused) => {
+                self.unfocused = !focused;
+
+                if !focused {
+                    for button in self.pressed.drain() {
+                        self.just_released.insert(button);
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Updates the state with a raw device event.
+    ///
+    /// Mouse motion is taken from device events, so it keeps working while the cursor is locked.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - A reference to the device event.
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta: (x, y) } = event {
+            if !self.unfocused {
+                self.mouse_delta += glm::vec2(*x as f32, *y as f32);
+            }
+        }
+    }
+
+    /// Resets per-update state. Called by `Game` after each update.
+    pub fn clear(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+        self.mouse_delta = glm::Vec2::zeros();
+        self.scroll_delta = 0.0;
+    }
+
+    /// Checks if a button is held down.
+    pub fn pressed(&self, button: impl Into<InputButton>) -> bool {
+        self.pressed.contains(&button.into())
+    }
+
+    /// Checks if a button has been pressed since the previous update.
+    pub fn just_pressed(&self, button: impl Into<InputButton>) -> bool {
+        self.just_pressed.contains(&button.into())
+    }
+
+    /// Checks if a button has been released since the previous update.
+    pub fn just_released(&self, button: impl Into<InputButton>) -> bool {
+        self.just_released.contains(&button.into())
+    }
+
+    /// Checks if any button bound to an action is held down.
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.bindings.buttons(action).iter().any(|b| self.pressed.contains(b))
+    }
+
+    /// Checks if any button bound to an action has been pressed since the previous update.
+    pub fn action_just_pressed(&self, action: &str) -> bool {
+        self.bindings.buttons(action).iter().any(|b| self.just_pressed.contains(b))
+    }
+
+    /// Checks if any button bound to an action has been released since the previous update.
+    pub fn action_just_released(&self, action: &str) -> bool {
+        self.bindings.buttons(action).iter().any(|b| self.just_released.contains(b))
+    }
+
+    /// Retrieves the raw mouse movement since the previous update.
+    pub fn mouse_delta(&self) -> glm::Vec2 {
+        self.mouse_delta
+    }
+
+    /// Retrieves the mouse wheel movement in lines since the previous update.
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+
+    /// Retrieves the cursor position in window coordinates, if the cursor is inside the window.
+    pub fn cursor_position(&self) -> Option<glm::Vec2> {
+        self.cursor_position
+    }
+
+    /// Checks if the window has input focus.
+    pub fn is_focused(&self) -> bool {
+        !self.unfocused
+    }
+
+    /// Updates the state with a keyboard key, ignoring repeats and unidentified keys.
+    fn handle_key(&mut self, key: PhysicalKey, state: ElementState, repeat: bool) {
+        if let (PhysicalKey::Code(key), false) = (key, repeat) {
+            self.set_button(InputButton::Key(key), state);
+        }
+    }
+
+    fn set_button(&mut self, button: InputButton, state: ElementState) {
+        match state {
+            ElementState::Pressed => {
+                if self.pressed.insert(button) {
+                    self.just_pressed.insert(button);
+                }
+            },
+            ElementState::Released => {
+                if self.pressed.remove(&button) {
+                    self.just_released.insert(button);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game_loop::winit::event::DeviceId;
+
+    use super::*;
+
+    fn mouse_input(state: ElementState, button: MouseButton) -> WindowEvent {
+        WindowEvent::MouseInput {
+            device_id: unsafe { DeviceId::dummy() },
+            state,
+            button,
+        }
+    }
+
+    fn mouse_motion(x: f64, y: f64) -> DeviceEvent {
+        DeviceEvent::MouseMotion { delta: (x, y) }
+    }
+
+    // `KeyEvent` cannot be built outside of winit, so keys are fed below the window event
+    fn key(input: &mut Input, key: KeyCode, state: ElementState) {
+        input.handle_key(PhysicalKey::Code(key), state, false);
+    }
+
+    #[test]
+    fn key_press_and_release() {
+        let mut input = Input::default();
+
+        key(&mut input, KeyCode::KeyW, ElementState::Pressed);
+        assert!(input.pressed(KeyCode::KeyW));
+        assert!(input.just_pressed(KeyCode::KeyW));
+        assert!(!input.just_released(KeyCode::KeyW));
+
+        input.clear();
+        assert!(input.pressed(KeyCode::KeyW));
+        assert!(!input.just_pressed(KeyCode::KeyW));
+
+        key(&mut input, KeyCode::KeyW, ElementState::Released);
+        assert!(!input.pressed(KeyCode::KeyW));
+        assert!(input.just_released(KeyCode::KeyW));
+    }
+
+    #[test]
+    fn repeated_keys_are_ignored() {
+        let mut input = Input::default();
+
+        key(&mut input, KeyCode::KeyA, ElementState::Pressed);
+        input.clear();
+        input.handle_key(PhysicalKey::Code(KeyCode::KeyA), ElementState::Pressed, true);
+
+        assert!(input.pressed(KeyCode::KeyA));
+        assert!(!input.just_pressed(KeyCode::KeyA));
+    }
+
+    #[test]
+    fn mouse_buttons() {
+        let mut input = Input::default();
+
+        input.handle_window_event(&mouse_input(ElementState::Pressed, MouseButton::Left));
+        assert!(input.pressed(MouseButton::Left));
+        assert!(input.just_pressed(MouseButton::Left));
+        assert!(!input.pressed(MouseButton::Right));
+
+        input.clear();
+        input.handle_window_event(&mouse_input(ElementState::Released, MouseButton::Left));
+        assert!(!input.pressed(MouseButton::Left));
+        assert!(input.just_released(MouseButton::Left));
+    }
+
+    #[test]
+    fn mouse_delta_accumulates_until_cleared() {
+        let mut input = Input::default();
+
+        input.handle_device_event(&mouse_motion(3.0, -1.0));
+        input.handle_device_event(&mouse_motion(2.0, 4.0));
+        assert_eq!(input.mouse_delta(), glm::vec2(5.0, 3.0));
+
+        input.clear();
+        assert_eq!(input.mouse_delta(), glm::Vec2::zeros());
+
+        // Motion is ignored while the window is unfocused
+        input.handle_window_event(&WindowEvent::Focused(false));
+        input.handle_device_event(&mouse_motion(1.0, 1.0));
+        assert_eq!(input.mouse_delta(), glm::Vec2::zeros());
+    }
+
+    #[test]
+    fn focus_loss_releases_buttons() {
+        let mut input = Input::default();
+
+        input.handle_window_event(&mouse_input(ElementState::Pressed, MouseButton::Right));
+        input.handle_window_event(&WindowEvent::Focused(false));
+
+        assert!(!input.pressed(MouseButton::Right));
+        assert!(input.just_released(MouseButton::Right));
+        assert!(!input.is_focused());
+    }
+
+    #[test]
+    fn actions_from_ron_bindings() {
+        let path = std::env::temp_dir().join(format!("tracengine_bindings_{}.ron", std::process::id()));
+        std::fs::write(&path, r#"{ "jump": [Key(Space), Mouse(Middle)], "fire": [Mouse(Left)] }"#).unwrap();
+
+        let bindings = InputBindings::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut input = Input::new(bindings.unwrap());
+        assert_eq!(input.bindings().buttons("jump"), [KeyCode::Space.into(), MouseButton::Middle.into()]);
+        assert!(input.bindings().buttons("unknown").is_empty());
+
+        key(&mut input, KeyCode::Space, ElementState::Pressed);
+        assert!(input.action_pressed("jump"));
+        assert!(input.action_just_pressed("jump"));
+        assert!(!input.action_pressed("fire"));
+
+        input.clear();
+        input.handle_window_event(&mouse_input(ElementState::Pressed, MouseButton::Middle));
+        key(&mut input, KeyCode::Space, ElementState::Released);
+        assert!(input.action_pressed("jump"));
+        assert!(input.action_just_released("jump"));
+    }
+
+    #[test]
+    fn default_bindings_keep_loaded_ones() {
+        let mut bindings: InputBindings = ron::from_str(r#"{ "fire": [Key(KeyF)] }"#).unwrap();
+
+        bindings
+            .bind_default("fire", MouseButton::Left)
+            .bind_default("jump", KeyCode::Space);
+
+        assert_eq!(bindings.buttons("fire"), [InputButton::Key(KeyCode::KeyF)]);
+        assert_eq!(bindings.buttons("jump"), [InputButton::Key(KeyCode::Space)]);
+    }
+}
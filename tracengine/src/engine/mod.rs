@@ -1,6 +1,16 @@
+pub mod controller;
+pub mod events;
+pub mod hot_reload;
+pub mod input;
+pub mod plugin;
+pub mod resources;
+pub mod schedule;
+pub mod time;
+
 use game_loop::winit::event::WindowEvent;
 use hecs::World;
 use crate::renderer::{error::RenderError, Renderer};
+use resources::Resources;
 
 /// The `Engine` trait defines the core functionality required for initializing,
 /// updating, handling input, and rendering within the voxel viewer application.
@@ -11,14 +21,22 @@ pub trait Engine {
     ///
     /// * `world` - A mutable reference to the game world.
     /// * `renderer` - A mutable reference to the renderer.
-    fn init(&mut self, world: &mut World, renderer: &mut Renderer);
+    /// * `resources` - The global resources, e.g. to insert engine state.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `RenderError` if the engine fails to set up its rendering resources,
+    /// which stops `Game::run` before the game loop starts.
+    fn init(&mut self, world: &mut World, renderer: &mut Renderer, resources: &mut Resources) -> Result<(), RenderError>;
 
     /// Updates the engine state with the given world.
     ///
     /// # Arguments
     ///
     /// * `world` - A mutable reference to the game world.
-    fn update(&mut self, world: &mut World);
+    /// * `resources` - The global resources, including `Input` collected since the
+    ///   previous update and `Time`, whose fixed delta the simulation should advance by.
+    fn update(&mut self, world: &mut World, resources: &mut Resources);
 
     /// Handles input events for the engine.
     ///
@@ -26,11 +44,12 @@ pub trait Engine {
     ///
     /// * `event` - A reference to the window event.
     /// * `world` - A mutable reference to the game world.
+    /// * `resources` - The global resources.
     ///
     /// # Returns
     ///
     /// A boolean indicating whether the event was handled.
-    fn input(&mut self, event: &WindowEvent, world: &mut World) -> bool;
+    fn input(&mut self, event: &WindowEvent, world: &mut World, resources: &mut Resources) -> bool;
 
     /// Renders the current state of the engine.
     ///
@@ -38,9 +57,10 @@ pub trait Engine {
     ///
     /// * `world` - A mutable reference to the game world.
     /// * `renderer` - A mutable reference to the renderer.
+    /// * `resources` - The global resources, including `Time` with the blending factor between updates.
     ///
     /// # Errors
     ///
     /// Returns a `RenderError` if the rendering process fails.
-    fn render(&mut self, world: &mut World, renderer: &mut Renderer) -> Result<(), RenderError>;
+    fn render(&mut self, world: &mut World, renderer: &mut Renderer, resources: &mut Resources) -> Result<(), RenderError>;
 }
@@ -0,0 +1,12 @@
+use crate::Game;
+
+/// A reusable part of a game, e.g. a renderer, world streaming or physics,
+/// which registers its systems and resources on the [`Game`].
+pub trait Plugin {
+    /// Registers the plugin on the game.
+    ///
+    /// # Arguments
+    ///
+    /// * `game` - The game to extend.
+    fn build(&self, game: &mut Game);
+}
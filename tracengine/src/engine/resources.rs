@@ -0,0 +1,205 @@
+use std::any::{type_name, Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ResourceError {
+    #[error("Resource `{0}` is not inserted")]
+    Missing(&'static str),
+    #[error("Resource `{0}` is already borrowed")]
+    Borrowed(&'static str),
+}
+
+/// A type-map of global resources owned by `Game`, e.g. `Time`, `Input` or renderer data.
+///
+/// Every resource is borrowed independently, so systems can access several
+/// resources at once, e.g. read `Input` while mutating `Time`.
+#[derive(Default)]
+pub struct Resources {
+    map: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl Resources {
+    /// Creates an empty resources container.
+    pub fn new() -> Resources {
+        Resources::default()
+    }
+
+    /// Inserts a resource, replacing the resource of the same type.
+    ///
+    /// # Arguments
+    ///
+    /// * `resource` - The resource to insert.
+    ///
+    /// # Returns
+    ///
+    /// The replaced resource, if any.
+    pub fn insert<T: 'static>(&mut self, resource: T) -> Option<T> {
+        self.map.insert(TypeId::of::<T>(), Box::new(RefCell::new(resource)))
+            .and_then(|old| old.downcast::<RefCell<T>>().ok())
+            .map(|old| old.into_inner())
+    }
+
+    /// Removes a resource.
+    ///
+    /// # Returns
+    ///
+    /// The removed resource, if any.
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        self.map.remove(&TypeId::of::<T>())
+            .and_then(|old| old.downcast::<RefCell<T>>().ok())
+            .map(|old| old.into_inner())
+    }
+
+    /// Checks if a resource of the given type is inserted.
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Borrows a resource immutably.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the resource is missing or already borrowed mutably.
+    pub fn get<T: 'static>(&self) -> Result<Ref<'_, T>, ResourceError> {
+        self.cell::<T>()?
+            .try_borrow()
+            .map_err(|_| ResourceError::Borrowed(type_name::<T>()))
+    }
+
+    /// Borrows a resource mutably.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the resource is missing or already borrowed.
+    pub fn get_mut<T: 'static>(&self) -> Result<RefMut<'_, T>, ResourceError> {
+        self.cell::<T>()?
+            .try_borrow_mut()
+            .map_err(|_| ResourceError::Borrowed(type_name::<T>()))
+    }
+
+    /// Retrieves a resource mutably without runtime borrow tracking.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the resource is missing.
+    pub fn get_exclusive<T: 'static>(&mut self) -> Result<&mut T, ResourceError> {
+        self.map.get_mut(&TypeId::of::<T>())
+            .and_then(|cell| cell.downcast_mut::<RefCell<T>>())
+            .map(RefCell::get_mut)
+            .ok_or(ResourceError::Missing(type_name::<T>()))
+    }
+
+    fn cell<T: 'static>(&self) -> Result<&RefCell<T>, ResourceError> {
+        self.map.get(&TypeId::of::<T>())
+            .and_then(|cell| cell.downcast_ref::<RefCell<T>>())
+            .ok_or(ResourceError::Missing(type_name::<T>()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Score(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct Lives(u32);
+
+    #[test]
+    fn inserted_resources_can_be_borrowed() {
+        let mut resources = Resources::new();
+        assert_eq!(resources.insert(Score(1)), None);
+
+        assert!(resources.contains::<Score>());
+        assert_eq!(*resources.get::<Score>().unwrap(), Score(1));
+    }
+
+    #[test]
+    fn inserting_replaces_the_resource_of_the_same_type() {
+        let mut resources = Resources::new();
+        resources.insert(Score(1));
+        resources.insert(Lives(3));
+
+        assert_eq!(resources.insert(Score(2)), Some(Score(1)));
+        assert_eq!(*resources.get::<Score>().unwrap(), Score(2));
+        assert_eq!(*resources.get::<Lives>().unwrap(), Lives(3));
+    }
+
+    #[test]
+    fn removed_resources_are_missing() {
+        let mut resources = Resources::new();
+        resources.insert(Score(1));
+
+        assert_eq!(resources.remove::<Score>(), Some(Score(1)));
+        assert_eq!(resources.remove::<Score>(), None);
+        assert!(!resources.contains::<Score>());
+    }
+
+    #[test]
+    fn missing_resources_are_reported() {
+        let mut resources = Resources::new();
+        let missing = ResourceError::Missing(type_name::<Score>());
+
+        assert_eq!(resources.get::<Score>().err(), Some(missing.clone()));
+        assert_eq!(resources.get_mut::<Score>().err(), Some(missing.clone()));
+        assert_eq!(resources.get_exclusive::<Score>().err(), Some(missing));
+    }
+
+    #[test]
+    fn shared_borrows_coexist() {
+        let mut resources = Resources::new();
+        resources.insert(Score(1));
+
+        let first = resources.get::<Score>().unwrap();
+        let second = resources.get::<Score>().unwrap();
+
+        assert_eq!(*first, *second);
+    }
+
+    #[test]
+    fn mutable_borrows_conflict_with_other_borrows() {
+        let mut resources = Resources::new();
+        resources.insert(Score(1));
+        let borrowed = ResourceError::Borrowed(type_name::<Score>());
+
+        {
+            let _score = resources.get::<Score>().unwrap();
+            assert_eq!(resources.get_mut::<Score>().err(), Some(borrowed.clone()));
+        }
+
+        let mut score = resources.get_mut::<Score>().unwrap();
+        assert_eq!(resources.get::<Score>().err(), Some(borrowed.clone()));
+        assert_eq!(resources.get_mut::<Score>().err(), Some(borrowed));
+        score.0 = 2;
+        drop(score);
+
+        assert_eq!(*resources.get::<Score>().unwrap(), Score(2));
+    }
+
+    #[test]
+    fn different_resources_are_borrowed_independently() {
+        let mut resources = Resources::new();
+        resources.insert(Score(1));
+        resources.insert(Lives(3));
+
+        let mut score = resources.get_mut::<Score>().unwrap();
+        let lives = resources.get::<Lives>().unwrap();
+        score.0 += lives.0;
+
+        assert_eq!(*score, Score(4));
+    }
+
+    #[test]
+    fn exclusive_access_modifies_the_resource() {
+        let mut resources = Resources::new();
+        resources.insert(Score(1));
+
+        resources.get_exclusive::<Score>().unwrap().0 = 5;
+
+        assert_eq!(*resources.get::<Score>().unwrap(), Score(5));
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+
+use hecs::World;
+use thiserror::Error;
+
+use crate::renderer::Renderer;
+use super::resources::Resources;
+
+/// A stage of the frame in which systems run.
+///
+/// `Input`, `FixedUpdate` and `Update` run once per fixed update, before `Engine::update`.
+/// `PreRender` and `Render` run once per rendered frame, before `Engine::render`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    /// Translates the input state into game actions.
+    Input,
+    /// Advances the simulation by the fixed time step.
+    FixedUpdate,
+    /// Game logic reacting to the simulation.
+    Update,
+    /// Prepares GPU data, e.g. uploads uniforms and resizes buffers.
+    PreRender,
+    /// Records and submits draw commands.
+    Render,
+}
+
+impl Stage {
+    /// Stages that run once per fixed update, in order.
+    pub const TICK: [Stage; 3] = [Stage::Input, Stage::FixedUpdate, Stage::Update];
+    /// Stages that run once per rendered frame, in order.
+    pub const FRAME: [Stage; 2] = [Stage::PreRender, Stage::Render];
+}
+
+/// Data available to systems.
+pub struct SystemContext<'a> {
+    pub world: &'a mut World,
+    pub resources: &'a mut Resources,
+    pub renderer: &'a mut Renderer,
+}
+
+/// A unit of game logic run by the [`Schedule`].
+///
+/// Implemented for all closures taking a [`SystemContext`].
+pub trait System {
+    /// Runs the system.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The data available to the system.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the system fails; render errors are handled by `Game`
+    /// in the same way as errors from `Engine::render`.
+    fn run(&mut self, ctx: &mut SystemContext<'_>) -> anyhow::Result<()>;
+}
+
+impl<F> System for F
+where
+    F: FnMut(&mut SystemContext<'_>) -> anyhow::Result<()>,
+{
+    fn run(&mut self, ctx: &mut SystemContext<'_>) -> anyhow::Result<()> {
+        self(ctx)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ScheduleError {
+    #[error("System `{0}` is registered more than once in stage `{1:?}`")]
+    DuplicateSystem(String, Stage),
+    #[error("Ordering constraints of systems {0:?} in stage `{1:?}` form a cycle")]
+    Cycle(Vec<String>, Stage),
+}
+
+struct SystemEntry {
+    name: String,
+    system: Box<dyn System>,
+    after: Vec<String>,
+    before: Vec<String>,
+}
+
+/// Ordering constraints of a system added to the [`Schedule`].
+pub struct SystemConfig<'a> {
+    entry: &'a mut SystemEntry,
+}
+
+impl SystemConfig<'_> {
+    /// Runs the system after another system of the same stage.
+    /// Constraints on systems which are not registered are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the other system.
+    pub fn after(&mut self, name: &str) -> &mut Self {
+        self.entry.after.push(name.to_owned());
+        self
+    }
+
+    /// Runs the system before another system of the same stage.
+    /// Constraints on systems which are not registered are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the other system.
+    pub fn before(&mut self, name: &str) -> &mut Self {
+        self.entry.before.push(name.to_owned());
+        self
+    }
+}
+
+/// Systems organised into stages.
+///
+/// Within a stage, systems run in the order they were added, unless reordered
+/// by the constraints set with [`SystemConfig::after`] and [`SystemConfig::before`].
+#[derive(Default)]
+pub struct Schedule {
+    stages: HashMap<Stage, Vec<SystemEntry>>,
+    sorted: bool,
+}
+
+impl Schedule {
+    /// Creates an empty schedule.
+    pub fn new() -> Schedule {
+        Schedule::default()
+    }
+
+    /// Adds a system to a stage.
+    ///
+    /// # Arguments
+    ///
+    /// * `stage` - The stage the system runs in.
+    /// * `name` - The unique name of the system, used in ordering constraints.
+    /// * `system` - The system.
+    ///
+    /// # Returns
+    ///
+    /// A `SystemConfig` to set ordering constraints of the system.
+    pub fn add_system(
+        &mut self,
+        stage: Stage,
+        name: &str,
+        system: impl System + 'static,
+    ) -> SystemConfig<'_> {
+        self.sorted = false;
+
+        let systems = self.stages.entry(stage).or_default();
+        systems.push(SystemEntry {
+            name: name.to_owned(),
+            system: Box::new(system),
+            after: vec![],
+            before: vec![],
+        });
+
+        SystemConfig { entry: systems.last_mut().unwrap() }
+    }
+
+    /// Orders the systems of every stage according to their constraints.
+    /// Called by `Game` before the game loop starts.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a system name is duplicated within a stage or the constraints form a cycle.
+    pub fn build(&mut self) -> Result<(), ScheduleError> {
+        if self.sorted {
+            return Ok(());
+        }
+
+        for (stage, systems) in self.stages.iter_mut() {
+            let order = Schedule::sort(*stage, systems)?;
+
+            let mut entries: Vec<Option<SystemEntry>> = systems.drain(..).map(Some).collect();
+            systems.extend(order.into_iter().map(|i| entries[i].take().unwrap()));
+        }
+
+        self.sorted = true;
+
+        Ok(())
+    }
+
+    /// Runs all systems of a stage.
+    ///
+    /// # Arguments
+    ///
+    /// * `stage` - The stage to run.
+    /// * `ctx` - The data available to the systems.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of a failed system. The remaining systems of the stage still run.
+    pub fn run(&mut self, stage: Stage, ctx: &mut SystemContext<'_>) -> anyhow::Result<()> {
+        let Some(systems) = self.stages.get_mut(&stage) else {
+            return Ok(());
+        };
+
+        let mut result = Ok(());
+
+        for entry in systems.iter_mut() {
+            if let Err(e) = entry.system.run(ctx) {
+                if result.is_ok() {
+                    result = Err(e.context(format!("System `{}` failed", entry.name)));
+                }
+            }
+        }
+
+        result
+    }
+
+    fn sort(stage: Stage, systems: &[SystemEntry]) -> Result<Vec<usize>, ScheduleError> {
+        let mut indices = HashMap::new();
+        for (i, entry) in systems.iter().enumerate() {
+            if indices.insert(entry.name.as_str(), i).is_some() {
+                return Err(ScheduleError::DuplicateSystem(entry.name.clone(), stage));
+            }
+        }
+
+        // Edges point from a system to the systems which must run after it
+        let mut successors = vec![vec![]; systems.len()];
+        let mut in_degree = vec![0usize; systems.len()];
+
+        for (i, entry) in systems.iter().enumerate() {
+            let after = entry.after.iter().filter_map(|n| indices.get(n.as_str())).map(|&j| (j, i));
+            let before = entry.before.iter().filter_map(|n| indices.get(n.as_str())).map(|&j| (i, j));
+
+            for (from, to) in after.chain(before) {
+                successors[from].push(to);
+                in_degree[to] += 1;
+            }
+        }
+
+        // Kahn's algorithm, preferring the insertion order among ready systems
+        let mut order = Vec::with_capacity(systems.len());
+        let mut ready: Vec<usize> = (0..systems.len()).filter(|&i| in_degree[i] == 0).collect();
+
+        while let Some(position) = ready.iter().enumerate().min_by_key(|(_, &i)| i).map(|(p, _)| p) {
+            let i = ready.swap_remove(position);
+            order.push(i);
+
+            for &j in &successors[i] {
+                in_degree[j] -= 1;
+                if in_degree[j] == 0 {
+                    ready.push(j);
+                }
+            }
+        }
+
+        if order.len() != systems.len() {
+            let cycle = (0..systems.len())
+                .filter(|i| !order.contains(i))
+                .map(|i| systems[i].name.clone())
+                .collect();
+
+            return Err(ScheduleError::Cycle(cycle, stage));
+        }
+
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn system(_: &mut SystemContext<'_>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn order(schedule: &Schedule, stage: Stage) -> Vec<&str> {
+        schedule.stages[&stage].iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[test]
+    fn systems_keep_insertion_order_without_constraints() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, "a", system);
+        schedule.add_system(Stage::Update, "b", system);
+        schedule.add_system(Stage::Update, "c", system);
+        schedule.build().unwrap();
+
+        assert_eq!(order(&schedule, Stage::Update), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn after_runs_systems_after_their_dependency() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, "a", system).after("c");
+        schedule.add_system(Stage::Update, "b", system);
+        schedule.add_system(Stage::Update, "c", system).after("b");
+        schedule.build().unwrap();
+
+        assert_eq!(order(&schedule, Stage::Update), ["b", "c", "a"]);
+    }
+
+    #[test]
+    fn before_runs_systems_before_their_dependent() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Render, "a", system);
+        schedule.add_system(Stage::Render, "b", system);
+        schedule.add_system(Stage::Render, "c", system).before("a");
+        schedule.build().unwrap();
+
+        assert_eq!(order(&schedule, Stage::Render), ["b", "c", "a"]);
+    }
+
+    #[test]
+    fn constraints_on_unknown_or_other_stage_systems_are_ignored() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, "a", system).after("b").before("missing");
+        schedule.add_system(Stage::PreRender, "b", system);
+        schedule.add_system(Stage::Update, "c", system);
+        schedule.build().unwrap();
+
+        assert_eq!(order(&schedule, Stage::Update), ["a", "c"]);
+    }
+
+    #[test]
+    fn adding_a_system_after_build_reorders_the_stage() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Input, "a", system);
+        schedule.build().unwrap();
+        schedule.add_system(Stage::Input, "b", system).before("a");
+        schedule.build().unwrap();
+
+        assert_eq!(order(&schedule, Stage::Input), ["b", "a"]);
+    }
+
+    #[test]
+    fn cycles_are_reported_with_their_stage() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, "a", system).after("b");
+        schedule.add_system(Stage::Update, "b", system).after("a");
+        schedule.add_system(Stage::Update, "c", system);
+
+        let error = schedule.build().unwrap_err();
+        let ScheduleError::Cycle(mut systems, stage) = error else {
+            panic!("expected a cycle, got {error:?}");
+        };
+        systems.sort();
+
+        assert_eq!(systems, ["a", "b"]);
+        assert_eq!(stage, Stage::Update);
+    }
+
+    #[test]
+    fn duplicate_systems_are_rejected() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Render, "draw", system);
+        schedule.add_system(Stage::Render, "draw", system);
+
+        let error = schedule.build().unwrap_err();
+        assert_eq!(error.to_string(), "System `draw` is registered more than once in stage `Render`");
+    }
+
+    #[test]
+    fn same_name_in_different_stages_is_allowed() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, "sync", system);
+        schedule.add_system(Stage::PreRender, "sync", system);
+
+        assert!(schedule.build().is_ok());
+    }
+
+    #[test]
+    fn stages_run_in_declaration_order() {
+        let stages: Vec<Stage> = Stage::TICK.into_iter().chain(Stage::FRAME).collect();
+
+        assert_eq!(stages, [Stage::Input, Stage::FixedUpdate, Stage::Update, Stage::PreRender, Stage::Render]);
+        assert!(stages.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
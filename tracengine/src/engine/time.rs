@@ -0,0 +1,181 @@
+/// Timing information of the game loop.
+///
+/// Updates run with a fixed timestep of `1 / tick_rate` seconds, while rendering
+/// happens as often as possible. Simulation code should advance by
+/// [`Time::fixed_delta`], which respects pausing and time scale, so the simulation
+/// stays deterministic regardless of the frame rate. Rendering code can use
+/// [`Time::alpha`] to interpolate between the last two simulation states.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Time {
+    tick_rate: u32,
+    max_frame_time: f64,
+    time_scale: f32,
+    paused: bool,
+    delta: f32,
+    alpha: f32,
+    elapsed: f64,
+    real_elapsed: f64,
+    frame_count: u64,
+    tick_count: u64,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Time::new(Time::DEFAULT_TICK_RATE)
+    }
+}
+
+impl Time {
+    /// Default number of fixed updates per second.
+    pub const DEFAULT_TICK_RATE: u32 = 240;
+    /// Default upper bound of a single frame's duration in seconds.
+    pub const DEFAULT_MAX_FRAME_TIME: f64 = 0.1;
+
+    /// Creates a new `Time` with the given tick rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `tick_rate` - The number of fixed updates per second.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Time` with nothing elapsed yet.
+    pub fn new(tick_rate: u32) -> Time {
+        Time {
+            tick_rate: tick_rate.max(1),
+            max_frame_time: Time::DEFAULT_MAX_FRAME_TIME,
+            time_scale: 1.0,
+            paused: false,
+            delta: 0.0,
+            alpha: 0.0,
+            elapsed: 0.0,
+            real_elapsed: 0.0,
+            frame_count: 0,
+            tick_count: 0,
+        }
+    }
+
+    /// Retrieves the number of fixed updates per second.
+    pub fn tick_rate(&self) -> u32 {
+        self.tick_rate
+    }
+
+    /// Sets the number of fixed updates per second. Takes effect on the next frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `tick_rate` - The new tick rate, at least 1.
+    pub fn set_tick_rate(&mut self, tick_rate: u32) {
+        self.tick_rate = tick_rate.max(1);
+    }
+
+    /// Retrieves the upper bound of a single frame's duration in seconds.
+    ///
+    /// Longer frames are clamped, so the loop does not try to catch up after a stall.
+    pub fn max_frame_time(&self) -> f64 {
+        self.max_frame_time
+    }
+
+    /// Sets the upper bound of a single frame's duration in seconds.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_frame_time` - The maximum frame time.
+    pub fn set_max_frame_time(&mut self, max_frame_time: f64) {
+        self.max_frame_time = max_frame_time;
+    }
+
+    /// Retrieves the simulation time step in seconds, scaled by the time scale.
+    /// Equals zero while paused.
+    pub fn fixed_delta(&self) -> f32 {
+        if self.paused {
+            0.0
+        } else {
+            self.unscaled_fixed_delta() * self.time_scale
+        }
+    }
+
+    /// Retrieves the real duration of a fixed update in seconds, ignoring pause and time scale.
+    pub fn unscaled_fixed_delta(&self) -> f32 {
+        1.0 / self.tick_rate as f32
+    }
+
+    /// Retrieves the real duration of the last rendered frame in seconds.
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    /// Retrieves the blending factor between the previous and the current simulation state,
+    /// in range `[0, 1)`.
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    /// Retrieves the simulated time in seconds, advanced by [`Time::fixed_delta`] every update.
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Retrieves the real time in seconds since the game loop has started.
+    pub fn real_elapsed(&self) -> f64 {
+        self.real_elapsed
+    }
+
+    /// Retrieves the number of rendered frames.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Retrieves the number of fixed updates performed.
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    /// Retrieves the time scale of the simulation.
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Sets the time scale of the simulation, e.g. `0.5` for slow motion.
+    ///
+    /// # Arguments
+    ///
+    /// * `time_scale` - The new non-negative time scale.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.0);
+    }
+
+    /// Checks if the simulation is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses or resumes the simulation. Updates keep running, but with zero [`Time::fixed_delta`].
+    ///
+    /// # Arguments
+    ///
+    /// * `paused` - Whether the simulation should be paused.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Advances the simulation clock by one fixed update. Called by `Game` after each update.
+    pub fn tick(&mut self) {
+        self.elapsed += self.fixed_delta() as f64;
+        self.tick_count += 1;
+    }
+
+    /// Records the timing of a rendered frame. Called by `Game` before each render.
+    ///
+    /// # Arguments
+    ///
+    /// * `delta` - The real duration of the frame in seconds.
+    /// * `alpha` - The blending factor between simulation states.
+    /// * `real_elapsed` - The real time since the game loop has started.
+    pub fn frame(&mut self, delta: f64, alpha: f64, real_elapsed: f64) {
+        self.delta = delta as f32;
+        self.alpha = alpha as f32;
+        self.real_elapsed = real_elapsed;
+        self.frame_count += 1;
+    }
+}
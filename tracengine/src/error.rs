@@ -0,0 +1,18 @@
+use game_loop::winit::error::{EventLoopError, OsError};
+use thiserror::Error;
+
+use crate::{engine::schedule::ScheduleError, renderer::error::RenderError};
+
+/// Errors returned by [`Game::new`](crate::Game::new) and [`Game::run`](crate::Game::run).
+#[derive(Debug, Error)]
+pub enum GameError {
+    #[error("Event loop error: {0}")]
+    EventLoop(#[from] EventLoopError),
+    #[error("Cannot create window: {0}")]
+    Window(#[from] OsError),
+    #[error(transparent)]
+    Render(#[from] RenderError),
+    #[error(transparent)]
+    Schedule(#[from] ScheduleError),
+}
+
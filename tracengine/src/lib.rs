@@ -2,27 +2,44 @@
 // #![warn(missing_docs)]
 
 pub mod engine;
+pub mod error;
 pub mod renderer;
 
 use std::sync::Arc;
 
-use game_loop::winit::event::{Event, WindowEvent};
+use game_loop::winit::event::{DeviceEvent, Event, WindowEvent};
 use game_loop::{game_loop, winit::event_loop::EventLoop};
 
-use engine::Engine;
-use renderer::{error::RenderError, Renderer};
+use engine::{
+    events::{BlockChanged, ChunkLoaded, ChunkUnloaded, EventSwap, Events, SurfaceLost, WindowResized},
+    input::{Input, InputBindings},
+    plugin::Plugin,
+    resources::Resources,
+    schedule::{Schedule, Stage, System, SystemConfig, SystemContext},
+    time::Time,
+    Engine,
+};
+use error::GameError;
+use renderer::{config::RendererConfig, error::RenderError, Renderer};
 
 pub use game_loop::winit::window::WindowBuilder;
 pub use game_loop::winit::dpi::PhysicalSize;
 pub use game_loop::winit::event;
+pub use game_loop::winit::keyboard;
 pub use hecs::World;
 pub use nalgebra_glm as glm;
 
 /// The main game struct that manages the game loop, rendering, and input.
+///
+/// `Game` owns the global [`Resources`], which always contain the [`Input`] and [`Time`] resources.
 pub struct Game {
     event_loop: Option<EventLoop<()>>,
     renderer: Renderer,
     world: World,
+    resources: Resources,
+    schedule: Schedule,
+    event_updaters: Vec<fn(&mut Resources)>,
+    event_swap: EventSwap,
     engine: Option<Box<dyn Engine>>,
 }
 
@@ -31,20 +48,39 @@ impl Game {
     ///
     /// # Parameters
     /// - `window`: The `WindowBuilder` used to create the game window.
+    /// - `renderer_config`: The configuration of the renderer, which can be changed later
+    ///   with [`Renderer::set_config`].
     ///
     /// # Returns
-    /// A `Result` containing the `Game` instance or an error.
-    pub fn new(window: WindowBuilder) -> anyhow::Result<Game> {
+    /// A `Result` containing the `Game` instance or an error, e.g. if no compatible
+    /// graphics adapter is found.
+    pub fn new(window: WindowBuilder, renderer_config: RendererConfig) -> Result<Game, GameError> {
         let event_loop = EventLoop::new()?;
         let window = Arc::new(window.build(&event_loop)?);
         let world = World::new();
 
-        Ok(Game {
+        let mut resources = Resources::new();
+        resources.insert(Input::default());
+        resources.insert(Time::default());
+
+        let mut game = Game {
             event_loop: Some(event_loop),
-            renderer: pollster::block_on(Renderer::new(window))?,
+            renderer: pollster::block_on(Renderer::new(window, renderer_config))?,
             world,
+            resources,
+            schedule: Schedule::new(),
+            event_updaters: vec![],
+            event_swap: EventSwap::default(),
             engine: None,
-        })
+        };
+
+        game.add_event::<WindowResized>()
+            .add_event::<SurfaceLost>()
+            .add_event::<ChunkLoaded>()
+            .add_event::<ChunkUnloaded>()
+            .add_event::<BlockChanged>();
+
+        Ok(game)
     }
 
     /// Sets the game engine.
@@ -55,37 +91,151 @@ impl Game {
         self.engine = Some(Box::new(engine));
     }
 
-    /// Runs the main game loop.
+    /// Registers a plugin on the game.
+    ///
+    /// # Parameters
+    /// - `plugin`: The plugin to register.
+    pub fn add_plugin(&mut self, plugin: impl Plugin) -> &mut Self {
+        plugin.build(self);
+        self
+    }
+
+    /// Adds a system to a stage of the game's schedule.
+    ///
+    /// # Parameters
+    /// - `stage`: The stage the system runs in.
+    /// - `name`: The unique name of the system, used in ordering constraints.
+    /// - `system`: The system.
     ///
     /// # Returns
-    /// A `Result` indicating success or failure.
+    /// A `SystemConfig` to set ordering constraints of the system.
+    pub fn add_system(&mut self, stage: Stage, name: &str, system: impl System + 'static) -> SystemConfig<'_> {
+        self.schedule.add_system(stage, name, system)
+    }
+
+    /// Retrieves the game world.
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Retrieves the renderer.
+    pub fn renderer_mut(&mut self) -> &mut Renderer {
+        &mut self.renderer
+    }
+
+    /// Retrieves the global resources.
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    /// Retrieves the global resources mutably, e.g. to insert resources in a plugin.
+    pub fn resources_mut(&mut self) -> &mut Resources {
+        &mut self.resources
+    }
+
+    /// Inserts a global resource, replacing the resource of the same type.
+    ///
+    /// # Parameters
+    /// - `resource`: The resource to insert.
+    pub fn insert_resource<T: 'static>(&mut self, resource: T) -> &mut Self {
+        self.resources.insert(resource);
+        self
+    }
+
+    /// Registers an event type, inserting its [`Events`] queue into the resources
+    /// and swapping its buffers after frames following a fixed update.
+    pub fn add_event<T: 'static>(&mut self) -> &mut Self {
+        if !self.resources.contains::<Events<T>>() {
+            self.resources.insert(Events::<T>::new());
+            self.event_updaters.push(Events::<T>::update_resource);
+        }
+
+        self
+    }
+
+    /// Sends an event of a registered type.
     ///
-    /// # Panics
-    /// Panics if the engine is not set.
-    pub fn run(mut self) -> anyhow::Result<()> {
-        if self.engine.is_none() {
-            panic!("Engine not set!");
+    /// # Parameters
+    /// - `event`: The event to send.
+    pub fn send_event<T: 'static>(&mut self, event: T) {
+        match self.resources.get_exclusive::<Events<T>>() {
+            Ok(events) => events.send(event),
+            Err(e) => eprintln!("Cannot send event: {e}"),
         }
+    }
 
-        let event_loop = std::mem::take(&mut self.event_loop).unwrap();
+    /// Sets the action bindings of the input state passed to the engine.
+    ///
+    /// # Parameters
+    /// - `bindings`: The action bindings, e.g. loaded with [`InputBindings::load`].
+    pub fn set_input_bindings(&mut self, bindings: InputBindings) {
+        *self.input_mut().bindings_mut() = bindings;
+    }
+
+    /// Sets the number of fixed updates per second.
+    ///
+    /// # Parameters
+    /// - `tick_rate`: The tick rate, [`Time::DEFAULT_TICK_RATE`] by default.
+    pub fn set_tick_rate(&mut self, tick_rate: u32) {
+        self.time_mut().set_tick_rate(tick_rate);
+    }
+
+    /// Runs the main game loop.
+    ///
+    /// The engine is optional: a game may consist of plugins and systems only.
+    ///
+    /// # Returns
+    /// A `Result` indicating success or failure, e.g. if system ordering constraints
+    /// form a cycle or the engine fails to initialize.
+    pub fn run(mut self) -> Result<(), GameError> {
+        self.schedule.build()?;
+
+        let Some(event_loop) = self.event_loop.take() else {
+            return Ok(());
+        };
         let window = self.renderer.window();
 
-        self.engine.as_mut().unwrap().init(&mut self.world, &mut self.renderer);
+        if let Some(engine) = self.engine.as_mut() {
+            engine.init(&mut self.world, &mut self.renderer, &mut self.resources)?;
+        }
+
+        let (tick_rate, max_frame_time) = (self.time_mut().tick_rate(), self.time_mut().max_frame_time());
 
         game_loop(
-            event_loop, window, self, 240, 0.1,
+            event_loop, window, self, tick_rate, max_frame_time,
             |g| {
                 g.game.update();
             },
             |g| {
+                let (last_frame_time, blending_factor, running_time) = 
+                    (g.last_frame_time(), g.blending_factor(), g.running_time());
+                g.game.time_mut().frame(last_frame_time, blending_factor, running_time);
+
                 match g.game.render() {
                     Ok(_) => {},
-                    Err(RenderError::Lost) => g.game.renderer.resize(),
+                    Err(RenderError::Lost) => {
+                        g.game.renderer.resize();
+                        g.game.send_event(SurfaceLost);
+                    },
                     Err(RenderError::OutOfMemory) => g.exit(),
                     Err(e) => eprintln!("{e}"),
                 }
+
+                // Apply timing changes made by the engine
+                let (tick_rate, max_frame_time) = 
+                    (g.game.time_mut().tick_rate(), g.game.time_mut().max_frame_time());
+                if g.updates_per_second != tick_rate {
+                    g.set_updates_per_second(tick_rate);
+                }
+                g.max_frame_time = max_frame_time;
+
+                g.game.update_events();
             },
             |g, event| { 
+                if let Event::DeviceEvent { ref event, .. } = event {
+                    g.game.device_input(event);
+                }
+
                 if let Event::WindowEvent { ref event, .. } = event {
                     if !g.game.input(event) {
                         match event { 
@@ -94,6 +244,10 @@ impl Game {
                             },
                             WindowEvent::Resized(size) => {
                                 g.game.renderer.resize_with(*size);
+                                g.game.send_event(WindowResized { 
+                                    width: size.width, 
+                                    height: size.height,
+                                });
                             },
                             _ => {},
                         }
@@ -106,14 +260,85 @@ impl Game {
     }
 
     fn update(&mut self) {
-        self.engine.as_mut().unwrap().update(&mut self.world);
+        for stage in Stage::TICK {
+            if let Err(e) = self.run_stage(stage) {
+                eprintln!("{e}");
+            }
+        }
+
+        if let Some(engine) = self.engine.as_mut() {
+            engine.update(&mut self.world, &mut self.resources);
+        }
+
+        self.input_mut().clear();
+        self.time_mut().tick();
+        self.event_swap.tick();
     }
 
     fn render(&mut self) -> Result<(), RenderError> {
-        self.engine.as_mut().unwrap().render(&mut self.world, &mut self.renderer)
+        for stage in Stage::FRAME {
+            self.run_stage(stage)?;
+        }
+
+        match self.engine.as_mut() {
+            Some(engine) => engine.render(&mut self.world, &mut self.renderer, &mut self.resources),
+            None => Ok(()),
+        }
+    }
+
+    fn run_stage(&mut self, stage: Stage) -> Result<(), RenderError> {
+        let mut ctx = SystemContext {
+            world: &mut self.world,
+            resources: &mut self.resources,
+            renderer: &mut self.renderer,
+        };
+
+        if let Err(e) = self.schedule.run(stage, &mut ctx) {
+            match e.downcast::<RenderError>() {
+                Ok(e) => return Err(e),
+                Err(e) => eprintln!("{e:?}"),
+            }
+        }
+
+        Ok(())
     }
     
+    fn update_events(&mut self) {
+        if !self.event_swap.frame() {
+            return;
+        }
+
+        for update in &self.event_updaters {
+            update(&mut self.resources);
+        }
+    }
+
+    fn device_input(&mut self, event: &DeviceEvent) {
+        self.input_mut().handle_device_event(event);
+    }
+
     fn input(&mut self, event: &WindowEvent) -> bool {
-        self.engine.as_mut().unwrap().input(event, &mut self.world)
+        self.input_mut().handle_window_event(event);
+
+        match self.engine.as_mut() {
+            Some(engine) => engine.input(event, &mut self.world, &mut self.resources),
+            None => false,
+        }
+    }
+
+    fn input_mut(&mut self) -> &mut Input {
+        if !self.resources.contains::<Input>() {
+            self.resources.insert(Input::default());
+        }
+
+        self.resources.get_exclusive::<Input>().unwrap()
+    }
+
+    fn time_mut(&mut self) -> &mut Time {
+        if !self.resources.contains::<Time>() {
+            self.resources.insert(Time::default());
+        }
+
+        self.resources.get_exclusive::<Time>().unwrap()
     }
 }
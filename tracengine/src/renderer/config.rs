@@ -0,0 +1,81 @@
+/// Presentation mode of the render surface.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Frames are synchronized with the display refresh rate.
+    #[default]
+    Vsync,
+    /// Frames are presented immediately if supported, which may cause tearing.
+    NoVsync,
+    /// Frames are presented on vertical blank, replacing queued frames instead of waiting.
+    /// Falls back to `Vsync` if not supported.
+    Mailbox,
+}
+
+/// Default per-frame budget of staged uploads in bytes.
+pub const DEFAULT_UPLOAD_BUDGET: u64 = 8 * 1024 * 1024;
+
+/// Configuration of the [`Renderer`](super::Renderer).
+///
+/// `backends` and `power_preference` select the graphics adapter, so they are only used
+/// when the renderer is created. Other options can be changed at runtime with
+/// [`Renderer::set_config`](super::Renderer::set_config).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RendererConfig {
+    /// Presentation mode of the render surface.
+    pub present_mode: PresentMode,
+    /// Graphics backends to choose the adapter from.
+    pub backends: wgpu::Backends,
+    /// Whether to prefer a discrete or an integrated adapter.
+    pub power_preference: wgpu::PowerPreference,
+    /// Number of MSAA samples per pixel; `1` disables multisampling.
+    pub sample_count: u32,
+    /// Whether to prefer a high dynamic range surface format over an sRGB one.
+    pub hdr: bool,
+    /// Maximum number of bytes of chunk, palette and other staged uploads per frame;
+    /// `None` uploads everything in the frame it is staged. Uploads exceeding the budget
+    /// are deferred to the next frames.
+    pub upload_budget: Option<u64>,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        RendererConfig {
+            present_mode: PresentMode::Vsync,
+            backends: wgpu::Backends::PRIMARY,
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            sample_count: 1,
+            hdr: false,
+            upload_budget: Some(DEFAULT_UPLOAD_BUDGET),
+        }
+    }
+}
+
+impl RendererConfig {
+    /// Selects the presentation mode supported by the surface.
+    pub(crate) fn surface_present_mode(&self, surface_caps: &wgpu::SurfaceCapabilities) -> wgpu::PresentMode {
+        match self.present_mode {
+            PresentMode::Vsync => wgpu::PresentMode::AutoVsync,
+            PresentMode::NoVsync => wgpu::PresentMode::AutoNoVsync,
+            PresentMode::Mailbox if surface_caps.present_modes.contains(&wgpu::PresentMode::Mailbox) => {
+                wgpu::PresentMode::Mailbox
+            },
+            PresentMode::Mailbox => wgpu::PresentMode::AutoVsync,
+        }
+    }
+
+    /// Selects the surface format, preferring HDR formats if requested and sRGB ones otherwise.
+    pub(crate) fn surface_format(&self, surface_caps: &wgpu::SurfaceCapabilities) -> wgpu::TextureFormat {
+        let hdr_format = self.hdr
+            .then(|| {
+                surface_caps.formats.iter().find(|f| {
+                    matches!(f, wgpu::TextureFormat::Rgba16Float | wgpu::TextureFormat::Rgb10a2Unorm)
+                })
+            })
+            .flatten();
+
+        hdr_format
+            .or_else(|| surface_caps.formats.iter().find(|f| f.is_srgb()))
+            .copied()
+            .unwrap_or(surface_caps.formats[0])
+    }
+}
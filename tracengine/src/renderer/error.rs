@@ -1,5 +1,8 @@
 use thiserror::Error;
 
+use super::graph::GraphError;
+use super::hal::{allocator::AllocError, buffer::InvalidBufferId, shader::ShaderError, texture::TextureError};
+
 #[derive(Debug, Error)]
 pub enum RenderError {
     #[error("The swap chain has been lost and needs to be recreated")]
@@ -14,6 +17,41 @@ pub enum RenderError {
     LoadVoxError(&'static str),
     #[error("Buffer with capacity `{0}` is overflowed")]
     BufferOverflow(usize),
+    #[error("Cannot create render surface: {0}")]
+    CreateSurface(String),
+    #[error("No graphics adapter compatible with the render surface is found")]
+    AdapterNotFound,
+    #[error("Sample count `{0}` is not supported by the surface and depth formats")]
+    UnsupportedSampleCount(u32),
+    #[error("Cannot request logical device: {0}")]
+    RequestDevice(String),
+    #[error("Invalid palette index `{index}` in palette with size `{palette_size}`")]
+    InvalidPaletteIndex {
+        index: u8,
+        palette_size: usize,
+    },
+    #[error(transparent)]
+    InvalidBufferId(#[from] InvalidBufferId),
+    #[error("Drawable is not set up with update()")]
+    DrawableNotSetUp,
+    #[error("Cannot use {0} pipeline in this command")]
+    PipelineMismatch(&'static str),
+    #[error("No pipeline with index `{0}` is passed")]
+    MissingPipeline(usize),
+    #[error("Shader resource has no binding named `{0}`")]
+    UnknownBinding(String),
+    #[error("Resource bound to `{0}` does not match the type of the binding")]
+    BindingTypeMismatch(String),
+    #[error("No resource is bound to `{0}`")]
+    UnboundResource(String),
+    #[error(transparent)]
+    Shader(#[from] ShaderError),
+    #[error(transparent)]
+    Alloc(#[from] AllocError),
+    #[error(transparent)]
+    Texture(#[from] TextureError),
+    #[error(transparent)]
+    Graph(#[from] GraphError),
 }
 
 impl From<wgpu::SurfaceError> for RenderError {
@@ -24,4 +62,15 @@ impl From<wgpu::SurfaceError> for RenderError {
             _ => RenderError::SurfaceError(value.to_string()),
         }
     }
-}
\ No newline at end of file
+}
+impl From<wgpu::CreateSurfaceError> for RenderError {
+    fn from(value: wgpu::CreateSurfaceError) -> Self {
+        RenderError::CreateSurface(value.to_string())
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for RenderError {
+    fn from(value: wgpu::RequestDeviceError) -> Self {
+        RenderError::RequestDevice(value.to_string())
+    }
+}
@@ -0,0 +1,1061 @@
+use std::fmt;
+use std::mem::size_of;
+use std::sync::Arc;
+
+use bytemuck::Pod;
+use game_loop::winit::dpi::PhysicalSize;
+use thiserror::Error;
+
+use crate::renderer::{
+    error::RenderError,
+    hal::{
+        buffer::Buffer,
+        pipeline::{BoundResource, ShaderBinding},
+        texture::{Texture, TextureDescriptor},
+    },
+    types::*,
+    Canvas, ComputePass, DrawContext, RenderPass, RenderSurface, Renderer,
+};
+
+#[derive(Debug, Error)]
+pub enum GraphError {
+    #[error("Passes {0:?} form or depend on a dependency cycle")]
+    Cycle(Vec<&'static str>),
+    #[error("Copy `{0}` must copy between two textures or two buffers")]
+    CopyMismatch(&'static str),
+    #[error("Copy `{0}` cannot copy from or to the surface")]
+    SurfaceCopy(&'static str),
+    #[error("Pass `{0}` is not a render pass")]
+    NotRenderPass(&'static str),
+    #[error("Resource `{0}` is not allocated, the graph must be updated first")]
+    Unallocated(&'static str),
+    #[error("Graph has changed and must be updated before it is executed")]
+    NotCompiled,
+}
+
+/// Size of a resource allocated by the graph, in pixels for textures and in elements for buffers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResourceSize {
+    /// Size of the surface, e.g. for G-buffers.
+    Surface,
+    /// Size of the surface multiplied by a factor, e.g. `0.5` for half resolution buffers.
+    Scaled(f32),
+    /// Size independent from the surface.
+    Fixed(u32, u32),
+}
+
+impl ResourceSize {
+    /// Resolves the size for the given surface size, at least one pixel in each direction.
+    pub fn resolve(&self, surface: PhysicalSize<u32>) -> PhysicalSize<u32> {
+        let (width, height) = match *self {
+            ResourceSize::Surface => (surface.width, surface.height),
+            ResourceSize::Scaled(scale) => (
+                (surface.width as f32 * scale).round() as u32,
+                (surface.height as f32 * scale).round() as u32,
+            ),
+            ResourceSize::Fixed(width, height) => (width, height),
+        };
+
+        PhysicalSize::new(width.max(1), height.max(1))
+    }
+}
+
+/// Description of a 2D texture allocated by the graph.
+///
+/// Render pass targets get the `RENDER_ATTACHMENT` usage, and sources and destinations
+/// of copies the `COPY_SRC` and `COPY_DST` usages, in addition to `usage`.
+#[derive(Debug, Clone, Copy)]
+pub struct GraphTextureDescriptor {
+    pub size: ResourceSize,
+    /// Format of the texture; the surface format if not specified.
+    pub format: Option<TextureFormat>,
+    pub usage: TextureUsages,
+    pub filter: FilterMode,
+}
+
+impl Default for GraphTextureDescriptor {
+    fn default() -> Self {
+        GraphTextureDescriptor {
+            size: ResourceSize::Surface,
+            format: None,
+            usage: TextureUsages::TEXTURE_BINDING,
+            filter: FilterMode::Linear,
+        }
+    }
+}
+
+/// Description of a buffer allocated by the graph, with one element per pixel of its size.
+#[derive(Debug, Clone, Copy)]
+pub struct GraphBufferDescriptor {
+    pub size: ResourceSize,
+    pub usage: BufferUsages,
+}
+
+impl Default for GraphBufferDescriptor {
+    fn default() -> Self {
+        GraphBufferDescriptor {
+            size: ResourceSize::Surface,
+            usage: BufferUsages::STORAGE,
+        }
+    }
+}
+
+/// A handle of a texture of a [`RenderGraph`].
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct TextureHandle(usize);
+
+/// A handle of a buffer of a [`RenderGraph`].
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct BufferHandle(usize);
+
+/// A handle of a texture or buffer read or written by passes.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum ResourceHandle {
+    Texture(TextureHandle),
+    Buffer(BufferHandle),
+}
+
+impl ResourceHandle {
+    fn index(&self) -> usize {
+        match self {
+            ResourceHandle::Texture(TextureHandle(index)) | ResourceHandle::Buffer(BufferHandle(index)) => *index,
+        }
+    }
+}
+
+impl From<TextureHandle> for ResourceHandle {
+    fn from(value: TextureHandle) -> Self {
+        ResourceHandle::Texture(value)
+    }
+}
+
+impl From<BufferHandle> for ResourceHandle {
+    fn from(value: BufferHandle) -> Self {
+        ResourceHandle::Buffer(value)
+    }
+}
+
+/// An untyped buffer of the graph, bound to shader resources like a [`Buffer`].
+#[derive(Debug, Clone)]
+pub struct GraphBuffer {
+    inner: Arc<wgpu::Buffer>,
+    size: u64,
+}
+
+impl GraphBuffer {
+    /// Retrieves the size of the buffer in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl ShaderBinding for GraphBuffer {
+    fn bound_resource(&self) -> BoundResource {
+        BoundResource::Buffer(self.inner.clone())
+    }
+}
+
+/// A resource of the graph. Resources without a descriptor are imported.
+#[derive(Debug)]
+enum Resource {
+    Surface,
+    Texture {
+        descriptor: Option<GraphTextureDescriptor>,
+        texture: Option<Texture>,
+    },
+    Buffer {
+        descriptor: Option<GraphBufferDescriptor>,
+        element_size: u64,
+        buffer: Option<GraphBuffer>,
+        /// Size the buffer has been allocated for, as buffers of equal length may differ in row length.
+        allocated_size: PhysicalSize<u32>,
+    },
+}
+
+#[derive(Debug)]
+struct ResourceNode {
+    name: &'static str,
+    resource: Resource,
+}
+
+impl ResourceNode {
+    /// Checks if the resource is used outside of the graph, so passes writing it are never culled.
+    fn is_output(&self) -> bool {
+        matches!(
+            self.resource,
+            Resource::Surface
+                | Resource::Texture { descriptor: None, .. }
+                | Resource::Buffer { descriptor: None, .. }
+        )
+    }
+}
+
+/// A handle of a pass of a [`RenderGraph`].
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct PassId(usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PassKind {
+    /// Records compute dispatches.
+    Compute,
+    /// Records draws into the targets, cleared at the start of the pass, and
+    /// optionally the depth texture of the renderer.
+    Render {
+        targets: Vec<TextureHandle>,
+        depth: bool,
+    },
+    /// Copies a texture or buffer into another one, recorded by the graph.
+    Copy {
+        from: ResourceHandle,
+        to: ResourceHandle,
+    },
+}
+
+/// A pass of a [`RenderGraph`] with the resources it accesses.
+#[derive(Debug)]
+pub struct GraphPass {
+    id: PassId,
+    name: &'static str,
+    kind: PassKind,
+    reads: Vec<ResourceHandle>,
+    previous_reads: Vec<ResourceHandle>,
+    writes: Vec<ResourceHandle>,
+}
+
+impl GraphPass {
+    /// Declares a resource read by the pass, after the passes writing it in this frame.
+    pub fn read(&mut self, resource: impl Into<ResourceHandle>) -> &mut Self {
+        self.reads.push(resource.into());
+        self
+    }
+
+    /// Declares a resource read by the pass before the passes writing it in this frame,
+    /// e.g. a history texture holding the previous frame.
+    pub fn read_previous(&mut self, resource: impl Into<ResourceHandle>) -> &mut Self {
+        self.previous_reads.push(resource.into());
+        self
+    }
+
+    /// Declares a resource written by the pass. Passes writing the same resource
+    /// run in the order they are added to the graph.
+    pub fn write(&mut self, resource: impl Into<ResourceHandle>) -> &mut Self {
+        self.writes.push(resource.into());
+        self
+    }
+
+    pub fn id(&self) -> PassId {
+        self.id
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn kind(&self) -> &PassKind {
+        &self.kind
+    }
+
+    fn reads_any(&self, resource: usize) -> bool {
+        self.reads.iter().chain(&self.previous_reads).any(|r| r.index() == resource)
+    }
+
+    fn writes(&self, resource: usize) -> bool {
+        self.writes.iter().any(|r| r.index() == resource)
+    }
+}
+
+/// A graph of compute, render and copy passes and the resources they access.
+///
+/// Passes declare the textures and buffers they read and write, and the graph runs them
+/// in the order of their dependencies, skipping passes whose results are never used.
+/// Textures and buffers created by the graph are sized relative to the surface and
+/// reallocated by [`RenderGraph::update`] when it is resized. Resources owned elsewhere,
+/// e.g. by [`Taa`](super::hal::taa::Taa), are imported and never culled.
+///
+/// The graph can be printed, or exported to Graphviz with [`RenderGraph::to_dot`].
+#[derive(Debug)]
+pub struct RenderGraph {
+    resources: Vec<ResourceNode>,
+    passes: Vec<GraphPass>,
+    order: Vec<PassId>,
+    culled: Vec<PassId>,
+    size: PhysicalSize<u32>,
+    compiled: bool,
+}
+
+impl Default for RenderGraph {
+    fn default() -> Self {
+        RenderGraph::new()
+    }
+}
+
+impl RenderGraph {
+    /// The canvas of the frame, which can be used as a render pass target.
+    pub const SURFACE: TextureHandle = TextureHandle(0);
+
+    /// Creates an empty graph with the surface as its only resource.
+    pub fn new() -> RenderGraph {
+        RenderGraph {
+            resources: vec![ResourceNode { name: "Surface", resource: Resource::Surface }],
+            passes: vec![],
+            order: vec![],
+            culled: vec![],
+            size: PhysicalSize::new(0, 0),
+            compiled: false,
+        }
+    }
+
+    /// Adds a texture allocated by the graph.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the texture, also used as its label.
+    /// * `descriptor` - The description of the texture.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the texture.
+    pub fn create_texture(&mut self, name: &'static str, descriptor: GraphTextureDescriptor) -> TextureHandle {
+        TextureHandle(self.add_resource(name, Resource::Texture { descriptor: Some(descriptor), texture: None }))
+    }
+
+    /// Adds a buffer of elements of type `T` allocated by the graph.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the buffer, also used as its label.
+    /// * `descriptor` - The description of the buffer.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the buffer.
+    pub fn create_buffer<T: Pod>(&mut self, name: &'static str, descriptor: GraphBufferDescriptor) -> BufferHandle {
+        BufferHandle(self.add_resource(name, Resource::Buffer {
+            descriptor: Some(descriptor),
+            element_size: size_of::<T>() as u64,
+            buffer: None,
+            allocated_size: PhysicalSize::new(0, 0),
+        }))
+    }
+
+    /// Imports a texture owned outside of the graph. Importing a texture with the name
+    /// of an imported texture replaces it, e.g. after the owner has resized it.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the texture in the graph.
+    /// * `texture` - The texture.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the texture.
+    pub fn import_texture(&mut self, name: &'static str, texture: &Texture) -> TextureHandle {
+        let resource = Resource::Texture { descriptor: None, texture: Some(texture.clone()) };
+
+        TextureHandle(self.import(name, resource))
+    }
+
+    /// Imports a buffer owned outside of the graph. Importing a buffer with the name
+    /// of an imported buffer replaces it, e.g. after the owner has resized it.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the buffer in the graph.
+    /// * `buffer` - The buffer.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the buffer.
+    pub fn import_buffer<T: Pod>(&mut self, name: &'static str, buffer: &Buffer<T>) -> BufferHandle {
+        let resource = Resource::Buffer {
+            descriptor: None,
+            element_size: size_of::<T>() as u64,
+            buffer: Some(GraphBuffer {
+                inner: buffer.inner().clone(),
+                size: (*buffer.capacity() * size_of::<T>()) as u64,
+            }),
+            allocated_size: PhysicalSize::new(0, 0),
+        };
+
+        BufferHandle(self.import(name, resource))
+    }
+
+    /// Adds a compute pass, recorded by the callback of [`RenderGraph::execute`].
+    pub fn add_compute_pass(&mut self, name: &'static str) -> &mut GraphPass {
+        self.add_pass(name, PassKind::Compute)
+    }
+
+    /// Adds a render pass writing into the targets, recorded by the callback of
+    /// [`RenderGraph::execute`]. Targets may include [`RenderGraph::SURFACE`].
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the pass.
+    /// * `targets` - The colour targets, in order of the fragment output locations.
+    /// * `depth` - Whether the pass uses the depth texture of the renderer.
+    pub fn add_render_pass(&mut self, name: &'static str, targets: &[TextureHandle], depth: bool) -> &mut GraphPass {
+        let pass = self.add_pass(name, PassKind::Render { targets: targets.to_vec(), depth });
+        for target in targets {
+            pass.write(*target);
+        }
+
+        pass
+    }
+
+    /// Adds a copy of a texture or buffer into another one of the same kind, recorded by the graph.
+    ///
+    /// Textures are copied in the size of the smaller one, and buffers up to the size of
+    /// the smaller one.
+    pub fn add_copy(
+        &mut self,
+        name: &'static str,
+        from: impl Into<ResourceHandle>,
+        to: impl Into<ResourceHandle>,
+    ) -> &mut GraphPass {
+        let (from, to) = (from.into(), to.into());
+
+        self.add_pass(name, PassKind::Copy { from, to })
+            .read(from)
+            .write(to)
+    }
+
+    /// Orders the passes by their dependencies and culls passes whose results are unused.
+    ///
+    /// Passes run after the passes writing the resources they read, and before the passes
+    /// writing the resources they read from the previous frame. Otherwise, passes keep the
+    /// order they were added in. Passes without writes are assumed to have side effects.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if passes depend on each other or a copy is invalid.
+    pub fn compile(&mut self) -> Result<(), GraphError> {
+        for pass in &self.passes {
+            if let PassKind::Copy { from, to } = pass.kind {
+                match (from, to) {
+                    (ResourceHandle::Texture(t), _) | (_, ResourceHandle::Texture(t)) if t == RenderGraph::SURFACE => {
+                        return Err(GraphError::SurfaceCopy(pass.name));
+                    },
+                    (ResourceHandle::Texture(_), ResourceHandle::Texture(_))
+                    | (ResourceHandle::Buffer(_), ResourceHandle::Buffer(_)) => {},
+                    _ => return Err(GraphError::CopyMismatch(pass.name)),
+                }
+            }
+        }
+
+        let dependencies = self.dependencies();
+
+        // Topological sort, taking the earliest added pass first
+        let mut remaining = dependencies.iter().map(Vec::len).collect::<Vec<_>>();
+        let mut order = Vec::with_capacity(self.passes.len());
+        while let Some(next) = (0..self.passes.len()).find(|&p| remaining[p] == 0) {
+            remaining[next] = usize::MAX;
+            order.push(next);
+
+            for (pass, deps) in dependencies.iter().enumerate() {
+                if remaining[pass] != usize::MAX {
+                    remaining[pass] -= deps.iter().filter(|&&d| d == next).count();
+                }
+            }
+        }
+
+        if order.len() < self.passes.len() {
+            let cycle = (0..self.passes.len())
+                .filter(|&p| remaining[p] != usize::MAX)
+                .map(|p| self.passes[p].name)
+                .collect();
+
+            return Err(GraphError::Cycle(cycle));
+        }
+
+        // Keep passes with side effects, and passes writing resources used by kept passes
+        let mut kept = vec![false; self.passes.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for (index, pass) in self.passes.iter().enumerate() {
+                let keep = !kept[index] && (pass.writes.is_empty() || pass.writes.iter().any(|resource| {
+                    self.resources[resource.index()].is_output()
+                        || self.passes.iter().enumerate().any(|(other, reader)| {
+                            other != index && kept[other] && reader.reads_any(resource.index())
+                        })
+                }));
+
+                if keep {
+                    kept[index] = true;
+                    changed = true;
+                }
+            }
+        }
+
+        self.order = order.iter().filter(|&&p| kept[p]).map(|&p| PassId(p)).collect();
+        self.culled = (0..self.passes.len()).filter(|&p| !kept[p]).map(PassId).collect();
+        self.compiled = true;
+
+        Ok(())
+    }
+
+    /// Compiles the graph if it has changed, and allocates the resources of the graph which
+    /// are missing or do not match the size and format of the surface anymore. Buffers are
+    /// reallocated if either dimension of their size changes, even if their length does not.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    ///
+    /// # Returns
+    ///
+    /// `true` if resources have been reallocated, so shader resources using them must be bound again.
+    /// The contents of reallocated resources are zeroed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the graph cannot be compiled.
+    pub fn update(&mut self, renderer: &Renderer) -> Result<bool, GraphError> {
+        if !self.compiled {
+            self.compile()?;
+        }
+
+        self.size = renderer.size();
+        let usages = self.inferred_usages();
+        let mut reallocated = false;
+
+        for (node, inferred_usage) in self.resources.iter_mut().zip(usages) {
+            match &mut node.resource {
+                Resource::Texture { descriptor: Some(descriptor), texture } => {
+                    let size = descriptor.size.resolve(self.size);
+                    let description = TextureDescriptor {
+                        width: size.width,
+                        height: size.height,
+                        format: descriptor.format.unwrap_or(renderer.surface_format()),
+                        usage: descriptor.usage | inferred_usage,
+                        filter: descriptor.filter,
+                        label: node.name,
+                        ..Default::default()
+                    };
+
+                    let outdated = texture.as_ref().is_none_or(|texture| {
+                        let current = texture.description();
+                        current.width != description.width
+                            || current.height != description.height
+                            || current.format != description.format
+                            || current.usage != description.usage
+                    });
+
+                    if outdated {
+                        *texture = Some(Texture::new(renderer, description));
+                        reallocated = true;
+                    }
+                },
+                Resource::Buffer { descriptor: Some(descriptor), element_size, buffer, allocated_size } => {
+                    let size = descriptor.size.resolve(self.size);
+                    let bytes = size.width as u64 * size.height as u64 * *element_size;
+
+                    if buffer.is_none() || *allocated_size != size {
+                        let inner = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+                            label: Some(node.name),
+                            size: bytes,
+                            usage: descriptor.usage | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+                            mapped_at_creation: false,
+                        });
+
+                        *buffer = Some(GraphBuffer { inner: Arc::new(inner), size: bytes });
+                        *allocated_size = size;
+                        reallocated = true;
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        Ok(reallocated)
+    }
+
+    /// Records the passes of the graph in order. Copies are recorded by the graph,
+    /// while compute and render passes are recorded by the callback.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `ctx` - The drawing context to record into.
+    /// * `canvas` - The canvas used as [`RenderGraph::SURFACE`].
+    /// * `record` - The callback recording a pass, e.g. by matching its `PassId`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the graph has not been updated since it has changed,
+    /// or the callback fails.
+    pub fn execute<F>(
+        &self,
+        renderer: &Renderer,
+        ctx: &mut DrawContext,
+        canvas: &Canvas,
+        mut record: F,
+    ) -> Result<(), RenderError>
+    where
+        F: FnMut(PassId, &mut PassContext<'_>) -> Result<(), RenderError>,
+    {
+        if !self.compiled {
+            return Err(GraphError::NotCompiled.into());
+        }
+
+        for id in &self.order {
+            let pass = &self.passes[id.0];
+
+            match pass.kind {
+                PassKind::Copy { from, to } => self.copy(ctx, from, to)?,
+                _ => record(*id, &mut PassContext { ctx, graph: self, renderer, canvas, pass })?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Retrieves a texture of the graph, e.g. to bind it to a shader resource.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GraphError::Unallocated` for the surface and textures not allocated by
+    /// [`RenderGraph::update`] yet.
+    pub fn texture(&self, handle: TextureHandle) -> Result<&Texture, GraphError> {
+        let node = &self.resources[handle.0];
+
+        match &node.resource {
+            Resource::Texture { texture: Some(texture), .. } => Ok(texture),
+            _ => Err(GraphError::Unallocated(node.name)),
+        }
+    }
+
+    /// Retrieves a buffer of the graph, e.g. to bind it to a shader resource.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GraphError::Unallocated` for buffers not allocated by [`RenderGraph::update`] yet.
+    pub fn buffer(&self, handle: BufferHandle) -> Result<&GraphBuffer, GraphError> {
+        let node = &self.resources[handle.0];
+
+        match &node.resource {
+            Resource::Buffer { buffer: Some(buffer), .. } => Ok(buffer),
+            _ => Err(GraphError::Unallocated(node.name)),
+        }
+    }
+
+    /// Retrieves the name of a resource.
+    pub fn resource_name(&self, handle: impl Into<ResourceHandle>) -> &'static str {
+        self.resources[handle.into().index()].name
+    }
+
+    pub fn pass(&self, id: PassId) -> &GraphPass {
+        &self.passes[id.0]
+    }
+
+    /// Retrieves the executed passes in order, as of the last compilation.
+    pub fn order(&self) -> &[PassId] {
+        &self.order
+    }
+
+    /// Retrieves the passes skipped as their results are unused, as of the last compilation.
+    pub fn culled(&self) -> &[PassId] {
+        &self.culled
+    }
+
+    /// Exports the graph in the Graphviz DOT format, with passes as boxes and resources
+    /// as ellipses. Reads of the previous frame are dashed and culled passes are grey.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph RenderGraph {\n    rankdir=LR;\n");
+
+        for (index, node) in self.resources.iter().enumerate() {
+            let shape = if node.is_output() { "doubleoctagon" } else { "ellipse" };
+            dot += &format!("    r{index} [label=\"{}\", shape={shape}];\n", node.name);
+        }
+
+        for pass in &self.passes {
+            let color = if self.culled.contains(&pass.id) { "grey" } else { "black" };
+            dot += &format!("    p{} [label=\"{}\", shape=box, color={color}];\n", pass.id.0, pass.name);
+
+            for read in &pass.reads {
+                dot += &format!("    r{} -> p{};\n", read.index(), pass.id.0);
+            }
+            for read in &pass.previous_reads {
+                dot += &format!("    r{} -> p{} [style=dashed];\n", read.index(), pass.id.0);
+            }
+            for write in &pass.writes {
+                dot += &format!("    p{} -> r{};\n", pass.id.0, write.index());
+            }
+        }
+
+        dot + "}\n"
+    }
+
+    fn add_resource(&mut self, name: &'static str, resource: Resource) -> usize {
+        self.resources.push(ResourceNode { name, resource });
+        self.resources.len() - 1
+    }
+
+    fn import(&mut self, name: &'static str, resource: Resource) -> usize {
+        let existing = self.resources.iter().position(|node| {
+            node.name == name && node.is_output() && std::mem::discriminant(&node.resource) == std::mem::discriminant(&resource)
+        });
+
+        match existing {
+            Some(index) => {
+                self.resources[index].resource = resource;
+                index
+            },
+            None => self.add_resource(name, resource),
+        }
+    }
+
+    fn add_pass(&mut self, name: &'static str, kind: PassKind) -> &mut GraphPass {
+        let id = PassId(self.passes.len());
+        self.compiled = false;
+
+        self.passes.push(GraphPass {
+            id,
+            name,
+            kind,
+            reads: vec![],
+            previous_reads: vec![],
+            writes: vec![],
+        });
+
+        self.passes.last_mut().expect("Pass has just been pushed")
+    }
+
+    /// Collects the passes each pass depends on.
+    fn dependencies(&self) -> Vec<Vec<usize>> {
+        let mut dependencies = vec![Vec::new(); self.passes.len()];
+        let mut depend = |pass: usize, on: usize| {
+            if pass != on && !dependencies[pass].contains(&on) {
+                dependencies[pass].push(on);
+            }
+        };
+
+        for resource in 0..self.resources.len() {
+            let writers = (0..self.passes.len())
+                .filter(|&p| self.passes[p].writes(resource))
+                .collect::<Vec<_>>();
+
+            for pair in writers.windows(2) {
+                depend(pair[1], pair[0]);
+            }
+
+            for (index, pass) in self.passes.iter().enumerate() {
+                if pass.reads.iter().any(|r| r.index() == resource) {
+                    // Passes reading and writing a resource see the writes of the passes added before
+                    let last_writer = writers.iter().rev().find(|&&w| !pass.writes(resource) || w < index);
+                    if let Some(&writer) = last_writer {
+                        depend(index, writer);
+                    }
+                }
+
+                if pass.previous_reads.iter().any(|r| r.index() == resource) {
+                    for &writer in &writers {
+                        depend(writer, index);
+                    }
+                }
+            }
+        }
+
+        dependencies
+    }
+
+    /// Collects the texture usages required by the passes, in addition to the described ones.
+    fn inferred_usages(&self) -> Vec<TextureUsages> {
+        let mut usages = vec![TextureUsages::empty(); self.resources.len()];
+
+        for pass in &self.passes {
+            match &pass.kind {
+                PassKind::Render { targets, .. } => for target in targets {
+                    usages[target.0] |= TextureUsages::RENDER_ATTACHMENT;
+                },
+                PassKind::Copy { from, to } => {
+                    usages[from.index()] |= TextureUsages::COPY_SRC;
+                    usages[to.index()] |= TextureUsages::COPY_DST;
+                },
+                PassKind::Compute => {},
+            }
+        }
+
+        usages
+    }
+
+    fn copy(&self, ctx: &mut DrawContext, from: ResourceHandle, to: ResourceHandle) -> Result<(), GraphError> {
+        match (from, to) {
+            (ResourceHandle::Texture(from), ResourceHandle::Texture(to)) => {
+                let (from, to) = (self.texture(from)?, self.texture(to)?);
+
+                ctx.encoder.copy_texture_to_texture(
+                    from.texture().as_image_copy(),
+                    to.texture().as_image_copy(),
+                    wgpu::Extent3d {
+                        width: from.description().width.min(to.description().width),
+                        height: from.description().height.min(to.description().height),
+                        depth_or_array_layers: 1,
+                    },
+                );
+            },
+            (ResourceHandle::Buffer(from), ResourceHandle::Buffer(to)) => {
+                let (from, to) = (self.buffer(from)?, self.buffer(to)?);
+                let size = from.size.min(to.size) / wgpu::COPY_BUFFER_ALIGNMENT * wgpu::COPY_BUFFER_ALIGNMENT;
+
+                ctx.encoder.copy_buffer_to_buffer(&from.inner, 0, &to.inner, 0, size);
+            },
+            _ => unreachable!("Copies are validated when the graph is compiled"),
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for RenderGraph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Render graph ({}x{})", self.size.width, self.size.height)?;
+
+        writeln!(f, "Resources:")?;
+        for (index, node) in self.resources.iter().enumerate() {
+            write!(f, "  [{index}] {}: ", node.name)?;
+
+            match &node.resource {
+                Resource::Surface => writeln!(f, "surface")?,
+                Resource::Texture { descriptor, texture } => {
+                    let origin = match descriptor {
+                        Some(descriptor) => format!("{:?}", descriptor.size),
+                        None => "imported".to_owned(),
+                    };
+
+                    match texture {
+                        Some(texture) => {
+                            let description = texture.description();
+                            writeln!(
+                                f,
+                                "texture {}x{} {:?} ({origin})",
+                                description.width, description.height, description.format,
+                            )?;
+                        },
+                        None => writeln!(f, "texture, unallocated ({origin})")?,
+                    }
+                },
+                Resource::Buffer { descriptor, buffer, .. } => {
+                    let origin = match descriptor {
+                        Some(descriptor) => format!("{:?}", descriptor.size),
+                        None => "imported".to_owned(),
+                    };
+
+                    match buffer {
+                        Some(buffer) => writeln!(f, "buffer {} bytes ({origin})", buffer.size)?,
+                        None => writeln!(f, "buffer, unallocated ({origin})")?,
+                    }
+                },
+            }
+        }
+
+        let names = |resources: &[ResourceHandle]| {
+            resources.iter().map(|r| self.resources[r.index()].name).collect::<Vec<_>>().join(", ")
+        };
+
+        writeln!(f, "Passes:")?;
+        for (position, id) in self.order.iter().enumerate() {
+            let pass = &self.passes[id.0];
+            let kind = match pass.kind {
+                PassKind::Compute => "compute",
+                PassKind::Render { .. } => "render",
+                PassKind::Copy { .. } => "copy",
+            };
+
+            writeln!(f, "  {position}. {} ({kind})", pass.name)?;
+            if !pass.reads.is_empty() {
+                writeln!(f, "       reads: {}", names(&pass.reads))?;
+            }
+            if !pass.previous_reads.is_empty() {
+                writeln!(f, "       reads previous: {}", names(&pass.previous_reads))?;
+            }
+            if !pass.writes.is_empty() {
+                writeln!(f, "       writes: {}", names(&pass.writes))?;
+            }
+        }
+
+        if !self.culled.is_empty() {
+            let culled = self.culled.iter().map(|id| self.passes[id.0].name).collect::<Vec<_>>();
+            writeln!(f, "Culled: {}", culled.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The context of a compute or render pass recorded by [`RenderGraph::execute`].
+pub struct PassContext<'g> {
+    ctx: &'g mut DrawContext,
+    graph: &'g RenderGraph,
+    renderer: &'g Renderer,
+    canvas: &'g Canvas,
+    pass: &'g GraphPass,
+}
+
+impl PassContext<'_> {
+    pub fn pass(&self) -> &GraphPass {
+        self.pass
+    }
+
+    pub fn graph(&self) -> &RenderGraph {
+        self.graph
+    }
+
+    /// Begins a compute pass.
+    pub fn compute_pass(&mut self) -> ComputePass<'_> {
+        self.ctx.compute_pass()
+    }
+
+    /// Begins a render pass into the targets of the graph pass.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the graph pass is not a render pass or a target is not allocated.
+    pub fn render_pass(&mut self) -> Result<RenderPass<'_>, GraphError> {
+        let PassKind::Render { targets, depth } = &self.pass.kind else {
+            return Err(GraphError::NotRenderPass(self.pass.name));
+        };
+
+        let (graph, canvas) = (self.graph, self.canvas);
+        let depth_texture = if *depth { self.renderer.depth_texture() } else { None };
+
+        match targets.as_slice() {
+            [RenderGraph::SURFACE] => Ok(self.ctx.render_pass(canvas, depth_texture)),
+            [target] => Ok(self.ctx.render_pass(graph.texture(*target)?, depth_texture)),
+            targets => {
+                let targets = targets
+                    .iter()
+                    .map(|&target| match target {
+                        RenderGraph::SURFACE => Ok(canvas as &dyn RenderSurface),
+                        target => graph.texture(target).map(|texture| texture as &dyn RenderSurface),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(self.ctx.render_pass_with_targets(&targets, depth_texture))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(graph: &RenderGraph, passes: &[PassId]) -> Vec<&'static str> {
+        passes.iter().map(|&id| graph.pass(id).name()).collect()
+    }
+
+    fn buffer(graph: &mut RenderGraph, name: &'static str) -> BufferHandle {
+        graph.create_buffer::<f32>(name, GraphBufferDescriptor::default())
+    }
+
+    #[test]
+    fn passes_run_after_the_writers_of_their_reads() {
+        let mut graph = RenderGraph::new();
+        let color = buffer(&mut graph, "Color");
+        let normal = buffer(&mut graph, "Normal");
+
+        graph.add_render_pass("Present", &[RenderGraph::SURFACE], false).read(color);
+        graph.add_compute_pass("Shade").read(normal).write(color);
+        graph.add_compute_pass("Trace").write(normal);
+        graph.compile().unwrap();
+
+        assert_eq!(names(&graph, graph.order()), ["Trace", "Shade", "Present"]);
+        assert!(graph.culled().is_empty());
+    }
+
+    #[test]
+    fn passes_run_before_the_writers_of_their_previous_reads() {
+        let mut graph = RenderGraph::new();
+        let history = buffer(&mut graph, "History");
+
+        graph.add_compute_pass("Accumulate").write(history);
+        graph.add_render_pass("Present", &[RenderGraph::SURFACE], false).read_previous(history);
+        graph.compile().unwrap();
+
+        assert_eq!(names(&graph, graph.order()), ["Present", "Accumulate"]);
+    }
+
+    #[test]
+    fn passes_may_read_and_write_their_previous_results() {
+        let mut graph = RenderGraph::new();
+        let history = buffer(&mut graph, "History");
+
+        graph.add_compute_pass("Accumulate").read_previous(history).write(history);
+        graph.add_render_pass("Present", &[RenderGraph::SURFACE], false).read(history);
+        graph.compile().unwrap();
+
+        assert_eq!(names(&graph, graph.order()), ["Accumulate", "Present"]);
+    }
+
+    #[test]
+    fn dependency_cycles_are_rejected() {
+        let mut graph = RenderGraph::new();
+        let a = buffer(&mut graph, "A");
+        let b = buffer(&mut graph, "B");
+
+        graph.add_compute_pass("Independent");
+        graph.add_compute_pass("First").read(a).write(b);
+        graph.add_compute_pass("Second").read(b).write(a);
+
+        let Err(GraphError::Cycle(passes)) = graph.compile() else {
+            panic!("expected a cycle");
+        };
+        assert_eq!(passes, ["First", "Second"]);
+    }
+
+    #[test]
+    fn passes_whose_writes_are_unread_are_culled() {
+        let mut graph = RenderGraph::new();
+        let color = buffer(&mut graph, "Color");
+        let debug = buffer(&mut graph, "Debug");
+        let unused = buffer(&mut graph, "Unused");
+
+        graph.add_compute_pass("Trace").write(color);
+        graph.add_compute_pass("Debug").read(color).write(debug);
+        graph.add_compute_pass("Debug view").read(debug).write(unused);
+        graph.add_render_pass("Present", &[RenderGraph::SURFACE], false).read(color);
+        graph.add_compute_pass("Readback").read(color);
+        graph.compile().unwrap();
+
+        assert_eq!(names(&graph, graph.order()), ["Trace", "Present", "Readback"]);
+        assert_eq!(names(&graph, graph.culled()), ["Debug", "Debug view"]);
+    }
+
+    #[test]
+    fn copies_cannot_involve_the_surface() {
+        let mut graph = RenderGraph::new();
+        let texture = graph.create_texture("Texture", GraphTextureDescriptor::default());
+
+        graph.add_copy("Capture", RenderGraph::SURFACE, texture);
+        assert!(matches!(graph.compile(), Err(GraphError::SurfaceCopy("Capture"))));
+
+        let mut graph = RenderGraph::new();
+        let texture = graph.create_texture("Texture", GraphTextureDescriptor::default());
+
+        graph.add_copy("Blit", texture, RenderGraph::SURFACE);
+        assert!(matches!(graph.compile(), Err(GraphError::SurfaceCopy("Blit"))));
+    }
+
+    #[test]
+    fn copies_must_copy_between_resources_of_the_same_kind() {
+        let mut graph = RenderGraph::new();
+        let texture = graph.create_texture("Texture", GraphTextureDescriptor::default());
+        let color = buffer(&mut graph, "Color");
+
+        graph.add_copy("Readback", texture, color);
+        assert!(matches!(graph.compile(), Err(GraphError::CopyMismatch("Readback"))));
+    }
+
+    #[test]
+    fn sizes_resolve_to_at_least_one_pixel() {
+        let surface = PhysicalSize::new(1280, 720);
+
+        assert_eq!(ResourceSize::Surface.resolve(surface), surface);
+        assert_eq!(ResourceSize::Scaled(0.5).resolve(surface), PhysicalSize::new(640, 360));
+        assert_eq!(ResourceSize::Fixed(16, 8).resolve(surface), PhysicalSize::new(16, 8));
+        assert_eq!(ResourceSize::Scaled(0.0001).resolve(surface), PhysicalSize::new(1, 1));
+        assert_eq!(ResourceSize::Surface.resolve(PhysicalSize::new(0, 0)), PhysicalSize::new(1, 1));
+    }
+}
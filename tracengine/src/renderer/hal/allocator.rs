@@ -0,0 +1,873 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::num::NonZeroU64;
+use std::ops::Range;
+use std::sync::Arc;
+
+use bytemuck::Pod;
+use pretty_type_name::pretty_type_name;
+use thiserror::Error;
+
+use crate::renderer::Renderer;
+use super::pipeline::{BoundResource, ShaderBinding};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum AllocError {
+    #[error("Buffer handle is invalid or has been freed")]
+    InvalidHandle,
+    #[error("Allocation of `{0}` bytes exceeds the maximum buffer size")]
+    TooLarge(u64),
+    #[error("Cannot write `{len}` elements at offset `{offset}` into a buffer range of `{capacity}` elements")]
+    Overflow {
+        offset: usize,
+        len: usize,
+        capacity: usize,
+    },
+    #[error("Offset of `{0}` bytes is not aligned to 4 bytes")]
+    Unaligned(u64),
+}
+
+/// A range of bytes reserved by a [`RangeAllocator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Allocation {
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl Allocation {
+    /// Retrieves the allocated bytes as a range.
+    pub fn range(&self) -> Range<u64> {
+        self.offset..self.offset + self.size
+    }
+}
+
+/// Allocates ranges of a fixed-size memory block on the CPU side,
+/// tracking unused ranges in a sorted free list.
+///
+/// Allocations pick the smallest free range that fits (best fit), and freed
+/// ranges are merged with adjacent free ranges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeAllocator {
+    capacity: u64,
+    free: Vec<Range<u64>>,
+    used: u64,
+    allocations: usize,
+}
+
+impl RangeAllocator {
+    /// Creates an allocator of an empty block.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The size of the block in bytes.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `RangeAllocator`.
+    pub fn new(capacity: u64) -> RangeAllocator {
+        RangeAllocator {
+            capacity,
+            free: std::iter::once(0..capacity).filter(|r| !r.is_empty()).collect(),
+            used: 0,
+            allocations: 0,
+        }
+    }
+
+    /// Reserves a range of the block.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The size of the range in bytes.
+    /// * `alignment` - The alignment of the offset in bytes, a power of two.
+    ///
+    /// # Returns
+    ///
+    /// The reserved range, or `None` if the size is zero or no free range is large enough.
+    pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<Allocation> {
+        // Empty ranges would split free ranges without reserving anything
+        if size == 0 {
+            return None;
+        }
+
+        let (index, offset) = self.free
+            .iter()
+            .enumerate()
+            .filter_map(|(i, range)| {
+                let offset = align_to(range.start, alignment);
+                (offset + size <= range.end).then_some((i, offset, range.end - range.start))
+            })
+            .min_by_key(|(_, _, free_size)| *free_size)
+            .map(|(i, offset, _)| (i, offset))?;
+
+        let range = self.free[index].clone();
+        let remainder = [range.start..offset, offset + size..range.end]
+            .into_iter()
+            .filter(|r| !r.is_empty());
+
+        self.free.splice(index..=index, remainder);
+        self.used += size;
+        self.allocations += 1;
+
+        Some(Allocation { offset, size })
+    }
+
+    /// Releases a range reserved with [`RangeAllocator::allocate`].
+    ///
+    /// # Arguments
+    ///
+    /// * `allocation` - The range to release. It must not be released twice.
+    ///   Empty ranges, which are never allocated, are ignored.
+    pub fn free(&mut self, allocation: Allocation) {
+        if allocation.size == 0 {
+            return;
+        }
+
+        let range = allocation.range();
+        let index = self.free.partition_point(|r| r.start < range.start);
+
+        let merges_previous = index > 0 && self.free[index - 1].end == range.start;
+        let merges_next = self.free.get(index).is_some_and(|next| next.start == range.end);
+
+        match (merges_previous, merges_next) {
+            (true, true) => {
+                self.free[index - 1].end = self.free[index].end;
+                self.free.remove(index);
+            },
+            (true, false) => self.free[index - 1].end = range.end,
+            (false, true) => self.free[index].start = range.start,
+            (false, false) => self.free.insert(index, range),
+        }
+
+        self.used -= allocation.size;
+        self.allocations -= 1;
+    }
+
+    /// Extends the block, keeping existing allocations.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The new size of the block in bytes; smaller sizes are ignored.
+    pub fn grow(&mut self, capacity: u64) {
+        if capacity <= self.capacity {
+            return;
+        }
+
+        match self.free.last_mut() {
+            Some(last) if last.end == self.capacity => last.end = capacity,
+            _ => self.free.push(self.capacity..capacity),
+        }
+
+        self.capacity = capacity;
+    }
+
+    /// Retrieves the size of the block in bytes.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Retrieves the number of allocated bytes.
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// Retrieves the number of live allocations.
+    pub fn allocations(&self) -> usize {
+        self.allocations
+    }
+
+    /// Retrieves the unused ranges, sorted by offset.
+    pub fn free_ranges(&self) -> &[Range<u64>] {
+        &self.free
+    }
+
+    /// Retrieves the size of the largest unused range in bytes.
+    pub fn largest_free_range(&self) -> u64 {
+        self.free.iter().map(|r| r.end - r.start).max().unwrap_or(0)
+    }
+}
+
+/// Memory usage of a [`BufferArena`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArenaStats {
+    /// Number of pooled GPU buffers.
+    pub blocks: usize,
+    /// Total size of the pooled buffers in bytes.
+    pub capacity: u64,
+    /// Number of allocated bytes, including alignment of allocation sizes.
+    pub used: u64,
+    /// Number of live allocations.
+    pub allocations: usize,
+    /// Number of unused ranges in all blocks.
+    pub free_ranges: usize,
+    /// Size of the largest unused range in bytes.
+    pub largest_free_range: u64,
+}
+
+impl ArenaStats {
+    /// Computes the share of unused memory not in the largest free range,
+    /// from `0.0` for compact blocks to `1.0` for scattered free memory.
+    pub fn fragmentation(&self) -> f32 {
+        let free = self.capacity - self.used;
+
+        if free == 0 {
+            0.0
+        } else {
+            1.0 - self.largest_free_range as f32 / free as f32
+        }
+    }
+}
+
+/// Parameters of a [`BufferArena`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArenaDescriptor {
+    /// Size of each pooled buffer in bytes. Larger allocations get a dedicated buffer.
+    pub block_size: u64,
+    /// Usage of the pooled buffers, e.g. `VERTEX`, `INDEX` or `STORAGE`.
+    pub usage: wgpu::BufferUsages,
+    pub label: &'static str,
+}
+
+impl Default for ArenaDescriptor {
+    fn default() -> Self {
+        ArenaDescriptor {
+            block_size: 32 * 1024 * 1024,
+            usage: wgpu::BufferUsages::VERTEX,
+            label: "Arena",
+        }
+    }
+}
+
+/// A typed handle of a range allocated from a [`BufferArena`].
+///
+/// Handles stay valid when the arena is defragmented, and become invalid when freed.
+pub struct SubBuffer<T> {
+    index: u32,
+    generation: u32,
+    len: usize,
+    _phantom_data: PhantomData<fn() -> T>,
+}
+
+impl<T> SubBuffer<T> {
+    /// Retrieves the number of elements of type `T` in the range.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks if the range holds no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T> fmt::Debug for SubBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct(&format!("SubBuffer<{}>", pretty_type_name::<T>()))
+            .field("index", &self.index)
+            .field("generation", &self.generation)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<T> Clone for SubBuffer<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for SubBuffer<T> {}
+
+impl<T> PartialEq for SubBuffer<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation && self.len == other.len
+    }
+}
+
+impl<T> Eq for SubBuffer<T> {}
+
+impl<T> Hash for SubBuffer<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.index, self.generation, self.len).hash(state);
+    }
+}
+
+/// A range of a pooled buffer, resolved from a [`SubBuffer`] handle.
+///
+/// The range is only valid until the arena is defragmented or the handle is freed.
+#[derive(Debug, Clone)]
+pub struct BufferRange {
+    buffer: Arc<wgpu::Buffer>,
+    offset: u64,
+    size: u64,
+}
+
+impl BufferRange {
+    /// Retrieves the pooled buffer.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Retrieves the offset of the range in bytes.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Retrieves the size of the range in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Slices the pooled buffer, e.g. to bind it as a vertex or index buffer.
+    pub fn slice(&self) -> wgpu::BufferSlice<'_> {
+        self.buffer.slice(self.offset..self.offset + self.size)
+    }
+}
+
+impl ShaderBinding for BufferRange {
+    fn bound_resource(&self) -> BoundResource {
+        BoundResource::BufferRange {
+            buffer: self.buffer.clone(),
+            offset: self.offset,
+            size: NonZeroU64::new(self.size),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Block {
+    buffer: Arc<wgpu::Buffer>,
+    allocator: RangeAllocator,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    generation: u32,
+    location: Option<(usize, Allocation)>,
+}
+
+/// Sub-allocates ranges out of large pooled GPU buffers, so many small buffers,
+/// e.g. chunk meshes, share a few `wgpu::Buffer`s.
+///
+/// Ranges are addressed by typed [`SubBuffer`] handles, resolved to buffer ranges
+/// when binding them. Freed ranges are reused by later allocations, and
+/// [`BufferArena::defragment`] compacts blocks on the GPU.
+pub struct BufferArena {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    descriptor: ArenaDescriptor,
+    alignment: u64,
+    blocks: Vec<Block>,
+    slots: Vec<Slot>,
+    free_slots: Vec<u32>,
+}
+
+impl BufferArena {
+    /// Creates an empty arena. Blocks are created on demand.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `descriptor` - The block size and usage of the pooled buffers.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `BufferArena`.
+    pub fn new(renderer: &Renderer, descriptor: ArenaDescriptor) -> BufferArena {
+        BufferArena::from_device(renderer.device.clone(), renderer.queue.clone(), descriptor)
+    }
+
+    pub(crate) fn from_device(
+        device: Arc<wgpu::Device>, 
+        queue: Arc<wgpu::Queue>, 
+        descriptor: ArenaDescriptor,
+    ) -> BufferArena {
+        let limits = device.limits();
+
+        // Offsets of bound ranges must respect the binding alignment of the device
+        let mut alignment = wgpu::COPY_BUFFER_ALIGNMENT;
+        if descriptor.usage.contains(wgpu::BufferUsages::STORAGE) {
+            alignment = alignment.max(limits.min_storage_buffer_offset_alignment as u64);
+        }
+        if descriptor.usage.contains(wgpu::BufferUsages::UNIFORM) {
+            alignment = alignment.max(limits.min_uniform_buffer_offset_alignment as u64);
+        }
+
+        BufferArena {
+            device,
+            queue,
+            descriptor,
+            alignment,
+            blocks: vec![],
+            slots: vec![],
+            free_slots: vec![],
+        }
+    }
+
+    /// Allocates a range for `len` elements of type `T`, creating a new block if none fits.
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - The number of elements.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the range.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AllocError::TooLarge` if the range exceeds the maximum buffer size.
+    pub fn allocate<T: Pod>(&mut self, len: usize) -> Result<SubBuffer<T>, AllocError> {
+        let size = self.allocation_size::<T>(len);
+
+        let location = self.blocks
+            .iter_mut()
+            .enumerate()
+            .find_map(|(i, block)| block.allocator.allocate(size, self.alignment).map(|a| (i, a)));
+
+        let location = match location {
+            Some(location) => location,
+            None => {
+                let capacity = size.max(self.descriptor.block_size);
+                if capacity > self.device.limits().max_buffer_size {
+                    return Err(AllocError::TooLarge(size));
+                }
+
+                let mut block = self.create_block(capacity);
+                let allocation = block.allocator.allocate(size, self.alignment)
+                    .expect("New block fits the allocation");
+
+                self.blocks.push(block);
+                (self.blocks.len() - 1, allocation)
+            },
+        };
+
+        let index = match self.free_slots.pop() {
+            Some(index) => {
+                self.slots[index as usize].location = Some(location);
+                index
+            },
+            None => {
+                self.slots.push(Slot { generation: 0, location: Some(location) });
+                (self.slots.len() - 1) as u32
+            },
+        };
+
+        Ok(SubBuffer {
+            index,
+            generation: self.slots[index as usize].generation,
+            len,
+            _phantom_data: PhantomData,
+        })
+    }
+
+    /// Releases a range, invalidating its handle.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The handle of the range.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AllocError::InvalidHandle` if the handle has already been freed.
+    pub fn free<T>(&mut self, handle: SubBuffer<T>) -> Result<(), AllocError> {
+        let (block, allocation) = self.location(&handle)?;
+        self.blocks[block].allocator.free(allocation);
+
+        let slot = &mut self.slots[handle.index as usize];
+        slot.location = None;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_slots.push(handle.index);
+
+        Ok(())
+    }
+
+    /// Resizes a range, keeping its contents and its handle valid.
+    ///
+    /// Ranges which are large enough are kept in place. Otherwise a new range is allocated,
+    /// the contents are copied on the GPU with `copy_buffer_to_buffer` and the old range is freed.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The handle of the range.
+    /// * `len` - The new number of elements.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the range with the new length.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the handle is invalid or the range exceeds the maximum buffer size.
+    pub fn resize<T: Pod>(&mut self, handle: SubBuffer<T>, len: usize) -> Result<SubBuffer<T>, AllocError> {
+        let (block, allocation) = self.location(&handle)?;
+        let resized = SubBuffer { len, ..handle };
+
+        if self.allocation_size::<T>(len) <= allocation.size {
+            return Ok(resized);
+        }
+
+        let new_handle = self.allocate::<T>(len)?;
+        let (new_block, new_allocation) = self.location(&new_handle)?;
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some(format!("{} resize", self.descriptor.label).as_str()),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.blocks[block].buffer,
+            allocation.offset,
+            &self.blocks[new_block].buffer,
+            new_allocation.offset,
+            allocation.size,
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        // Move the new range into the slot of the old one, so the handle stays valid
+        self.blocks[block].allocator.free(allocation);
+        self.slots[handle.index as usize].location = Some((new_block, new_allocation));
+
+        let new_slot = &mut self.slots[new_handle.index as usize];
+        new_slot.location = None;
+        new_slot.generation = new_slot.generation.wrapping_add(1);
+        self.free_slots.push(new_handle.index);
+
+        Ok(resized)
+    }
+
+    /// Writes elements into a range.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The handle of the range.
+    /// * `offset` - The index of the first element to write.
+    /// * `data` - The elements to write.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the handle is invalid or the data does not fit into the range.
+    pub fn write<T: Pod>(&self, handle: &SubBuffer<T>, offset: usize, data: &[T]) -> Result<(), AllocError> {
+        if offset + data.len() > handle.len {
+            return Err(AllocError::Overflow { offset, len: data.len(), capacity: handle.len });
+        }
+
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let (block, allocation) = self.location(handle)?;
+        let byte_offset = allocation.offset + (offset * size_of::<T>()) as u64;
+        if byte_offset % wgpu::COPY_BUFFER_ALIGNMENT != 0 {
+            return Err(AllocError::Unaligned(byte_offset));
+        }
+
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        let buffer = &self.blocks[block].buffer;
+
+        // Writes must be a multiple of 4 bytes, which allocation sizes are rounded up to
+        if bytes.len() as u64 % wgpu::COPY_BUFFER_ALIGNMENT == 0 {
+            self.queue.write_buffer(buffer, byte_offset, bytes);
+        } else {
+            let mut padded = bytes.to_vec();
+            padded.resize(align_to(bytes.len() as u64, wgpu::COPY_BUFFER_ALIGNMENT) as usize, 0);
+            self.queue.write_buffer(buffer, byte_offset, &padded);
+        }
+
+        Ok(())
+    }
+
+    /// Resolves a handle to the range of its pooled buffer, e.g. to bind it to a shader resource.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The handle of the range.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AllocError::InvalidHandle` if the handle has been freed.
+    pub fn range<T>(&self, handle: &SubBuffer<T>) -> Result<BufferRange, AllocError> {
+        let (block, allocation) = self.location(handle)?;
+
+        Ok(BufferRange {
+            buffer: self.blocks[block].buffer.clone(),
+            offset: allocation.offset,
+            size: allocation.size,
+        })
+    }
+
+    /// Slices the pooled buffer of a handle, e.g. to bind it as a vertex or index buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The handle of the range.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AllocError::InvalidHandle` if the handle has been freed.
+    pub fn slice<T>(&self, handle: &SubBuffer<T>) -> Result<wgpu::BufferSlice<'_>, AllocError> {
+        let (block, allocation) = self.location(handle)?;
+
+        Ok(self.blocks[block].buffer.slice(allocation.range()))
+    }
+
+    /// Retrieves the pooled buffer of a handle with the offset of its range in bytes,
+    /// e.g. to draw several ranges of the same buffer with a single indirect draw.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The handle of the range.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AllocError::InvalidHandle` if the handle has been freed.
+    pub fn block<T>(&self, handle: &SubBuffer<T>) -> Result<(&wgpu::Buffer, u64), AllocError> {
+        let (block, allocation) = self.location(handle)?;
+
+        Ok((&self.blocks[block].buffer, allocation.offset))
+    }
+
+    /// Compacts fragmented blocks by copying live ranges to the start of new buffers
+    /// on the GPU, and releases empty blocks.
+    ///
+    /// Handles stay valid, but ranges resolved before must be resolved again,
+    /// e.g. resources of shader resources must be set again.
+    ///
+    /// # Returns
+    ///
+    /// The number of moved ranges.
+    pub fn defragment(&mut self) -> usize {
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some(format!("{} defragmentation", self.descriptor.label).as_str()),
+        });
+        let mut moved = 0;
+
+        for block_index in 0..self.blocks.len() {
+            let block = &self.blocks[block_index];
+            let is_compact = match block.allocator.free_ranges() {
+                [] => true,
+                [last] => last.end == block.allocator.capacity(),
+                _ => false,
+            };
+
+            if is_compact || block.allocator.allocations() == 0 {
+                continue;
+            }
+
+            let mut compacted = self.create_block(block.allocator.capacity());
+            let mut slots = self.slots
+                .iter_mut()
+                .filter_map(|slot| slot.location.as_mut())
+                .filter(|(block, _)| *block == block_index)
+                .collect::<Vec<_>>();
+            slots.sort_by_key(|(_, allocation)| allocation.offset);
+
+            for (_, allocation) in slots {
+                let new_allocation = compacted.allocator.allocate(allocation.size, self.alignment)
+                    .expect("Compacted allocations fit the block");
+
+                if new_allocation.offset != allocation.offset {
+                    moved += 1;
+                }
+
+                encoder.copy_buffer_to_buffer(
+                    &block.buffer,
+                    allocation.offset,
+                    &compacted.buffer,
+                    new_allocation.offset,
+                    allocation.size,
+                );
+                *allocation = new_allocation;
+            }
+
+            self.blocks[block_index] = compacted;
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        // Release empty blocks and remap locations of the remaining ones
+        let mut remap = Vec::with_capacity(self.blocks.len());
+        let mut kept = 0;
+        for block in &self.blocks {
+            remap.push(kept);
+            kept += (block.allocator.allocations() > 0) as usize;
+        }
+
+        self.blocks.retain(|block| block.allocator.allocations() > 0);
+        for (block, _) in self.slots.iter_mut().filter_map(|slot| slot.location.as_mut()) {
+            *block = remap[*block];
+        }
+
+        moved
+    }
+
+    /// Retrieves the memory usage of the arena.
+    pub fn stats(&self) -> ArenaStats {
+        self.blocks.iter().fold(ArenaStats::default(), |stats, block| {
+            let allocator = &block.allocator;
+
+            ArenaStats {
+                blocks: stats.blocks + 1,
+                capacity: stats.capacity + allocator.capacity(),
+                used: stats.used + allocator.used(),
+                allocations: stats.allocations + allocator.allocations(),
+                free_ranges: stats.free_ranges + allocator.free_ranges().len(),
+                largest_free_range: stats.largest_free_range.max(allocator.largest_free_range()),
+            }
+        })
+    }
+
+    /// Retrieves the parameters of the arena.
+    pub fn descriptor(&self) -> &ArenaDescriptor {
+        &self.descriptor
+    }
+
+    fn allocation_size<T>(&self, len: usize) -> u64 {
+        // Empty buffer slices cannot be bound, so every allocation takes at least one aligned unit
+        align_to((len * size_of::<T>()) as u64, wgpu::COPY_BUFFER_ALIGNMENT).max(self.alignment)
+    }
+
+    fn location<T>(&self, handle: &SubBuffer<T>) -> Result<(usize, Allocation), AllocError> {
+        self.slots
+            .get(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.location)
+            .ok_or(AllocError::InvalidHandle)
+    }
+
+    fn create_block(&self, capacity: u64) -> Block {
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(format!("{} block ({:?})", self.descriptor.label, self.descriptor.usage).as_str()),
+            size: capacity,
+            usage: self.descriptor.usage | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        Block {
+            buffer: Arc::new(buffer),
+            allocator: RangeAllocator::new(capacity),
+        }
+    }
+}
+
+fn align_to(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Allocates ranges of the given sizes from the start of a new block.
+    fn allocate_all(capacity: u64, sizes: &[u64]) -> (RangeAllocator, Vec<Allocation>) {
+        let mut allocator = RangeAllocator::new(capacity);
+        let allocations = sizes.iter().map(|size| allocator.allocate(*size, 1).unwrap()).collect();
+
+        (allocator, allocations)
+    }
+
+    #[test]
+    fn allocate_picks_the_smallest_fitting_range() {
+        let (mut allocator, allocations) = allocate_all(100, &[10, 30, 10, 20, 10]);
+        allocator.free(allocations[1]);
+        allocator.free(allocations[3]);
+        assert_eq!(allocator.free_ranges(), &[10..40, 50..70, 80..100]);
+
+        // The first of the smallest fitting ranges is used, keeping the larger one for larger allocations
+        assert_eq!(allocator.allocate(15, 1), Some(Allocation { offset: 50, size: 15 }));
+        assert_eq!(allocator.free_ranges(), &[10..40, 65..70, 80..100]);
+
+        assert_eq!(allocator.allocate(25, 1), Some(Allocation { offset: 10, size: 25 }));
+        assert_eq!(allocator.allocate(25, 1), None);
+    }
+
+    #[test]
+    fn allocate_pads_offsets_to_the_alignment() {
+        let mut allocator = RangeAllocator::new(64);
+        allocator.allocate(3, 1).unwrap();
+
+        assert_eq!(allocator.allocate(8, 16), Some(Allocation { offset: 16, size: 8 }));
+        assert_eq!(allocator.free_ranges(), &[3..16, 24..64]);
+
+        // The padding counts towards the size of the free range
+        assert_eq!(allocator.allocate(40, 16), None);
+        assert_eq!(allocator.allocate(40, 8), Some(Allocation { offset: 24, size: 40 }));
+    }
+
+    #[test]
+    fn free_merges_adjacent_ranges() {
+        let (mut allocator, allocations) = allocate_all(50, &[10, 10, 10, 10, 10]);
+
+        // Neither neighbour is free
+        allocator.free(allocations[1]);
+        assert_eq!(allocator.free_ranges(), std::slice::from_ref(&(10..20)));
+
+        // The previous range is free
+        allocator.free(allocations[2]);
+        assert_eq!(allocator.free_ranges(), std::slice::from_ref(&(10..30)));
+
+        // The next range is free
+        allocator.free(allocations[0]);
+        assert_eq!(allocator.free_ranges(), std::slice::from_ref(&(0..30)));
+
+        // Both ranges are free
+        allocator.free(allocations[4]);
+        allocator.free(allocations[3]);
+        assert_eq!(allocator.free_ranges(), std::slice::from_ref(&(0..50)));
+        assert_eq!(allocator, RangeAllocator::new(50));
+    }
+
+    #[test]
+    fn grow_extends_the_trailing_free_range() {
+        let mut allocator = RangeAllocator::new(20);
+        allocator.allocate(10, 1).unwrap();
+
+        allocator.grow(30);
+        assert_eq!(allocator.free_ranges(), std::slice::from_ref(&(10..30)));
+        assert_eq!(allocator.capacity(), 30);
+
+        // Smaller capacities are ignored
+        allocator.grow(5);
+        assert_eq!(allocator.capacity(), 30);
+    }
+
+    #[test]
+    fn grow_appends_a_range_after_allocations() {
+        let (mut allocator, _) = allocate_all(20, &[20]);
+        assert!(allocator.free_ranges().is_empty());
+
+        allocator.grow(30);
+        assert_eq!(allocator.free_ranges(), std::slice::from_ref(&(20..30)));
+        assert_eq!(allocator.allocate(10, 1), Some(Allocation { offset: 20, size: 10 }));
+    }
+
+    #[test]
+    fn bookkeeping_tracks_allocations() {
+        let (mut allocator, allocations) = allocate_all(100, &[10, 20, 30]);
+        assert_eq!((allocator.used(), allocator.allocations()), (60, 3));
+        assert_eq!(allocator.largest_free_range(), 40);
+
+        allocator.free(allocations[1]);
+        assert_eq!((allocator.used(), allocator.allocations()), (40, 2));
+        assert_eq!(allocator.largest_free_range(), 40);
+
+        allocator.free(allocations[2]);
+        assert_eq!((allocator.used(), allocator.allocations()), (10, 1));
+        assert_eq!(allocator.largest_free_range(), 90);
+
+        let (full, _) = allocate_all(10, &[10]);
+        assert_eq!(full.largest_free_range(), 0);
+    }
+
+    #[test]
+    fn empty_ranges_are_neither_allocated_nor_freed() {
+        let mut allocator = RangeAllocator::new(10);
+        allocator.allocate(1, 1).unwrap();
+
+        assert_eq!(allocator.allocate(0, 4), None);
+        assert_eq!(allocator.free_ranges(), std::slice::from_ref(&(1..10)));
+
+        allocator.free(Allocation { offset: 0, size: 0 });
+        assert_eq!(allocator.free_ranges(), std::slice::from_ref(&(1..10)));
+        assert_eq!((allocator.used(), allocator.allocations()), (1, 1));
+    }
+}
@@ -1,5 +1,6 @@
 use std::marker::PhantomData;
-use std::mem::{size_of, size_of_val};
+use std::sync::Arc;
+use std::mem::size_of;
 
 use bytemuck::Pod;
 use derive_getters::Getters;
@@ -8,15 +9,117 @@ use thiserror::Error;
 
 use crate::renderer::error::RenderError;
 use crate::renderer::Renderer;
+use crate::renderer::hal::pipeline::{BoundResource, ShaderBinding};
+use crate::renderer::hal::staging::UploadTarget;
 use crate::renderer::types::*;
 
+/// A generational handle of a vertex buffer owned by the [`Renderer`].
+///
+/// Handles of destroyed buffers stay invalid when their slot is reused.
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
-pub struct BufferId(pub(crate) usize);
+pub struct BufferId {
+    pub(crate) index: u32,
+    pub(crate) generation: u32,
+}
 
 #[derive(Debug, Error)]
-#[error("Invalid buffer id {}", self.0.0)]
+#[error("Invalid buffer id {} (generation {})", self.0.index, self.0.generation)]
 pub struct InvalidBufferId(pub BufferId);
 
+/// A slot of a buffer, reused with a new generation after the buffer is removed.
+#[derive(Debug, Clone, Copy)]
+struct BufferSlot<T> {
+    generation: u32,
+    buffer: Option<T>,
+}
+
+/// Buffers addressed by generational `BufferId`s, e.g. the vertex buffers of the [`Renderer`].
+#[derive(Debug)]
+pub(crate) struct BufferSlots<T> {
+    slots: Vec<BufferSlot<T>>,
+    free: Vec<u32>,
+}
+
+impl<T> Default for BufferSlots<T> {
+    fn default() -> Self {
+        BufferSlots { slots: vec![], free: vec![] }
+    }
+}
+
+impl<T> BufferSlots<T> {
+    /// Inserts a buffer, reusing a free slot if possible.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The buffer to insert.
+    ///
+    /// # Returns
+    ///
+    /// The ID of the inserted buffer.
+    pub(crate) fn insert(&mut self, buffer: T) -> BufferId {
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.slots[index as usize].buffer = Some(buffer);
+                index
+            },
+            None => {
+                self.slots.push(BufferSlot { generation: 0, buffer: Some(buffer) });
+                (self.slots.len() - 1) as u32
+            },
+        };
+
+        BufferId {
+            index,
+            generation: self.slots[index as usize].generation,
+        }
+    }
+
+    /// Retrieves a reference to a buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidBufferId` if the ID is out of range or its buffer has been removed.
+    pub(crate) fn get(&self, id: BufferId) -> Result<&T, InvalidBufferId> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.buffer.as_ref())
+            .ok_or(InvalidBufferId(id))
+    }
+
+    /// Retrieves a mutable reference to a buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidBufferId` if the ID is out of range or its buffer has been removed.
+    pub(crate) fn get_mut(&mut self, id: BufferId) -> Result<&mut T, InvalidBufferId> {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.buffer.as_mut())
+            .ok_or(InvalidBufferId(id))
+    }
+
+    /// Removes a buffer, invalidating its ID and all of its copies.
+    ///
+    /// # Returns
+    ///
+    /// The removed buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidBufferId` if the ID is invalid, e.g. if the buffer has already been removed.
+    pub(crate) fn remove(&mut self, id: BufferId) -> Result<T, InvalidBufferId> {
+        self.get(id)?;
+
+        let slot = &mut self.slots[id.index as usize];
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(id.index);
+
+        Ok(slot.buffer.take().unwrap())
+    }
+}
+
 /// A generic buffer used for storing data on the GPU.
 /// 
 /// # Type Parameters
@@ -24,7 +127,7 @@ pub struct InvalidBufferId(pub BufferId);
 /// * `T` - The type of data stored in the buffer. Must implement the `Pod` trait.
 #[derive(Debug, Getters)]
 pub struct Buffer<T> {
-    inner: wgpu::Buffer,
+    inner: Arc<wgpu::Buffer>,
     capacity: usize,
     #[getter(skip)]
     _phantom_data: PhantomData<T>,
@@ -44,7 +147,7 @@ impl<T: Pod> Buffer<T> {
     /// A new instance of `Buffer<T>`.
     pub fn new(renderer: &Renderer, capacity: usize, usage: BufferUsages) -> Buffer<T> {
         Buffer {
-            inner: Buffer::<T>::new_inner(&renderer.device, capacity * size_of::<T>(), usage),
+            inner: Arc::new(Buffer::<T>::new_inner(&renderer.device, capacity * size_of::<T>(), usage)),
             capacity,
             _phantom_data: PhantomData,
         }
@@ -77,6 +180,46 @@ impl<T: Pod> Buffer<T> {
         Ok(())
     }
 
+    /// Stages the given data to be written into the buffer when the next `DrawContext`
+    /// is applied, within the upload budget of the renderer.
+    ///
+    /// Unlike [`Buffer::fill_exact`], the write may be deferred to a later frame, and a
+    /// newer staged write replaces the pending ones it fully overwrites. Writes staged before
+    /// the buffer is resized are moved to the resized buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer staging the upload.
+    /// * `offset` - The offset in number of elements of type `T`.
+    /// * `data` - A slice of data to be written to the buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RenderError::BufferOverflow` if the data does not fit into the buffer after the offset.
+    pub fn stage(
+        &self,
+        renderer: &Renderer,
+        offset: u64,
+        data: &[T],
+    ) -> Result<(), RenderError> {
+        if offset as usize + data.len() > self.capacity {
+            return Err(RenderError::BufferOverflow(offset as usize + data.len()));
+        }
+
+        if !data.is_empty() {
+            renderer.stage_upload(
+                UploadTarget::Buffer {
+                    buffer: self.inner.clone(),
+                    offset: offset * size_of::<T>() as u64,
+                },
+                bytemuck::cast_slice(data).to_vec(),
+                true,
+            );
+        }
+
+        Ok(())
+    }
+
     /// Fills the buffer with the given data, resizing the buffer if necessary.
     ///
     /// # Arguments
@@ -90,16 +233,38 @@ impl<T: Pod> Buffer<T> {
         offset: u64,
         data: &[T],
     ) {
-        let bytes_to_write = size_of_val(data);
-        if bytes_to_write > self.capacity * size_of::<T>() {
-            self.resize(renderer, data.len());
+        let required_capacity = offset as usize + data.len();
+        if required_capacity > self.capacity {
+            self.resize(renderer, required_capacity);
         }
 
         self.fill_exact(renderer, offset, data).unwrap();
     }
 
+    /// Resizes the buffer, keeping the elements which fit into the new capacity.
+    ///
+    /// The contents are copied into the new buffer on the GPU with `copy_buffer_to_buffer`,
+    /// and pending staged writes are moved to the new buffer, so they are copied after the contents.
+    /// Shader resources using the buffer must be bound again.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `capacity` - The new capacity in number of elements of type `T`.
     pub fn resize(&mut self, renderer: &Renderer, capacity: usize) {
-        self.inner = Buffer::<T>::new_inner(&renderer.device, capacity * size_of::<T>(), self.inner.usage());
+        let inner = Arc::new(Buffer::<T>::new_inner(&renderer.device, capacity * size_of::<T>(), self.inner.usage()));
+
+        let copy_size = (self.capacity.min(capacity) * size_of::<T>()) as u64 / wgpu::COPY_BUFFER_ALIGNMENT * wgpu::COPY_BUFFER_ALIGNMENT;
+        if copy_size > 0 {
+            let mut encoder = renderer.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Buffer resize"),
+            });
+            encoder.copy_buffer_to_buffer(&self.inner, 0, &inner, 0, copy_size);
+            renderer.queue.submit(std::iter::once(encoder.finish()));
+        }
+
+        renderer.retarget_uploads(&self.inner, &inner, (capacity * size_of::<T>()) as u64);
+        self.inner = inner;
         self.capacity = capacity;
     }
 
@@ -107,13 +272,75 @@ impl<T: Pod> Buffer<T> {
         device.create_buffer(&wgpu::BufferDescriptor {
             label: Some(format!("Buffer ({:?}, {})", usage, pretty_type_name::<T>()).as_str()),
             size: capacity as u64,
-            usage: usage | wgpu::BufferUsages::COPY_DST,
+            usage: usage | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
             mapped_at_creation: false,
         })
     }
 }
 
+impl<T> ShaderBinding for Buffer<T> {
+    fn bound_resource(&self) -> BoundResource {
+        BoundResource::Buffer(self.inner.clone())
+    }
+}
+
 pub struct BufferResourceDescriptor {
     pub visibility: ShaderStages,
     pub buffer_type: BufferBindingType,
-}
\ No newline at end of file
+}
+
+impl BufferResourceDescriptor {
+    /// Creates the bind group layout entry of a buffer bound with this descriptor.
+    ///
+    /// # Arguments
+    ///
+    /// * `binding` - The binding index of the buffer.
+    pub fn layout_entry(&self, binding: u32) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: self.visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: self.buffer_type,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_ids_stay_invalid_when_slots_are_reused() {
+        let mut slots = BufferSlots::default();
+        let first = slots.insert(1);
+        assert_eq!(slots.get(first).copied().unwrap(), 1);
+
+        assert_eq!(slots.remove(first).unwrap(), 1);
+        assert_eq!(slots.get(first).unwrap_err().0, first);
+        assert!(slots.remove(first).is_err());
+
+        // The slot is reused with a new generation
+        let second = slots.insert(2);
+        assert_eq!(second.index, first.index);
+        assert_ne!(second.generation, first.generation);
+        assert!(slots.get(first).is_err());
+        assert!(slots.get_mut(first).is_err());
+
+        *slots.get_mut(second).unwrap() = 3;
+        assert_eq!(slots.get(second).copied().unwrap(), 3);
+    }
+
+    #[test]
+    fn out_of_range_ids_are_invalid() {
+        let slots = BufferSlots::<u32>::default();
+        let id = BufferId { index: 4, generation: 0 };
+
+        let error = slots.get(id).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid buffer id 4 (generation 0)");
+        assert!(matches!(RenderError::from(error), RenderError::InvalidBufferId(InvalidBufferId(err)) if err == id));
+    }
+}
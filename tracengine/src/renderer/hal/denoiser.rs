@@ -0,0 +1,403 @@
+use bytemuck::{Pod, Zeroable};
+use winit::dpi::PhysicalSize;
+use crate::renderer::{
+    error::RenderError,
+    graph::{BufferHandle, GraphBufferDescriptor, RenderGraph},
+    InstanceData, Renderer,
+};
+use crate::glm;
+
+use super::{
+    buffer::{Buffer, BufferResourceDescriptor}, pipeline::ShaderResource
+};
+
+/// Parameters of the spatio-temporal (SVGF) denoiser.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DenoiserDescriptor {
+    /// Number of à-trous wavelet iterations. Each iteration doubles the filter footprint.
+    pub iterations: u32,
+    /// Maximum number of frames the temporal history is accumulated over.
+    pub max_history: u32,
+    /// Luminance edge-stopping strength, scaled by the estimated standard deviation.
+    pub phi_color: f32,
+    /// Normal edge-stopping exponent.
+    pub phi_normal: f32,
+    /// Depth edge-stopping strength.
+    pub phi_depth: f32,
+    /// Minimum blend factor of the current frame during temporal accumulation.
+    pub color_alpha: f32,
+    /// Minimum blend factor of the current frame for luminance moments.
+    pub moments_alpha: f32,
+}
+
+impl Default for DenoiserDescriptor {
+    fn default() -> Self {
+        DenoiserDescriptor {
+            iterations: 5,
+            max_history: 32,
+            phi_color: 4.0,
+            phi_normal: 128.0,
+            phi_depth: 1.0,
+            color_alpha: 0.2,
+            moments_alpha: 0.2,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+pub struct DenoiserConfig {
+    canvas_width: u32,
+    canvas_height: u32,
+    iterations: u32,
+    max_history: u32,
+    phi_color: f32,
+    phi_normal: f32,
+    phi_depth: f32,
+    color_alpha: f32,
+    moments_alpha: f32,
+    history_valid: u32,
+    _padding: [u32; 2],
+}
+
+impl DenoiserConfig {
+    /// Creates the denoiser configuration of a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The size of the canvas.
+    /// * `descriptor` - The denoiser parameters.
+    /// * `history_valid` - Whether the history buffers hold a previous frame of the same size.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `DenoiserConfig`.
+    pub fn new(size: PhysicalSize<u32>, descriptor: &DenoiserDescriptor, history_valid: bool) -> DenoiserConfig {
+        DenoiserConfig {
+            canvas_width: size.width,
+            canvas_height: size.height,
+            iterations: descriptor.iterations,
+            max_history: descriptor.max_history,
+            phi_color: descriptor.phi_color,
+            phi_normal: descriptor.phi_normal,
+            phi_depth: descriptor.phi_depth,
+            color_alpha: descriptor.color_alpha,
+            moments_alpha: descriptor.moments_alpha,
+            history_valid: history_valid as u32,
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// Tracks whether the temporal history of the denoiser can be reprojected into the current frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DenoiserHistory {
+    size: PhysicalSize<u32>,
+    frames: u32,
+}
+
+impl DenoiserHistory {
+    /// Creates an empty history for a canvas of the given size.
+    pub fn new(size: PhysicalSize<u32>) -> DenoiserHistory {
+        DenoiserHistory { size, frames: 0 }
+    }
+
+    /// Discards the history, e.g. after the denoiser has been disabled for a while.
+    pub fn reset(&mut self) {
+        self.frames = 0;
+    }
+
+    /// Discards the history if either dimension of the canvas has changed, as pixels of
+    /// the previous frame would be read with the wrong row stride.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the size has changed.
+    pub fn resize(&mut self, size: PhysicalSize<u32>) -> bool {
+        if size == self.size {
+            return false;
+        }
+
+        self.size = size;
+        self.reset();
+
+        true
+    }
+
+    /// Starts a new frame, which writes the history read by the next one.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the history holds a previous frame.
+    pub fn next_frame(&mut self) -> bool {
+        let valid = self.frames > 0;
+        self.frames = self.frames.saturating_add(1);
+
+        valid
+    }
+
+    /// Retrieves the number of frames written since the history has been discarded.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+}
+
+/// Kind of a single denoiser compute pass, matching the `PASS_*` constants in the shader.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenoisePassKind {
+    /// Reprojects the history, accumulates color and luminance moments and estimates variance.
+    Temporal = 0,
+    /// A single edge-avoiding à-trous wavelet iteration.
+    Atrous = 1,
+    /// Writes the filtered image back to the color buffer and stores the current G-buffer as history.
+    Resolve = 2,
+}
+
+/// Push constant data describing a single denoiser compute pass.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Zeroable, Pod)]
+pub struct DenoisePass {
+    kind: u32,
+    iteration: u32,
+    step_size: u32,
+    flip: u32,
+}
+
+impl DenoisePass {
+    fn new(kind: DenoisePassKind, iteration: u32, flip: bool) -> DenoisePass {
+        DenoisePass {
+            kind: kind as u32,
+            iteration,
+            step_size: 1 << iteration,
+            flip: flip as u32,
+        }
+    }
+
+    /// Creates the passes of one denoised frame: the temporal pass, the à-trous iterations
+    /// alternating between the ping-pong buffers and the resolve pass.
+    fn sequence(iterations: u32) -> Vec<DenoisePass> {
+        let mut passes = Vec::with_capacity(iterations as usize + 2);
+
+        passes.push(DenoisePass::new(DenoisePassKind::Temporal, 0, true));
+        passes.extend((0..iterations).map(|i| DenoisePass::new(DenoisePassKind::Atrous, i, i % 2 == 1)));
+        passes.push(DenoisePass::new(DenoisePassKind::Resolve, 0, iterations % 2 == 1));
+
+        passes
+    }
+}
+
+impl InstanceData for DenoisePass {
+    type UniformData = DenoisePass;
+
+    fn uniform_data(&mut self) -> Self::UniformData {
+        *self
+    }
+}
+
+/// Screen-sized storage buffers of the denoiser, as named in the render graph and in the shader.
+const SCREEN_BUFFERS: [(&str, &str); 6] = [
+    ("Denoiser history", "history_buffer"),
+    ("Denoiser moments", "moments_buffer"),
+    ("Denoiser previous moments", "prev_moments_buffer"),
+    ("Denoiser previous G-buffer", "prev_gbuffer"),
+    ("Denoiser ping", "ping_buffer"),
+    ("Denoiser pong", "pong_buffer"),
+];
+
+/// SVGF-style denoiser operating on the ray tracer's G-buffers.
+///
+/// The history and ping-pong buffers are allocated by the render graph, which zeroes and
+/// reallocates them when the canvas is resized, while the color, normal, depth and velocity
+/// buffers are read from the tracer and TAA resources bound in the groups preceding
+/// [`Denoiser::shader_resource`].
+#[readonly::make]
+pub struct Denoiser {
+    /// Handles of the history, moments, previous moments, previous G-buffer, ping and pong buffers.
+    pub buffers: [BufferHandle; 6],
+    pub config_buffer: Buffer<DenoiserConfig>,
+    pub shader_resource: ShaderResource,
+    #[readonly]
+    pub descriptor: DenoiserDescriptor,
+    #[readonly]
+    pub history: DenoiserHistory,
+    pub enabled: bool,
+}
+
+impl Denoiser {
+    /// Creates the denoiser buffers in the render graph and its shader resource. The buffers
+    /// must be bound with [`Denoiser::bind_buffers`] once the graph has allocated them.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `graph` - The render graph allocating the screen-sized buffers.
+    /// * `descriptor` - The denoiser parameters.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Denoiser`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration buffer cannot be filled.
+    pub fn new(renderer: &Renderer, graph: &mut RenderGraph, descriptor: DenoiserDescriptor) -> Result<Denoiser, RenderError> {
+        let buffers = SCREEN_BUFFERS.map(|(name, _)| graph.create_buffer::<glm::Vec4>(name, GraphBufferDescriptor::default()));
+
+        let config_buffer = Buffer::new(
+            renderer,
+            1,
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+        config_buffer.fill_exact(renderer, 0, &[DenoiserConfig::new(renderer.size(), &descriptor, false)])?;
+
+        let names = std::iter::once("denoiser_config")
+            .chain(SCREEN_BUFFERS.map(|(_, binding)| binding))
+            .map(|name| vec![name.to_owned()])
+            .collect();
+        let mut shader_resource = ShaderResource::new(renderer, Some("Denoiser".to_owned()), Denoiser::layout_entries(), names);
+        shader_resource.set("denoiser_config", &config_buffer)?;
+
+        Ok(Denoiser {
+            buffers,
+            config_buffer,
+            shader_resource,
+            descriptor,
+            history: DenoiserHistory::new(renderer.size()),
+            enabled: true,
+        })
+    }
+
+    /// Replaces the denoiser parameters. Takes effect on the next [`Denoiser::update`].
+    pub fn set_descriptor(&mut self, descriptor: DenoiserDescriptor) {
+        self.descriptor = descriptor;
+    }
+
+    /// Discards the temporal history, e.g. after the denoiser has been disabled for a while.
+    pub fn reset_history(&mut self) {
+        self.history.reset();
+    }
+
+    /// Binds the buffers allocated by the render graph and discards the history, as
+    /// reallocated buffers are zeroed.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `graph` - The render graph the buffers have been created in.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the graph has not allocated the buffers.
+    pub fn bind_buffers(&mut self, renderer: &Renderer, graph: &RenderGraph) -> Result<(), RenderError> {
+        for ((_, binding), handle) in SCREEN_BUFFERS.iter().zip(self.buffers) {
+            self.shader_resource.set(binding, graph.buffer(handle)?)?;
+        }
+
+        self.shader_resource.update(renderer)?;
+        self.history.reset();
+
+        Ok(())
+    }
+
+    /// Uploads the denoiser parameters of the next frame. If the canvas has been resized,
+    /// the history is discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration buffer cannot be filled.
+    pub fn update(&mut self, renderer: &Renderer) -> Result<(), RenderError> {
+        self.history.resize(renderer.size());
+
+        let history_valid = self.history.next_frame();
+        self.config_buffer.fill_exact(renderer, 0, &[DenoiserConfig::new(renderer.size(), &self.descriptor, history_valid)])?;
+
+        Ok(())
+    }
+
+    /// Returns the sequence of compute passes to dispatch for one denoised frame.
+    pub fn passes(&self) -> Vec<DenoisePass> {
+        DenoisePass::sequence(self.descriptor.iterations)
+    }
+
+    /// Retrieves the entries of the bind group layout of the denoiser: the configuration
+    /// uniform followed by the storage buffers, e.g. to validate shaders without a GPU device.
+    pub fn layout_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+        Denoiser::resource_descriptors()
+            .iter()
+            .enumerate()
+            .map(|(binding, descriptor)| descriptor.layout_entry(binding as u32))
+            .collect()
+    }
+
+    fn resource_descriptors() -> [BufferResourceDescriptor; 7] {
+        let storage = || BufferResourceDescriptor {
+            visibility: wgpu::ShaderStages::COMPUTE,
+            buffer_type: wgpu::BufferBindingType::Storage { read_only: false },
+        };
+
+        [
+            BufferResourceDescriptor {
+                visibility: wgpu::ShaderStages::COMPUTE,
+                buffer_type: wgpu::BufferBindingType::Uniform,
+            },
+            storage(), storage(), storage(), storage(), storage(), storage(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_matches_the_descriptor() {
+        let descriptor = DenoiserDescriptor { iterations: 3, max_history: 8, ..Default::default() };
+        let config = DenoiserConfig::new(PhysicalSize::new(1280, 720), &descriptor, true);
+
+        assert_eq!((config.canvas_width, config.canvas_height), (1280, 720));
+        assert_eq!((config.iterations, config.max_history), (3, 8));
+        assert_eq!(config.phi_color, descriptor.phi_color);
+        assert_eq!(config.history_valid, 1);
+        assert_eq!(DenoiserConfig::new(PhysicalSize::new(1, 1), &descriptor, false).history_valid, 0);
+    }
+
+    #[test]
+    fn history_is_valid_after_the_first_frame() {
+        let mut history = DenoiserHistory::new(PhysicalSize::new(1280, 720));
+
+        assert!(!history.next_frame());
+        assert!(history.next_frame());
+        assert_eq!(history.frames(), 2);
+
+        history.reset();
+        assert!(!history.next_frame());
+        assert!(history.next_frame());
+    }
+
+    #[test]
+    fn resizing_either_dimension_discards_the_history() {
+        let mut history = DenoiserHistory::new(PhysicalSize::new(1280, 720));
+        history.next_frame();
+
+        assert!(!history.resize(PhysicalSize::new(1280, 720)));
+        assert!(history.next_frame());
+
+        // Same number of pixels with another row stride
+        assert!(history.resize(PhysicalSize::new(720, 1280)));
+        assert!(!history.next_frame());
+
+        assert!(history.resize(PhysicalSize::new(720, 1281)));
+        assert_eq!(history.frames(), 0);
+    }
+
+    #[test]
+    fn passes_alternate_the_ping_pong_buffers() {
+        let passes = DenoisePass::sequence(3);
+        let kinds = passes.iter().map(|pass| pass.kind).collect::<Vec<_>>();
+
+        assert_eq!(kinds, [0, 1, 1, 1, 2].map(|kind| kind as u32));
+        assert_eq!(passes.iter().map(|pass| pass.step_size).collect::<Vec<_>>(), [1, 1, 2, 4, 1]);
+        assert_eq!(passes.iter().map(|pass| pass.flip).collect::<Vec<_>>(), [1, 0, 1, 0, 1]);
+    }
+}
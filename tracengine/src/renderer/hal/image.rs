@@ -0,0 +1,394 @@
+use std::path::{Path, PathBuf};
+
+use derive_getters::Getters;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ImageError {
+    #[error("Cannot read image `{0}`: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("Unknown image format, only PNG images are supported")]
+    UnknownFormat,
+    #[error("Unsupported image: {0}")]
+    Unsupported(String),
+    #[error("Corrupt image: {0}")]
+    Corrupt(String),
+    #[error("Image of {width}x{height} texels exceeds the maximum size of {MAX_IMAGE_DIMENSION}x{MAX_IMAGE_DIMENSION} texels")]
+    TooLarge {
+        width: u32,
+        height: u32,
+    },
+    #[error("Image data has {found} bytes, but {expected} are expected")]
+    InvalidDataSize {
+        expected: usize,
+        found: usize,
+    },
+    #[error("Layers of an image array must have equal sizes: expected {expected:?}, found {found:?}")]
+    LayerSizeMismatch {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+}
+
+/// Maximum width and height of decoded images, bounding the memory allocated for corrupt files.
+pub const MAX_IMAGE_DIMENSION: u32 = 16384;
+
+/// Decoded RGBA8 image data with one or more layers of equal size, e.g. block textures
+/// stacked into a 2D array texture.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    layers: u32,
+    /// Tightly packed RGBA8 texels, row by row and layer by layer.
+    data: Vec<u8>,
+}
+
+impl Image {
+    /// Creates an image of a single layer from RGBA8 texels.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the image in texels.
+    /// * `height` - The height of the image in texels.
+    /// * `data` - Tightly packed RGBA8 texels, row by row.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Image`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ImageError::InvalidDataSize` if the data does not match the size,
+    /// or `ImageError::TooLarge` if the size overflows.
+    pub fn from_rgba8(width: u32, height: u32, data: Vec<u8>) -> Result<Image, ImageError> {
+        let expected = texel_bytes(width, height, 4)?;
+        if data.len() != expected {
+            return Err(ImageError::InvalidDataSize { expected, found: data.len() });
+        }
+
+        Ok(Image { width, height, layers: 1, data })
+    }
+
+    /// Loads and decodes an image file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of a PNG image.
+    ///
+    /// # Returns
+    ///
+    /// The decoded `Image`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or decoded.
+    pub fn load(path: impl AsRef<Path>) -> Result<Image, ImageError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| ImageError::Io(path.to_owned(), e))?;
+
+        Image::decode(&bytes)
+    }
+
+    /// Decodes a PNG image with the `png` crate.
+    ///
+    /// Images of all colour types and bit depths are supported. 16-bit channels are truncated
+    /// to 8 bits. Images larger than [`MAX_IMAGE_DIMENSION`] in either direction are rejected
+    /// before their data is decoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The encoded image.
+    ///
+    /// # Returns
+    ///
+    /// The decoded `Image`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the image is not a PNG, is unsupported or is corrupt, e.g. if a chunk
+    /// has an invalid checksum.
+    pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+        if !bytes.starts_with(&PNG_SIGNATURE) {
+            return Err(ImageError::UnknownFormat);
+        }
+
+        let mut decoder = png::Decoder::new_with_limits(bytes, png::Limits {
+            bytes: MAX_DECODED_BYTES,
+        });
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+        let mut reader = decoder.read_info().map_err(png_error)?;
+        let (width, height) = (reader.info().width, reader.info().height);
+        check_dimensions(width, height)?;
+
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut buffer).map_err(png_error)?;
+        buffer.truncate(frame.buffer_size());
+
+        let data = match frame.color_type {
+            png::ColorType::Rgba => buffer,
+            png::ColorType::Rgb => buffer.chunks(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => buffer.chunks(2).flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]]).collect(),
+            png::ColorType::Grayscale => buffer.iter().flat_map(|&gray| [gray, gray, gray, 255]).collect(),
+            png::ColorType::Indexed => {
+                return Err(ImageError::Unsupported("PNG palette was not expanded".to_owned()));
+            },
+        };
+
+        Image::from_rgba8(width, height, data)
+    }
+
+    /// Stacks images of equal size into layers of a single image, e.g. for a 2D array texture.
+    ///
+    /// # Arguments
+    ///
+    /// * `images` - The images to stack, in order of the layers.
+    ///
+    /// # Returns
+    ///
+    /// An `Image` with the layers of all images.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ImageError::LayerSizeMismatch` if the images have different sizes.
+    pub fn array(images: &[Image]) -> Result<Image, ImageError> {
+        let Some(first) = images.first() else {
+            return Ok(Image { width: 0, height: 0, layers: 0, data: vec![] });
+        };
+
+        let mut data = Vec::with_capacity(images.iter().map(|image| image.data.len()).sum());
+
+        for image in images {
+            if (image.width, image.height) != (first.width, first.height) {
+                return Err(ImageError::LayerSizeMismatch {
+                    expected: (first.width, first.height),
+                    found: (image.width, image.height),
+                });
+            }
+
+            data.extend_from_slice(&image.data);
+        }
+
+        Ok(Image {
+            width: first.width,
+            height: first.height,
+            layers: images.iter().map(|image| image.layers).sum(),
+            data,
+        })
+    }
+}
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+/// Maximum number of bytes allocated by the PNG decoder, enough for the largest RGBA8 image.
+const MAX_DECODED_BYTES: usize = MAX_IMAGE_DIMENSION as usize * MAX_IMAGE_DIMENSION as usize * 4;
+
+/// Checks the size of an image to decode.
+fn check_dimensions(width: u32, height: u32) -> Result<(), ImageError> {
+    if width > MAX_IMAGE_DIMENSION || height > MAX_IMAGE_DIMENSION {
+        return Err(ImageError::TooLarge { width, height });
+    }
+
+    Ok(())
+}
+
+/// Computes the number of bytes of an image, failing instead of overflowing.
+fn texel_bytes(width: u32, height: u32, bytes_per_texel: usize) -> Result<usize, ImageError> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|texels| texels.checked_mul(bytes_per_texel))
+        .ok_or(ImageError::TooLarge { width, height })
+}
+
+fn png_error(error: png::DecodingError) -> ImageError {
+    match error {
+        // The data is read from memory, so I/O errors are caused by truncated images
+        png::DecodingError::IoError(e) => ImageError::Corrupt(e.to_string()),
+        png::DecodingError::Format(e) => ImageError::Corrupt(e.to_string()),
+        png::DecodingError::Parameter(e) => ImageError::Unsupported(e.to_string()),
+        png::DecodingError::LimitsExceeded => ImageError::Unsupported("PNG exceeds the decoding memory limit".to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes a single row PNG from packed samples, with an optional palette and transparency chunk.
+    fn png(width: u32, color: png::ColorType, depth: png::BitDepth, samples: &[u8], palette: &[u8], transparency: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut encoder = png::Encoder::new(&mut bytes, width, 1);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        if !palette.is_empty() {
+            encoder.set_palette(palette);
+        }
+        if !transparency.is_empty() {
+            encoder.set_trns(transparency);
+        }
+
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(samples).unwrap();
+        writer.finish().unwrap();
+
+        bytes
+    }
+
+    /// Byte offset of the first chunk after the signature, the image header.
+    const IHDR: usize = PNG_SIGNATURE.len();
+
+    /// Replaces the width of an image header and updates its checksum.
+    fn set_width(png: &mut [u8], width: u32) {
+        png[IHDR + 8..IHDR + 12].copy_from_slice(&width.to_be_bytes());
+
+        let crc = crc32(&png[IHDR + 4..IHDR + 21]);
+        png[IHDR + 21..IHDR + 25].copy_from_slice(&crc.to_be_bytes());
+    }
+
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for byte in bytes {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            }
+        }
+
+        !crc
+    }
+
+    #[test]
+    fn decodes_every_colour_type_and_bit_depth() {
+        use png::{BitDepth::*, ColorType::*};
+
+        const BLACK: [u8; 4] = [0, 0, 0, 255];
+        const GRAY: [u8; 4] = [85, 85, 85, 255];
+        const WHITE: [u8; 4] = [255, 255, 255, 255];
+        const PALETTE: [[u8; 4]; 3] = [[10, 20, 30, 255], [40, 50, 60, 128], [100, 110, 120, 255]];
+
+        // Colour type, bit depth, packed samples of three pixels and the expected texels
+        type Case = (png::ColorType, png::BitDepth, &'static [u8], [[u8; 4]; 3]);
+        let cases: &[Case] = &[
+            (Grayscale, One, &[0b0110_0000], [BLACK, WHITE, WHITE]),
+            (Grayscale, Two, &[0b0001_1100], [BLACK, GRAY, WHITE]),
+            (Grayscale, Four, &[0x05, 0xf0], [BLACK, GRAY, WHITE]),
+            (Grayscale, Eight, &[0, 85, 255], [BLACK, GRAY, WHITE]),
+            (Grayscale, Sixteen, &[0x00, 0xff, 0x55, 0xaa, 0xff, 0xff], [BLACK, GRAY, WHITE]),
+            (Indexed, One, &[0b0110_0000], [PALETTE[0], PALETTE[1], PALETTE[1]]),
+            (Indexed, Two, &[0b0001_1100], PALETTE),
+            (Indexed, Four, &[0x01, 0x30], PALETTE),
+            (Indexed, Eight, &[0, 1, 3], PALETTE),
+            (GrayscaleAlpha, Eight, &[0, 255, 85, 128, 255, 0], [BLACK, [85, 85, 85, 128], [255, 255, 255, 0]]),
+            (
+                GrayscaleAlpha,
+                Sixteen,
+                &[0x00, 0xff, 0xff, 0xff, 0x55, 0x00, 0x80, 0x00, 0xff, 0xff, 0x00, 0x00],
+                [BLACK, [85, 85, 85, 128], [255, 255, 255, 0]],
+            ),
+            (Rgb, Eight, &[1, 2, 3, 4, 5, 6, 7, 8, 9], [[1, 2, 3, 255], [4, 5, 6, 255], [7, 8, 9, 255]]),
+            (
+                Rgb,
+                Sixteen,
+                &[1, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6, 0, 7, 0, 8, 0, 9, 0xff],
+                [[1, 2, 3, 255], [4, 5, 6, 255], [7, 8, 9, 255]],
+            ),
+            (Rgba, Eight, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12], [[1, 2, 3, 4], [5, 6, 7, 8], [9, 10, 11, 12]]),
+            (
+                Rgba,
+                Sixteen,
+                &[1, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6, 0, 7, 0, 8, 0, 9, 0, 10, 0, 11, 0, 12, 0xff],
+                [[1, 2, 3, 4], [5, 6, 7, 8], [9, 10, 11, 12]],
+            ),
+        ];
+
+        // The fourth palette entry has no transparency entry and is opaque
+        let palette = [10, 20, 30, 40, 50, 60, 0, 0, 0, 100, 110, 120];
+        let transparency = [255, 128];
+
+        for (color, depth, samples, expected) in cases {
+            let (palette, transparency): (&[u8], &[u8]) = match color {
+                Indexed => (&palette, &transparency),
+                _ => (&[], &[]),
+            };
+
+            let image = Image::decode(&png(3, *color, *depth, samples, palette, transparency))
+                .unwrap_or_else(|e| panic!("{color:?} with bit depth {depth:?}: {e}"));
+
+            assert_eq!((image.width, image.height, image.layers), (3, 1, 1));
+            assert_eq!(image.data, expected.concat(), "{color:?} with bit depth {depth:?}");
+        }
+    }
+
+    #[test]
+    fn transparency_keys_grayscale_and_rgb_colours() {
+        let gray = png(2, png::ColorType::Grayscale, png::BitDepth::Eight, &[7, 8], &[], &[0, 7]);
+        assert_eq!(Image::decode(&gray).unwrap().data, [7, 7, 7, 0, 8, 8, 8, 255]);
+
+        let rgb = png(
+            2,
+            png::ColorType::Rgb,
+            png::BitDepth::Sixteen,
+            &[1, 0, 2, 0, 3, 0, 1, 0, 2, 0, 4, 0],
+            &[],
+            &[1, 0, 2, 0, 3, 0],
+        );
+        assert_eq!(Image::decode(&rgb).unwrap().data, [1, 2, 3, 0, 1, 2, 4, 255]);
+    }
+
+    #[test]
+    fn rejects_invalid_checksums() {
+        let mut image = png(1, png::ColorType::Rgba, png::BitDepth::Eight, &[1, 2, 3, 4], &[], &[]);
+        // The last byte belongs to the checksum of the image data
+        let idat = image.len() - 12 - 1;
+        image[idat] ^= 0xff;
+
+        assert!(matches!(Image::decode(&image), Err(ImageError::Corrupt(_))));
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        let image = png(4, png::ColorType::Rgba, png::BitDepth::Eight, &[7; 16], &[], &[]);
+
+        for len in [PNG_SIGNATURE.len(), IHDR + 12, image.len() - 12 - 8] {
+            assert!(matches!(Image::decode(&image[..len]), Err(ImageError::Corrupt(_))), "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_image_data() {
+        // The header declares more texels than the image data contains
+        let mut image = png(1, png::ColorType::Rgba, png::BitDepth::Eight, &[1, 2, 3, 4], &[], &[]);
+        set_width(&mut image, 64);
+
+        assert!(matches!(Image::decode(&image), Err(ImageError::Corrupt(_))));
+    }
+
+    #[test]
+    fn rejects_oversized_images() {
+        let mut wide = png(1, png::ColorType::Grayscale, png::BitDepth::Eight, &[0], &[], &[]);
+        set_width(&mut wide, MAX_IMAGE_DIMENSION + 1);
+        assert!(matches!(Image::decode(&wide), Err(ImageError::TooLarge { .. })));
+
+        assert!(matches!(Image::from_rgba8(u32::MAX, u32::MAX, vec![]), Err(ImageError::TooLarge { .. })));
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        assert!(matches!(Image::decode(b"P6 1 1 255 \x01\x02\x03"), Err(ImageError::UnknownFormat)));
+        assert!(matches!(Image::decode(&[]), Err(ImageError::UnknownFormat)));
+    }
+
+    #[test]
+    fn stacks_layers_of_equal_size() {
+        let red = Image::from_rgba8(1, 1, vec![255, 0, 0, 255]).unwrap();
+        let green = Image::from_rgba8(1, 1, vec![0, 255, 0, 255]).unwrap();
+
+        let array = Image::array(&[red.clone(), green]).unwrap();
+        assert_eq!(array.layers, 2);
+        assert_eq!(array.data, [255, 0, 0, 255, 0, 255, 0, 255]);
+
+        let wide = Image::from_rgba8(2, 1, vec![0; 8]).unwrap();
+        assert!(matches!(Image::array(&[red, wide]), Err(ImageError::LayerSizeMismatch { .. })));
+    }
+}
@@ -0,0 +1,204 @@
+use std::cell::Cell;
+use std::sync::Arc;
+
+use bytemuck::Pod;
+
+use crate::renderer::{error::RenderError, Renderer};
+
+use super::{pipeline::Shader, shader::push_constants_to_uniforms, staging::UploadTarget};
+
+/// Maximum size in bytes of `InstanceData::uniform_data`.
+pub const MAX_INSTANCE_DATA_SIZE: u32 = 128;
+
+/// Maximum number of instance data uploads per submitted `DrawContext`
+/// when push constants are not supported.
+pub const MAX_INSTANCES_PER_FRAME: u32 = 4096;
+
+/// Fallback for adapters without push constants.
+///
+/// Instance data is written into slots of a single uniform buffer, which is bound
+/// as the last bind group of every pipeline with a dynamic offset pointing to the slot.
+/// The first slot is always zeroed and bound when a command has no instance data.
+#[derive(Debug)]
+pub(crate) struct InstanceUniforms {
+    buffer: Arc<wgpu::Buffer>,
+    pub(crate) bind_group_layout: wgpu::BindGroupLayout,
+    pub(crate) bind_group: wgpu::BindGroup,
+    slot_size: u32,
+    cursor: Cell<u32>,
+}
+
+impl InstanceUniforms {
+    /// Creates the instance data buffer and its bind group.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The device to create resources on.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `InstanceUniforms`.
+    pub(crate) fn new(device: &wgpu::Device) -> InstanceUniforms {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment;
+        let slot_size = MAX_INSTANCE_DATA_SIZE.div_ceil(alignment) * alignment;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance data buffer"),
+            size: slot_size as u64 * MAX_INSTANCES_PER_FRAME as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Instance data bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(MAX_INSTANCE_DATA_SIZE as u64),
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Instance data bind group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(MAX_INSTANCE_DATA_SIZE as u64),
+                }),
+            }],
+        });
+
+        InstanceUniforms {
+            buffer: Arc::new(buffer),
+            bind_group_layout,
+            bind_group,
+            slot_size,
+            cursor: Cell::new(1),
+        }
+    }
+
+    /// Writes instance data into the next free slot. The data is staged with the
+    /// uploads of the frame, regardless of the upload budget.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer staging the upload.
+    /// * `data` - The instance data.
+    ///
+    /// # Returns
+    ///
+    /// The dynamic offset of the slot.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RenderError::BufferOverflow` if all slots of the frame are used.
+    pub(crate) fn push<T: Pod>(&self, renderer: &Renderer, data: &T) -> Result<u32, RenderError> {
+        let slot = self.cursor.get();
+        if slot >= MAX_INSTANCES_PER_FRAME {
+            return Err(RenderError::BufferOverflow(MAX_INSTANCES_PER_FRAME as usize));
+        }
+
+        let offset = slot * self.slot_size;
+        renderer.stage_upload(
+            UploadTarget::Buffer { buffer: self.buffer.clone(), offset: offset as u64 },
+            bytemuck::bytes_of(data).to_vec(),
+            false,
+        );
+        self.cursor.set(slot + 1);
+
+        Ok(offset)
+    }
+
+    /// Frees all slots. Called after the commands using them are submitted.
+    pub(crate) fn reset(&self) {
+        self.cursor.set(1);
+    }
+
+    /// Rewrites push constants of a WGSL shader into the instance data uniform.
+    ///
+    /// # Arguments
+    ///
+    /// * `shader` - The shader to rewrite.
+    /// * `group` - The index of the instance data bind group.
+    ///
+    /// # Returns
+    ///
+    /// The shader reading instance data from the uniform buffer.
+    pub(crate) fn rewrite_shader(shader: Shader, group: u32) -> Shader {
+        let source = match shader.source {
+            wgpu::ShaderSource::Wgsl(source) => wgpu::ShaderSource::Wgsl(
+                push_constants_to_uniforms(&source, group).into()
+            ),
+            source => source,
+        };
+
+        Shader { label: shader.label, source }
+    }
+}
+
+/// Checks that instance data fits into push constants or a uniform slot.
+///
+/// # Errors
+///
+/// Returns `RenderError::BufferOverflow` if the data exceeds `MAX_INSTANCE_DATA_SIZE`.
+pub(crate) fn check_instance_data_size<T: Pod>() -> Result<(), RenderError> {
+    if std::mem::size_of::<T>() > MAX_INSTANCE_DATA_SIZE as usize {
+        return Err(RenderError::BufferOverflow(MAX_INSTANCE_DATA_SIZE as usize));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::hal::reflection::{BindingKind, ShaderReflection};
+
+    const SHADER: &str = "
+        struct InstanceData { model: mat4x4<f32> }
+
+        // Declared with var<push_constant> when supported
+        var < push_constant >
+            instance: InstanceData;
+
+        @vertex
+        fn vs_main(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+            /* not a var<push_constant> either */
+            return instance.model * vec4<f32>(position, 1.0);
+        }
+    ";
+
+    #[test]
+    fn push_constants_are_rewritten_into_uniforms() {
+        let shader = InstanceUniforms::rewrite_shader(
+            Shader { label: Some("test"), source: wgpu::ShaderSource::Wgsl(SHADER.into()) },
+            2,
+        );
+
+        let wgpu::ShaderSource::Wgsl(source) = &shader.source else {
+            panic!("Rewritten shader is not WGSL");
+        };
+        assert_eq!(source.matches("var<push_constant>").count(), 2, "comments are rewritten");
+
+        let reflection = ShaderReflection::new(&shader).unwrap();
+        assert!(!reflection.uses_push_constants);
+        assert_eq!(reflection.bindings.len(), 1);
+        assert_eq!((reflection.bindings[0].group, reflection.bindings[0].binding), (2, 0));
+        assert_eq!(reflection.bindings[0].kind, BindingKind::UniformBuffer);
+    }
+
+    #[test]
+    fn shaders_without_push_constants_are_unchanged() {
+        let source = "var<private> counter: u32; // var<push_constant>";
+
+        assert_eq!(push_constants_to_uniforms(source, 0), source);
+    }
+}
@@ -3,7 +3,14 @@ use bytemuck::{Pod, Zeroable};
 pub mod pipeline;
 pub mod buffer;
 pub mod texture;
+pub mod image;
 pub mod taa;
+pub mod denoiser;
+pub mod instance;
+pub mod reflection;
+pub mod shader;
+pub mod allocator;
+pub mod staging;
 
 /// A structure used for padding to align data to specific byte boundaries.
 /// 
@@ -1,4 +1,4 @@
-use crate::renderer::{pbr::mesh::Vertex, Renderer};
+use crate::renderer::{error::RenderError, pbr::mesh::Vertex, Renderer};
 
 #[macro_export]
 macro_rules! include_wgsl {
@@ -15,20 +15,24 @@ macro_rules! include_wgsl {
     };
 }
 
+use std::num::NonZeroU64;
+use std::path::Path;
+use std::sync::Arc;
+
 use bytemuck::Pod;
 pub use include_wgsl;
 
-use super::{buffer::{Buffer, BufferResourceDescriptor}, texture::{Texture, TextureResourceDescriptor, TextureResourceUsage}};
+use super::{buffer::{Buffer, BufferResourceDescriptor}, instance::{InstanceUniforms, MAX_INSTANCE_DATA_SIZE}, reflection::ShaderReflection, shader::{load_wgsl, tokenize, ShaderError, Token, TokenKind}, texture::{Texture, TextureResourceDescriptor, TextureResourceUsage}};
 
 pub type Shader = wgpu::ShaderModuleDescriptor<'static>;
 
-pub struct ShaderResourceBuilder<'a> {
+pub struct ShaderResourceBuilder {
     label: Option<String>,
     bind_group_layout_entries: Vec<wgpu::BindGroupLayoutEntry>,
-    bind_group_entries: Vec<wgpu::BindGroupEntry<'a>>,
+    resources: Vec<BoundResource>,
 }
 
-impl<'a> ShaderResourceBuilder<'a> {
+impl ShaderResourceBuilder {
     pub fn set_label(&mut self, label: String) -> &mut Self {
         self.label = Some(label);
         self
@@ -36,38 +40,22 @@ impl<'a> ShaderResourceBuilder<'a> {
 
     pub fn add_buffer<T: Pod>(
         &mut self,
-        buffer: &'a Buffer<T>,
+        buffer: &Buffer<T>,
         descriptor: &BufferResourceDescriptor,
     ) -> &mut Self {
-        self.bind_group_layout_entries.push(wgpu::BindGroupLayoutEntry {
-            binding: self.bind_group_layout_entries.len() as u32,
-            visibility: descriptor.visibility,
-            ty: wgpu::BindingType::Buffer {
-                ty: descriptor.buffer_type,
-                has_dynamic_offset: false,
-                min_binding_size: None,
-            },
-            count: None,
-        });
+        self.bind_group_layout_entries.push(descriptor.layout_entry(self.bind_group_layout_entries.len() as u32));
 
-        self.bind_group_entries.push(wgpu::BindGroupEntry {
-            binding: self.bind_group_entries.len() as u32,
-            resource: buffer.inner().as_entire_binding(),
-        });
+        self.resources.push(buffer.bound_resource());
 
         self
     }
 
     pub fn add_texture(
         &mut self,
-        texture: &'a Texture,
+        texture: &Texture,
         descriptor: &TextureResourceDescriptor,
     ) -> &mut Self {
-        let view_dimension = match texture.description().dimension {
-            wgpu::TextureDimension::D1 => wgpu::TextureViewDimension::D1,
-            wgpu::TextureDimension::D2 => wgpu::TextureViewDimension::D2,
-            wgpu::TextureDimension::D3 => wgpu::TextureViewDimension::D3,
-        };
+        let view_dimension = texture.description().view_dimension();
 
         let bind_group_layout_entries = descriptor.usage
             .iter()
@@ -101,7 +89,7 @@ impl<'a> ShaderResourceBuilder<'a> {
                             binding: (self.bind_group_layout_entries.len() + i) as u32,
                             visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
                             ty: wgpu::BindingType::StorageTexture {
-                                access: wgpu::StorageTextureAccess::WriteOnly,
+                                access: descriptor.storage_access.unwrap_or(wgpu::StorageTextureAccess::WriteOnly),
                                 format: texture.description().format,
                                 view_dimension,
                             },
@@ -113,145 +101,534 @@ impl<'a> ShaderResourceBuilder<'a> {
             })
             .collect::<Vec<_>>();
 
-        let bind_group_entries = descriptor.usage
-            .iter()
-            .enumerate()
-            .filter_map(|(i, usage)| {
-                match usage {
-                    TextureResourceUsage::STORAGE | TextureResourceUsage::TEXTURE => {
-                        Some(wgpu::BindGroupEntry {
-                            binding: (self.bind_group_entries.len() + i) as u32,
-                            resource: wgpu::BindingResource::TextureView(texture.view())
-                        },)
-                    },
-                    TextureResourceUsage::SAMPLER => {
-                        Some(wgpu::BindGroupEntry {
-                            binding: (self.bind_group_entries.len() + i) as u32,
-                            resource: wgpu::BindingResource::Sampler(texture.sampler()),
-                        })
-                    },
-                    _ => None,
-                }
-            })
-            .collect::<Vec<_>>();
-
+        self.resources.extend(bind_group_layout_entries.iter().map(|_| texture.bound_resource()));
         self.bind_group_layout_entries.extend(bind_group_layout_entries);
-        self.bind_group_entries.extend(bind_group_entries);
 
         self
     }
 
     pub fn build(&self, renderer: &Renderer) -> ShaderResource {
-        let bind_group_layout = renderer.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: self.label
-                .as_ref()
-                .map(|label| format!("{label} bind group layout"))
-                .as_deref(),
-            entries: &self.bind_group_layout_entries,
-        });
+        let mut shader_resource = ShaderResource::new(
+            renderer, 
+            self.label.clone(), 
+            self.bind_group_layout_entries.clone(), 
+            vec![vec![]; self.bind_group_layout_entries.len()],
+        );
 
-        let bind_group = renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: self.label
-                .as_ref()
-                .map(|label| format!("{label} bind group"))
-                .as_deref(),
-            layout: &bind_group_layout,
-            entries: &self.bind_group_entries,
-        });
+        shader_resource.resources = self.resources.iter().cloned().map(Some).collect();
+        shader_resource.update(renderer)
+            .expect("All resources of the builder are bound");
 
-        ShaderResource { bind_group_layout, bind_group }
+        shader_resource
     }
 }
 
+/// A GPU resource bound to a [`ShaderResource`], shared with the buffer or texture owning it.
+#[derive(Debug, Clone)]
+pub enum BoundResource {
+    Buffer(Arc<wgpu::Buffer>),
+    BufferRange {
+        buffer: Arc<wgpu::Buffer>,
+        offset: u64,
+        size: Option<NonZeroU64>,
+    },
+    Texture {
+        view: Arc<wgpu::TextureView>,
+        /// A view of a single mip level, bound to storage texture bindings.
+        storage_view: Arc<wgpu::TextureView>,
+        sampler: Arc<wgpu::Sampler>,
+    },
+}
+
+/// A resource which can be bound to a [`ShaderResource`] by name.
+pub trait ShaderBinding {
+    /// Retrieves the GPU resource to bind. Textures are bound as views to texture
+    /// bindings and as samplers to sampler bindings.
+    fn bound_resource(&self) -> BoundResource;
+}
+
+/// A bind group with its layout.
+///
+/// Resources are bound either sequentially with [`ShaderResourceBuilder`], or by the
+/// names of variables in the shaders, if the layout is derived from shader reflection
+/// with [`ShaderResource::from_reflection`].
 #[derive(Debug)]
 pub struct ShaderResource {
     pub(crate) bind_group_layout: wgpu::BindGroupLayout,
-    pub(crate) bind_group: wgpu::BindGroup,
+    pub(crate) bind_group: Option<wgpu::BindGroup>,
+    pub(crate) layout_entries: Vec<wgpu::BindGroupLayoutEntry>,
+    label: Option<String>,
+    names: Vec<Vec<String>>,
+    resources: Vec<Option<BoundResource>>,
+    dirty: bool,
 }
 
 impl ShaderResource {
-    pub fn builder<'a>() -> ShaderResourceBuilder<'a> {
+    /// Retrieves the entries of the bind group layout, in order of their binding indices.
+    pub fn layout_entries(&self) -> &[wgpu::BindGroupLayoutEntry] {
+        &self.layout_entries
+    }
+
+    pub fn builder() -> ShaderResourceBuilder {
         ShaderResourceBuilder {
             bind_group_layout_entries: vec![],
-            bind_group_entries: vec![],
+            resources: vec![],
             label: None,
         }
     }
+
+    /// Creates a shader resource with the layout of a bind group declared in shaders.
+    ///
+    /// The layout entries are merged from every shader using the group: a binding is visible
+    /// to all stages using it, and storage buffers are writable if any shader writes them.
+    /// Resources are then bound with [`ShaderResource::set`] and [`ShaderResource::update`].
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `reflections` - The reflected shaders of all pipelines using the bind group.
+    /// * `group` - The index of the bind group in the shaders.
+    /// * `label` - The debug label of the bind group.
+    ///
+    /// # Returns
+    ///
+    /// A `ShaderResource` without bound resources.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if shaders declare incompatible types for the same binding.
+    pub fn from_reflection(
+        renderer: &Renderer,
+        reflections: &[&ShaderReflection],
+        group: u32,
+        label: &str,
+    ) -> Result<ShaderResource, ShaderError> {
+        let (layout_entries, names) = ShaderResource::merge_reflections(reflections, group)?;
+
+        Ok(ShaderResource::new(renderer, Some(label.to_owned()), layout_entries, names))
+    }
+
+    /// Merges the layout entries of a bind group declared in shaders, as
+    /// [`ShaderResource::from_reflection`] does, e.g. to validate shaders without a GPU device.
+    ///
+    /// # Arguments
+    ///
+    /// * `reflections` - The reflected shaders of all pipelines using the bind group.
+    /// * `group` - The index of the bind group in the shaders.
+    ///
+    /// # Returns
+    ///
+    /// The layout entries, in order of their binding indices.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if shaders declare incompatible types for the same binding.
+    pub fn reflected_layout_entries(
+        reflections: &[&ShaderReflection],
+        group: u32,
+    ) -> Result<Vec<wgpu::BindGroupLayoutEntry>, ShaderError> {
+        ShaderResource::merge_reflections(reflections, group).map(|(layout_entries, _)| layout_entries)
+    }
+
+    /// Merges the layout entries of a bind group with the names of the variables of each binding.
+    fn merge_reflections(
+        reflections: &[&ShaderReflection],
+        group: u32,
+    ) -> Result<(Vec<wgpu::BindGroupLayoutEntry>, Vec<Vec<String>>), ShaderError> {
+        let mut entries = Vec::<(wgpu::BindGroupLayoutEntry, Vec<String>)>::new();
+
+        let bindings = reflections
+            .iter()
+            .flat_map(|reflection| &reflection.bindings)
+            .filter(|binding| binding.group == group);
+
+        for binding in bindings {
+            let ty = binding.kind.binding_type();
+            let name = binding.undecorated_name().map(str::to_owned);
+
+            let Some((entry, names)) = entries.iter_mut().find(|(e, _)| e.binding == binding.binding) else {
+                entries.push((
+                    wgpu::BindGroupLayoutEntry {
+                        binding: binding.binding,
+                        visibility: binding.visibility,
+                        ty,
+                        count: None,
+                    },
+                    name.into_iter().collect(),
+                ));
+                continue;
+            };
+
+            entry.ty = match (entry.ty, ty) {
+                (
+                    wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Storage { read_only: a }, .. },
+                    wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Storage { read_only: b }, .. },
+                ) => wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: a && b },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                (a, b) if a == b => a,
+                (a, _) => return Err(ShaderError::ConflictingBinding(binding.clone(), a)),
+            };
+            entry.visibility |= binding.visibility;
+
+            if let Some(name) = name.filter(|name| !names.contains(name)) {
+                names.push(name);
+            }
+        }
+
+        entries.sort_by_key(|(entry, _)| entry.binding);
+
+        Ok(entries.into_iter().unzip())
+    }
+
+    /// Binds a resource to the binding with the given variable name.
+    /// The bind group is recreated on the next [`ShaderResource::update`].
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the variable in the shaders.
+    /// * `resource` - The buffer or texture to bind.
+    ///
+    /// # Returns
+    ///
+    /// The shader resource, so calls can be chained.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no binding with the name, or the resource is of another type.
+    pub fn set(&mut self, name: &str, resource: &impl ShaderBinding) -> Result<&mut Self, RenderError> {
+        let index = self.names
+            .iter()
+            .position(|names| names.iter().any(|n| n == name))
+            .ok_or_else(|| RenderError::UnknownBinding(name.to_owned()))?;
+
+        let resource = resource.bound_resource();
+        let is_buffer = matches!(self.layout_entries[index].ty, wgpu::BindingType::Buffer { .. });
+
+        if is_buffer != matches!(resource, BoundResource::Buffer(_) | BoundResource::BufferRange { .. }) {
+            return Err(RenderError::BindingTypeMismatch(name.to_owned()));
+        }
+
+        self.resources[index] = Some(resource);
+        self.dirty = true;
+
+        Ok(self)
+    }
+
+    /// Recreates the bind group if resources have been set since the last update,
+    /// keeping the bind group layout, so pipelines don't need to be recreated.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a binding has no resource set.
+    pub fn update(&mut self, renderer: &Renderer) -> Result<(), RenderError> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let entries = self.layout_entries
+            .iter()
+            .zip(&self.resources)
+            .map(|(entry, resource)| {
+                let resource = match (resource, entry.ty) {
+                    (None, _) => return Err(RenderError::UnboundResource(self.binding_name(entry.binding))),
+                    (Some(BoundResource::Buffer(buffer)), _) => buffer.as_entire_binding(),
+                    (Some(BoundResource::BufferRange { buffer, offset, size }), _) => {
+                        wgpu::BindingResource::Buffer(wgpu::BufferBinding { buffer, offset: *offset, size: *size })
+                    },
+                    (Some(BoundResource::Texture { sampler, .. }), wgpu::BindingType::Sampler(_)) => {
+                        wgpu::BindingResource::Sampler(sampler)
+                    },
+                    (Some(BoundResource::Texture { storage_view, .. }), wgpu::BindingType::StorageTexture { .. }) => {
+                        wgpu::BindingResource::TextureView(storage_view)
+                    },
+                    (Some(BoundResource::Texture { view, .. }), _) => wgpu::BindingResource::TextureView(view),
+                };
+
+                Ok(wgpu::BindGroupEntry { binding: entry.binding, resource })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.bind_group = Some(renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: self.label
+                .as_ref()
+                .map(|label| format!("{label} bind group"))
+                .as_deref(),
+            layout: &self.bind_group_layout,
+            entries: &entries,
+        }));
+        self.dirty = false;
+
+        Ok(())
+    }
+
+    pub(crate) fn bind_group(&self) -> Result<&wgpu::BindGroup, RenderError> {
+        self.bind_group
+            .as_ref()
+            .ok_or_else(|| RenderError::UnboundResource(self.label.clone().unwrap_or_default()))
+    }
+
+    pub(crate) fn new(
+        renderer: &Renderer,
+        label: Option<String>,
+        layout_entries: Vec<wgpu::BindGroupLayoutEntry>,
+        names: Vec<Vec<String>>,
+    ) -> ShaderResource {
+        let bind_group_layout = renderer.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: label
+                .as_ref()
+                .map(|label| format!("{label} bind group layout"))
+                .as_deref(),
+            entries: &layout_entries,
+        });
+
+        ShaderResource {
+            bind_group_layout,
+            bind_group: None,
+            resources: vec![None; layout_entries.len()],
+            layout_entries,
+            label,
+            names,
+            dirty: true,
+        }
+    }
+
+    fn binding_name(&self, binding: u32) -> String {
+        self.layout_entries
+            .iter()
+            .position(|e| e.binding == binding)
+            .and_then(|i| self.names[i].first().cloned())
+            .unwrap_or_else(|| format!("@binding({binding})"))
+    }
 }
 
-pub trait ShaderBinding {
-    
+/// A colour target of a render pipeline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorTarget {
+    /// Format of the target texture; `None` for the surface format.
+    pub format: Option<wgpu::TextureFormat>,
+    /// Blending of the fragment output with the target; `None` to overwrite it.
+    pub blend: Option<wgpu::BlendState>,
+    /// Colour channels written to the target.
+    pub write_mask: wgpu::ColorWrites,
 }
 
-pub enum Pipeline {
-    Render(wgpu::RenderPipeline),
-    Compute(wgpu::ComputePipeline),
+impl Default for ColorTarget {
+    fn default() -> Self {
+        ColorTarget {
+            format: None,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::ALL,
+        }
+    }
 }
 
-impl Pipeline {
-    pub fn new_render(
-        renderer: &Renderer,
-        shader: Shader,  
-        bindings: &[&ShaderResource],
-        label: &str,
-        use_vertices: bool,
-    ) -> Pipeline {
-        let shader = renderer.device.create_shader_module(shader);
+/// Depth testing of a render pipeline against the renderer's `Depth32Float` depth texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthState {
+    /// Comparison of the fragment depth with the stored depth.
+    pub compare: wgpu::CompareFunction,
+    /// Whether passing fragments write their depth.
+    pub write: bool,
+}
 
-        let layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some(format!("{label} Pipeline Layout").as_str()),
-            bind_group_layouts: &bindings
-                .to_vec()
-                .iter()
-                .map(|b| &b.bind_group_layout)
-                .collect::<Vec<_>>(),
-            push_constant_ranges: &[wgpu::PushConstantRange {
-                stages: wgpu::ShaderStages::VERTEX,
-                range: 0..128,
-            }],
-        });
+impl Default for DepthState {
+    fn default() -> Self {
+        DepthState {
+            compare: wgpu::CompareFunction::Less,
+            write: true,
+        }
+    }
+}
+
+/// Builder of a render [`Pipeline`].
+pub struct PipelineBuilder<'a> {
+    shader: Shader,
+    label: Option<String>,
+    bindings: Vec<&'a ShaderResource>,
+    vertex_entry_point: String,
+    fragment_entry_point: Option<String>,
+    vertex_layouts: Vec<wgpu::VertexBufferLayout<'a>>,
+    color_targets: Vec<ColorTarget>,
+    topology: wgpu::PrimitiveTopology,
+    front_face: wgpu::FrontFace,
+    cull_mode: Option<wgpu::Face>,
+    polygon_mode: wgpu::PolygonMode,
+    depth: Option<DepthState>,
+    sample_count: Option<u32>,
+    constants: Vec<(String, f64)>,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    pub fn set_label(&mut self, label: String) -> &mut Self {
+        self.label = Some(label);
+        self
+    }
+
+    /// Adds bind groups after the already added ones.
+    pub fn add_bindings(&mut self, bindings: &[&'a ShaderResource]) -> &mut Self {
+        self.bindings.extend_from_slice(bindings);
+        self
+    }
+
+    pub fn set_vertex_entry_point(&mut self, entry_point: &str) -> &mut Self {
+        self.vertex_entry_point = entry_point.to_owned();
+        self
+    }
+
+    /// Sets the fragment entry point; `None` creates a depth-only pipeline.
+    pub fn set_fragment_entry_point(&mut self, entry_point: Option<&str>) -> &mut Self {
+        self.fragment_entry_point = entry_point.map(str::to_owned);
+        self
+    }
+
+    /// Adds a vertex buffer layout, e.g. [`Vertex::vertex_buffer_layout`], for the next vertex buffer slot.
+    pub fn add_vertex_layout(&mut self, layout: wgpu::VertexBufferLayout<'a>) -> &mut Self {
+        self.vertex_layouts.push(layout);
+        self
+    }
+
+    /// Adds a colour target for the next fragment output location.
+    /// Without added targets, the pipeline renders into a single surface format target.
+    pub fn add_color_target(&mut self, target: ColorTarget) -> &mut Self {
+        self.color_targets.push(target);
+        self
+    }
+
+    pub fn set_topology(&mut self, topology: wgpu::PrimitiveTopology) -> &mut Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn set_front_face(&mut self, front_face: wgpu::FrontFace) -> &mut Self {
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn set_cull_mode(&mut self, cull_mode: Option<wgpu::Face>) -> &mut Self {
+        self.cull_mode = cull_mode;
+        self
+    }
 
-        let buffers = if use_vertices {
-            vec![Vertex::vertex_buffer_layout()]
+    pub fn set_polygon_mode(&mut self, polygon_mode: wgpu::PolygonMode) -> &mut Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    /// Sets depth testing; `None` disables it, for passes without a depth texture.
+    pub fn set_depth(&mut self, depth: Option<DepthState>) -> &mut Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Overrides the sample count of the renderer, e.g. `1` for G-buffer passes.
+    pub fn set_sample_count(&mut self, sample_count: u32) -> &mut Self {
+        self.sample_count = Some(sample_count);
+        self
+    }
+
+    /// Sets the value of a WGSL `override` constant.
+    ///
+    /// The shader source is specialized before compilation: `override` declarations
+    /// become `const` ones with the given value, or with their default value if none is set.
+    /// Building fails if an `override` has neither.
+    pub fn set_constant(&mut self, name: &str, value: f64) -> &mut Self {
+        self.constants.retain(|(n, _)| n != name);
+        self.constants.push((name.to_owned(), value));
+        self
+    }
+
+    fn specialized_shader(&self) -> Result<Shader, ShaderError> {
+        let wgpu::ShaderSource::Wgsl(source) = &self.shader.source else {
+            return Err(ShaderError::UnsupportedSource);
+        };
+
+        Ok(Shader {
+            label: self.shader.label,
+            source: wgpu::ShaderSource::Wgsl(specialize(source, &self.constants)?.into()),
+        })
+    }
+
+    /// Builds the pipeline, reporting invalid shaders and layouts instead of panicking,
+    /// e.g. when reloading a shader loaded with [`load_wgsl`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shader is not WGSL, an `override` constant is unresolved
+    /// or the pipeline does not validate.
+    pub fn try_build(&self, renderer: &Renderer) -> Result<Pipeline, ShaderError> {
+        ShaderReflection::new(&self.specialized_shader()?)?.check(&self.bindings)?;
+
+        Pipeline::catch_errors(renderer, self.label.as_deref().unwrap_or("Render"), || self.build(renderer))
+    }
+
+    /// Builds the pipeline.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the shader is not WGSL, an `override` constant is unresolved, the bindings
+    /// of the shader do not match the added bind groups or the pipeline does not validate.
+    pub fn build(&self, renderer: &Renderer) -> Pipeline {
+        let label = self.label.as_deref().unwrap_or("Render");
+        let shader = self
+            .specialized_shader()
+            .unwrap_or_else(|e| panic!("Cannot specialize shader of pipeline `{label}`: {e}"));
+        Pipeline::assert_bindings(&shader, &self.bindings, label);
+
+        let (shader, layout) = Pipeline::create_layout(renderer, shader, &self.bindings, label, wgpu::ShaderStages::VERTEX);
+
+        let targets = if self.color_targets.is_empty() {
+            vec![ColorTarget::default()]
         } else {
-            vec![]
+            self.color_targets.clone()
         };
 
+        let targets = targets
+            .into_iter()
+            .map(|target| Some(wgpu::ColorTargetState {
+                format: target.format.unwrap_or(renderer.surface_format()),
+                blend: target.blend,
+                write_mask: target.write_mask,
+            }))
+            .collect::<Vec<_>>();
+
         let pipeline = renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some(format!("{label} Pipeline").as_str()),
             layout: Some(&layout),
             vertex: wgpu::VertexState {
                 module: &shader,
-                entry_point: "vs_main", 
-                buffers: &buffers,
+                entry_point: &self.vertex_entry_point, 
+                buffers: &self.vertex_layouts,
             },
-            fragment: Some(wgpu::FragmentState {
+            fragment: self.fragment_entry_point.as_ref().map(|entry_point| wgpu::FragmentState {
                 module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: renderer.config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
+                entry_point,
+                targets: &targets,
             }),
             primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, 
+                topology: self.topology, 
                 strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw, 
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
+                front_face: self.front_face, 
+                cull_mode: self.cull_mode,
+                polygon_mode: self.polygon_mode,
                 unclipped_depth: false,
                 conservative: false,
             },
-            depth_stencil: Some(wgpu::DepthStencilState {
+            depth_stencil: self.depth.map(|depth| wgpu::DepthStencilState {
                 format: wgpu::TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
+                depth_write_enabled: depth.write,
+                depth_compare: depth.compare,
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
             }),
             multisample: wgpu::MultisampleState {
-                count: 1, 
+                count: self.sample_count.unwrap_or(renderer.sample_count()), 
                 mask: !0, 
                 alpha_to_coverage_enabled: false, 
             },
@@ -260,6 +637,74 @@ impl Pipeline {
 
         Pipeline::Render(pipeline)
     }
+}
+
+pub enum Pipeline {
+    Render(wgpu::RenderPipeline),
+    Compute(wgpu::ComputePipeline),
+}
+
+impl Pipeline {
+    /// Creates a render pipeline with the default state of [`PipelineBuilder`]:
+    /// `vs_main`/`fs_main` entry points, a single surface format target and depth testing.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `shader` - The shader with both entry points.
+    /// * `bindings` - The bind groups, in order of their group indices.
+    /// * `label` - The debug label of the pipeline.
+    /// * `use_vertices` - Whether the pipeline reads the `Vertex` layout from a vertex buffer.
+    ///
+    /// # Returns
+    ///
+    /// A new render `Pipeline`.
+    pub fn new_render(
+        renderer: &Renderer,
+        shader: Shader,  
+        bindings: &[&ShaderResource],
+        label: &str,
+        use_vertices: bool,
+    ) -> Pipeline {
+        let mut builder = Pipeline::builder(shader);
+        builder
+            .set_label(label.to_owned())
+            .add_bindings(bindings);
+
+        if use_vertices {
+            builder.add_vertex_layout(Vertex::vertex_buffer_layout());
+        }
+
+        builder.build(renderer)
+    }
+
+    /// Creates a builder of a render pipeline.
+    ///
+    /// # Arguments
+    ///
+    /// * `shader` - The shader containing the vertex and fragment entry points.
+    ///
+    /// # Returns
+    ///
+    /// A `PipelineBuilder` with the same defaults as [`Pipeline::new_render`].
+    pub fn builder<'a>(shader: Shader) -> PipelineBuilder<'a> {
+        PipelineBuilder {
+            shader,
+            label: None,
+            bindings: vec![],
+            vertex_entry_point: "vs_main".to_owned(),
+            fragment_entry_point: Some("fs_main".to_owned()),
+            vertex_layouts: vec![],
+            color_targets: vec![],
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            depth: Some(DepthState::default()),
+            sample_count: None,
+            constants: vec![],
+        }
+    }
 
     pub fn new_compute(
         renderer: &Renderer,
@@ -267,20 +712,9 @@ impl Pipeline {
         bindings: &[&ShaderResource],
         label: &str,
     ) -> Pipeline {
-        let shader = renderer.device.create_shader_module(shader);
+        Pipeline::assert_bindings(&shader, bindings, label);
 
-        let layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some(format!("{label} Pipeline Layout").as_str()),
-            bind_group_layouts: &bindings
-                .to_vec()
-                .iter()
-                .map(|b| &b.bind_group_layout)
-                .collect::<Vec<_>>(),
-            push_constant_ranges: &[wgpu::PushConstantRange {
-                stages: wgpu::ShaderStages::COMPUTE,
-                range: 0..128,
-            }],
-        });
+        let (shader, layout) = Pipeline::create_layout(renderer, shader, bindings, label, wgpu::ShaderStages::COMPUTE);
 
         let pipeline = renderer.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
             label: Some(format!("{label} Pipeline layout").as_str()),
@@ -291,4 +725,253 @@ impl Pipeline {
 
         Pipeline::Compute(pipeline)
     }
-}
\ No newline at end of file
+
+    /// Creates a compute pipeline, reporting invalid shaders and layouts instead of panicking.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `shader` - The shader with the `cs_main` entry point.
+    /// * `bindings` - The bind groups, in order of their group indices.
+    /// * `label` - The debug label of the pipeline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pipeline does not validate.
+    pub fn try_new_compute(
+        renderer: &Renderer,
+        shader: Shader,  
+        bindings: &[&ShaderResource],
+        label: &str,
+    ) -> Result<Pipeline, ShaderError> {
+        ShaderReflection::new(&shader)?.check(bindings)?;
+
+        Pipeline::catch_errors(renderer, label, || Pipeline::new_compute(renderer, shader, bindings, label))
+    }
+
+    /// Loads a shader from disk with [`load_wgsl`] and creates a compute pipeline,
+    /// e.g. to reload it after the shader has been modified.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `path` - The path of the shader with the `cs_main` entry point.
+    /// * `bindings` - The bind groups, in order of their group indices.
+    /// * `label` - The debug label of the pipeline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shader cannot be loaded or the pipeline does not validate.
+    pub fn load_compute(
+        renderer: &Renderer,
+        path: impl AsRef<Path>,
+        bindings: &[&ShaderResource],
+        label: &str,
+    ) -> Result<Pipeline, ShaderError> {
+        Pipeline::try_new_compute(renderer, load_wgsl(path)?, bindings, label)
+    }
+
+    /// Loads a shader from disk with [`load_wgsl`] and creates a builder of a render pipeline,
+    /// to be built with [`PipelineBuilder::try_build`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the shader containing the vertex and fragment entry points.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shader cannot be loaded.
+    pub fn load_builder<'a>(path: impl AsRef<Path>) -> Result<PipelineBuilder<'a>, ShaderError> {
+        Ok(Pipeline::builder(load_wgsl(path)?))
+    }
+
+    /// Checks the bindings of a shader against bind groups, panicking with a description
+    /// of the mismatching binding instead of a wgpu validation error.
+    fn assert_bindings(shader: &Shader, bindings: &[&ShaderResource], label: &str) {
+        // Shaders naga cannot parse are left to be reported by wgpu
+        if let Ok(reflection) = ShaderReflection::new(shader) {
+            if let Err(e) = reflection.check(bindings) {
+                panic!("Invalid bindings of pipeline `{label}`: {e}");
+            }
+        }
+    }
+
+    fn catch_errors(
+        renderer: &Renderer, 
+        label: &str, 
+        create: impl FnOnce() -> Pipeline,
+    ) -> Result<Pipeline, ShaderError> {
+        renderer.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipeline = create();
+
+        match pollster::block_on(renderer.device.pop_error_scope()) {
+            Some(e) => Err(ShaderError::Pipeline(label.to_owned(), e.to_string())),
+            None => Ok(pipeline),
+        }
+    }
+
+    fn create_layout(
+        renderer: &Renderer,
+        shader: Shader,
+        bindings: &[&ShaderResource],
+        label: &str,
+        stages: wgpu::ShaderStages,
+    ) -> (wgpu::ShaderModule, wgpu::PipelineLayout) {
+        let mut bind_group_layouts = bindings
+            .iter()
+            .map(|b| &b.bind_group_layout)
+            .collect::<Vec<_>>();
+
+        // Without push constants, instance data is bound as the last bind group
+        let (shader, push_constant_ranges) = match &renderer.instance_uniforms {
+            None => (shader, vec![wgpu::PushConstantRange {
+                stages,
+                range: 0..MAX_INSTANCE_DATA_SIZE,
+            }]),
+            Some(instance_uniforms) => {
+                let shader = InstanceUniforms::rewrite_shader(shader, bindings.len() as u32);
+                bind_group_layouts.push(&instance_uniforms.bind_group_layout);
+
+                (shader, vec![])
+            },
+        };
+
+        let shader = renderer.device.create_shader_module(shader);
+
+        let layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(format!("{label} Pipeline Layout").as_str()),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &push_constant_ranges,
+        });
+
+        (shader, layout)
+    }
+}
+
+/// Replaces WGSL `override` declarations with `const` ones, as pipeline-overridable
+/// constants are not supported by the backend.
+///
+/// # Errors
+///
+/// Returns `ShaderError::UnresolvedOverride` if an `override` has neither a value nor a default.
+fn specialize(source: &str, constants: &[(String, f64)]) -> Result<String, ShaderError> {
+    let tokens = tokenize(source);
+    let text = |i: usize| tokens.get(i).map_or("", |t: &Token| &source[t.start..t.end]);
+    // Finds the next token of a declaration, which ends with `;`
+    let find = |from: usize, targets: &[&str]| (from..tokens.len())
+        .find(|&i| targets.contains(&text(i)) || text(i) == ";");
+
+    let mut output = String::with_capacity(source.len());
+    let mut last = 0;
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        if depth > 0 || text(i) != "override" {
+            match text(i) {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {},
+            }
+
+            i += 1;
+            continue;
+        }
+
+        // `[@id(n)] override name[: type][ = default];`, where the literal of `@id` is not a token
+        let start = match i.checked_sub(4) {
+            Some(id) if (id..i).map(text).eq(["@", "id", "(", ")"]) => tokens[id].start,
+            _ => tokens[i].start,
+        };
+
+        let name = text(i + 1);
+        let invalid = || ShaderError::InvalidOverride(source[start..].lines().next().unwrap_or("").to_owned());
+
+        if tokens.get(i + 1).is_none_or(|t| t.kind != TokenKind::Ident) {
+            return Err(invalid());
+        }
+
+        let mut next = find(i + 2, &["="]).ok_or_else(invalid)?;
+        let ty = (text(i + 2) == ":").then(|| source[tokens[i + 2].end..tokens[next].start].trim());
+
+        let mut default = None;
+        if text(next) == "=" {
+            let end = find(next + 1, &[]).ok_or_else(invalid)?;
+            default = Some(source[tokens[next].end..tokens[end].start].trim());
+            next = end;
+        }
+
+        let value = constants
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| match ty {
+                Some("u32") => format!("{}u", *value as u32),
+                Some("i32") => format!("{}i", *value as i32),
+                Some("f32") => format!("{value}f"),
+                Some("bool") => (*value != 0.0).to_string(),
+                _ => format!("{value:?}"),
+            })
+            .or(default.map(str::to_owned))
+            .ok_or_else(|| ShaderError::UnresolvedOverride(name.to_owned()))?;
+
+        let ty = ty.map(|ty| format!(": {ty}")).unwrap_or_default();
+
+        output.push_str(&source[last..start]);
+        output.push_str(&format!("const {name}{ty} = {value};"));
+        last = tokens[next].end;
+        i = next + 1;
+    }
+
+    output.push_str(&source[last..]);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "
+        // override samples: u32;
+        @id(0) override samples: u32 = 4u;
+        override
+            strength: f32;
+        override enabled = true;
+
+        fn uses_override() -> f32 {
+            let no_override_here = 1.0;
+            return f32(samples) * strength * no_override_here * select(0.0, 1.0, enabled);
+        }
+    ";
+
+    fn constants(constants: &[(&str, f64)]) -> Vec<(String, f64)> {
+        constants.iter().map(|(name, value)| (name.to_string(), *value)).collect()
+    }
+
+    #[test]
+    fn overrides_are_replaced_with_constants() {
+        let source = specialize(SHADER, &constants(&[("strength", 0.5), ("samples", 8.0)])).unwrap();
+
+        assert!(source.contains("// override samples: u32;"), "comments are rewritten");
+        assert!(source.contains("const samples: u32 = 8u;"));
+        assert!(source.contains("const strength: f32 = 0.5f;"));
+        assert!(source.contains("const enabled = true;"));
+        assert!(source.contains("let no_override_here = 1.0;"));
+
+        let module = naga::front::wgsl::parse_str(&source).unwrap();
+        assert_eq!(module.constants.len(), 3);
+    }
+
+    #[test]
+    fn overrides_without_values_are_rejected() {
+        let error = specialize(SHADER, &constants(&[("samples", 8.0)])).unwrap_err();
+
+        assert!(matches!(error, ShaderError::UnresolvedOverride(name) if name == "strength"));
+    }
+
+    #[test]
+    fn unterminated_overrides_are_rejected() {
+        let error = specialize("override strength: f32", &[]).unwrap_err();
+
+        assert!(matches!(error, ShaderError::InvalidOverride(_)));
+    }
+}
@@ -0,0 +1,374 @@
+use std::fmt;
+
+use super::{
+    pipeline::{Shader, ShaderResource},
+    shader::{demote_local_consts, undecorate, ShaderError},
+};
+
+/// Type of a resource bound to a shader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingKind {
+    UniformBuffer,
+    StorageBuffer {
+        /// Whether the shader only reads the buffer.
+        read_only: bool,
+    },
+    Texture {
+        sample_type: naga::ScalarKind,
+        dimension: wgpu::TextureViewDimension,
+        multisampled: bool,
+    },
+    DepthTexture {
+        dimension: wgpu::TextureViewDimension,
+        multisampled: bool,
+    },
+    StorageTexture {
+        access: wgpu::StorageTextureAccess,
+        format: wgpu::TextureFormat,
+        dimension: wgpu::TextureViewDimension,
+    },
+    Sampler {
+        comparison: bool,
+    },
+}
+
+impl fmt::Display for BindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingKind::UniformBuffer => write!(f, "uniform buffer"),
+            BindingKind::StorageBuffer { read_only: true } => write!(f, "read-only storage buffer"),
+            BindingKind::StorageBuffer { read_only: false } => write!(f, "read-write storage buffer"),
+            BindingKind::Texture { sample_type, dimension, multisampled } => {
+                let ms = if *multisampled { "multisampled " } else { "" };
+                write!(f, "{ms}{dimension:?} texture of {sample_type:?}")
+            },
+            BindingKind::DepthTexture { dimension, .. } => write!(f, "{dimension:?} depth texture"),
+            BindingKind::StorageTexture { access, format, dimension } => write!(f, "{access:?} {dimension:?} storage texture of {format:?}"),
+            BindingKind::Sampler { comparison: true } => write!(f, "comparison sampler"),
+            BindingKind::Sampler { comparison: false } => write!(f, "sampler"),
+        }
+    }
+}
+
+/// A resource declared with `@group(g) @binding(b)` in a shader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflectedBinding {
+    pub group: u32,
+    pub binding: u32,
+    /// The name of the global variable.
+    pub name: Option<String>,
+    pub kind: BindingKind,
+    /// The stages of entry points using the binding.
+    pub visibility: wgpu::ShaderStages,
+}
+
+impl ReflectedBinding {
+    /// Retrieves the name of the variable without the prefix or suffix added to items of imported modules.
+    pub fn undecorated_name(&self) -> Option<&str> {
+        self.name.as_deref().map(undecorate)
+    }
+}
+
+/// Resources used by a shader, reflected with naga without a GPU device.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShaderReflection {
+    /// The bindings sorted by group and binding index.
+    pub bindings: Vec<ReflectedBinding>,
+    /// Whether the shader declares a `var<push_constant>` for `InstanceData`.
+    pub uses_push_constants: bool,
+}
+
+impl ShaderReflection {
+    /// Parses a WGSL shader and lists its bindings.
+    ///
+    /// # Arguments
+    ///
+    /// * `shader` - The shader to reflect.
+    ///
+    /// # Returns
+    ///
+    /// The reflected resources.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shader is not WGSL or cannot be parsed.
+    pub fn new(shader: &Shader) -> Result<ShaderReflection, ShaderError> {
+        let wgpu::ShaderSource::Wgsl(source) = &shader.source else {
+            return Err(ShaderError::UnsupportedSource);
+        };
+
+        let label = shader.label.unwrap_or("shader");
+        let module = naga::front::wgsl::parse_str(source).or_else(|e| {
+            // Shaders composed by `include_wgsl!` may declare constants in function scope
+            naga::front::wgsl::parse_str(&demote_local_consts(source))
+                .map_err(|_| ShaderError::Parse(label.into(), e.emit_to_string(source)))
+        })?;
+
+        // Stages are only known for shaders which validate, otherwise every stage of the module is assumed
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+            .validate(&module)
+            .ok();
+
+        let mut reflection = ShaderReflection::default();
+
+        for (handle, variable) in module.global_variables.iter() {
+            if variable.space == naga::AddressSpace::PushConstant {
+                reflection.uses_push_constants = true;
+            }
+
+            let Some(binding) = &variable.binding else {
+                continue;
+            };
+
+            let kind = match (variable.space, &module.types[variable.ty].inner) {
+                (naga::AddressSpace::Uniform, _) => BindingKind::UniformBuffer,
+                (naga::AddressSpace::Storage { access }, _) => BindingKind::StorageBuffer {
+                    read_only: !access.contains(naga::StorageAccess::STORE),
+                },
+                (_, naga::TypeInner::Sampler { comparison }) => BindingKind::Sampler { comparison: *comparison },
+                (_, naga::TypeInner::Image { dim, arrayed, class }) => {
+                    let dimension = view_dimension(*dim, *arrayed);
+
+                    match class {
+                        naga::ImageClass::Sampled { kind, multi } => BindingKind::Texture {
+                            sample_type: *kind,
+                            dimension,
+                            multisampled: *multi,
+                        },
+                        naga::ImageClass::Depth { multi } => BindingKind::DepthTexture {
+                            dimension,
+                            multisampled: *multi,
+                        },
+                        naga::ImageClass::Storage { access, format } => BindingKind::StorageTexture {
+                            access: storage_texture_access(*access),
+                            format: storage_format(*format),
+                            dimension,
+                        },
+                    }
+                },
+                (space, ty) => {
+                    return Err(ShaderError::Validation(
+                        label.into(),
+                        format!("Unsupported binding `{:?}` of type `{ty:?}` in {space:?}", variable.name),
+                    ));
+                },
+            };
+
+            let visibility = module.entry_points
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| info.as_ref().is_none_or(|info| !info.get_entry_point(*i)[handle].is_empty()))
+                .fold(wgpu::ShaderStages::NONE, |stages, (_, entry_point)| stages | shader_stage(entry_point.stage));
+
+            reflection.bindings.push(ReflectedBinding {
+                group: binding.group,
+                binding: binding.binding,
+                name: variable.name.clone(),
+                kind,
+                visibility,
+            });
+        }
+
+        reflection.bindings.sort_by_key(|b| (b.group, b.binding));
+
+        Ok(reflection)
+    }
+
+    /// Checks the bindings of the shader against bind groups passed to a pipeline.
+    ///
+    /// # Arguments
+    ///
+    /// * `resources` - The bind groups, in order of their group indices.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the first binding missing from or mismatching the layouts.
+    pub fn check(&self, resources: &[&ShaderResource]) -> Result<(), ShaderError> {
+        let groups = resources
+            .iter()
+            .map(|r| r.layout_entries.as_slice())
+            .collect::<Vec<_>>();
+
+        self.check_layouts(&groups)
+    }
+
+    /// Checks the bindings of the shader against bind group layout entries,
+    /// e.g. to validate shaders without a GPU device.
+    ///
+    /// # Arguments
+    ///
+    /// * `groups` - The layout entries of every bind group, in order of their group indices.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the first binding missing from or mismatching the layouts.
+    pub fn check_layouts(&self, groups: &[&[wgpu::BindGroupLayoutEntry]]) -> Result<(), ShaderError> {
+        for binding in &self.bindings {
+            let entry = groups
+                .get(binding.group as usize)
+                .and_then(|entries| entries.iter().find(|e| e.binding == binding.binding))
+                .ok_or_else(|| ShaderError::MissingBinding(binding.clone()))?;
+
+            if !binding.kind.is_compatible(&entry.ty) {
+                return Err(ShaderError::BindingMismatch(binding.clone(), entry.ty));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl BindingKind {
+    /// Creates the layout type of a resource matching this shader binding.
+    ///
+    /// Float textures are assumed to be filterable and samplers to be filtering.
+    pub fn binding_type(&self) -> wgpu::BindingType {
+        use wgpu::{BindingType, BufferBindingType, SamplerBindingType, TextureSampleType};
+
+        match *self {
+            BindingKind::UniformBuffer => BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            BindingKind::StorageBuffer { read_only } => BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            BindingKind::Texture { sample_type, dimension, multisampled } => BindingType::Texture {
+                sample_type: match sample_type {
+                    naga::ScalarKind::Sint => TextureSampleType::Sint,
+                    naga::ScalarKind::Uint => TextureSampleType::Uint,
+                    _ => TextureSampleType::Float { filterable: !multisampled },
+                },
+                view_dimension: dimension,
+                multisampled,
+            },
+            BindingKind::DepthTexture { dimension, multisampled } => BindingType::Texture {
+                sample_type: TextureSampleType::Depth,
+                view_dimension: dimension,
+                multisampled,
+            },
+            BindingKind::StorageTexture { access, format, dimension } => BindingType::StorageTexture {
+                access,
+                format,
+                view_dimension: dimension,
+            },
+            BindingKind::Sampler { comparison: true } => BindingType::Sampler(SamplerBindingType::Comparison),
+            BindingKind::Sampler { comparison: false } => BindingType::Sampler(SamplerBindingType::Filtering),
+        }
+    }
+
+    /// Checks if a resource of the given layout type can be bound to this shader binding.
+    pub fn is_compatible(&self, ty: &wgpu::BindingType) -> bool {
+        use wgpu::{BindingType, BufferBindingType, SamplerBindingType, TextureSampleType};
+
+        match (self, ty) {
+            (BindingKind::UniformBuffer, BindingType::Buffer { ty: BufferBindingType::Uniform, .. }) => true,
+            (
+                BindingKind::StorageBuffer { read_only: shader_read_only },
+                BindingType::Buffer { ty: BufferBindingType::Storage { read_only }, .. },
+            ) => *shader_read_only || !read_only,
+            (
+                BindingKind::Texture { sample_type, dimension, multisampled },
+                BindingType::Texture { sample_type: layout_type, view_dimension, multisampled: layout_ms },
+            ) => {
+                let kind_matches = matches!(
+                    (sample_type, layout_type),
+                    (naga::ScalarKind::Float, TextureSampleType::Float { .. })
+                        | (naga::ScalarKind::Sint, TextureSampleType::Sint)
+                        | (naga::ScalarKind::Uint, TextureSampleType::Uint)
+                );
+
+                kind_matches && dimension == view_dimension && multisampled == layout_ms
+            },
+            (
+                BindingKind::DepthTexture { dimension, multisampled },
+                BindingType::Texture { sample_type: TextureSampleType::Depth, view_dimension, multisampled: layout_ms },
+            ) => dimension == view_dimension && multisampled == layout_ms,
+            (
+                BindingKind::StorageTexture { access, format, dimension },
+                BindingType::StorageTexture { access: layout_access, format: layout_format, view_dimension },
+            ) => access == layout_access && format == layout_format && dimension == view_dimension,
+            (BindingKind::Sampler { comparison: true }, BindingType::Sampler(SamplerBindingType::Comparison)) => true,
+            (BindingKind::Sampler { comparison: false }, BindingType::Sampler(SamplerBindingType::Filtering | SamplerBindingType::NonFiltering)) => true,
+            _ => false,
+        }
+    }
+}
+
+fn view_dimension(dim: naga::ImageDimension, arrayed: bool) -> wgpu::TextureViewDimension {
+    match (dim, arrayed) {
+        (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+        (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+        (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+        (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+        (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+        (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+    }
+}
+
+fn storage_texture_access(access: naga::StorageAccess) -> wgpu::StorageTextureAccess {
+    match (access.contains(naga::StorageAccess::LOAD), access.contains(naga::StorageAccess::STORE)) {
+        (true, true) => wgpu::StorageTextureAccess::ReadWrite,
+        (true, false) => wgpu::StorageTextureAccess::ReadOnly,
+        _ => wgpu::StorageTextureAccess::WriteOnly,
+    }
+}
+
+fn shader_stage(stage: naga::ShaderStage) -> wgpu::ShaderStages {
+    match stage {
+        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+    }
+}
+
+fn storage_format(format: naga::StorageFormat) -> wgpu::TextureFormat {
+    use naga::StorageFormat as S;
+    use wgpu::TextureFormat as T;
+
+    match format {
+        S::R8Unorm => T::R8Unorm,
+        S::R8Snorm => T::R8Snorm,
+        S::R8Uint => T::R8Uint,
+        S::R8Sint => T::R8Sint,
+        S::R16Uint => T::R16Uint,
+        S::R16Sint => T::R16Sint,
+        S::R16Float => T::R16Float,
+        S::Rg8Unorm => T::Rg8Unorm,
+        S::Rg8Snorm => T::Rg8Snorm,
+        S::Rg8Uint => T::Rg8Uint,
+        S::Rg8Sint => T::Rg8Sint,
+        S::R32Uint => T::R32Uint,
+        S::R32Sint => T::R32Sint,
+        S::R32Float => T::R32Float,
+        S::Rg16Uint => T::Rg16Uint,
+        S::Rg16Sint => T::Rg16Sint,
+        S::Rg16Float => T::Rg16Float,
+        S::Rgba8Unorm => T::Rgba8Unorm,
+        S::Rgba8Snorm => T::Rgba8Snorm,
+        S::Rgba8Uint => T::Rgba8Uint,
+        S::Rgba8Sint => T::Rgba8Sint,
+        S::Bgra8Unorm => T::Bgra8Unorm,
+        S::Rgb10a2Uint => T::Rgb10a2Uint,
+        S::Rgb10a2Unorm => T::Rgb10a2Unorm,
+        S::Rg11b10Float => T::Rg11b10Float,
+        S::Rg32Uint => T::Rg32Uint,
+        S::Rg32Sint => T::Rg32Sint,
+        S::Rg32Float => T::Rg32Float,
+        S::Rgba16Uint => T::Rgba16Uint,
+        S::Rgba16Sint => T::Rgba16Sint,
+        S::Rgba16Float => T::Rgba16Float,
+        S::Rgba32Uint => T::Rgba32Uint,
+        S::Rgba32Sint => T::Rgba32Sint,
+        S::Rgba32Float => T::Rgba32Float,
+        S::R16Unorm => T::R16Unorm,
+        S::R16Snorm => T::R16Snorm,
+        S::Rg16Unorm => T::Rg16Unorm,
+        S::Rg16Snorm => T::Rg16Snorm,
+        S::Rgba16Unorm => T::Rgba16Unorm,
+        S::Rgba16Snorm => T::Rgba16Snorm,
+    }
+}
@@ -0,0 +1,514 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use naga_oil::compose::{ComposableModuleDescriptor, Composer, ComposerError, NagaModuleDescriptor, ShaderDefValue};
+use thiserror::Error;
+
+use super::{pipeline::Shader, reflection::ReflectedBinding};
+
+#[derive(Debug, Error)]
+pub enum ShaderError {
+    #[error("Cannot read shader `{0}`: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("Invalid import `{1}` in shader `{0}`")]
+    InvalidImport(PathBuf, String),
+    #[error("Shader `{0}` imports itself")]
+    ImportCycle(PathBuf),
+    #[error("Cannot compose shader `{0}`:\n{1}")]
+    Compose(PathBuf, String),
+    #[error("Cannot parse shader `{0}`:\n{1}")]
+    Parse(PathBuf, String),
+    #[error("Invalid shader `{0}`:\n{1}")]
+    Validation(PathBuf, String),
+    #[error("Cannot create pipeline `{0}`: {1}")]
+    Pipeline(String, String),
+    #[error("Invalid override declaration `{0}`")]
+    InvalidOverride(String),
+    #[error("Override `{0}` has neither a value nor a default value")]
+    UnresolvedOverride(String),
+    #[error("Only WGSL shaders are supported")]
+    UnsupportedSource,
+    #[error(
+        "Binding `{}` at @group({}) @binding({}) is missing from the bind group layouts", 
+        .0.name.as_deref().unwrap_or("_"), .0.group, .0.binding,
+    )]
+    MissingBinding(ReflectedBinding),
+    #[error(
+        "Binding `{}` at @group({}) @binding({}) is declared as {} in the shader, but as `{:?}` in the bind group layout", 
+        .0.name.as_deref().unwrap_or("_"), .0.group, .0.binding, .0.kind, .1,
+    )]
+    BindingMismatch(ReflectedBinding, wgpu::BindingType),
+    #[error(
+        "Binding `{}` at @group({}) @binding({}) is declared as {} in one shader, but as `{:?}` in another", 
+        .0.name.as_deref().unwrap_or("_"), .0.group, .0.binding, .0.kind, .1,
+    )]
+    ConflictingBinding(ReflectedBinding, wgpu::BindingType),
+}
+
+/// Loads a WGSL shader from disk, resolving its `#import` directives with `naga_oil`.
+///
+/// Imports use the same syntax and produce the same names as `include_wgsl!`:
+/// `#import path/to/module.wgsl as Alias`, with paths relative to the importing file,
+/// and `Alias::item` to access imported items. The composed shader is validated with naga,
+/// so errors are reported instead of crashing the device when the pipeline is created.
+///
+/// # Arguments
+///
+/// * `path` - The path of the shader with the entry points.
+///
+/// # Returns
+///
+/// The composed `Shader`.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read, an import is invalid or the shader does not validate.
+pub fn load_wgsl(path: impl AsRef<Path>) -> Result<Shader, ShaderError> {
+    let path = path.as_ref();
+    let compose_error = |e: ComposerError, composer: &Composer| ShaderError::Compose(path.to_owned(), e.emit_to_string(composer));
+
+    let mut modules = vec![];
+    ShaderModule::load(path, &mut vec![], &mut modules)?;
+    let names = ShaderModule::names(&modules);
+
+    // `include_wgsl!` composes with the same definitions
+    let mut shader_defs = HashMap::new();
+    if cfg!(debug_assertions) {
+        shader_defs.insert("__DEBUG".to_owned(), ShaderDefValue::Bool(true));
+    }
+
+    let mut composer = Composer::default().with_capabilities(naga::valid::Capabilities::all());
+    let (root, imports) = modules.split_last().expect("the root module is always loaded");
+
+    // Modules are loaded after their imports
+    for module in imports {
+        let added = composer
+            .add_composable_module(ComposableModuleDescriptor {
+                source: &module.source_with_names(&names),
+                file_path: &module.path.to_string_lossy(),
+                as_name: Some(names[&module.path].clone()),
+                shader_defs: shader_defs.clone(),
+                ..Default::default()
+            })
+            .map(|_| ());
+
+        added.map_err(|e| compose_error(e, &composer))?;
+    }
+
+    let module = composer
+        .make_naga_module(NagaModuleDescriptor {
+            source: &root.source_with_names(&names),
+            file_path: &root.path.to_string_lossy(),
+            shader_defs,
+            ..Default::default()
+        })
+        .map_err(|e| compose_error(e, &composer))?;
+
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+        .validate(&module)
+        .map_err(|e| ShaderError::Validation(path.to_owned(), e.emit_to_string("")))?;
+
+    let source = naga::back::wgsl::write_string(&module, &info, naga::back::wgsl::WriterFlags::empty())
+        .map_err(|e| ShaderError::Validation(path.to_owned(), e.to_string()))?;
+
+    Ok(Shader {
+        label: None,
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    })
+}
+
+/// A WGSL file and the files it imports.
+struct ShaderModule {
+    path: PathBuf,
+    source: String,
+    /// Byte ranges of the import paths in the source, with the imported files.
+    imports: Vec<(Range<usize>, PathBuf)>,
+}
+
+impl ShaderModule {
+    /// Loads a file and its imports, pushing every file after its imports.
+    fn load(path: &Path, stack: &mut Vec<PathBuf>, modules: &mut Vec<ShaderModule>) -> Result<(), ShaderError> {
+        let path = path
+            .canonicalize()
+            .map_err(|e| ShaderError::Io(path.to_owned(), e))?;
+
+        if stack.contains(&path) {
+            return Err(ShaderError::ImportCycle(path));
+        }
+
+        if modules.iter().any(|module| module.path == path) {
+            return Ok(());
+        }
+
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| ShaderError::Io(path.clone(), e))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        let mut imports = vec![];
+        let mut offset = 0;
+
+        for line in source.split_inclusive('\n') {
+            if let Some(import) = line.trim_start().strip_prefix("#import") {
+                let start = offset + line.len() - import.len() + (import.len() - import.trim_start().len());
+                let name = import.split_whitespace().next().unwrap_or("");
+
+                // Imports of modules defined with `#define_import_path` are left to `naga_oil`
+                if let Some(end) = name.find(".wgsl").map(|end| end + ".wgsl".len()) {
+                    imports.push((start..start + end, dir.join(&name[..end])));
+                } else if name.is_empty() {
+                    return Err(ShaderError::InvalidImport(path, line.trim().to_owned()));
+                }
+            }
+
+            offset += line.len();
+        }
+
+        stack.push(path.clone());
+        for (_, import) in &imports {
+            ShaderModule::load(import, stack, modules)?;
+        }
+        stack.pop();
+
+        for (_, import) in &mut imports {
+            *import = import.canonicalize().map_err(|e| ShaderError::Io(import.clone(), e))?;
+        }
+
+        modules.push(ShaderModule { path, source, imports });
+
+        Ok(())
+    }
+
+    /// Names modules by their file names, extended with their directories until they are unique,
+    /// e.g. `utils` or `utils_rt` and `utils_pbr`, as `include_wgsl!` does.
+    fn names(modules: &[ShaderModule]) -> HashMap<PathBuf, String> {
+        let stem = |path: &Path| path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let mut names = modules
+            .iter()
+            .map(|module| (module.path.clone(), (stem(&module.path), 1)))
+            .collect::<HashMap<_, _>>();
+
+        loop {
+            let mut counts = HashMap::<String, usize>::new();
+            for (name, _) in names.values() {
+                *counts.entry(name.clone()).or_default() += 1;
+            }
+
+            let mut colliding = names
+                .iter_mut()
+                .filter(|(_, (name, _))| counts[name] > 1)
+                .peekable();
+
+            if colliding.peek().is_none() {
+                break;
+            }
+
+            for (i, (path, (name, depth))) in colliding.enumerate() {
+                match path.components().nth_back(*depth) {
+                    Some(component) => name.push_str(&format!("_{}", component.as_os_str().to_string_lossy())),
+                    None => name.push_str(&i.to_string()),
+                }
+                *depth += 1;
+            }
+        }
+
+        names.into_iter().map(|(path, (name, _))| (path, name)).collect()
+    }
+
+    /// Replaces import paths with the names of the imported modules, right-aligned
+    /// so `#import path.wgsl::item` stays a valid `naga_oil` import.
+    fn source_with_names(&self, names: &HashMap<PathBuf, String>) -> String {
+        let mut source = self.source.clone();
+
+        for (range, import) in self.imports.iter().rev() {
+            source.replace_range(range.clone(), &format!("{:>len$}", names[import], len = range.len()));
+        }
+
+        source
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TokenKind {
+    Ident,
+    Punct,
+}
+
+/// A WGSL token, spanning `start..end` of the source.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Token {
+    pub(crate) kind: TokenKind,
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+}
+
+/// Splits WGSL into identifiers and punctuation, skipping whitespace, comments and numeric literals.
+pub(crate) fn tokenize(source: &str) -> Vec<Token> {
+    let bytes = source.as_bytes();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if source[i..].starts_with("//") {
+            while i < bytes.len() && bytes[i] != b'\n' {
+                i += 1;
+            }
+        } else if source[i..].starts_with("/*") {
+            // Block comments may be nested
+            let mut depth = 0;
+            while i < bytes.len() {
+                if source[i..].starts_with("/*") {
+                    depth += 1;
+                    i += 2;
+                } else if source[i..].starts_with("*/") {
+                    depth -= 1;
+                    i += 2;
+                    if depth == 0 {
+                        break;
+                    }
+                } else {
+                    i += 1;
+                }
+            }
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            tokens.push(Token { kind: TokenKind::Ident, start, end: i });
+        } else if c.is_ascii_digit() {
+            // Numeric literals, including suffixes and exponents
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'.') {
+                i += 1;
+            }
+        } else {
+            tokens.push(Token { kind: TokenKind::Punct, start: i, end: i + 1 });
+            i += 1;
+        }
+    }
+
+    tokens
+}
+
+/// Replaces `const` declarations in function scope with `let` ones, which older
+/// WGSL frontends do not support.
+pub(crate) fn demote_local_consts(source: &str) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut last = 0;
+    let mut depth = 0;
+
+    for token in tokenize(source) {
+        match &source[token.start..token.end] {
+            "{" => depth += 1,
+            "}" => depth -= 1,
+            "const" if depth > 0 => {
+                output.push_str(&source[last..token.start]);
+                output.push_str("let");
+                last = token.end;
+            },
+            _ => {},
+        }
+    }
+
+    output.push_str(&source[last..]);
+    output
+}
+
+/// Replaces `var<push_constant>` declarations with uniform variables bound to a group.
+pub(crate) fn push_constants_to_uniforms(source: &str, group: u32) -> String {
+    let tokens = tokenize(source);
+    let text = |i: usize| tokens.get(i).map(|t: &Token| &source[t.start..t.end]);
+
+    let mut output = String::with_capacity(source.len());
+    let mut last = 0;
+
+    for i in 0..tokens.len() {
+        if text(i) == Some("var")
+            && text(i + 1) == Some("<")
+            && text(i + 2) == Some("push_constant")
+            && text(i + 3) == Some(">")
+        {
+            output.push_str(&source[last..tokens[i].start]);
+            output.push_str(&format!("@group({group}) @binding(0) var<uniform>"));
+            last = tokens[i + 3].end;
+        }
+    }
+
+    output.push_str(&source[last..]);
+    output
+}
+
+/// Strips the suffix added by `naga_oil` to names of items of imported modules,
+/// e.g. by `include_wgsl!` and [`load_wgsl`].
+pub(crate) fn undecorate(name: &str) -> &str {
+    name.split_once("X_naga_oil_mod_").map_or(name, |(name, _)| name)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::hal::reflection::ShaderReflection;
+
+    /// Writes shader files into a new temporary directory.
+    fn write_shaders(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tracengine_shaders_{name}_{}", std::process::id()));
+
+        for (path, source) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+
+        dir
+    }
+
+    fn source(shader: &Shader) -> &str {
+        let wgpu::ShaderSource::Wgsl(source) = &shader.source else {
+            panic!("Shader is not WGSL");
+        };
+
+        source
+    }
+
+    fn entry_points(shader: &Shader) -> Vec<(String, naga::ShaderStage)> {
+        let source = source(shader);
+        let module = naga::front::wgsl::parse_str(source)
+            .or_else(|_| naga::front::wgsl::parse_str(&demote_local_consts(source)))
+            .unwrap();
+
+        module.entry_points.iter().map(|entry| (entry.name.clone(), entry.stage)).collect()
+    }
+
+    #[test]
+    fn composes_like_include_wgsl() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets/shaders");
+        let shaders = [
+            ("main_shader.wgsl", crate::include_wgsl!("../../../../assets/shaders/main_shader.wgsl")),
+            ("rt_shader.wgsl", crate::include_wgsl!("../../../../assets/shaders/rt_shader.wgsl")),
+            ("denoise_shader.wgsl", crate::include_wgsl!("../../../../assets/shaders/denoise_shader.wgsl")),
+            ("taa_shader.wgsl", crate::include_wgsl!("../../../../assets/shaders/taa_shader.wgsl")),
+        ];
+
+        // `include_wgsl!` is written by a newer naga, so the composed sources differ in formatting
+        for (path, expected) in shaders {
+            let shader = load_wgsl(dir.join(path)).unwrap();
+
+            assert_eq!(ShaderReflection::new(&shader).unwrap(), ShaderReflection::new(&expected).unwrap(), "{path}");
+            assert_eq!(entry_points(&shader), entry_points(&expected), "{path}");
+        }
+    }
+
+    #[test]
+    fn items_of_modules_do_not_clash() {
+        let dir = write_shaders("clash", &[
+            ("a/utils.wgsl", "const SIZE: u32 = 8u;\nfn scale(x: f32) -> f32 { return x * 2.0; }\n"),
+            ("b/utils.wgsl", "const SIZE: u32 = 4u;\nfn scale(x: f32) -> f32 { return x * 3.0; }\n"),
+            ("main.wgsl", "
+                #import a/utils.wgsl as A
+                #import b/utils.wgsl as B
+
+                @group(0) @binding(0) var<storage, read_write> output: array<f32>;
+
+                @compute @workgroup_size(A::SIZE, B::SIZE)
+                fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+                    // Locals may shadow items of imported modules
+                    let scale = A::scale(1.0) + B::scale(1.0);
+                    let SIZE = f32(A::SIZE);
+                    output[id.x] = scale * SIZE;
+                }
+            "),
+        ]);
+
+        let shader = load_wgsl(dir.join("main.wgsl"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let shader = shader.unwrap();
+        assert!(source(&shader).contains("@workgroup_size(8, 4, 1)"));
+
+        let reflection = ShaderReflection::new(&shader).unwrap();
+        assert_eq!(reflection.bindings[0].undecorated_name(), Some("output"));
+    }
+
+    #[test]
+    fn modules_imported_twice_are_composed_once() {
+        let dir = write_shaders("diamond", &[
+            ("common.wgsl", "const ONE: f32 = 1.0;\n"),
+            ("left.wgsl", "#import common.wgsl as Common\nfn left() -> f32 { return Common::ONE; }\n"),
+            ("right.wgsl", "#import common.wgsl as Common\nfn right() -> f32 { return -Common::ONE; }\n"),
+            ("main.wgsl", "
+                #import left.wgsl as Left
+                #import right.wgsl as Right
+
+                @compute @workgroup_size(1)
+                fn cs_main() {
+                    let sum = Left::left() + Right::right();
+                }
+            "),
+        ]);
+
+        let shader = load_wgsl(dir.join("main.wgsl"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(source(&shader.unwrap()).matches("const ONE").count(), 1);
+    }
+
+    #[test]
+    fn import_cycles_are_rejected() {
+        let dir = write_shaders("cycle", &[
+            ("a.wgsl", "#import b.wgsl as B\nfn a() {}\n"),
+            ("b.wgsl", "#import a.wgsl as A\nfn b() {}\n"),
+        ]);
+
+        let error = load_wgsl(dir.join("a.wgsl")).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(error, ShaderError::ImportCycle(path) if path.ends_with("a.wgsl")));
+    }
+
+    #[test]
+    fn missing_imports_and_items_are_reported() {
+        let dir = write_shaders("missing", &[
+            ("missing_file.wgsl", "#import none.wgsl as None\n"),
+            ("empty.wgsl", "#import\n"),
+            ("utils.wgsl", "fn helper() {}\n"),
+            ("missing_item.wgsl", "#import utils.wgsl as Utils\n@compute @workgroup_size(1) fn cs_main() { Utils::other(); }\n"),
+        ]);
+
+        let missing_file = load_wgsl(dir.join("missing_file.wgsl")).unwrap_err();
+        let empty = load_wgsl(dir.join("empty.wgsl")).unwrap_err();
+        let missing_item = load_wgsl(dir.join("missing_item.wgsl")).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(missing_file, ShaderError::Io(path, _) if path.ends_with("none.wgsl")));
+        assert!(matches!(empty, ShaderError::InvalidImport(..)));
+        assert!(matches!(missing_item, ShaderError::Compose(..)));
+    }
+
+    #[test]
+    fn module_names_are_extended_with_directories_on_collision() {
+        let module = |path: &str| ShaderModule {
+            path: PathBuf::from(path),
+            source: String::new(),
+            imports: vec![],
+        };
+        let names = ShaderModule::names(&[module("/shaders/rt/utils.wgsl"), module("/shaders/pbr/utils.wgsl"), module("/shaders/main.wgsl")]);
+
+        assert_eq!(names[Path::new("/shaders/rt/utils.wgsl")], "utils_rt");
+        assert_eq!(names[Path::new("/shaders/pbr/utils.wgsl")], "utils_pbr");
+        assert_eq!(names[Path::new("/shaders/main.wgsl")], "main");
+    }
+
+    #[test]
+    fn undecorate_strips_naga_oil_suffixes() {
+        assert_eq!(undecorate("chunksX_naga_oil_mod_XMNUHK3TLX"), "chunks");
+        assert_eq!(undecorate("chunks"), "chunks");
+    }
+}
@@ -0,0 +1,622 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::{mpsc, Arc};
+
+/// Default size of a staging buffer in bytes. Larger uploads get a dedicated staging buffer.
+pub const STAGING_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Uploads of the last submitted frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UploadStats {
+    /// Number of bytes copied to buffers and textures.
+    pub uploaded_bytes: u64,
+    /// Number of copied uploads.
+    pub uploads: usize,
+    /// Number of bytes deferred to the next frames as they exceed the upload budget.
+    pub deferred_bytes: u64,
+    /// Number of deferred uploads.
+    pub deferred_uploads: usize,
+}
+
+/// Destination of a staged upload.
+#[derive(Debug)]
+pub(crate) enum UploadTarget {
+    Buffer {
+        buffer: Arc<wgpu::Buffer>,
+        offset: u64,
+    },
+    Texture {
+        texture: Arc<wgpu::Texture>,
+        origin: wgpu::Origin3d,
+        size: wgpu::Extent3d,
+        /// Number of bytes of a tightly packed row of the data.
+        bytes_per_row: u32,
+    },
+}
+
+/// The region of a buffer or texture written by an upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum UploadRegion {
+    /// A byte range of the buffer with the given id.
+    Buffer { id: usize, range: Range<u64> },
+    /// The x, y and z ranges of texels of the texture with the given id.
+    Texture { id: usize, axes: [Range<u64>; 3] },
+}
+
+impl UploadRegion {
+    /// Checks if this region contains the whole `other` region.
+    fn contains(&self, other: &UploadRegion) -> bool {
+        self.zip(other, contains)
+    }
+
+    /// Checks if this region shares at least one byte or texel with the `other` region.
+    fn overlaps(&self, other: &UploadRegion) -> bool {
+        self.zip(other, overlaps)
+    }
+
+    /// Checks if both regions belong to the same resource and `f` holds for all their ranges.
+    fn zip(&self, other: &UploadRegion, f: fn(Range<u64>, Range<u64>) -> bool) -> bool {
+        match (self, other) {
+            (UploadRegion::Buffer { id, range }, UploadRegion::Buffer { id: other_id, range: other_range }) => {
+                id == other_id && f(range.clone(), other_range.clone())
+            },
+            (UploadRegion::Texture { id, axes }, UploadRegion::Texture { id: other_id, axes: other_axes }) => {
+                id == other_id && axes.iter().zip(other_axes).all(|(a, b)| f(a.clone(), b.clone()))
+            },
+            _ => false,
+        }
+    }
+}
+
+/// A destination of uploads, which writes a region of a resource.
+trait Destination {
+    /// Retrieves the region written by an upload of `len` bytes.
+    fn region(&self, len: u64) -> UploadRegion;
+}
+
+impl Destination for UploadTarget {
+    fn region(&self, len: u64) -> UploadRegion {
+        match self {
+            UploadTarget::Buffer { buffer, offset } => UploadRegion::Buffer {
+                id: Arc::as_ptr(buffer) as usize,
+                range: *offset..*offset + len,
+            },
+            UploadTarget::Texture { texture, origin, size, .. } => UploadRegion::Texture {
+                id: Arc::as_ptr(texture) as usize,
+                axes: [
+                    origin.x as u64..(origin.x + size.width) as u64,
+                    origin.y as u64..(origin.y + size.height) as u64,
+                    origin.z as u64..(origin.z + size.depth_or_array_layers) as u64,
+                ],
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Upload<T = UploadTarget> {
+    target: T,
+    data: Vec<u8>,
+    deferrable: bool,
+}
+
+impl<T: Destination> Upload<T> {
+    fn region(&self) -> UploadRegion {
+        self.target.region(self.data.len() as u64)
+    }
+}
+
+/// Pending uploads in the order they are copied.
+#[derive(Debug)]
+struct UploadQueue<T = UploadTarget> {
+    pending: VecDeque<Upload<T>>,
+}
+
+impl<T: Destination> UploadQueue<T> {
+    fn new() -> UploadQueue<T> {
+        UploadQueue { pending: VecDeque::new() }
+    }
+
+    fn pending_bytes(&self) -> u64 {
+        self.pending.iter().map(|upload| upload.data.len() as u64).sum()
+    }
+
+    /// Queues an upload, replacing the pending uploads it fully overwrites.
+    /// The upload is only deferrable if all replaced uploads are.
+    fn push(&mut self, target: T, data: Vec<u8>, mut deferrable: bool) {
+        // Partially overwritten uploads are kept, as the new upload is copied after them
+        let region = target.region(data.len() as u64);
+        self.pending.retain(|upload| {
+            let covered = region.contains(&upload.region());
+            if covered {
+                deferrable &= upload.deferrable;
+            }
+
+            !covered
+        });
+
+        self.pending.push_back(Upload { target, data, deferrable });
+    }
+
+    /// Takes the uploads of this frame, keeping the uploads exceeding the budget for the next frames.
+    ///
+    /// Deferrable uploads are taken while they fit into the budget. The first one is always taken,
+    /// even if it is larger than the budget, as it would never be uploaded otherwise.
+    /// Uploads overlapping a deferred upload are deferred as well, even if they are not deferrable,
+    /// so they are still copied after it.
+    ///
+    /// # Returns
+    ///
+    /// The uploads to copy, in order, and the statistics of the frame.
+    fn take(&mut self, budget: Option<u64>) -> (Vec<Upload<T>>, UploadStats) {
+        let mut stats = UploadStats::default();
+        let mut taken = vec![];
+        let mut deferred = VecDeque::new();
+        let mut deferred_regions: Vec<UploadRegion> = vec![];
+        let mut budget_used = 0;
+
+        while let Some(upload) = self.pending.pop_front() {
+            let size = upload.data.len() as u64;
+            let region = upload.region();
+
+            let blocked = deferred_regions.iter().any(|deferred| deferred.overlaps(&region));
+            let within_budget = !upload.deferrable
+                || budget.is_none_or(|budget| budget_used == 0 || budget_used + size <= budget);
+
+            if blocked || !within_budget {
+                stats.deferred_bytes += size;
+                stats.deferred_uploads += 1;
+                deferred_regions.push(region);
+                deferred.push_back(upload);
+                continue;
+            }
+
+            if upload.deferrable {
+                budget_used += size;
+            }
+
+            stats.uploaded_bytes += size;
+            stats.uploads += 1;
+            taken.push(upload);
+        }
+
+        self.pending = deferred;
+
+        (taken, stats)
+    }
+}
+
+#[derive(Debug)]
+struct StagingChunk {
+    buffer: Arc<wgpu::Buffer>,
+    size: u64,
+    offset: u64,
+}
+
+/// A ring of mapped staging buffers. Buffers are unmapped when the frame is submitted,
+/// and mapped again to be reused once the GPU has finished copying from them.
+#[derive(Debug)]
+struct StagingBelt {
+    chunk_size: u64,
+    active: Vec<StagingChunk>,
+    closed: Vec<StagingChunk>,
+    free: Vec<StagingChunk>,
+    sender: mpsc::Sender<StagingChunk>,
+    receiver: mpsc::Receiver<StagingChunk>,
+}
+
+impl StagingBelt {
+    fn new(chunk_size: u64) -> StagingBelt {
+        let (sender, receiver) = mpsc::channel();
+
+        StagingBelt {
+            chunk_size,
+            active: vec![],
+            closed: vec![],
+            free: vec![],
+            sender,
+            receiver,
+        }
+    }
+
+    /// Copies data into a staging buffer.
+    ///
+    /// # Returns
+    ///
+    /// The staging buffer and the offset of the data in it.
+    fn stage(&mut self, device: &wgpu::Device, data: &[u8], alignment: u64) -> (Arc<wgpu::Buffer>, u64) {
+        self.receive_chunks();
+
+        let size = data.len() as u64;
+        let index = self.active
+            .iter()
+            .position(|chunk| align_to(chunk.offset, alignment) + size <= chunk.size);
+
+        let chunk = match index {
+            Some(index) => &mut self.active[index],
+            None => {
+                let chunk = match self.free.iter().position(|chunk| chunk.size >= size) {
+                    Some(index) => self.free.swap_remove(index),
+                    None => {
+                        let size = align_to(size.max(self.chunk_size), wgpu::COPY_BUFFER_ALIGNMENT);
+                        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                            label: Some("Staging buffer"),
+                            size,
+                            usage: wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+                            mapped_at_creation: true,
+                        });
+
+                        StagingChunk { buffer: Arc::new(buffer), size, offset: 0 }
+                    },
+                };
+
+                self.active.push(chunk);
+                self.active.last_mut().expect("Chunk has just been pushed")
+            },
+        };
+
+        let offset = align_to(chunk.offset, alignment);
+        chunk.buffer
+            .slice(offset..offset + size)
+            .get_mapped_range_mut()
+            .copy_from_slice(data);
+        chunk.offset = offset + size;
+
+        (chunk.buffer.clone(), offset)
+    }
+
+    /// Unmaps the staging buffers written this frame, before submitting the copies.
+    fn finish(&mut self) {
+        for chunk in self.active.drain(..) {
+            chunk.buffer.unmap();
+            self.closed.push(chunk);
+        }
+    }
+
+    /// Maps the submitted staging buffers again.
+    fn recall(&mut self) {
+        self.receive_chunks();
+
+        for mut chunk in self.closed.drain(..) {
+            let sender = self.sender.clone();
+            let buffer = chunk.buffer.clone();
+            chunk.offset = 0;
+
+            buffer.slice(..).map_async(wgpu::MapMode::Write, move |_| {
+                let _ = sender.send(chunk);
+            });
+        }
+    }
+
+    fn receive_chunks(&mut self) {
+        while let Ok(chunk) = self.receiver.try_recv() {
+            self.free.push(chunk);
+        }
+    }
+}
+
+/// Batches buffer and texture uploads of a frame through a staging belt.
+///
+/// Uploads are recorded into a separate command buffer, submitted before the commands
+/// of the frame. Deferrable uploads, e.g. chunks and palettes, exceeding the per-frame
+/// byte budget are kept for the next frames, while others, e.g. instance data, are
+/// always uploaded with the frame.
+#[derive(Debug)]
+pub(crate) struct Uploads {
+    belt: StagingBelt,
+    queue: UploadQueue,
+    budget: Option<u64>,
+    stats: UploadStats,
+}
+
+impl Uploads {
+    pub(crate) fn new(budget: Option<u64>) -> Uploads {
+        Uploads {
+            belt: StagingBelt::new(STAGING_CHUNK_SIZE),
+            queue: UploadQueue::new(),
+            budget,
+            stats: UploadStats::default(),
+        }
+    }
+
+    pub(crate) fn set_budget(&mut self, budget: Option<u64>) {
+        self.budget = budget;
+    }
+
+    pub(crate) fn stats(&self) -> UploadStats {
+        self.stats
+    }
+
+    /// Retrieves the number of bytes waiting to be uploaded.
+    pub(crate) fn pending_bytes(&self) -> u64 {
+        self.queue.pending_bytes()
+    }
+
+    /// Queues an upload, replacing the pending uploads it fully overwrites.
+    pub(crate) fn push(&mut self, target: UploadTarget, mut data: Vec<u8>, deferrable: bool) {
+        if let UploadTarget::Buffer { .. } = target {
+            // Buffer copies must be a multiple of 4 bytes
+            data.resize(align_to(data.len() as u64, wgpu::COPY_BUFFER_ALIGNMENT) as usize, 0);
+        }
+
+        self.queue.push(target, data, deferrable);
+    }
+
+    /// Moves the pending uploads of a buffer to the buffer replacing it, e.g. after a resize.
+    /// Uploads past the size of the new buffer are truncated or dropped.
+    ///
+    /// The new buffer must already contain the copied contents of the old one, so the moved
+    /// uploads are copied after them.
+    pub(crate) fn retarget(&mut self, old: &Arc<wgpu::Buffer>, new: &Arc<wgpu::Buffer>, size: u64) {
+        self.queue.pending.retain_mut(|upload| {
+            let UploadTarget::Buffer { buffer, offset } = &mut upload.target else {
+                return true;
+            };
+
+            if !Arc::ptr_eq(buffer, old) {
+                return true;
+            }
+
+            let len = size.saturating_sub(*offset).min(upload.data.len() as u64)
+                / wgpu::COPY_BUFFER_ALIGNMENT * wgpu::COPY_BUFFER_ALIGNMENT;
+            upload.data.truncate(len as usize);
+            *buffer = new.clone();
+
+            len > 0
+        });
+    }
+
+    /// Records the copies of pending uploads within the budget.
+    ///
+    /// # Returns
+    ///
+    /// The command buffer with the copies, or `None` if nothing is uploaded.
+    pub(crate) fn flush(&mut self, device: &wgpu::Device) -> Option<wgpu::CommandBuffer> {
+        let (uploads, stats) = self.queue.take(self.budget);
+        self.stats = stats;
+
+        if uploads.is_empty() {
+            return None;
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Uploads") });
+        for upload in uploads {
+            self.record(device, &mut encoder, upload);
+        }
+
+        self.belt.finish();
+
+        Some(encoder.finish())
+    }
+
+    /// Reclaims staging buffers after the uploads have been submitted.
+    pub(crate) fn recall(&mut self) {
+        self.belt.recall();
+    }
+
+    fn record(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, upload: Upload) {
+        match upload.target {
+            UploadTarget::Buffer { buffer, offset } => {
+                let (staging, staging_offset) = self.belt.stage(device, &upload.data, wgpu::COPY_BUFFER_ALIGNMENT);
+                encoder.copy_buffer_to_buffer(&staging, staging_offset, &buffer, offset, upload.data.len() as u64);
+            },
+            UploadTarget::Texture { texture, origin, size, bytes_per_row } => {
+                // Rows of buffer-to-texture copies must be aligned to 256 bytes
+                let padded_bytes_per_row = align_to(bytes_per_row as u64, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64) as u32;
+                let data = if padded_bytes_per_row == bytes_per_row {
+                    upload.data
+                } else {
+                    let mut padded = vec![0; (padded_bytes_per_row * size.height * size.depth_or_array_layers) as usize];
+                    for (src, dst) in upload.data
+                        .chunks(bytes_per_row as usize)
+                        .zip(padded.chunks_mut(padded_bytes_per_row as usize))
+                    {
+                        dst[..src.len()].copy_from_slice(src);
+                    }
+
+                    padded
+                };
+
+                // Offsets must be a multiple of the texel size, which is at most 16 bytes
+                let (staging, staging_offset) = self.belt.stage(device, &data, 16);
+
+                encoder.copy_buffer_to_texture(
+                    wgpu::ImageCopyBuffer {
+                        buffer: &staging,
+                        layout: wgpu::ImageDataLayout {
+                            offset: staging_offset,
+                            bytes_per_row: Some(padded_bytes_per_row),
+                            rows_per_image: Some(size.height),
+                        },
+                    },
+                    wgpu::ImageCopyTexture {
+                        texture: &texture,
+                        mip_level: 0,
+                        origin,
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    size,
+                );
+            },
+        }
+    }
+}
+
+fn align_to(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+/// Checks if the `outer` range contains the whole `inner` range.
+fn contains(outer: Range<u64>, inner: Range<u64>) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end
+}
+
+/// Checks if two ranges share at least one value.
+fn overlaps(a: Range<u64>, b: Range<u64>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_fully_covered_ranges_are_contained() {
+        assert!(contains(0..16, 0..16));
+        assert!(contains(0..16, 4..8));
+        assert!(!contains(4..8, 0..16));
+        assert!(!contains(0..8, 4..12));
+        assert!(!contains(4..12, 0..8));
+        assert!(!contains(0..4, 4..8));
+    }
+
+    #[test]
+    fn align_to_rounds_up() {
+        assert_eq!(align_to(0, 4), 0);
+        assert_eq!(align_to(1, 4), 4);
+        assert_eq!(align_to(8, 4), 8);
+        assert_eq!(align_to(9, 16), 16);
+    }
+
+    /// A buffer target identified by an id, to plan uploads without a device.
+    struct TestBuffer {
+        id: usize,
+        offset: u64,
+    }
+
+    impl Destination for TestBuffer {
+        fn region(&self, len: u64) -> UploadRegion {
+            UploadRegion::Buffer { id: self.id, range: self.offset..self.offset + len }
+        }
+    }
+
+    fn queue(uploads: &[(usize, u64, u64, bool)]) -> UploadQueue<TestBuffer> {
+        let mut queue = UploadQueue::new();
+        for &(id, offset, len, deferrable) in uploads {
+            queue.push(TestBuffer { id, offset }, vec![0; len as usize], deferrable);
+        }
+
+        queue
+    }
+
+    fn targets(uploads: &[Upload<TestBuffer>]) -> Vec<(usize, u64)> {
+        uploads.iter().map(|upload| (upload.target.id, upload.target.offset)).collect()
+    }
+
+    fn pending(queue: &UploadQueue<TestBuffer>) -> Vec<(usize, u64)> {
+        queue.pending.iter().map(|upload| (upload.target.id, upload.target.offset)).collect()
+    }
+
+    #[test]
+    fn overlapping_ranges_share_a_value() {
+        assert!(overlaps(0..16, 4..8));
+        assert!(overlaps(0..8, 4..12));
+        assert!(!overlaps(0..4, 4..8));
+        assert!(!overlaps(4..8, 0..4));
+    }
+
+    #[test]
+    fn regions_of_different_resources_never_overlap() {
+        let a = UploadRegion::Buffer { id: 0, range: 0..16 };
+        let b = UploadRegion::Buffer { id: 1, range: 0..16 };
+        let texture = UploadRegion::Texture { id: 0, axes: [0..16, 0..1, 0..1] };
+
+        assert!(!a.overlaps(&b));
+        assert!(!a.overlaps(&texture));
+        assert!(!a.contains(&b));
+    }
+
+    #[test]
+    fn texture_regions_overlap_on_every_axis() {
+        let region = UploadRegion::Texture { id: 0, axes: [0..4, 0..4, 0..1] };
+        let inside = UploadRegion::Texture { id: 0, axes: [1..3, 1..3, 0..1] };
+        let beside = UploadRegion::Texture { id: 0, axes: [2..6, 4..8, 0..1] };
+
+        assert!(region.contains(&inside));
+        assert!(region.overlaps(&inside));
+        assert!(!region.overlaps(&beside));
+    }
+
+    #[test]
+    fn covered_uploads_are_coalesced() {
+        let mut queue = queue(&[(0, 0, 8, true), (0, 4, 8, true), (1, 0, 8, true)]);
+        queue.push(TestBuffer { id: 0, offset: 0 }, vec![0; 8], true);
+
+        // The partially overwritten upload is kept before the new one
+        assert_eq!(pending(&queue), [(0, 4), (1, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn coalesced_uploads_are_only_deferrable_if_all_replaced_ones_are() {
+        let mut queue = queue(&[(0, 0, 8, false)]);
+        queue.push(TestBuffer { id: 0, offset: 0 }, vec![0; 16], true);
+
+        assert_eq!(queue.pending.len(), 1);
+        assert!(!queue.pending[0].deferrable);
+    }
+
+    #[test]
+    fn deferrable_uploads_are_limited_by_the_budget() {
+        let mut queue = queue(&[(0, 0, 8, true), (1, 0, 8, true), (2, 0, 8, true)]);
+
+        let (uploads, stats) = queue.take(Some(16));
+
+        assert_eq!(targets(&uploads), [(0, 0), (1, 0)]);
+        assert_eq!(pending(&queue), [(2, 0)]);
+        assert_eq!(stats, UploadStats { uploaded_bytes: 16, uploads: 2, deferred_bytes: 8, deferred_uploads: 1 });
+    }
+
+    #[test]
+    fn uploads_are_unlimited_without_a_budget() {
+        let mut queue = queue(&[(0, 0, 64, true), (1, 0, 64, true)]);
+
+        let (uploads, _) = queue.take(None);
+
+        assert_eq!(uploads.len(), 2);
+        assert_eq!(queue.pending_bytes(), 0);
+    }
+
+    #[test]
+    fn first_upload_larger_than_the_budget_is_uploaded() {
+        let mut queue = queue(&[(0, 0, 64, true), (1, 0, 4, true)]);
+
+        let (uploads, _) = queue.take(Some(16));
+        assert_eq!(targets(&uploads), [(0, 0)]);
+
+        let (uploads, _) = queue.take(Some(16));
+        assert_eq!(targets(&uploads), [(1, 0)]);
+    }
+
+    #[test]
+    fn non_deferrable_uploads_ignore_the_budget() {
+        let mut queue = queue(&[(0, 0, 16, true), (1, 0, 64, false), (2, 0, 4, true)]);
+
+        let (uploads, stats) = queue.take(Some(16));
+
+        assert_eq!(targets(&uploads), [(0, 0), (1, 0)]);
+        assert_eq!(pending(&queue), [(2, 0)]);
+        assert_eq!(stats.uploaded_bytes, 80);
+    }
+
+    #[test]
+    fn uploads_overlapping_deferred_ones_are_deferred_in_order() {
+        let mut queue = queue(&[
+            (0, 0, 16, true),
+            (1, 0, 16, true),
+            (1, 8, 16, false),
+            (2, 0, 4, false),
+            (1, 20, 4, true),
+        ]);
+
+        let (uploads, _) = queue.take(Some(16));
+
+        // The non-deferrable upload to buffer 1 must not be copied before the deferred one,
+        // and the upload overlapping it in turn waits for both
+        assert_eq!(targets(&uploads), [(0, 0), (2, 0)]);
+        assert_eq!(pending(&queue), [(1, 0), (1, 8), (1, 20)]);
+
+        let (uploads, _) = queue.take(Some(16));
+        assert_eq!(targets(&uploads), [(1, 0), (1, 8)]);
+        assert_eq!(pending(&queue), [(1, 20)]);
+    }
+}
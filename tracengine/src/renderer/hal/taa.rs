@@ -1,148 +1,229 @@
 use bytemuck::{Pod, Zeroable};
 use rand::Rng;
-use crate::renderer::Renderer;
+use crate::renderer::{InstanceData, Renderer};
 use crate::glm;
 
+use crate::renderer::error::RenderError;
+
 use super::{
-    buffer::{Buffer, BufferResourceDescriptor}, pipeline::ShaderResource, texture::*
+    buffer::Buffer, pipeline::ShaderResource, reflection::ShaderReflection, texture::*
 };
 
+/// Number of sub-pixel jitter offsets in the Halton sequence before it repeats.
+pub const JITTER_SEQUENCE_LENGTH: u32 = 16;
+
+/// Computes the `index`-th element of the Halton low-discrepancy sequence in the given `base`.
+///
+/// # Arguments
+///
+/// * `index` - The index of the element, starting from 1.
+/// * `base` - The base of the sequence, usually a prime number.
+///
+/// # Returns
+///
+/// A value in the range `[0, 1)`.
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+
+    result
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Zeroable, Pod)]
 pub struct TaaConfig {
     canvas_width: u32,
     canvas_height: u32,
-    jitter: f32,
+    jitter: glm::Vec2,
+    seed: f32,
+    _padding: u32,
 }
 
 impl TaaConfig {
-    #[allow(clippy::new_without_default)]
-    pub fn new(renderer: &Renderer) -> TaaConfig {
+    /// Creates the TAA configuration for the given frame.
+    ///
+    /// The sub-pixel jitter follows the Halton (2, 3) sequence, in pixels
+    /// within `[-0.5, 0.5)`, while `seed` is a random value used by the
+    /// path tracer to decorrelate noise between frames.
+    pub fn new(renderer: &Renderer, frame_index: u32) -> TaaConfig {
         let mut rng = rand::thread_rng();
+        let sample_index = frame_index % JITTER_SEQUENCE_LENGTH + 1;
+
         TaaConfig {
             canvas_width: renderer.size().width,
             canvas_height: renderer.size().height,
-            jitter: rng.gen_range(-1.0..=1.0)
+            jitter: glm::vec2(
+                halton(sample_index, 2) - 0.5,
+                halton(sample_index, 3) - 0.5,
+            ),
+            seed: rng.gen_range(-1.0..=1.0),
+            _padding: 0,
         }
     }
 }
 
+/// Push constant data selecting what the TAA render pipeline outputs.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Zeroable, Pod)]
+pub struct TaaPass {
+    present: u32,
+}
+
+impl TaaPass {
+    /// Resolves the current frame against the reprojected history.
+    pub fn resolve() -> TaaPass {
+        TaaPass { present: 0 }
+    }
+
+    /// Outputs the already resolved history texture without reprojection.
+    pub fn present() -> TaaPass {
+        TaaPass { present: 1 }
+    }
+}
+
+impl InstanceData for TaaPass {
+    type UniformData = TaaPass;
+
+    fn uniform_data(&mut self) -> Self::UniformData {
+        *self
+    }
+}
+
+/// Buffers and bindings of temporal anti-aliasing.
+///
+/// The colour and history textures are owned by the render graph, which reallocates
+/// them when the canvas is resized, and the history texture is bound with [`Taa::bind_history`].
 #[readonly::make]
 pub struct Taa {
-    pub render_texture: Texture,
-    pub history_texture: Texture,
     pub velocity_buffer: Buffer<glm::Vec4>,
+    pub history_depth_buffer: Buffer<f32>,
     pub config_buffer: Buffer<TaaConfig>,
     pub shader_resource: ShaderResource,
     #[readonly]
-    pub current_jitter: f32,
+    pub current_jitter: glm::Vec2,
+    #[readonly]
+    pub frame_index: u32,
 }
 
 impl Taa {
-    pub fn new(renderer: &Renderer) -> Taa {
-        let render_texture = Texture::new(renderer, TextureDescriptor {
-            width: renderer.size().width,
-            height: renderer.size().height,
-            filter: wgpu::FilterMode::Linear,
-            dimension: wgpu::TextureDimension::D2,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            depth: None,
-            label: "TAA Color",
-        });
-
-        let history_texture = Texture::new(renderer, TextureDescriptor {
-            width: renderer.size().width,
-            height: renderer.size().height,
-            filter: wgpu::FilterMode::Linear,
-            dimension: wgpu::TextureDimension::D2,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            depth: None,
-            label: "TAA History",
-        });
-
+    /// Index of the TAA bind group in shaders using it.
+    pub const BIND_GROUP: u32 = 0;
+
+    /// Creates the TAA buffers, with the layout of the bind group declared at
+    /// [`Taa::BIND_GROUP`] in the shaders. The bind group is created once the
+    /// history texture is bound with [`Taa::bind_history`].
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `reflections` - The reflected shaders of all pipelines using the TAA bind group.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Taa`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shaders declare incompatible or unknown TAA bindings.
+    pub fn new(renderer: &Renderer, reflections: &[&ShaderReflection]) -> Result<Taa, RenderError> {
         let velocity_buffer = Buffer::new(
             renderer,
             (renderer.size().width * renderer.size().height) as usize,
             wgpu::BufferUsages::STORAGE,
         );
 
+        let history_depth_buffer = Buffer::new(
+            renderer,
+            (renderer.size().width * renderer.size().height) as usize,
+            wgpu::BufferUsages::STORAGE,
+        );
+
         let config_buffer = Buffer::new(
             renderer, 
             1, 
             wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         );
 
-        let shader_resource = ShaderResource::builder()
-            .add_texture(&history_texture, &TextureResourceDescriptor {
-                usage: TextureResourceUsage::TEXTURE | TextureResourceUsage::SAMPLER,
-                sample_type: Some(wgpu::TextureSampleType::Float { filterable: true }),
-            })
-            .add_buffer(&velocity_buffer, &BufferResourceDescriptor {
-                visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
-                buffer_type: wgpu::BufferBindingType::Storage { read_only: false },
-            })
-            .add_buffer(&config_buffer, &BufferResourceDescriptor {
-                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
-                buffer_type: wgpu::BufferBindingType::Uniform,
-            })
-            .build(renderer);
-
-        let current_jitter = 0.0;
-
-        Taa { 
-            render_texture, 
-            history_texture, 
+        let shader_resource = ShaderResource::from_reflection(renderer, reflections, Taa::BIND_GROUP, "TAA")?;
+
+        let current_jitter = glm::Vec2::zeros();
+
+        let mut taa = Taa { 
             velocity_buffer, 
+            history_depth_buffer,
             config_buffer, 
             shader_resource, 
             current_jitter,
-        }
+            frame_index: 0,
+        };
+        taa.bind_buffers()?;
+
+        Ok(taa)
     }
 
-    pub fn update(&mut self, renderer: &Renderer) {
-        let taa_config = TaaConfig::new(renderer);
+    /// Advances the jitter sequence and resizes the buffers if the canvas has been resized.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration buffer cannot be filled, or the buffers
+    /// are resized before the history texture has been bound.
+    pub fn update(&mut self, renderer: &Renderer) -> Result<(), RenderError> {
+        self.frame_index = self.frame_index.wrapping_add(1);
+        let taa_config = TaaConfig::new(renderer, self.frame_index);
 
         self.current_jitter = taa_config.jitter;
-        self.config_buffer.fill_exact(renderer, 0, &[taa_config])
-            .expect("Cannot fill TAA buffer");
+        self.config_buffer.fill_exact(renderer, 0, &[taa_config])?;
 
         let mut rebind_resources = false;
 
-        let render_descr = *self.render_texture.description();
-        if render_descr.width != renderer.size().width || render_descr.height != renderer.size().height {
-            self.render_texture.resize(renderer, renderer.size());
-            rebind_resources = true;
-        }
-
-        let history_descr = *self.history_texture.description();
-        if history_descr.width != renderer.size().width || history_descr.height != renderer.size().height {
-            self.history_texture.resize(renderer, renderer.size());
-            rebind_resources = true;
-        }
-
         let viewport_size = renderer.size().width as usize * renderer.size().height as usize;
         if *self.velocity_buffer.capacity() != viewport_size {
             self.velocity_buffer.resize(renderer, viewport_size);
             rebind_resources = true;
         }
 
+        if *self.history_depth_buffer.capacity() != viewport_size {
+            self.history_depth_buffer.resize(renderer, viewport_size);
+            rebind_resources = true;
+        }
+
         if rebind_resources {
-            self.shader_resource = ShaderResource::builder()
-                .add_texture(&self.history_texture, &TextureResourceDescriptor {
-                    usage: TextureResourceUsage::TEXTURE | TextureResourceUsage::SAMPLER,
-                    sample_type: Some(wgpu::TextureSampleType::Float { filterable: true }),
-                })
-                .add_buffer(&self.velocity_buffer, &BufferResourceDescriptor {
-                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
-                    buffer_type: wgpu::BufferBindingType::Storage { read_only: false },
-                })
-                .add_buffer(&self.config_buffer, &BufferResourceDescriptor {
-                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
-                    buffer_type: wgpu::BufferBindingType::Uniform,
-                })
-                .build(renderer);
+            self.bind_buffers()?;
+            self.shader_resource.update(renderer)?;
         }
+
+        Ok(())
     }
-}
\ No newline at end of file
+
+    /// Binds the history texture, e.g. after the render graph owning it has reallocated it.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `history_texture` - The texture holding the resolved previous frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shaders do not declare the history texture and sampler.
+    pub fn bind_history(&mut self, renderer: &Renderer, history_texture: &Texture) -> Result<(), RenderError> {
+        self.shader_resource
+            .set("history_texture", history_texture)?
+            .set("history_sampler", history_texture)?
+            .update(renderer)
+    }
+
+    fn bind_buffers(&mut self) -> Result<(), RenderError> {
+        self.shader_resource
+            .set("velocity_buffer", &self.velocity_buffer)?
+            .set("taa_config", &self.config_buffer)?
+            .set("history_depth_buffer", &self.history_depth_buffer)?;
+
+        Ok(())
+    }
+}
@@ -1,22 +1,93 @@
+use std::sync::Arc;
+
 use bitflags::bitflags;
 use derive_getters::Getters;
 use game_loop::winit::dpi::PhysicalSize;
+use thiserror::Error;
 
 use crate::renderer::{RenderSurface, Renderer};
 use crate::renderer::types::*;
 
+use super::{
+    image::{Image, ImageError},
+    pipeline::{BoundResource, ShaderBinding},
+    staging::UploadTarget,
+};
+
+#[derive(Debug, Error)]
+pub enum TextureError {
+    #[error(transparent)]
+    Image(#[from] ImageError),
+    #[error("Image with size {0}x{1} cannot be used as a texture")]
+    EmptyImage(u32, u32),
+    #[error("Images can only be loaded into `Rgba8Unorm` and `Rgba8UnormSrgb` textures, found `{0:?}`")]
+    UnsupportedImageFormat(TextureFormat),
+    #[error("Mipmaps can only be generated for filterable and renderable 2D textures, found {0:?} texture with format `{1:?}`")]
+    UnsupportedMipmaps(TextureDimension, TextureFormat),
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct TextureDescriptor {
     pub width: u32,
     pub height: u32,
+    /// Depth of 3D textures, or number of layers of 2D array textures.
     pub depth: Option<u32>,
     pub filter: FilterMode,
     pub dimension: TextureDimension,
     pub usage: TextureUsages,
     pub format: TextureFormat,
+    /// Number of MSAA samples per pixel; `1` for regular textures.
+    pub sample_count: u32,
+    /// Number of mip levels, clamped to the full mip chain of the texture size,
+    /// so `u32::MAX` creates all levels.
+    pub mip_level_count: u32,
+    /// Addressing mode of the sampler outside of the texture, in all directions.
+    pub address_mode: AddressMode,
+    /// Maximum anisotropy of the sampler; `1` disables anisotropic filtering.
+    /// Only used with the `Linear` filter.
+    pub anisotropy: u16,
     pub label: &'static str,
 }
 
+impl Default for TextureDescriptor {
+    fn default() -> Self {
+        TextureDescriptor {
+            width: 1,
+            height: 1,
+            depth: None,
+            filter: FilterMode::Linear,
+            dimension: TextureDimension::D2,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            format: TextureFormat::Rgba8UnormSrgb,
+            sample_count: 1,
+            mip_level_count: 1,
+            address_mode: AddressMode::ClampToEdge,
+            anisotropy: 1,
+            label: "Unnamed",
+        }
+    }
+}
+
+impl TextureDescriptor {
+    /// Retrieves the dimension of views of the texture. 2D textures with a depth are array textures.
+    pub fn view_dimension(&self) -> wgpu::TextureViewDimension {
+        match (self.dimension, self.depth) {
+            (TextureDimension::D1, _) => wgpu::TextureViewDimension::D1,
+            (TextureDimension::D2, None) => wgpu::TextureViewDimension::D2,
+            (TextureDimension::D2, Some(_)) => wgpu::TextureViewDimension::D2Array,
+            (TextureDimension::D3, _) => wgpu::TextureViewDimension::D3,
+        }
+    }
+
+    fn size(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: self.depth.unwrap_or(1),
+        }
+    }
+}
+
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct TextureResourceUsage: u8 {
@@ -29,14 +100,18 @@ bitflags! {
 pub struct TextureResourceDescriptor {
     pub usage: TextureResourceUsage,
     pub sample_type: Option<TextureSampleType>,
+    /// Access of the `STORAGE` binding; write-only if not specified.
+    pub storage_access: Option<wgpu::StorageTextureAccess>,
 }
 
 /// A structure representing a depth texture, including its view and sampler.
-#[derive(Debug, Getters)]
+#[derive(Debug, Clone, Getters)]
 pub struct Texture {
-    texture: wgpu::Texture,
-    view: wgpu::TextureView,
-    sampler: wgpu::Sampler,
+    texture: Arc<wgpu::Texture>,
+    view: Arc<wgpu::TextureView>,
+    /// A view of the first mip level, as storage bindings cannot access several levels.
+    storage_view: Arc<wgpu::TextureView>,
+    sampler: Arc<wgpu::Sampler>,
     description: TextureDescriptor,
 }
 
@@ -50,6 +125,16 @@ impl RenderSurface for Texture {
     }
 }
 
+impl ShaderBinding for Texture {
+    fn bound_resource(&self) -> BoundResource {
+        BoundResource::Texture {
+            view: self.view.clone(),
+            storage_view: self.storage_view.clone(),
+            sampler: self.sampler.clone(),
+        }
+    }
+}
+
 impl Texture {
     /// Creates a new depth texture with the specified device and surface configuration.
     ///
@@ -63,48 +148,293 @@ impl Texture {
     /// A new instance of `Texture`.
     pub fn new(
         renderer: &Renderer, 
-        description: TextureDescriptor,
+        mut description: TextureDescriptor,
     ) -> Texture {
-        let size = wgpu::Extent3d {
-            width: description.width,
-            height: description.height,
-            depth_or_array_layers: description.depth.unwrap_or(1),
+        let size = description.size();
+        description.mip_level_count = description.mip_level_count.clamp(1, size.max_mips(description.dimension));
+
+        // Mip levels are generated by rendering each level from the previous one, if the format allows it
+        let usage = if description.mip_level_count > 1 && Texture::supports_mipmaps(renderer, &description) {
+            description.usage | wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+        } else {
+            description.usage
         };
 
         let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
             label: Some(format!("{} texture", description.label).as_str()),
             size,
-            mip_level_count: 1,
-            sample_count: 1,
+            mip_level_count: description.mip_level_count,
+            sample_count: description.sample_count,
             dimension: description.dimension,
             format: description.format,
-            usage: description.usage,
+            usage,
             view_formats: &[],
         });
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let view = Arc::new(texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(description.view_dimension()),
+            ..Default::default()
+        }));
+
+        let storage_view = if description.mip_level_count > 1 {
+            Arc::new(texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(description.view_dimension()),
+                mip_level_count: Some(1),
+                ..Default::default()
+            }))
+        } else {
+            view.clone()
+        };
+
+        // Anisotropic filtering requires linear filtering
+        let anisotropy = match description.filter {
+            wgpu::FilterMode::Linear => description.anisotropy.max(1),
+            wgpu::FilterMode::Nearest => 1,
+        };
 
         let sampler = renderer.device.create_sampler(&wgpu::SamplerDescriptor {
             label: Some(format!("{} texture sampler", description.label).as_str()),
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            address_mode_u: description.address_mode,
+            address_mode_v: description.address_mode,
+            address_mode_w: description.address_mode,
             mag_filter: description.filter,
             min_filter: description.filter,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: description.filter,
             lod_min_clamp: 0.0,
             lod_max_clamp: 100.0,
+            anisotropy_clamp: anisotropy,
             ..Default::default()
         });
 
         Texture { 
-            texture, 
-            view, 
-            sampler,
+            texture: Arc::new(texture), 
+            view,
+            storage_view,
+            sampler: Arc::new(sampler),
             description,
         }
     }
 
+    /// Creates a texture from an image, generating its mip levels if the description has several.
+    ///
+    /// The size is taken from the image. Images with several layers create 2D array
+    /// textures, which can be used e.g. as block texture atlases.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `image` - The image, e.g. loaded with [`Image::load`].
+    /// * `description` - The description of the texture, with a `Rgba8Unorm` or `Rgba8UnormSrgb` format.
+    ///   Its size is ignored; `depth` is only used to create an array texture from a single layer.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Texture`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the format is not supported, the image is empty or
+    /// mipmaps cannot be generated for the format.
+    pub fn from_image(
+        renderer: &Renderer,
+        image: &Image,
+        description: TextureDescriptor,
+    ) -> Result<Texture, TextureError> {
+        if !matches!(description.format, TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb) {
+            return Err(TextureError::UnsupportedImageFormat(description.format));
+        }
+
+        if description.mip_level_count > 1 && !Texture::supports_mipmaps(renderer, &description) {
+            return Err(TextureError::UnsupportedMipmaps(description.dimension, description.format));
+        }
+
+        if *image.width() == 0 || *image.height() == 0 || *image.layers() == 0 {
+            return Err(TextureError::EmptyImage(*image.width(), *image.height()));
+        }
+
+        let texture = Texture::new(renderer, TextureDescriptor {
+            width: *image.width(),
+            height: *image.height(),
+            depth: (*image.layers() > 1 || description.depth.is_some()).then_some(*image.layers()),
+            usage: description.usage | wgpu::TextureUsages::COPY_DST,
+            ..description
+        });
+
+        renderer.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            image.data(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * image.width()),
+                rows_per_image: Some(*image.height()),
+            },
+            texture.description.size(),
+        );
+
+        if texture.description.mip_level_count > 1 {
+            texture.generate_mipmaps(renderer)?;
+        }
+
+        Ok(texture)
+    }
+
+    /// Checks if mip levels can be generated for a texture, which must be a single-sampled
+    /// 2D texture with a filterable and renderable format.
+    fn supports_mipmaps(renderer: &Renderer, description: &TextureDescriptor) -> bool {
+        let features = renderer.adapter.get_texture_format_features(description.format);
+
+        description.dimension == TextureDimension::D2
+            && description.sample_count == 1
+            && features.flags.contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+            && features.allowed_usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+    }
+
+    /// Generates all mip levels of the texture from the first one, by rendering
+    /// each level from the previous one with linear filtering.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TextureError::UnsupportedMipmaps` if the texture is not a 2D texture,
+    /// or its format is not filterable or renderable.
+    pub fn generate_mipmaps(&self, renderer: &Renderer) -> Result<(), TextureError> {
+        let format = self.description.format;
+
+        if !Texture::supports_mipmaps(renderer, &self.description) {
+            return Err(TextureError::UnsupportedMipmaps(self.description.dimension, format));
+        }
+
+        let shader = renderer.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Mipmap shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("mipmap.wgsl").into()),
+        });
+
+        let pipeline = renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Mipmap pipeline"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let sampler = renderer.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Mipmap sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let level_view = |layer: u32, level: u32| {
+            self.texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            })
+        };
+
+        let mut encoder = renderer.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Mipmap generation"),
+        });
+
+        for layer in 0..self.description.depth.unwrap_or(1) {
+            for level in 1..self.description.mip_level_count {
+                let source = level_view(layer, level - 1);
+                let target = level_view(layer, level);
+
+                let bind_group = renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Mipmap bind group"),
+                    layout: &pipeline.get_bind_group_layout(0),
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&source),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                    ],
+                });
+
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Mipmap pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &target,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                });
+
+                pass.set_pipeline(&pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+
+        renderer.queue.submit(std::iter::once(encoder.finish()));
+
+        Ok(())
+    }
+
+    /// Stages tightly packed texel data to be written into a region of the texture
+    /// when the next `DrawContext` is applied, within the upload budget of the renderer.
+    /// A newer staged write of the same region replaces a pending one.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer staging the upload.
+    /// * `origin` - The first texel of the region.
+    /// * `size` - The size of the region in texels.
+    /// * `data` - The texels of the region, row by row and layer by layer.
+    pub fn stage(
+        &self,
+        renderer: &Renderer,
+        origin: wgpu::Origin3d,
+        size: wgpu::Extent3d,
+        data: Vec<u8>,
+    ) {
+        let bytes_per_row = size.width * self.description.format.block_copy_size(None).unwrap_or(4);
+
+        renderer.stage_upload(
+            UploadTarget::Texture {
+                texture: self.texture.clone(),
+                origin,
+                size,
+                bytes_per_row,
+            },
+            data,
+            true,
+        );
+    }
+
     pub fn resize(&mut self, renderer: &Renderer, size: PhysicalSize<u32>) {
         let mut descr = self.description;
         descr.width = size.width;
@@ -1,18 +1,28 @@
+use std::cell::RefCell;
 use std::sync::Arc;
 use bytemuck::Pod;
+use config::RendererConfig;
 use error::RenderError;
 use hal::{
-    buffer::{Buffer, BufferId, InvalidBufferId}, 
+    allocator::{ArenaDescriptor, BufferArena, SubBuffer},
+    buffer::{Buffer, BufferId, BufferSlots, InvalidBufferId}, 
+    instance::{check_instance_data_size, InstanceUniforms, MAX_INSTANCE_DATA_SIZE},
     pipeline::{Pipeline, ShaderResource}, 
+    staging::{UploadStats, UploadTarget, Uploads},
     texture::*
 };
 use game_loop::winit::{
     dpi::PhysicalSize,
     window::Window,
 };
-use pbr::mesh::Vertex;
+use pbr::{
+    batch::{BatchCommand, DrawBatch, DrawIndirect},
+    mesh::Vertex,
+};
 
+pub mod config;
 pub mod error;
+pub mod graph;
 pub mod voxel;
 pub mod pbr;
 pub mod hal;
@@ -28,24 +38,77 @@ pub mod types {
         TextureUsages,
         TextureFormat,
         TextureSampleType,
+        TextureViewDimension,
+        StorageTextureAccess,
+        AddressMode,
         Extent3d,
         ShaderSource,
         TextureView,
+        Backends,
+        PowerPreference,
+        BlendState,
+        ColorWrites,
+        CompareFunction,
+        Face,
+        FrontFace,
+        PolygonMode,
+        PrimitiveTopology,
+        VertexBufferLayout,
     };
 }
 
 pub use include_wgsl_oil::include_wgsl_oil;
 
+/// Optional features of the graphics adapter used by the renderer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Whether `InstanceData` is passed with push constants. Otherwise it is passed
+    /// through a uniform buffer bound as the last bind group of every pipeline.
+    pub push_constants: bool,
+    /// Whether a `DrawBatch` merges draws into indirect draws with instance offsets.
+    /// Otherwise every drawable of the batch is drawn separately.
+    pub multi_draw_indirect: bool,
+}
+
+impl Capabilities {
+    /// Features required for `multi_draw_indirect`.
+    const MULTI_DRAW_INDIRECT: wgpu::Features = wgpu::Features::MULTI_DRAW_INDIRECT
+        .union(wgpu::Features::INDIRECT_FIRST_INSTANCE);
+
+    fn detect(adapter: &wgpu::Adapter) -> Capabilities {
+        Capabilities {
+            push_constants: adapter.features().contains(wgpu::Features::PUSH_CONSTANTS)
+                && adapter.limits().max_push_constant_size >= MAX_INSTANCE_DATA_SIZE,
+            multi_draw_indirect: adapter.features().contains(Capabilities::MULTI_DRAW_INDIRECT),
+        }
+    }
+}
+
+/// A vertex buffer sub-allocated from the vertex arena.
+#[derive(Debug, Clone, Copy)]
+struct VertexBuffer {
+    handle: SubBuffer<Vertex>,
+    /// Number of vertices to draw, which may be less than the capacity of the range.
+    count: usize,
+}
+
 /// Represents a renderer that handles drawing to a window using wgpu.
 pub struct Renderer {
     window: Arc<Window>,
     surface: wgpu::Surface<'static>,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
+    adapter: wgpu::Adapter,
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
     config: wgpu::SurfaceConfiguration,
+    renderer_config: RendererConfig,
     size: PhysicalSize<u32>,
-    vertex_buffers: Vec<Buffer<Vertex>>,
+    capabilities: Capabilities,
+    instance_uniforms: Option<InstanceUniforms>,
+    vertex_arena: BufferArena,
+    vertex_buffers: BufferSlots<VertexBuffer>,
+    uploads: RefCell<Uploads>,
     depth_texture: Option<Texture>,
+    msaa_texture: Option<Texture>,
 }
 
 impl Renderer {
@@ -53,53 +116,110 @@ impl Renderer {
     ///
     /// # Parameters
     /// - `window`: A shared Window instance that represents the window to render into.
+    /// - `renderer_config`: The configuration of the renderer.
     ///
     /// # Returns
     /// A `Result` containing the `Renderer` instance or an error if creation fails.
-    pub async fn new(window: Arc<Window>) -> anyhow::Result<Renderer> {
+    ///
+    /// # Errors
+    /// Returns an error if the surface cannot be created, no compatible adapter is found,
+    /// the logical device cannot be requested or the sample count is not supported.
+    pub async fn new(window: Arc<Window>, renderer_config: RendererConfig) -> Result<Renderer, RenderError> {
         let size = window.inner_size();
 
-        let instance = Self::init_instance();
-        let surface = instance.create_surface(window.clone()).unwrap();
-        let adapter = Self::init_adapter(instance, &surface).await;
-        let (device, queue) = Self::init_device(&adapter).await?;
+        let instance = Self::init_instance(&renderer_config);
+        let surface = instance.create_surface(window.clone())?;
+        let adapter = Self::init_adapter(instance, &surface, &renderer_config).await?;
+        let capabilities = Capabilities::detect(&adapter);
+        let (device, queue) = Self::init_device(&adapter, capabilities).await?;
+        let (device, queue) = (Arc::new(device), Arc::new(queue));
 
-        let surface_caps = surface.get_capabilities(&adapter);
-        let surface_format = surface_caps.formats.iter()
-            .find(|f| f.is_srgb())
-            .copied()
-            .unwrap_or(surface_caps.formats[0]);
+        let instance_uniforms = (!capabilities.push_constants)
+            .then(|| InstanceUniforms::new(&device));
 
-        let config = Self::init_config(surface_format, size, surface_caps);
+        let config = Self::init_config(size, surface.get_capabilities(&adapter), &renderer_config);
+
+        let vertex_arena = BufferArena::from_device(device.clone(), queue.clone(), ArenaDescriptor {
+            usage: wgpu::BufferUsages::VERTEX,
+            label: "Vertex arena",
+            ..Default::default()
+        });
 
         let mut renderer = Renderer {
             surface,
             device,
             queue,
+            adapter,
             config,
+            renderer_config,
             size,
             window,
-            vertex_buffers: vec![],
+            capabilities,
+            instance_uniforms,
+            vertex_arena,
+            vertex_buffers: BufferSlots::default(),
+            uploads: RefCell::new(Uploads::new(renderer_config.upload_budget)),
             depth_texture: None,
+            msaa_texture: None,
         };
 
-        renderer.depth_texture = Some(Texture::new(
-            &renderer, 
-            TextureDescriptor {
-                width: renderer.config.width,
-                height: renderer.config.height,
-                filter: wgpu::FilterMode::Linear,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Depth32Float,
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-                depth: None,
-                label: "Depth data",
-            },
-        ));
+        renderer.check_sample_count(&renderer_config, renderer.config.format)?;
+        renderer.surface.configure(&renderer.device, &renderer.config);
+        renderer.create_attachments();
 
         Ok(renderer)
     }
 
+    /// Retrieves the configuration of the renderer.
+    ///
+    /// # Returns
+    /// A reference to the `RendererConfig`.
+    pub fn config(&self) -> &RendererConfig {
+        &self.renderer_config
+    }
+
+    /// Changes the configuration of the renderer, reconfiguring the surface and
+    /// recreating the depth and multisampled textures.
+    ///
+    /// Pipelines created before a change of the sample count or the surface format
+    /// must be recreated. `backends` and `power_preference` are ignored, as they are
+    /// only used to select the adapter when the renderer is created.
+    ///
+    /// # Parameters
+    /// - `renderer_config`: The new configuration.
+    ///
+    /// # Returns
+    /// A `Result` indicating failure if the sample count is not supported.
+    pub fn set_config(&mut self, renderer_config: RendererConfig) -> Result<(), RenderError> {
+        let surface_caps = self.surface.get_capabilities(&self.adapter);
+        let format = renderer_config.surface_format(&surface_caps);
+        self.check_sample_count(&renderer_config, format)?;
+
+        self.config.format = format;
+        self.config.present_mode = renderer_config.surface_present_mode(&surface_caps);
+        self.renderer_config = RendererConfig {
+            backends: self.renderer_config.backends,
+            power_preference: self.renderer_config.power_preference,
+            ..renderer_config
+        };
+
+        self.uploads.get_mut().set_budget(renderer_config.upload_budget);
+        self.surface.configure(&self.device, &self.config);
+        self.create_attachments();
+
+        Ok(())
+    }
+
+    /// Retrieves the format of the render surface, which is also the format of render pipeline targets.
+    pub fn surface_format(&self) -> wgpu::TextureFormat {
+        self.config.format
+    }
+
+    /// Retrieves the number of MSAA samples per pixel used by render passes and pipelines.
+    pub fn sample_count(&self) -> u32 {
+        self.renderer_config.sample_count
+    }
+
     /// Retrieves the current canvas for drawing.
     ///
     /// # Returns
@@ -118,6 +238,9 @@ impl Renderer {
     pub fn draw_ctx(&self) -> DrawContext {
         DrawContext {
             encoder: self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default()),
+            msaa_view: self.msaa_texture
+                .as_ref()
+                .map(|t| t.texture().create_view(&wgpu::TextureViewDescriptor::default())),
         }
     }
 
@@ -145,35 +268,31 @@ impl Renderer {
         self.config.width = new_size.width;
         self.config.height = new_size.height;
         self.surface.configure(&self.device, &self.config);
-
-        if let Some(depth_texture) = &self.depth_texture {
-            let mut depth_descr = *depth_texture.description();
-            depth_descr.width = self.config.width;
-            depth_descr.height = self.config.height;
-            self.depth_texture = Some(Texture::new(self, depth_descr));
-        }
+        self.create_attachments();
     }
 
-    /// Creates a new vertex buffer with a specified capacity.
+    /// Creates a new vertex buffer with a specified capacity,
+    /// sub-allocated from the pooled vertex buffers.
     ///
     /// # Parameters
     /// - `capacity`: The capacity of the vertex buffer.
     ///
     /// # Returns
     /// The ID of the newly created vertex buffer.
-    pub fn create_vertex_buffer(&mut self, capacity: usize) -> BufferId {
-        let id = self.vertex_buffers.len();
-
-        self.vertex_buffers.push(Buffer::new(
-            self,
-            capacity,
-            wgpu::BufferUsages::VERTEX,
-        ));
+    ///
+    /// # Errors
+    /// Returns an error if the buffer exceeds the maximum buffer size.
+    pub fn create_vertex_buffer(&mut self, capacity: usize) -> Result<BufferId, RenderError> {
+        let buffer = VertexBuffer {
+            handle: self.vertex_arena.allocate(capacity)?,
+            count: 0,
+        };
 
-        BufferId(id)
+        Ok(self.vertex_buffers.insert(buffer))
     }
 
-    /// Updates the data in an existing vertex buffer.
+    /// Replaces the vertices of an existing vertex buffer, growing it if the data does not fit.
+    /// Only the new vertices are drawn, even if the buffer has a larger capacity.
     ///
     /// # Parameters
     /// - `id`: The ID of the vertex buffer to update.
@@ -181,20 +300,100 @@ impl Renderer {
     ///
     /// # Returns
     /// A `Result` indicating success or failure. 
-    pub fn update_vertex_buffer(&mut self, id: BufferId, data: &[Vertex]) -> Result<(), InvalidBufferId> {
-        if self.vertex_buffers
-            .get(id.0)
-            .ok_or(InvalidBufferId(id))?
-            .fill_exact(self, 0, data).is_err() 
-            {
-                let mut buffer = self.vertex_buffers.swap_remove(id.0);
-                buffer.fill(self, 0, data);
-                self.vertex_buffers.insert(id.0, buffer);
-            }
+    ///
+    /// # Errors
+    /// Returns an error if the ID is invalid or the buffer cannot grow.
+    pub fn update_vertex_buffer(&mut self, id: BufferId, data: &[Vertex]) -> Result<(), RenderError> {
+        let mut buffer = *self.vertex_buffer(id)?;
+
+        if data.len() > buffer.handle.len() {
+            // Grow geometrically, so meshes growing by a few vertices are not reallocated on every update
+            let capacity = data.len().max(buffer.handle.len() * 2);
+            buffer.handle = self.vertex_arena.resize(buffer.handle, capacity)?;
+        }
+
+        self.vertex_arena.write(&buffer.handle, 0, data)?;
+        buffer.count = data.len();
+
+        *self.vertex_buffers.get_mut(id)? = buffer;
+
+        Ok(())
+    }
+
+    /// Destroys a vertex buffer, releasing its memory for other buffers.
+    /// The ID and its copies become invalid.
+    ///
+    /// # Parameters
+    /// - `id`: The ID of the vertex buffer to destroy.
+    ///
+    /// # Errors
+    /// Returns an error if the ID is invalid, e.g. if the buffer has already been destroyed.
+    pub fn destroy_vertex_buffer(&mut self, id: BufferId) -> Result<(), RenderError> {
+        let buffer = *self.vertex_buffer(id)?;
+        self.vertex_arena.free(buffer.handle)?;
+        self.vertex_buffers.remove(id)?;
 
         Ok(())
     }
 
+    /// Retrieves the number of vertices drawn from a vertex buffer.
+    ///
+    /// # Parameters
+    /// - `id`: The ID of the vertex buffer.
+    ///
+    /// # Returns
+    /// The number of vertices written by the last update.
+    ///
+    /// # Errors
+    /// Returns an error if the ID is invalid.
+    pub fn vertex_count(&self, id: BufferId) -> Result<usize, InvalidBufferId> {
+        self.vertex_buffer(id).map(|buffer| buffer.count)
+    }
+
+    fn vertex_buffer(&self, id: BufferId) -> Result<&VertexBuffer, InvalidBufferId> {
+        self.vertex_buffers.get(id)
+    }
+
+    /// Retrieves the pooled buffers vertex buffers are sub-allocated from, e.g. for memory statistics.
+    ///
+    /// # Returns
+    /// The vertex `BufferArena`.
+    pub fn vertex_arena(&self) -> &BufferArena {
+        &self.vertex_arena
+    }
+
+    /// Compacts the pooled vertex buffers, e.g. after many chunks have been unloaded.
+    ///
+    /// # Returns
+    /// The number of moved vertex buffers.
+    pub fn defragment_vertex_buffers(&mut self) -> usize {
+        self.vertex_arena.defragment()
+    }
+
+    /// Retrieves the staged uploads of the last submitted frame, including
+    /// how many bytes were deferred as they exceed the upload budget.
+    ///
+    /// # Returns
+    /// The `UploadStats` of the last frame.
+    pub fn upload_stats(&self) -> UploadStats {
+        self.uploads.borrow().stats()
+    }
+
+    /// Retrieves the number of bytes of staged uploads waiting for the next frame.
+    pub fn pending_upload_bytes(&self) -> u64 {
+        self.uploads.borrow().pending_bytes()
+    }
+
+    /// Queues an upload, copied from a staging buffer when the next `DrawContext` is applied.
+    pub(crate) fn stage_upload(&self, target: UploadTarget, data: Vec<u8>, deferrable: bool) {
+        self.uploads.borrow_mut().push(target, data, deferrable);
+    }
+
+    /// Moves the staged uploads of a buffer to the buffer replacing it, once its contents have been copied.
+    pub(crate) fn retarget_uploads(&self, old: &Arc<wgpu::Buffer>, new: &Arc<wgpu::Buffer>, size: u64) {
+        self.uploads.borrow_mut().retarget(old, new, size);
+    }
+
     /// Retrieves the current size of the renderer.
     ///
     /// # Returns
@@ -203,6 +402,14 @@ impl Renderer {
         self.size
     }
 
+    /// Retrieves the optional features of the graphics adapter used by the renderer.
+    ///
+    /// # Returns
+    /// The detected `Capabilities`.
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
     /// Retrieves the depth texture used for depth testing.
     ///
     /// # Returns
@@ -211,44 +418,133 @@ impl Renderer {
         self.depth_texture.as_ref()
     }
 
-    async fn init_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
-        adapter.request_device(
+    fn create_attachments(&mut self) {
+        self.depth_texture = Some(Texture::new(self, TextureDescriptor {
+            width: self.config.width,
+            height: self.config.height,
+            filter: wgpu::FilterMode::Linear,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Depth32Float,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            depth: None,
+            sample_count: self.sample_count(),
+            label: "Depth data",
+            ..Default::default()
+        }));
+
+        self.msaa_texture = (self.sample_count() > 1).then(|| Texture::new(self, TextureDescriptor {
+            width: self.config.width,
+            height: self.config.height,
+            filter: wgpu::FilterMode::Linear,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            depth: None,
+            sample_count: self.sample_count(),
+            label: "Multisampled color",
+            ..Default::default()
+        }));
+    }
+
+    fn check_sample_count(&self, renderer_config: &RendererConfig, format: wgpu::TextureFormat) -> Result<(), RenderError> {
+        // Without adapter specific format features only 1 and 4 samples are guaranteed
+        let format_features = self.device.features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+            .then(|| [format, wgpu::TextureFormat::Depth32Float].map(|format| {
+                self.adapter.get_texture_format_features(format).flags
+            }));
+
+        Renderer::validate_sample_count(renderer_config.sample_count, format_features)
+    }
+
+    /// Checks if a sample count is supported by the color and depth attachments.
+    ///
+    /// # Parameters
+    /// - `sample_count`: The requested number of samples.
+    /// - `format_features`: The adapter specific features of the color and depth formats,
+    ///   or `None` if only the guaranteed sample counts can be used.
+    ///
+    /// # Returns
+    /// An error if any of the attachments cannot be multisampled with `sample_count` samples.
+    fn validate_sample_count(
+        sample_count: u32,
+        format_features: Option<[wgpu::TextureFormatFeatureFlags; 2]>,
+    ) -> Result<(), RenderError> {
+        let supported = match format_features {
+            Some(features) => features.iter().all(|flags| flags.sample_count_supported(sample_count)),
+            None => matches!(sample_count, 1 | 4),
+        };
+
+        if !supported {
+            return Err(RenderError::UnsupportedSampleCount(sample_count));
+        }
+
+        Ok(())
+    }
+
+    async fn init_device(adapter: &wgpu::Adapter, capabilities: Capabilities) -> Result<(wgpu::Device, wgpu::Queue), RenderError> {
+        let (mut required_features, max_push_constant_size) = if capabilities.push_constants {
+            (wgpu::Features::PUSH_CONSTANTS, MAX_INSTANCE_DATA_SIZE)
+        } else {
+            (wgpu::Features::empty(), 0)
+        };
+
+        // Allows sample counts other than 1 and 4
+        required_features |= adapter.features() & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+
+        if capabilities.multi_draw_indirect {
+            required_features |= Capabilities::MULTI_DRAW_INDIRECT;
+        }
+
+        // Request everything the adapter supports, so downlevel adapters are not rejected
+        // by the default limits and capable ones are not restricted to them
+        let device = adapter.request_device(
             &wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::PUSH_CONSTANTS,
+                required_features,
                 required_limits: wgpu::Limits {
-                    max_push_constant_size: 128,
-                    ..Default::default()
+                    max_push_constant_size,
+                    ..adapter.limits()
                 },
                 label: Some("Logical device"),
             },
             None,
-        ).await
+        ).await?;
+
+        Ok(device)
     }
 
-    async fn init_adapter(instance: wgpu::Instance, surface: &wgpu::Surface<'static>) -> wgpu::Adapter {
+    async fn init_adapter(
+        instance: wgpu::Instance, 
+        surface: &wgpu::Surface<'static>,
+        renderer_config: &RendererConfig,
+    ) -> Result<wgpu::Adapter, RenderError> {
         instance.request_adapter(
             &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
+                power_preference: renderer_config.power_preference,
                 compatible_surface: Some(surface),
                 force_fallback_adapter: false,
             }
-        ).await.unwrap()
+        ).await.ok_or(RenderError::AdapterNotFound)
     }
 
-    fn init_instance() -> wgpu::Instance {
+    fn init_instance(renderer_config: &RendererConfig) -> wgpu::Instance {
         wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
+            backends: renderer_config.backends,
             ..Default::default()
         })
     }
 
-    fn init_config(surface_format: wgpu::TextureFormat, size: PhysicalSize<u32>, surface_caps: wgpu::SurfaceCapabilities) -> wgpu::SurfaceConfiguration {
+    fn init_config(
+        size: PhysicalSize<u32>, 
+        surface_caps: wgpu::SurfaceCapabilities,
+        renderer_config: &RendererConfig,
+    ) -> wgpu::SurfaceConfiguration {
         wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
+            format: renderer_config.surface_format(&surface_caps),
             width: size.width,
             height: size.height,
-            present_mode: surface_caps.present_modes[0],
+            present_mode: renderer_config.surface_present_mode(&surface_caps),
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
@@ -259,11 +555,15 @@ impl Renderer {
 /// Represents a drawing context used for issuing draw commands.
 pub struct DrawContext {
     encoder: wgpu::CommandEncoder,
+    msaa_view: Option<wgpu::TextureView>,
 }
 
 impl DrawContext {
     /// Begins a new render pass with the specified canvas and depth texture.
     ///
+    /// With multisampling enabled, the pass renders into the multisampled texture of the
+    /// renderer and resolves it into the canvas, which must have the size of the surface.
+    ///
     /// # Parameters
     /// - `canvas`: The canvas to render to.
     /// - `depth_texture`: The depth texture to use for depth testing.
@@ -278,8 +578,8 @@ impl DrawContext {
         let pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: canvas.view(),
-                resolve_target: None,
+                view: self.msaa_view.as_ref().unwrap_or(canvas.view()),
+                resolve_target: self.msaa_view.as_ref().map(|_| canvas.view()),
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                     store: wgpu::StoreOp::Store,
@@ -302,6 +602,54 @@ impl DrawContext {
         RenderPass { pass }
     }
 
+    /// Begins a new render pass writing into several colour targets, e.g. G-buffers.
+    ///
+    /// The targets are not multisampled, so pipelines used in this pass must be built
+    /// with `PipelineBuilder::set_sample_count(1)` when multisampling is enabled.
+    ///
+    /// # Parameters
+    /// - `targets`: The colour targets, in order of the fragment output locations.
+    /// - `depth_texture`: The depth texture to use for depth testing.
+    ///
+    /// # Returns
+    /// A `RenderPass` instance for issuing draw commands.
+    pub fn render_pass_with_targets<'a>(
+        &'a mut self,
+        targets: &[&'a dyn RenderSurface],
+        depth_texture: Option<&'a Texture>,
+    ) -> RenderPass<'a> {
+        let color_attachments = targets
+            .iter()
+            .map(|target| Some(wgpu::RenderPassColorAttachment {
+                view: target.view(),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            }))
+            .collect::<Vec<_>>();
+
+        let pass = self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render pass"),
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: depth_texture.map(|t| {
+                wgpu::RenderPassDepthStencilAttachment {
+                    view: t.view(),
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        RenderPass { pass }
+    }
+
     pub fn compute_pass(&mut self) -> ComputePass<'_> {
         let pass = self.encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
             label: Some("Compute pass"),
@@ -315,6 +663,12 @@ impl DrawContext {
         self.encoder.clear_buffer(buffer.inner(), 0, None);
     }
 
+    pub fn copy_buffer<T: Pod>(&mut self, from: &Buffer<T>, to: &Buffer<T>) {
+        let size = (*from.capacity()).min(*to.capacity()) * std::mem::size_of::<T>();
+
+        self.encoder.copy_buffer_to_buffer(from.inner(), 0, to.inner(), 0, size as u64);
+    }
+
     pub fn copy_texture(&mut self, from: &Texture, to: &Texture) {
         self.encoder.copy_texture_to_texture(
             wgpu::ImageCopyTexture {
@@ -339,12 +693,22 @@ impl DrawContext {
 
     /// Applies the drawing commands and presents the canvas.
     ///
+    /// Staged uploads within the upload budget are copied before the drawing commands.
+    ///
     /// # Parameters
     /// - `canvas`: The canvas to present.
     /// - `renderer`: The renderer instance used to submit commands.
-    pub fn apply(self, canvas: Canvas, renderer: &Renderer) {        
-        renderer.queue.submit(std::iter::once(self.encoder.finish()));
+    pub fn apply(self, canvas: Canvas, renderer: &Renderer) {
+        let uploads = renderer.uploads.borrow_mut().flush(&renderer.device);
+
+        renderer.queue.submit(uploads.into_iter().chain(std::iter::once(self.encoder.finish())));
         canvas.texture.present();
+
+        renderer.uploads.borrow_mut().recall();
+
+        if let Some(instance_uniforms) = &renderer.instance_uniforms {
+            instance_uniforms.reset();
+        }
     }
 }
 
@@ -353,31 +717,59 @@ pub struct ComputePass<'a> {
 }
 
 impl<'a> ComputePass<'a> {
+    /// Dispatches a compute pipeline.
+    ///
+    /// # Parameters
+    /// - `renderer`: The renderer instance, used to upload instance data without push constants.
+    /// - `instance_data`: Optional data passed as push constants or the instance data uniform.
+    /// - `pipeline`: The compute pipeline.
+    /// - `shader_resources`: The bind groups, in order of their group indices.
+    /// - `size`: The number of workgroups in X and Y.
+    ///
+    /// # Returns
+    /// A `Result` indicating failure, e.g. if a render pipeline is passed.
     pub fn compute<T: Pod>(
         &mut self,
+        renderer: &'a Renderer,
         instance_data: Option<&mut dyn InstanceData<UniformData = T>>,
         pipeline: &'a Pipeline,
         shader_resources: &[&'a ShaderResource],
         size: PhysicalSize<u32>,
-    ) {
+    ) -> Result<(), RenderError> {
         if let Pipeline::Compute(p) = pipeline {
             self.pass.set_pipeline(p);
         } else {
-            panic!("Cannot use render pipeline in compute() command");
+            return Err(RenderError::PipelineMismatch("render"));
         }
 
         for (i, binding) in shader_resources.iter().enumerate() {
-            self.pass.set_bind_group(i as u32, &binding.bind_group, &[]);
+            self.pass.set_bind_group(i as u32, binding.bind_group()?, &[]);
         }
 
-        if let Some(instance_data) = instance_data {
-            self.pass.set_push_constants(
-                0,
-                bytemuck::cast_slice(&[instance_data.uniform_data()]),
-            );
+        match &renderer.instance_uniforms {
+            None => if let Some(instance_data) = instance_data {
+                check_instance_data_size::<T>()?;
+                self.pass.set_push_constants(
+                    0,
+                    bytemuck::cast_slice(&[instance_data.uniform_data()]),
+                );
+            },
+            Some(instance_uniforms) => {
+                let offset = match instance_data {
+                    Some(instance_data) => {
+                        check_instance_data_size::<T>()?;
+                        instance_uniforms.push(renderer, &instance_data.uniform_data())?
+                    },
+                    None => 0,
+                };
+
+                self.pass.set_bind_group(shader_resources.len() as u32, &instance_uniforms.bind_group, &[offset]);
+            },
         }
 
         self.pass.dispatch_workgroups(size.width, size.height, 1);
+
+        Ok(())
     }
 }
 
@@ -387,6 +779,17 @@ pub struct RenderPass<'a> {
 }
 
 impl<'a> RenderPass<'a> {
+    /// Draws a drawable, or a full-screen quad of 6 vertices if no drawable is passed.
+    ///
+    /// # Parameters
+    /// - `renderer`: The renderer instance owning the vertex buffers.
+    /// - `drawable`: The optional drawable to draw.
+    /// - `instance_data`: Optional data passed as push constants or the instance data uniform.
+    /// - `pipeline`: The render pipeline.
+    /// - `shader_resources`: The bind groups, in order of their group indices.
+    ///
+    /// # Returns
+    /// A `Result` indicating failure, e.g. if a compute pipeline is passed.
     pub fn draw<T: Pod>(
         &mut self,
         renderer: &'a Renderer,
@@ -394,31 +797,116 @@ impl<'a> RenderPass<'a> {
         instance_data: Option<&mut dyn InstanceData<UniformData = T>>,
         pipeline: &'a Pipeline,
         shader_resources: &[&'a ShaderResource],
-    ) {
+    ) -> Result<(), RenderError> {
         if let Pipeline::Render(p) = pipeline {
             self.pass.set_pipeline(p);
         } else {
-            panic!("Cannot use compute pipeline in draw() command");
+            return Err(RenderError::PipelineMismatch("compute"));
         }
 
         for (i, binding) in shader_resources.iter().enumerate() {
-            self.pass.set_bind_group(i as u32, &binding.bind_group, &[]);
+            self.pass.set_bind_group(i as u32, binding.bind_group()?, &[]);
         }
 
-        if let Some(instance_data) = instance_data {
-            self.pass.set_push_constants(
-                wgpu::ShaderStages::VERTEX,
-                0,
-                bytemuck::cast_slice(&[instance_data.uniform_data()]),
-            );
+        match &renderer.instance_uniforms {
+            None => if let Some(instance_data) = instance_data {
+                check_instance_data_size::<T>()?;
+                self.pass.set_push_constants(
+                    wgpu::ShaderStages::VERTEX,
+                    0,
+                    bytemuck::cast_slice(&[instance_data.uniform_data()]),
+                );
+            },
+            Some(instance_uniforms) => {
+                let offset = match instance_data {
+                    Some(instance_data) => {
+                        check_instance_data_size::<T>()?;
+                        instance_uniforms.push(renderer, &instance_data.uniform_data())?
+                    },
+                    None => 0,
+                };
+
+                self.pass.set_bind_group(shader_resources.len() as u32, &instance_uniforms.bind_group, &[offset]);
+            },
         }
         
         if let Some(drawable) = drawable {
-            self.pass.set_vertex_buffer(0, renderer.vertex_buffers[drawable.vertex_buffer().0].inner().slice(..)); 
-            self.pass.draw(0..*renderer.vertex_buffers[drawable.vertex_buffer().0].capacity() as u32, 0..1);
+            let id = drawable.vertex_buffer().ok_or(RenderError::DrawableNotSetUp)?;
+            let vertex_buffer = renderer.vertex_buffer(id)?;
+
+            if vertex_buffer.count > 0 {
+                self.pass.set_vertex_buffer(0, renderer.vertex_arena().slice(&vertex_buffer.handle)?); 
+                self.pass.draw(0..vertex_buffer.count as u32, 0..1);
+            }
         } else {
             self.pass.draw(0..6, 0..1);
         }
+
+        Ok(())
+    }
+
+    /// Draws the drawables of a prepared batch, switching pipelines in the order they are sorted.
+    ///
+    /// # Parameters
+    /// - `renderer`: The renderer instance owning the vertex buffers.
+    /// - `batch`: The batch, prepared with `DrawBatch::prepare` for this frame.
+    /// - `pipelines`: The render pipelines, indexed by the pipeline indices of the drawables.
+    /// - `shader_resources`: The bind groups, in order of their group indices, including
+    ///   the shader resource of the batch.
+    ///
+    /// # Returns
+    /// A `Result` indicating failure, e.g. if a pipeline is missing or a compute pipeline is passed.
+    pub fn draw_batch<T: Pod>(
+        &mut self,
+        renderer: &'a Renderer,
+        batch: &'a DrawBatch<T>,
+        pipelines: &[&'a Pipeline],
+        shader_resources: &[&'a ShaderResource],
+    ) -> Result<(), RenderError> {
+        for (i, binding) in shader_resources.iter().enumerate() {
+            self.pass.set_bind_group(i as u32, binding.bind_group()?, &[]);
+        }
+
+        if let Some(instance_uniforms) = &renderer.instance_uniforms {
+            self.pass.set_bind_group(shader_resources.len() as u32, &instance_uniforms.bind_group, &[0]);
+        }
+
+        let mut current_pipeline = None;
+
+        for command in batch.commands() {
+            let (BatchCommand::Direct { pipeline, vertex_buffer, .. }
+                | BatchCommand::Indirect { pipeline, vertex_buffer, .. }) = *command;
+
+            if current_pipeline != Some(pipeline) {
+                match pipelines.get(pipeline) {
+                    Some(Pipeline::Render(p)) => self.pass.set_pipeline(p),
+                    Some(_) => return Err(RenderError::PipelineMismatch("compute")),
+                    None => return Err(RenderError::MissingPipeline(pipeline)),
+                }
+                current_pipeline = Some(pipeline);
+            }
+
+            let vertex_buffer = renderer.vertex_buffer(vertex_buffer)?;
+
+            match *command {
+                BatchCommand::Direct { instance, .. } => {
+                    self.pass.set_vertex_buffer(0, renderer.vertex_arena().slice(&vertex_buffer.handle)?);
+                    self.pass.draw(0..vertex_buffer.count as u32, instance..instance + 1);
+                },
+                BatchCommand::Indirect { first, count, .. } => {
+                    let (buffer, _) = renderer.vertex_arena().block(&vertex_buffer.handle)?;
+
+                    self.pass.set_vertex_buffer(0, buffer.slice(..));
+                    self.pass.multi_draw_indirect(
+                        batch.indirect_buffer(),
+                        first as u64 * std::mem::size_of::<DrawIndirect>() as u64,
+                        count,
+                    );
+                },
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -444,17 +932,51 @@ pub trait Drawable {
     ///
     /// # Parameters
     /// - `renderer`: The renderer instance used to update the drawable.
-    fn update(&mut self, renderer: &mut Renderer);
+    ///
+    /// # Errors
+    /// Returns an error if the drawable data cannot be built or uploaded.
+    fn update(&mut self, renderer: &mut Renderer) -> Result<(), RenderError>;
 
     /// Retrieves the ID of the vertex buffer used by the drawable.
     ///
     /// # Returns
-    /// The ID of the vertex buffer.
-    fn vertex_buffer(&self) -> BufferId;
+    /// The ID of the vertex buffer, or `None` if the drawable is not set up with `update()` yet.
+    fn vertex_buffer(&self) -> Option<BufferId>;
 }
 
 pub trait InstanceData {
     type UniformData: Pod;
 
     fn uniform_data(&mut self) -> Self::UniformData;
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guaranteed_sample_counts_are_accepted_without_format_features() {
+        assert!(Renderer::validate_sample_count(1, None).is_ok());
+        assert!(Renderer::validate_sample_count(4, None).is_ok());
+    }
+
+    #[test]
+    fn other_sample_counts_are_rejected_without_format_features() {
+        for sample_count in [2, 8, 16] {
+            let error = Renderer::validate_sample_count(sample_count, None).unwrap_err();
+            assert!(matches!(error, RenderError::UnsupportedSampleCount(count) if count == sample_count));
+        }
+    }
+
+    #[test]
+    fn format_features_decide_the_supported_sample_counts() {
+        let x8 = wgpu::TextureFormatFeatureFlags::MULTISAMPLE_X4 | wgpu::TextureFormatFeatureFlags::MULTISAMPLE_X8;
+        let x4 = wgpu::TextureFormatFeatureFlags::MULTISAMPLE_X4;
+
+        assert!(Renderer::validate_sample_count(8, Some([x8, x8])).is_ok());
+        assert!(matches!(
+            Renderer::validate_sample_count(8, Some([x8, x4])),
+            Err(RenderError::UnsupportedSampleCount(8))
+        ));
+    }
+}
@@ -0,0 +1,331 @@
+use std::mem::size_of;
+
+use bytemuck::{Pod, Zeroable};
+use nalgebra_glm as glm;
+
+use crate::renderer::{
+    error::RenderError,
+    hal::{
+        buffer::{Buffer, BufferId, BufferResourceDescriptor},
+        pipeline::ShaderResource,
+    },
+    pbr::{
+        camera::CameraProjection,
+        frustum::{Aabb, Frustum},
+        mesh::Vertex,
+        transform::Transform,
+    },
+    types::*,
+    Drawable, Renderer,
+};
+
+/// Arguments of an indirect draw, in the layout expected by `multi_draw_indirect`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Zeroable, Pod)]
+pub struct DrawIndirect {
+    pub vertex_count: u32,
+    pub instance_count: u32,
+    pub first_vertex: u32,
+    pub first_instance: u32,
+}
+
+/// Draws of the last prepared [`DrawBatch`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    /// Number of drawables inside the frustum.
+    pub visible: usize,
+    /// Number of drawables skipped by frustum culling.
+    pub culled: usize,
+    /// Number of recorded draw calls, each indirect draw counting once.
+    pub draw_calls: usize,
+}
+
+#[derive(Debug)]
+struct DrawItem<T> {
+    pipeline: usize,
+    distance: f32,
+    vertex_buffer: BufferId,
+    data: T,
+}
+
+/// A prepared draw, recorded by [`RenderPass::draw_batch`](crate::renderer::RenderPass::draw_batch).
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum BatchCommand {
+    /// Draws a single vertex buffer as the given instance.
+    Direct {
+        pipeline: usize,
+        vertex_buffer: BufferId,
+        instance: u32,
+    },
+    /// Draws `count` indirect commands of vertex buffers sharing a pooled buffer.
+    Indirect {
+        pipeline: usize,
+        vertex_buffer: BufferId,
+        first: u32,
+        count: u32,
+    },
+}
+
+/// Collects the drawables of a frame to cull them against the camera frustum, sort them
+/// by pipeline and front to back, and draw them with as few draw calls as possible.
+///
+/// The instance data of every draw, e.g. its `TransformUniform`, is written into a storage
+/// buffer bound by [`DrawBatch::shader_resource`], and read by the vertex shader with the
+/// instance index:
+///
+/// ```wgsl
+/// @group(2) @binding(0)
+/// var<storage, read> instances: array<TransformUniform>;
+///
+/// @vertex
+/// fn vs_main(input: VertexInput, @builtin(instance_index) instance: u32) -> VertexOutput {
+///     let transform = instances[instance];
+///     ...
+/// }
+/// ```
+///
+/// With [`Capabilities::multi_draw_indirect`](crate::renderer::Capabilities::multi_draw_indirect),
+/// consecutive draws of a pipeline sharing a pooled vertex buffer are merged into a single
+/// indirect draw. Otherwise each drawable is drawn separately.
+#[derive(Debug)]
+pub struct DrawBatch<T: Pod> {
+    items: Vec<DrawItem<T>>,
+    frustum: Option<Frustum>,
+    eye: glm::Vec3,
+    instance_buffer: Buffer<T>,
+    indirect_buffer: Buffer<DrawIndirect>,
+    shader_resource: ShaderResource,
+    commands: Vec<BatchCommand>,
+    stats: BatchStats,
+}
+
+impl<T: Pod> DrawBatch<T> {
+    /// Name of the instance data buffer in the shader resource.
+    const INSTANCES: &'static str = "instances";
+
+    /// Retrieves the entries of the bind group layout of the batch, which binds the
+    /// instance data as a read-only storage buffer, e.g. to validate shaders without a GPU device.
+    pub fn layout_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+        vec![BufferResourceDescriptor {
+            visibility: ShaderStages::VERTEX,
+            buffer_type: BufferBindingType::Storage { read_only: true },
+        }.layout_entry(0)]
+    }
+
+    /// Creates an empty batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `capacity` - The initial number of draws, grown when more drawables are visible.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `DrawBatch`.
+    pub fn new(renderer: &Renderer, capacity: usize) -> DrawBatch<T> {
+        let capacity = capacity.max(1);
+        let instance_buffer = Buffer::new(renderer, capacity, BufferUsages::STORAGE);
+        let indirect_buffer = Buffer::new(renderer, capacity, BufferUsages::INDIRECT);
+
+        let mut shader_resource = ShaderResource::new(
+            renderer,
+            Some("Draw batch".to_owned()),
+            Self::layout_entries(),
+            vec![vec![Self::INSTANCES.to_owned()]],
+        );
+        shader_resource
+            .set(Self::INSTANCES, &instance_buffer)
+            .and_then(|shader_resource| shader_resource.update(renderer))
+            .expect("Instance buffer matches its binding");
+
+        DrawBatch {
+            items: vec![],
+            frustum: None,
+            eye: glm::Vec3::zeros(),
+            instance_buffer,
+            indirect_buffer,
+            shader_resource,
+            commands: vec![],
+            stats: BatchStats::default(),
+        }
+    }
+
+    /// Removes all drawables and sets the camera they are culled and sorted against.
+    ///
+    /// # Arguments
+    ///
+    /// * `camera` - The camera projection.
+    /// * `transform` - The transform of the camera.
+    pub fn begin(&mut self, camera: &impl CameraProjection, transform: &Transform) {
+        let view = camera.view(transform);
+
+        self.frustum = Some(Frustum::from_view_projection(&camera.build_view_projection(transform)));
+        self.eye = view
+            .try_inverse()
+            .map(|inverse| inverse.column(3).xyz())
+            .unwrap_or(transform.translation);
+        self.items.clear();
+        self.stats = BatchStats::default();
+    }
+
+    /// Adds a drawable if its bounds are inside the frustum of the camera.
+    ///
+    /// # Arguments
+    ///
+    /// * `pipeline` - The index of the pipeline in the pipelines passed to `draw_batch`.
+    /// * `drawable` - The drawable, set up with `update()`.
+    /// * `bounds` - The bounds of the drawable in world space, e.g. a transformed `Chunk::aabb`.
+    /// * `data` - The instance data of the drawable.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the drawable is visible and will be drawn.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RenderError::DrawableNotSetUp` if the drawable has no vertex buffer.
+    pub fn push(&mut self, pipeline: usize, drawable: &dyn Drawable, bounds: &Aabb, data: T) -> Result<bool, RenderError> {
+        let vertex_buffer = drawable.vertex_buffer().ok_or(RenderError::DrawableNotSetUp)?;
+
+        if self.frustum.is_some_and(|frustum| !frustum.intersects_aabb(bounds)) {
+            self.stats.culled += 1;
+            return Ok(false);
+        }
+
+        self.items.push(DrawItem {
+            pipeline,
+            distance: glm::distance2(&self.eye, &bounds.center()),
+            vertex_buffer,
+            data,
+        });
+        self.stats.visible += 1;
+
+        Ok(true)
+    }
+
+    /// Sorts the visible drawables, uploads their instance data and builds the draw commands.
+    /// Must be called before the render pass is begun.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer owning the vertex buffers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a vertex buffer of a drawable has been destroyed.
+    pub fn prepare(&mut self, renderer: &Renderer) -> Result<(), RenderError> {
+        // Front to back within a pipeline, so early depth testing rejects hidden fragments
+        self.items.sort_by(|a, b| a.pipeline.cmp(&b.pipeline).then(a.distance.total_cmp(&b.distance)));
+
+        let multi_draw = renderer.capabilities().multi_draw_indirect;
+        let mut instances = Vec::with_capacity(self.items.len());
+        let mut indirect = Vec::new();
+        let mut previous = None;
+        self.commands.clear();
+
+        for item in &self.items {
+            let vertex_buffer = renderer.vertex_buffer(item.vertex_buffer)?;
+            if vertex_buffer.count == 0 {
+                continue;
+            }
+
+            let instance = instances.len() as u32;
+            instances.push(item.data);
+
+            let (buffer, offset) = renderer.vertex_arena().block(&vertex_buffer.handle)?;
+            let vertex_size = size_of::<Vertex>() as u64;
+
+            if !multi_draw || offset % vertex_size != 0 {
+                self.commands.push(BatchCommand::Direct {
+                    pipeline: item.pipeline,
+                    vertex_buffer: item.vertex_buffer,
+                    instance,
+                });
+                previous = None;
+                continue;
+            }
+
+            indirect.push(DrawIndirect {
+                vertex_count: vertex_buffer.count as u32,
+                instance_count: 1,
+                first_vertex: (offset / vertex_size) as u32,
+                first_instance: instance,
+            });
+
+            let key = (item.pipeline, buffer.global_id());
+            match self.commands.last_mut() {
+                Some(BatchCommand::Indirect { count, .. }) if previous == Some(key) => *count += 1,
+                _ => self.commands.push(BatchCommand::Indirect {
+                    pipeline: item.pipeline,
+                    vertex_buffer: item.vertex_buffer,
+                    first: indirect.len() as u32 - 1,
+                    count: 1,
+                }),
+            }
+            previous = Some(key);
+        }
+
+        let capacity = *self.instance_buffer.capacity();
+        self.instance_buffer.fill(renderer, 0, &instances);
+        self.indirect_buffer.fill(renderer, 0, &indirect);
+
+        // The storage binding must point to the new buffer after it has grown
+        if *self.instance_buffer.capacity() != capacity {
+            self.shader_resource
+                .set(Self::INSTANCES, &self.instance_buffer)?
+                .update(renderer)?;
+        }
+
+        self.stats.draw_calls = self.commands.len();
+
+        Ok(())
+    }
+
+    /// Retrieves the bind group of the instance data, which must be passed to pipelines
+    /// and `draw_batch` at the group index the shaders declare `instances` at.
+    pub fn shader_resource(&self) -> &ShaderResource {
+        &self.shader_resource
+    }
+
+    /// Retrieves the position of the camera set by `begin()`, e.g. to select levels of detail.
+    pub fn eye(&self) -> glm::Vec3 {
+        self.eye
+    }
+
+    /// Retrieves the statistics of the last prepared frame.
+    pub fn stats(&self) -> BatchStats {
+        self.stats
+    }
+
+    pub(crate) fn commands(&self) -> &[BatchCommand] {
+        &self.commands
+    }
+
+    pub(crate) fn indirect_buffer(&self) -> &wgpu::Buffer {
+        self.indirect_buffer.inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::renderer::{
+        hal::{reflection::ShaderReflection, shader::load_wgsl},
+        pbr::transform::TransformUniform,
+    };
+
+    #[test]
+    fn main_shader_matches_the_batch_layout() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets/shaders/main_shader.wgsl");
+        let reflection = ShaderReflection::new(&load_wgsl(path).unwrap()).unwrap();
+
+        // The camera and texture groups are created by the application
+        let camera = ShaderResource::reflected_layout_entries(&[&reflection], 0).unwrap();
+        let textures = ShaderResource::reflected_layout_entries(&[&reflection], 1).unwrap();
+        let batch = DrawBatch::<TransformUniform>::layout_entries();
+
+        reflection.check_layouts(&[&camera, &textures, &batch]).unwrap();
+    }
+}
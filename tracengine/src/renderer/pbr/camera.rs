@@ -13,7 +13,7 @@ pub const OPENGL_TO_WGPU_MATRIX: glm::Mat4 = glm::Mat4::new(
 );
 
 /// Enumeration of different types of cameras.
-#[derive(Clone, Default, Debug, Hash, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Default, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CameraType {
     /// First person camera type.
     #[default]
@@ -22,6 +22,65 @@ pub enum CameraType {
     LookAt,
 }
 
+/// Common interface of the cameras used by the rasteriser and the ray tracer.
+///
+/// Position and orientation of a camera are taken from the `Transform` of its
+/// entity, so any implementor can drive both renderers from the same ECS camera.
+pub trait CameraProjection {
+    /// Retrieves the type of the camera, which defines how the view matrix is built.
+    fn camera_type(&self) -> CameraType;
+
+    /// Retrieves the aspect ratio of the camera's view.
+    fn aspect(&self) -> f32;
+
+    /// Updates the camera to match the new size of the render target.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The new width in pixels.
+    /// * `height` - The new height in pixels.
+    fn resize(&mut self, width: u32, height: u32);
+
+    /// Builds the projection matrix of the camera in wgpu clip space.
+    ///
+    /// # Returns
+    ///
+    /// The projection matrix.
+    fn projection(&self) -> glm::Mat4;
+
+    /// Builds the view matrix of the camera based on its transform.
+    ///
+    /// # Arguments
+    ///
+    /// * `transform` - The transform of the camera.
+    ///
+    /// # Returns
+    ///
+    /// The view matrix.
+    fn view(&self, transform: &Transform) -> glm::Mat4 {
+        let rotation_matrix = glm::quat_cast(&transform.rotation);
+        let translation_matrix = glm::translation(&transform.translation);
+
+        match self.camera_type() {
+            CameraType::FirstPerson => rotation_matrix * translation_matrix,
+            CameraType::LookAt => translation_matrix * rotation_matrix,
+        }
+    }
+
+    /// Builds the view-projection matrix for the camera based on its transform.
+    ///
+    /// # Arguments
+    ///
+    /// * `transform` - The transform of the camera.
+    ///
+    /// # Returns
+    ///
+    /// The view-projection matrix.
+    fn build_view_projection(&self, transform: &Transform) -> glm::Mat4 {
+        self.projection() * self.view(transform)
+    }
+}
+
 /// Represents a camera in the scene, holding information about its type and projection parameters.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Camera {
@@ -53,36 +112,31 @@ impl Camera {
         }
     }
 
-    /// Builds the view-projection matrix for the camera based on its transform.
+    /// Sets the aspect ratio of the camera's view.
     ///
     /// # Arguments
     ///
-    /// * `transform` - The transform of the camera.
-    ///
-    /// # Returns
-    ///
-    /// The view-projection matrix.
-    pub fn build_view_projection(&self, transform: &Transform) -> glm::Mat4 {
-        let rotation_matrix = glm::quat_cast(&transform.rotation);
-        let translation_matrix = glm::translation(&transform.translation);
+    /// * `aspect` - The new aspect ratio.
+    pub fn set_aspect(&mut self, aspect: f32) {
+        self.aspect = aspect;
+    }
+}
 
-        let view = match self.camera_type {
-            CameraType::FirstPerson => rotation_matrix * translation_matrix,
-            CameraType::LookAt => translation_matrix * rotation_matrix,
-        };
+impl CameraProjection for Camera {
+    fn camera_type(&self) -> CameraType {
+        self.camera_type
+    }
 
-        let projection = glm::perspective(self.aspect, self.fovy, self.near, self.far);
+    fn aspect(&self) -> f32 {
+        self.aspect
+    }
 
-        OPENGL_TO_WGPU_MATRIX * projection * view
+    fn resize(&mut self, width: u32, height: u32) {
+        self.set_aspect(width as f32 / height as f32);
     }
 
-    /// Sets the aspect ratio of the camera's view.
-    ///
-    /// # Arguments
-    ///
-    /// * `aspect` - The new aspect ratio.
-    pub fn set_aspect(&mut self, aspect: f32) {
-        self.aspect = aspect;
+    fn projection(&self) -> glm::Mat4 {
+        OPENGL_TO_WGPU_MATRIX * glm::perspective(self.aspect, self.fovy, self.near, self.far)
     }
 }
 
@@ -116,7 +170,7 @@ impl CameraUniform {
     /// # Returns
     ///
     /// A new instance of `CameraUniform`.
-    pub fn new(camera: &Camera, transform: &Transform) -> CameraUniform {
+    pub fn new(camera: &impl CameraProjection, transform: &Transform) -> CameraUniform {
         CameraUniform {
             position: transform.translation,
             view_projection: camera.build_view_projection(transform),
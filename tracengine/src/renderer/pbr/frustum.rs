@@ -0,0 +1,219 @@
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+/// An axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Aabb {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+}
+
+impl Aabb {
+    /// Creates a bounding box from its minimum and maximum corners.
+    pub fn new(min: glm::Vec3, max: glm::Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// Retrieves the center of the box.
+    pub fn center(&self) -> glm::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Retrieves the half size of the box along each axis.
+    pub fn extents(&self) -> glm::Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    /// Computes the distance from a point to the closest point of the box.
+    ///
+    /// # Arguments
+    ///
+    /// * `point` - The point, e.g. the position of the camera.
+    ///
+    /// # Returns
+    ///
+    /// The distance, or `0.0` if the point is inside the box.
+    pub fn distance(&self, point: &glm::Vec3) -> f32 {
+        let closest = glm::clamp_vec(point, &self.min, &self.max);
+
+        glm::distance(point, &closest)
+    }
+
+    /// Computes the bounding box of this box transformed by an affine matrix.
+    ///
+    /// # Arguments
+    ///
+    /// * `matrix` - The transformation, e.g. a `TransformUniform::transform_matrix`.
+    ///
+    /// # Returns
+    ///
+    /// The axis-aligned box enclosing the transformed box.
+    pub fn transformed(&self, matrix: &glm::Mat4) -> Aabb {
+        let center = (matrix * self.center().push(1.0)).xyz();
+        let extents = self.extents();
+
+        // Each axis of the transformed box spans the projections of all rotated extents
+        let extents = glm::vec3(
+            (0..3).map(|j| matrix[(0, j)].abs() * extents[j]).sum(),
+            (0..3).map(|j| matrix[(1, j)].abs() * extents[j]).sum(),
+            (0..3).map(|j| matrix[(2, j)].abs() * extents[j]).sum(),
+        );
+
+        Aabb::new(center - extents, center + extents)
+    }
+}
+
+/// The view volume of a camera, bounded by six planes pointing inwards.
+///
+/// # Examples
+///
+/// ```
+/// use tracengine::glm;
+/// use tracengine::renderer::pbr::frustum::{Aabb, Frustum};
+///
+/// // A box from -1 to 1 in X and Y, between 1 and 10 units in front of the camera
+/// let frustum = Frustum::from_view_projection(&glm::ortho_rh_zo(-1.0, 1.0, -1.0, 1.0, 1.0, 10.0));
+/// let unit = |x: f32, y: f32, z: f32| Aabb::new(glm::vec3(x, y, z), glm::vec3(x + 1.0, y + 1.0, z + 1.0));
+///
+/// assert!(frustum.intersects_aabb(&unit(-0.5, -0.5, -5.0)));
+/// // Partially inside
+/// assert!(frustum.intersects_aabb(&unit(0.5, 0.5, -1.5)));
+/// // Beside, behind and beyond the far plane
+/// assert!(!frustum.intersects_aabb(&unit(1.5, 0.0, -5.0)));
+/// assert!(!frustum.intersects_aabb(&unit(0.0, 0.0, 1.0)));
+/// assert!(!frustum.intersects_aabb(&unit(0.0, 0.0, -12.0)));
+///
+/// assert!(frustum.contains_point(&glm::vec3(0.0, 0.0, -1.5)));
+/// assert!(!frustum.contains_point(&glm::vec3(0.0, 0.0, -0.5)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// Planes as `(normal, distance)`, with `dot(normal, p) + distance >= 0` inside.
+    planes: [glm::Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of the frustum from a view-projection matrix in wgpu clip space,
+    /// with depth from 0 to 1, e.g. from [`CameraProjection::build_view_projection`](super::camera::CameraProjection::build_view_projection).
+    ///
+    /// # Arguments
+    ///
+    /// * `view_projection` - The view-projection matrix.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Frustum` in world space.
+    pub fn from_view_projection(view_projection: &glm::Mat4) -> Frustum {
+        let row = |i: usize| view_projection.row(i).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+            let length = plane.xyz().norm();
+            if length > 0.0 { plane / length } else { plane }
+        });
+
+        Frustum { planes }
+    }
+
+    /// Checks if a bounding box is at least partially inside the frustum.
+    ///
+    /// Boxes close to a corner of the frustum may be reported as intersecting even
+    /// though they are outside, which is conservative for culling.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let (center, extents) = (aabb.center(), aabb.extents());
+
+        self.planes.iter().all(|plane| {
+            let normal = plane.xyz();
+            let radius = extents.dot(&normal.abs());
+
+            normal.dot(&center) + plane.w + radius >= 0.0
+        })
+    }
+
+    /// Checks if a point is inside the frustum.
+    pub fn contains_point(&self, point: &glm::Vec3) -> bool {
+        self.planes.iter().all(|plane| plane.xyz().dot(point) + plane.w >= 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::pbr::camera::OPENGL_TO_WGPU_MATRIX;
+
+    fn assert_aabb_eq(actual: &Aabb, expected: &Aabb) {
+        assert!(
+            glm::distance(&actual.min, &expected.min) < 1e-5 && glm::distance(&actual.max, &expected.max) < 1e-5,
+            "{actual:?} != {expected:?}",
+        );
+    }
+
+    #[test]
+    fn perspective_frustum_is_bounded_by_slanted_planes() {
+        // A camera at Z = 5 looking at the origin, with a field of view of 90° and depth from 1 to 10
+        let view = glm::look_at_rh(&glm::vec3(0.0, 0.0, 5.0), &glm::Vec3::zeros(), &glm::Vec3::y());
+        let projection = OPENGL_TO_WGPU_MATRIX * glm::perspective(1.0, std::f32::consts::FRAC_PI_2, 1.0, 10.0);
+        let frustum = Frustum::from_view_projection(&(projection * view));
+
+        // Near and far planes, with depth from 0 to 1 in clip space
+        assert!(frustum.contains_point(&glm::vec3(0.0, 0.0, 3.9)));
+        assert!(!frustum.contains_point(&glm::vec3(0.0, 0.0, 4.1)));
+        assert!(frustum.contains_point(&glm::vec3(0.0, 0.0, -4.9)));
+        assert!(!frustum.contains_point(&glm::vec3(0.0, 0.0, -5.1)));
+
+        // The sides widen with the distance to the camera
+        assert!(!frustum.contains_point(&glm::vec3(2.5, 0.0, 3.0)));
+        assert!(frustum.contains_point(&glm::vec3(2.5, 0.0, -4.0)));
+        assert!(frustum.contains_point(&glm::vec3(0.0, -4.5, 0.0)));
+        assert!(!frustum.contains_point(&glm::vec3(0.0, -5.5, 0.0)));
+
+        let unit = |x: f32, y: f32, z: f32| Aabb::new(glm::vec3(x, y, z), glm::vec3(x + 1.0, y + 1.0, z + 1.0));
+        assert!(frustum.intersects_aabb(&unit(-0.5, -0.5, -0.5)));
+        // Partially inside
+        assert!(frustum.intersects_aabb(&unit(4.5, 0.0, -0.5)));
+        // Beside, behind and beyond the far plane
+        assert!(!frustum.intersects_aabb(&unit(6.0, 0.0, -0.5)));
+        assert!(!frustum.intersects_aabb(&unit(0.0, 0.0, 6.0)));
+        assert!(!frustum.intersects_aabb(&unit(0.0, 0.0, -7.0)));
+    }
+
+    #[test]
+    fn transformed_box_encloses_the_rotated_box() {
+        let aabb = Aabb::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(2.0, 1.0, 1.0));
+
+        // A quarter turn around Z maps X to Y and Y to -X
+        let matrix = glm::translation(&glm::vec3(10.0, 0.0, 0.0))
+            * glm::rotation(std::f32::consts::FRAC_PI_2, &glm::Vec3::z());
+        assert_aabb_eq(
+            &aabb.transformed(&matrix),
+            &Aabb::new(glm::vec3(9.0, 0.0, 0.0), glm::vec3(10.0, 2.0, 1.0)),
+        );
+
+        // An eighth turn widens the box to enclose its diagonal
+        let cube = Aabb::new(glm::vec3(-1.0, -1.0, -1.0), glm::vec3(1.0, 1.0, 1.0));
+        let matrix = glm::rotation(std::f32::consts::FRAC_PI_4, &glm::Vec3::z());
+        let diagonal = std::f32::consts::SQRT_2;
+        assert_aabb_eq(
+            &cube.transformed(&matrix),
+            &Aabb::new(glm::vec3(-diagonal, -diagonal, -1.0), glm::vec3(diagonal, diagonal, 1.0)),
+        );
+
+        // Scaling scales the extents around the transformed center
+        let matrix = glm::scaling(&glm::vec3(2.0, 3.0, 1.0));
+        assert_aabb_eq(
+            &aabb.transformed(&matrix),
+            &Aabb::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(4.0, 3.0, 1.0)),
+        );
+    }
+
+    #[test]
+    fn distance_is_measured_to_the_closest_point() {
+        let aabb = Aabb::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 1.0, 1.0));
+
+        assert_eq!(aabb.distance(&glm::vec3(0.5, 0.5, 0.5)), 0.0);
+        assert_eq!(aabb.distance(&glm::vec3(1.0, 0.5, 0.0)), 0.0);
+        assert_eq!(aabb.distance(&glm::vec3(3.0, 0.5, 0.5)), 2.0);
+        assert_eq!(aabb.distance(&glm::vec3(0.5, -4.0, 0.5)), 4.0);
+        assert!((aabb.distance(&glm::vec3(2.0, 2.0, 1.0)) - std::f32::consts::SQRT_2).abs() < 1e-6);
+    }
+}
@@ -4,21 +4,30 @@ use serde::{Deserialize, Serialize};
 
 use super::Color;
 
-/// A vertex structure containing position, normal, and color attributes.
+/// A vertex structure containing position, normal, color and texture attributes.
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, Zeroable, Pod)]
 pub struct Vertex {
     pub position: glm::Vec3,
     pub normal: glm::Vec3,
     pub color: Color,
+    /// Texture coordinates, exceeding `1.0` on faces of merged blocks tiling the texture.
+    pub uv: glm::Vec2,
+    /// Layer of the block texture array, or `Vertex::UNTEXTURED` to use the color.
+    pub texture: u32,
 }
 
 impl Vertex {
-    /// Vertex attributes for position, normal, and color.
-    const ATTRIBS: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
+    /// Texture layer of vertices without a texture.
+    pub const UNTEXTURED: u32 = u32::MAX;
+
+    /// Vertex attributes for position, normal, color, texture coordinates and texture layer.
+    const ATTRIBS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
         0 => Float32x3,
         1 => Float32x3,
         2 => Float32x3,
+        3 => Float32x2,
+        4 => Uint32,
     ];
 
     /// Returns a description of the vertex buffer layout.
@@ -26,7 +35,7 @@ impl Vertex {
     /// # Returns
     ///
     /// A `wgpu::VertexBufferLayout` describing the layout of the vertex buffer.
-    pub(crate) fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+    pub fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Vertex,
@@ -35,6 +44,36 @@ impl Vertex {
     }
 }
 
+/// The direction a face of a block is facing.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FaceDirection {
+    Top,
+    Bottom,
+    Front,
+    Back,
+    Left,
+    Right,
+}
+
+impl FaceDirection {
+    /// Retrieves the normal of faces in this direction.
+    pub fn normal(&self) -> glm::Vec3 {
+        match self {
+            FaceDirection::Top => glm::vec3(0.0, 1.0, 0.0),
+            FaceDirection::Bottom => glm::vec3(0.0, -1.0, 0.0),
+            FaceDirection::Front => glm::vec3(0.0, 0.0, 1.0),
+            FaceDirection::Back => glm::vec3(0.0, 0.0, -1.0),
+            FaceDirection::Left => glm::vec3(-1.0, 0.0, 0.0),
+            FaceDirection::Right => glm::vec3(1.0, 0.0, 0.0),
+        }
+    }
+
+    /// Checks if the face is a side one, i.e. neither the top nor the bottom face.
+    pub fn is_side(&self) -> bool {
+        !matches!(self, FaceDirection::Top | FaceDirection::Bottom)
+    }
+}
+
 /// A mesh structure containing vertex data.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Mesh {
@@ -42,6 +81,86 @@ pub struct Mesh {
 }
 
 impl Mesh {
+    /// Adds a quad covering the faces of `scale` by `scale` blocks.
+    ///
+    /// Texture coordinates range from `0.0` to `scale`, so a repeating texture is tiled
+    /// once per block, upright on side faces.
+    fn add_quad(
+        &mut self,
+        direction: FaceDirection,
+        position: [usize; 3],
+        scale: usize,
+        color: Color,
+        texture: Option<u32>,
+    ) {
+        let x_axis = glm::vec3(1.0, 0.0, 0.0);
+        let y_axis = glm::vec3(0.0, 1.0, 0.0);
+        let z_axis = glm::vec3(0.0, 0.0, 1.0);
+
+        // Plane of the quad, its axes and corners in order of the triangles, keeping the winding
+        const A: [(f32, f32); 6] = [(0.0, 0.0), (0.0, 1.0), (1.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)];
+        const B: [(f32, f32); 6] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        const C: [(f32, f32); 6] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (0.0, 1.0), (1.0, 0.0), (1.0, 1.0)];
+
+        let (offset, u_axis, v_axis, corners) = match direction {
+            FaceDirection::Top => (y_axis, x_axis, z_axis, A),
+            FaceDirection::Bottom => (glm::Vec3::zeros(), x_axis, z_axis, B),
+            FaceDirection::Front => (glm::Vec3::zeros(), x_axis, y_axis, A),
+            FaceDirection::Back => (z_axis, x_axis, y_axis, B),
+            FaceDirection::Left => (glm::Vec3::zeros(), z_axis, y_axis, C),
+            FaceDirection::Right => (x_axis, z_axis, y_axis, A),
+        };
+
+        let [x, y, z] = position;
+        let origin = glm::vec3(x as f32, y as f32, z as f32) + offset;
+        let scale = scale as f32;
+        let normal = direction.normal();
+        let texture = texture.unwrap_or(Vertex::UNTEXTURED);
+
+        self.vertex_data.extend(corners.iter().map(|&(u, v)| {
+            // Rows of textures go downwards, so side faces are flipped to be upright
+            let texture_v = if direction.is_side() { 1.0 - v } else { v };
+
+            Vertex {
+                position: origin + (u_axis * u + v_axis * v) * scale,
+                normal,
+                color,
+                uv: glm::vec2(u, texture_v) * scale,
+                texture,
+            }
+        }));
+    }
+
+    /// Adds a face of a cube of `scale` blocks, e.g. a block merged by a level of detail.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - The direction the face is facing.
+    /// * `position` - The coordinates of the first block of the cube.
+    /// * `scale` - The number of blocks along each axis of the cube.
+    /// * `color` - The color of the face.
+    /// * `texture` - The layer of the block texture array, or `None` to use the color.
+    pub fn add_face(
+        &mut self,
+        direction: FaceDirection,
+        position: [usize; 3],
+        scale: usize,
+        color: Color,
+        texture: Option<u32>,
+    ) {
+        // Faces on the far side of the cube are offset by a single block by `add_quad`
+        let [x, y, z] = position;
+        let far = scale - 1;
+        let position = match direction {
+            FaceDirection::Top => [x, y + far, z],
+            FaceDirection::Back => [x, y, z + far],
+            FaceDirection::Right => [x + far, y, z],
+            _ => position,
+        };
+
+        self.add_quad(direction, position, scale, color, texture);
+    }
+
     /// Adds a top face to the mesh at the specified position with the given color.
     ///
     /// # Arguments
@@ -50,17 +169,9 @@ impl Mesh {
     /// * `y` - The y-coordinate of the face.
     /// * `z` - The z-coordinate of the face.
     /// * `color` - The color of the face.
-    pub fn add_top_face(&mut self, x: usize, y: usize, z: usize, color: Color) {
-        let normal = glm::vec3(0.0, 1.0, 0.0);
-
-        self.vertex_data.extend(&[
-            Vertex { position: glm::vec3(x as f32, y as f32 + 1.0, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32, y as f32 + 1.0, z as f32 + 1.0), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32 + 1.0, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32 + 1.0, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32, y as f32 + 1.0, z as f32 + 1.0), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32 + 1.0, z as f32 + 1.0), normal, color },
-        ]);
+    /// * `texture` - The layer of the block texture array, or `None` to use the color.
+    pub fn add_top_face(&mut self, x: usize, y: usize, z: usize, color: Color, texture: Option<u32>) {
+        self.add_quad(FaceDirection::Top, [x, y, z], 1, color, texture);
     }
 
     /// Adds a bottom face to the mesh at the specified position with the given color.
@@ -71,17 +182,9 @@ impl Mesh {
     /// * `y` - The y-coordinate of the face.
     /// * `z` - The z-coordinate of the face.
     /// * `color` - The color of the face.
-    pub fn add_bottom_face(&mut self, x: usize, y: usize, z: usize, color: Color) {
-        let normal = glm::vec3(0.0, -1.0, 0.0);
-
-        self.vertex_data.extend(&[
-            Vertex { position: glm::vec3(x as f32, y as f32, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32, y as f32, z as f32 + 1.0), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32, z as f32 + 1.0), normal, color },
-            Vertex { position: glm::vec3(x as f32, y as f32, z as f32 + 1.0), normal, color },
-        ]);
+    /// * `texture` - The layer of the block texture array, or `None` to use the color.
+    pub fn add_bottom_face(&mut self, x: usize, y: usize, z: usize, color: Color, texture: Option<u32>) {
+        self.add_quad(FaceDirection::Bottom, [x, y, z], 1, color, texture);
     }
 
     /// Adds a front face to the mesh at the specified position with the given color.
@@ -92,17 +195,9 @@ impl Mesh {
     /// * `y` - The y-coordinate of the face.
     /// * `z` - The z-coordinate of the face.
     /// * `color` - The color of the face.
-    pub fn add_front_face(&mut self, x: usize, y: usize, z: usize, color: Color) {
-        let normal = glm::vec3(0.0, 0.0, 1.0);
-
-        self.vertex_data.extend(&[
-            Vertex { position: glm::vec3(x as f32, y as f32, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32, y as f32 + 1.0, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32, y as f32 + 1.0, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32 + 1.0, z as f32), normal, color },
-        ]);
+    /// * `texture` - The layer of the block texture array, or `None` to use the color.
+    pub fn add_front_face(&mut self, x: usize, y: usize, z: usize, color: Color, texture: Option<u32>) {
+        self.add_quad(FaceDirection::Front, [x, y, z], 1, color, texture);
     }
 
     /// Adds a back face to the mesh at the specified position with the given color.
@@ -113,17 +208,9 @@ impl Mesh {
     /// * `y` - The y-coordinate of the face.
     /// * `z` - The z-coordinate of the face.
     /// * `color` - The color of the face.
-    pub fn add_back_face(&mut self, x: usize, y: usize, z: usize, color: Color) {
-        let normal = glm::vec3(0.0, 0.0, -1.0);
-
-        self.vertex_data.extend(&[
-            Vertex { position: glm::vec3(x as f32, y as f32, z as f32 + 1.0), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32, z as f32 + 1.0), normal, color },
-            Vertex { position: glm::vec3(x as f32, y as f32 + 1.0, z as f32 + 1.0), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32, z as f32 + 1.0), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32 + 1.0, z as f32 + 1.0), normal, color },
-            Vertex { position: glm::vec3(x as f32, y as f32 + 1.0, z as f32 + 1.0), normal, color },
-        ]);
+    /// * `texture` - The layer of the block texture array, or `None` to use the color.
+    pub fn add_back_face(&mut self, x: usize, y: usize, z: usize, color: Color, texture: Option<u32>) {
+        self.add_quad(FaceDirection::Back, [x, y, z], 1, color, texture);
     }
 
     /// Adds a left face to the mesh at the specified position with the given color.
@@ -134,17 +221,9 @@ impl Mesh {
     /// * `y` - The y-coordinate of the face.
     /// * `z` - The z-coordinate of the face.
     /// * `color` - The color of the face.
-    pub fn add_left_face(&mut self, x: usize, y: usize, z: usize, color: Color) {
-        let normal = glm::vec3(-1.0, 0.0, 0.0);
-
-        self.vertex_data.extend(&[
-            Vertex { position: glm::vec3(x as f32, y as f32, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32, y as f32, z as f32 + 1.0), normal, color },
-            Vertex { position: glm::vec3(x as f32, y as f32 + 1.0, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32, y as f32 + 1.0, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32, y as f32, z as f32 + 1.0), normal, color },
-            Vertex { position: glm::vec3(x as f32, y as f32 + 1.0, z as f32 + 1.0), normal, color },
-        ]);
+    /// * `texture` - The layer of the block texture array, or `None` to use the color.
+    pub fn add_left_face(&mut self, x: usize, y: usize, z: usize, color: Color, texture: Option<u32>) {
+        self.add_quad(FaceDirection::Left, [x, y, z], 1, color, texture);
     }
 
     /// Adds a right face to the mesh at the specified position with the given color.
@@ -155,16 +234,106 @@ impl Mesh {
     /// * `y` - The y-coordinate of the face.
     /// * `z` - The z-coordinate of the face.
     /// * `color` - The color of the face.
-    pub fn add_right_face(&mut self, x: usize, y: usize, z: usize, color: Color) {
-        let normal = glm::vec3(1.0, 0.0, 0.0);
-
-        self.vertex_data.extend(&[
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32 + 1.0, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32, z as f32 + 1.0), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32, z as f32 + 1.0), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32 + 1.0, z as f32), normal, color },
-            Vertex { position: glm::vec3(x as f32 + 1.0, y as f32 + 1.0, z as f32 + 1.0), normal, color },
-        ]);
+    /// * `texture` - The layer of the block texture array, or `None` to use the color.
+    pub fn add_right_face(&mut self, x: usize, y: usize, z: usize, color: Color, texture: Option<u32>) {
+        self.add_quad(FaceDirection::Right, [x, y, z], 1, color, texture);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIRECTIONS: [FaceDirection; 6] = [
+        FaceDirection::Top,
+        FaceDirection::Bottom,
+        FaceDirection::Front,
+        FaceDirection::Back,
+        FaceDirection::Left,
+        FaceDirection::Right,
+    ];
+
+    fn single_face(direction: FaceDirection, position: [usize; 3], texture: Option<u32>) -> Mesh {
+        let [x, y, z] = position;
+        let color = Color::new(1.0, 0.5, 0.25);
+        let mut mesh = Mesh::default();
+
+        match direction {
+            FaceDirection::Top => mesh.add_top_face(x, y, z, color, texture),
+            FaceDirection::Bottom => mesh.add_bottom_face(x, y, z, color, texture),
+            FaceDirection::Front => mesh.add_front_face(x, y, z, color, texture),
+            FaceDirection::Back => mesh.add_back_face(x, y, z, color, texture),
+            FaceDirection::Left => mesh.add_left_face(x, y, z, color, texture),
+            FaceDirection::Right => mesh.add_right_face(x, y, z, color, texture),
+        }
+
+        mesh
+    }
+
+    /// Computes the expected texture coordinates of a vertex from its position relative to the block.
+    fn expected_uv(direction: FaceDirection, local: glm::Vec3) -> glm::Vec2 {
+        match direction {
+            FaceDirection::Top | FaceDirection::Bottom => glm::vec2(local.x, local.z),
+            FaceDirection::Front | FaceDirection::Back => glm::vec2(local.x, 1.0 - local.y),
+            FaceDirection::Left | FaceDirection::Right => glm::vec2(local.z, 1.0 - local.y),
+        }
+    }
+
+    #[test]
+    fn faces_map_the_whole_texture_onto_the_block() {
+        let block = glm::vec3(2.0, 3.0, 4.0);
+
+        for direction in DIRECTIONS {
+            let mesh = single_face(direction, [2, 3, 4], Some(7));
+            assert_eq!(mesh.vertex_data.len(), 6, "{direction:?}");
+
+            for vertex in &mesh.vertex_data {
+                let local = vertex.position - block;
+
+                assert_eq!(vertex.uv, expected_uv(direction, local), "{direction:?} at {local:?}");
+                assert_eq!(vertex.normal, direction.normal(), "{direction:?}");
+                assert_eq!(vertex.texture, 7, "{direction:?}");
+            }
+
+            // Both triangles together cover all four corners of the texture
+            let mut corners = mesh.vertex_data.iter().map(|vertex| (vertex.uv.x as u8, vertex.uv.y as u8)).collect::<Vec<_>>();
+            corners.sort();
+            corners.dedup();
+            assert_eq!(corners, [(0, 0), (0, 1), (1, 0), (1, 1)], "{direction:?}");
+        }
+    }
+
+    #[test]
+    fn faces_lie_on_the_block_boundary() {
+        for direction in DIRECTIONS {
+            let mesh = single_face(direction, [0, 0, 0], None);
+            let normal = direction.normal();
+            let depth = glm::dot(&mesh.vertex_data[0].position, &normal).abs();
+
+            assert!(depth == 0.0 || depth == 1.0, "{direction:?}");
+            for vertex in &mesh.vertex_data {
+                assert_eq!(glm::dot(&vertex.position, &normal).abs(), depth, "{direction:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn untextured_faces_use_the_color() {
+        let mesh = single_face(FaceDirection::Front, [0, 0, 0], None);
+
+        assert!(mesh.vertex_data.iter().all(|vertex| vertex.texture == Vertex::UNTEXTURED));
+    }
+
+    #[test]
+    fn merged_faces_tile_the_texture_per_block() {
+        let mut mesh = Mesh::default();
+        mesh.add_face(FaceDirection::Right, [4, 0, 8], 4, Color::new(1.0, 1.0, 1.0), Some(0));
+
+        for vertex in &mesh.vertex_data {
+            let local = vertex.position - glm::vec3(4.0, 0.0, 8.0);
+
+            assert_eq!(local.x, 4.0);
+            assert_eq!(vertex.uv, glm::vec2(local.z, 4.0 - local.y));
+        }
     }
 }
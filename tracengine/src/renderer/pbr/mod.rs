@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 pub mod mesh;
 pub mod transform;
 pub mod camera;
+pub mod frustum;
+pub mod batch;
 
 /// A structure representing a color with red, green, and blue components.
 ///
@@ -0,0 +1,185 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::renderer::{pbr::transform::Transform, InstanceData};
+
+/// Progressive accumulation state for the path tracer.
+///
+/// While the camera and the world stay static, every traced frame is blended
+/// into the color buffer with weight `1 / (frame_count + 1)`, so the image
+/// converges to a noise-free reference. Accumulation restarts from scratch
+/// whenever one of the tracked transforms changes or [`Accumulator::reset`] is called
+/// (e.g. after a chunk was rewritten or the viewport was resized).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Accumulator {
+    enabled: bool,
+    frame_count: u32,
+    max_samples: Option<u32>,
+    last_transforms: Vec<Transform>,
+}
+
+impl Accumulator {
+    /// Creates a new accumulator.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether progressive accumulation is initially enabled.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Accumulator` with no accumulated samples.
+    pub fn new(enabled: bool) -> Accumulator {
+        Accumulator {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    /// Limits the number of accumulated samples. Once the limit is reached,
+    /// the frame counter stops advancing and the image stays converged.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_samples` - The maximum number of samples, or `None` for no limit.
+    pub fn set_max_samples(&mut self, max_samples: Option<u32>) {
+        self.max_samples = max_samples;
+    }
+
+    /// Enables or disables progressive accumulation, discarding accumulated samples.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether accumulation should be enabled.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.reset();
+    }
+
+    /// Checks if progressive accumulation is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Retrieves the number of samples accumulated so far.
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// Checks if the sample limit set with [`Accumulator::set_max_samples`] has been reached.
+    pub fn is_converged(&self) -> bool {
+        self.max_samples.is_some_and(|max| self.frame_count >= max)
+    }
+
+    /// Discards all accumulated samples.
+    pub fn reset(&mut self) {
+        self.frame_count = 0;
+    }
+
+    /// Compares the given transforms with the ones seen on the previous frame
+    /// and resets accumulation if any of them has changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `transforms` - The current transforms of the camera and the traced scene.
+    pub fn track(&mut self, transforms: &[Transform]) {
+        if self.last_transforms != transforms {
+            self.last_transforms = transforms.to_vec();
+            self.reset();
+        }
+    }
+
+    /// Advances the frame counter after a frame has been traced.
+    pub fn advance(&mut self) {
+        if self.enabled && !self.is_converged() {
+            self.frame_count = self.frame_count.saturating_add(1);
+        }
+    }
+}
+
+impl InstanceData for Accumulator {
+    type UniformData = AccumulationUniform;
+
+    fn uniform_data(&mut self) -> Self::UniformData {
+        AccumulationUniform {
+            frame_count: self.frame_count,
+            enabled: self.enabled as u32,
+            converged: self.is_converged() as u32,
+            _padding: 0,
+        }
+    }
+}
+
+/// Uniform data for progressive accumulation, matching `Utils::Accumulation` in the shaders.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Zeroable, Pod)]
+pub struct AccumulationUniform {
+    frame_count: u32,
+    enabled: u32,
+    converged: u32,
+    _padding: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::glm;
+
+    use super::*;
+
+    #[test]
+    fn samples_are_only_counted_while_enabled() {
+        let mut accumulator = Accumulator::new(false);
+        accumulator.advance();
+        assert_eq!(accumulator.frame_count(), 0);
+
+        accumulator.set_enabled(true);
+        accumulator.advance();
+        accumulator.advance();
+        assert_eq!(accumulator.frame_count(), 2);
+        assert_eq!(accumulator.uniform_data(), AccumulationUniform {
+            frame_count: 2,
+            enabled: 1,
+            converged: 0,
+            _padding: 0,
+        });
+
+        // Toggling discards the accumulated samples
+        accumulator.set_enabled(false);
+        assert_eq!(accumulator.frame_count(), 0);
+    }
+
+    #[test]
+    fn changed_transforms_reset_accumulation() {
+        let mut accumulator = Accumulator::new(true);
+        let mut transform = Transform::default();
+
+        accumulator.track(&[transform]);
+        accumulator.advance();
+        accumulator.track(&[transform]);
+        accumulator.advance();
+        assert_eq!(accumulator.frame_count(), 2);
+
+        transform.translation = glm::vec3(1.0, 0.0, 0.0);
+        accumulator.track(&[transform]);
+        assert_eq!(accumulator.frame_count(), 0);
+
+        accumulator.advance();
+        accumulator.reset();
+        assert_eq!(accumulator.frame_count(), 0);
+    }
+
+    #[test]
+    fn accumulation_converges_at_the_sample_limit() {
+        let mut accumulator = Accumulator::new(true);
+        accumulator.set_max_samples(Some(3));
+
+        for _ in 0..5 {
+            accumulator.advance();
+        }
+
+        assert_eq!(accumulator.frame_count(), 3);
+        assert!(accumulator.is_converged());
+        assert_eq!(accumulator.uniform_data().converged, 1);
+
+        accumulator.reset();
+        assert!(!accumulator.is_converged());
+    }
+}
@@ -1,71 +1,144 @@
 use bytemuck::{Pod, Zeroable};
 use nalgebra_glm as glm;
 
-use crate::renderer::{hal::Padding, InstanceData};
+use crate::renderer::{
+    hal::Padding,
+    pbr::{
+        camera::{CameraProjection, CameraType, OPENGL_TO_WGPU_MATRIX},
+        transform::Transform,
+    },
+};
+
+/// Projection model of the ray tracing camera.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum ProjectionType {
+    /// Perspective projection with the vertical field of view of the camera.
+    #[default]
+    Perspective,
+    /// Orthographic projection with parallel rays.
+    Orthographic {
+        /// Height of the view volume in world units.
+        height: f32,
+    },
+}
 
 pub struct RtCameraDescriptor {
-    pub image_width: u32, 
-    pub image_height: u32, 
-    pub scan_depth: u32, 
-    pub jitter: f32,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub scan_depth: u32,
+    pub camera_type: CameraType,
+    pub projection: ProjectionType,
+    pub fovy: f32,
+    pub near: f32,
+    pub far: f32,
+    pub aperture: f32,
+    pub focus_distance: f32,
+}
+
+impl Default for RtCameraDescriptor {
+    fn default() -> Self {
+        RtCameraDescriptor {
+            image_width: 1,
+            image_height: 1,
+            scan_depth: 10,
+            camera_type: CameraType::LookAt,
+            projection: ProjectionType::Perspective,
+            fovy: 90f32.to_radians(),
+            near: 0.01,
+            far: 1000.0,
+            aperture: 0.0,
+            focus_distance: 10.0,
+        }
+    }
 }
 
+/// A ray tracing camera.
+///
+/// The camera position and orientation are taken from the `Transform` of its entity,
+/// in the same way as for [`Camera`](crate::renderer::pbr::camera::Camera).
 #[derive(Default, Debug, Clone, Copy, PartialEq)]
 pub struct RtCamera {
     pub image_width: u32,
     pub image_height: u32,
-    pub center: glm::Vec3,
-    pub first_pixel: glm::Vec3,
-    pub pixel_delta_u: glm::Vec3,
-    pub pixel_delta_v: glm::Vec3,
     pub scan_depth: u32,
+    pub camera_type: CameraType,
+    pub projection: ProjectionType,
+    /// Vertical field of view in radians.
+    pub fovy: f32,
+    /// Distance from the camera to the near plane, below which primary rays ignore hits.
+    pub near: f32,
+    /// Distance from the camera to the far plane, beyond which primary rays hit the background.
+    pub far: f32,
+    /// Diameter of the thin lens. Zero disables depth of field.
+    pub aperture: f32,
+    /// Distance to the plane in perfect focus.
+    pub focus_distance: f32,
+    prev_view_projection: Option<glm::Mat4>,
 }
 
 impl RtCamera {
     pub fn new(descriptor: &RtCameraDescriptor) -> RtCamera {
-        let aspect = descriptor.image_width as f32 / descriptor.image_height as f32;
-
-        let focal_length = 1.0;
-        let viewport_height = 2.0;
-        let viewport_width = viewport_height * aspect;
-        let center = glm::vec3(0.0, 0.0, 0.0);
-
-        let viewport_u = glm::vec3(viewport_width, 0.0, 0.0);
-        let viewport_v = glm::vec3(0.0, -viewport_height, 0.0);
-
-        let pixel_delta_u = viewport_u / descriptor.image_width as f32;
-        let pixel_delta_v = viewport_v / descriptor.image_height as f32;
-
-        let jitter = descriptor.jitter / descriptor.image_height as f32;
-        let viewport_upper_left = center - glm::vec3(0.0, 0.0, focal_length) - viewport_u/2.0 - viewport_v/2.0;
-        let first_pixel = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v) + glm::vec3(jitter, jitter, 0.);
-
         RtCamera {
-            image_height: descriptor.image_height,
             image_width: descriptor.image_width,
-            center,
-            first_pixel,
-            pixel_delta_u,
-            pixel_delta_v,
+            image_height: descriptor.image_height,
             scan_depth: descriptor.scan_depth,
+            camera_type: descriptor.camera_type,
+            projection: descriptor.projection,
+            fovy: descriptor.fovy,
+            near: descriptor.near,
+            far: descriptor.far,
+            aperture: descriptor.aperture,
+            focus_distance: descriptor.focus_distance,
+            prev_view_projection: None,
         }
     }
+
+    /// Builds the uniform data for the current frame and remembers the view-projection
+    /// matrix, so that the next frame can compute motion vectors against it.
+    ///
+    /// # Arguments
+    ///
+    /// * `transform` - The transform of the camera.
+    ///
+    /// # Returns
+    ///
+    /// The `RtCameraUniform` for the current frame.
+    pub fn update(&mut self, transform: &Transform) -> RtCameraUniform {
+        let view_projection = self.build_view_projection(transform);
+        let prev_view_projection = self.prev_view_projection.unwrap_or(view_projection);
+        self.prev_view_projection = Some(view_projection);
+
+        RtCameraUniform::new(self, transform, prev_view_projection)
+    }
 }
 
-impl InstanceData for RtCamera {
-    type UniformData = RtCameraUniform;
+impl CameraProjection for RtCamera {
+    fn camera_type(&self) -> CameraType {
+        self.camera_type
+    }
+
+    fn aspect(&self) -> f32 {
+        self.image_width as f32 / self.image_height as f32
+    }
 
-    fn uniform_data(&mut self) -> Self::UniformData {
-        RtCameraUniform {
-            image_height: self.image_height,
-            image_width: self.image_width,
-            center: self.center,
-            first_pixel: self.first_pixel,
-            pixel_delta_u: self.pixel_delta_u,
-            pixel_delta_v: self.pixel_delta_v,
-            scan_depth: self.scan_depth,
-            ..Default::default()
-        }
+    fn resize(&mut self, width: u32, height: u32) {
+        self.image_width = width;
+        self.image_height = height;
+    }
+
+    fn projection(&self) -> glm::Mat4 {
+        let aspect = self.aspect();
+
+        let projection = match self.projection {
+            ProjectionType::Perspective => glm::perspective(aspect, self.fovy, self.near, self.far),
+            ProjectionType::Orthographic { height } => {
+                let half_width = height * aspect / 2.0;
+                let half_height = height / 2.0;
+                glm::ortho(-half_width, half_width, -half_height, half_height, self.near, self.far)
+            },
+        };
+
+        OPENGL_TO_WGPU_MATRIX * projection
     }
 }
 
@@ -74,18 +147,152 @@ impl InstanceData for RtCamera {
 pub struct RtCameraUniform {
     image_width: u32,
     image_height: u32,
-    _padding0: Padding,
-    _padding1: Padding,
+    scan_depth: u32,
+    orthographic: u32,
 
     center: glm::Vec3,
-    _padding3: Padding,
+    lens_radius: f32,
 
     first_pixel: glm::Vec3,
-    _padding2: Padding,
+    near: f32,
 
     pixel_delta_u: glm::Vec3,
-    _padding4: Padding,
+    far: f32,
 
     pixel_delta_v: glm::Vec3,
-    scan_depth: u32,
-}
\ No newline at end of file
+    _padding0: Padding,
+
+    defocus_disk_u: glm::Vec3,
+    _padding1: Padding,
+
+    defocus_disk_v: glm::Vec3,
+    _padding2: Padding,
+
+    forward: glm::Vec3,
+    _padding3: Padding,
+
+    view_projection: glm::Mat4,
+    prev_view_projection: glm::Mat4,
+}
+
+impl RtCameraUniform {
+    /// Creates a new `RtCameraUniform` from a given camera and transform.
+    ///
+    /// # Arguments
+    ///
+    /// * `camera` - The camera from which to create the uniform.
+    /// * `transform` - The transform of the camera.
+    /// * `prev_view_projection` - The view-projection matrix of the previous frame.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `RtCameraUniform`.
+    pub fn new(camera: &RtCamera, transform: &Transform, prev_view_projection: glm::Mat4) -> RtCameraUniform {
+        let view = camera.view(transform);
+        let camera_to_world = view.try_inverse().unwrap_or_else(glm::Mat4::identity);
+
+        let center = (camera_to_world * glm::vec4(0.0, 0.0, 0.0, 1.0)).xyz();
+        let u = (camera_to_world * glm::vec4(1.0, 0.0, 0.0, 0.0)).xyz().normalize();
+        let v = (camera_to_world * glm::vec4(0.0, 1.0, 0.0, 0.0)).xyz().normalize();
+        let w = (camera_to_world * glm::vec4(0.0, 0.0, 1.0, 0.0)).xyz().normalize();
+
+        let lens_radius = camera.aperture.max(0.0) / 2.0;
+
+        // Without depth of field the image plane is kept at unit distance,
+        // so that hit distances are measured in view-space units
+        let focus_distance = if lens_radius > 0.0 { camera.focus_distance } else { 1.0 };
+
+        let (viewport_height, viewport_center, orthographic) = match camera.projection {
+            ProjectionType::Perspective => (
+                2.0 * (camera.fovy / 2.0).tan() * focus_distance,
+                center - focus_distance * w,
+                false,
+            ),
+            ProjectionType::Orthographic { height } => (height, center, true),
+        };
+        let viewport_width = viewport_height * camera.aspect();
+
+        let viewport_u = viewport_width * u;
+        let viewport_v = -viewport_height * v;
+
+        let pixel_delta_u = viewport_u / camera.image_width as f32;
+        let pixel_delta_v = viewport_v / camera.image_height as f32;
+
+        let viewport_upper_left = viewport_center - viewport_u/2.0 - viewport_v/2.0;
+        let first_pixel = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
+
+        RtCameraUniform {
+            image_width: camera.image_width,
+            image_height: camera.image_height,
+            scan_depth: camera.scan_depth,
+            orthographic: orthographic as u32,
+            center,
+            lens_radius,
+            first_pixel,
+            near: camera.near,
+            pixel_delta_u,
+            far: camera.far,
+            pixel_delta_v,
+            defocus_disk_u: u * lens_radius,
+            defocus_disk_v: v * lens_radius,
+            forward: -w,
+            view_projection: camera.projection() * view,
+            prev_view_projection,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::renderer::hal::shader::{load_wgsl, undecorate};
+
+    use super::*;
+
+    #[test]
+    fn uniform_matches_the_shader_camera() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets/shaders/rt_shader.wgsl");
+        let shader = load_wgsl(path).unwrap();
+        let wgpu::ShaderSource::Wgsl(source) = &shader.source else {
+            unreachable!("Shaders are loaded as WGSL");
+        };
+
+        let module = naga::front::wgsl::parse_str(source).unwrap();
+        // Globals may be renamed when composed, unlike the struct of the uniform
+        let camera = module.types
+            .iter()
+            .map(|(_, ty)| ty)
+            .find(|ty| ty.name.as_deref().map(undecorate) == Some("Camera"))
+            .unwrap();
+
+        let naga::TypeInner::Struct { members, span } = &camera.inner else {
+            panic!("Camera is not a struct");
+        };
+        let offset = |name: &str| members
+            .iter()
+            .find(|member| member.name.as_deref().map(undecorate) == Some(name))
+            .map(|member| member.offset as usize);
+
+        assert_eq!(*span as usize, size_of::<RtCameraUniform>());
+        assert_eq!(offset("near"), Some(std::mem::offset_of!(RtCameraUniform, near)));
+        assert_eq!(offset("far"), Some(std::mem::offset_of!(RtCameraUniform, far)));
+        assert_eq!(offset("view_projection"), Some(std::mem::offset_of!(RtCameraUniform, view_projection)));
+    }
+
+    #[test]
+    fn uniform_carries_the_clipping_planes() {
+        let camera = RtCamera::new(&RtCameraDescriptor {
+            image_width: 16,
+            image_height: 9,
+            near: 0.5,
+            far: 64.0,
+            ..Default::default()
+        });
+        let uniform = RtCameraUniform::new(&camera, &Transform::default(), glm::Mat4::identity());
+
+        assert_eq!((uniform.near, uniform.far), (0.5, 64.0));
+        assert_eq!(uniform.view_projection, camera.projection() * camera.view(&Transform::default()));
+    }
+}
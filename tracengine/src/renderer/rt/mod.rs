@@ -1,2 +1,3 @@
+pub mod accumulation;
 pub mod camera;
 pub mod transform;
\ No newline at end of file
@@ -10,26 +10,26 @@ use crate::{
     },
 };
 
+/// Transform of a traced object, which also keeps the matrix of the previous
+/// frame to compute motion vectors of moving objects.
+///
+/// The previous matrix only changes with [`RtTransform::advance`], so the uniform
+/// data can be uploaded any number of times within a frame.
+#[derive(Default)]
 pub struct RtTransform {
     transform: Transform,
-    uniform: RtTransformUniform,
+    last_transform_matrix: Option<glm::Mat4>,
 }
 
 impl RtTransform {
     pub fn identity() -> RtTransform {
         RtTransform::default()
     }
-}
-
-impl Default for RtTransform {
-    fn default() -> Self {
-        let mut transform = Transform::default();
-        let uniform = RtTransformUniform {
-            inverse_matrix: transform.uniform_data().inverse_matrix,
-            prev_matrix: glm::Mat4::identity(),
-        };
 
-        RtTransform { transform, uniform }
+    /// Stores the current matrix as the one of the previous frame.
+    /// Must be called once per frame, after the transform has been uploaded for all passes.
+    pub fn advance(&mut self) {
+        self.last_transform_matrix = Some(self.transform.uniform_data().transform_matrix);
     }
 }
 
@@ -51,10 +51,12 @@ impl InstanceData for RtTransform {
     type UniformData = RtTransformUniform;
 
     fn uniform_data(&mut self) -> Self::UniformData {
-        self.uniform.prev_matrix = self.uniform.inverse_matrix.try_inverse().unwrap();
-        self.uniform.inverse_matrix = self.transform.uniform_data().inverse_matrix;
+        let transform_uniform = self.transform.uniform_data();
 
-        self.uniform
+        RtTransformUniform {
+            inverse_matrix: transform_uniform.inverse_matrix,
+            prev_transform_matrix: self.last_transform_matrix.unwrap_or(transform_uniform.transform_matrix),
+        }
     }
 }
 
@@ -62,5 +64,5 @@ impl InstanceData for RtTransform {
 #[derive(Debug, Clone, Copy, Zeroable, Pod)]
 pub struct RtTransformUniform {
     inverse_matrix: glm::Mat4,
-    prev_matrix: glm::Mat4,
-}
\ No newline at end of file
+    prev_transform_matrix: glm::Mat4,
+}
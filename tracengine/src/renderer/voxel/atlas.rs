@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+use crate::renderer::{
+    error::RenderError,
+    hal::{
+        buffer::Buffer,
+        image::{Image, ImageError},
+        texture::{Texture, TextureDescriptor, TextureError},
+    },
+    pbr::mesh::FaceDirection,
+    types::*,
+    Renderer,
+};
+
+/// Layers of the block texture array used by the faces of a block type.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockFaces {
+    pub top: u32,
+    pub side: u32,
+    pub bottom: u32,
+}
+
+impl BlockFaces {
+    /// Creates faces using the same texture layer.
+    pub fn all(layer: u32) -> BlockFaces {
+        BlockFaces { top: layer, side: layer, bottom: layer }
+    }
+
+    /// Retrieves the texture layer of a face.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - The direction of the face.
+    ///
+    /// # Returns
+    ///
+    /// The layer of the top or bottom texture, or of the side one for other faces.
+    pub fn layer(&self, direction: FaceDirection) -> u32 {
+        match direction {
+            FaceDirection::Top => self.top,
+            FaceDirection::Bottom => self.bottom,
+            _ => self.side,
+        }
+    }
+}
+
+/// Face textures of block types. Blocks are typed by their color identifier, so a
+/// texture can be assigned to every color of the palette; other blocks stay flat-colored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockTextures {
+    faces: Vec<Option<BlockFaces>>,
+}
+
+impl Default for BlockTextures {
+    fn default() -> Self {
+        BlockTextures { faces: vec![None; BlockTextures::BLOCK_TYPES] }
+    }
+}
+
+impl BlockTextures {
+    /// Number of block types, one per color identifier.
+    pub const BLOCK_TYPES: usize = 256;
+
+    /// Retrieves the face textures of a block type.
+    ///
+    /// # Arguments
+    ///
+    /// * `color_id` - The color identifier of the blocks.
+    ///
+    /// # Returns
+    ///
+    /// The `BlockFaces`, or `None` if the blocks are flat-colored.
+    pub fn get(&self, color_id: u8) -> Option<BlockFaces> {
+        self.faces[color_id as usize]
+    }
+
+    /// Sets the face textures of a block type.
+    ///
+    /// # Arguments
+    ///
+    /// * `color_id` - The color identifier of the blocks.
+    /// * `faces` - The face textures, or `None` to use the palette color.
+    pub fn set(&mut self, color_id: u8, faces: Option<BlockFaces>) {
+        self.faces[color_id as usize] = faces;
+    }
+
+    /// Assigns textures named after color identifiers to their block types: `<id>` to all
+    /// faces, and `<id>_top`, `<id>_side` and `<id>_bottom` to single faces.
+    /// Block types missing the texture of a face stay flat-colored.
+    fn from_layer_names(layers: &HashMap<String, u32>) -> BlockTextures {
+        let mut textures = BlockTextures::default();
+
+        for color_id in 0..=u8::MAX {
+            let layer = |suffix: &str| layers.get(&format!("{color_id}{suffix}")).copied();
+            let all = layer("");
+            let (top, side, bottom) = (layer("_top").or(all), layer("_side").or(all), layer("_bottom").or(all));
+
+            if let (Some(top), Some(side), Some(bottom)) = (top, side, bottom) {
+                textures.set(color_id, Some(BlockFaces { top, side, bottom }));
+            }
+        }
+
+        textures
+    }
+
+    /// Packs the faces for the `block_faces` shader buffer: top, side and bottom layers,
+    /// and whether the block type is textured.
+    fn gpu_data(&self) -> Vec<glm::UVec4> {
+        self.faces
+            .iter()
+            .map(|faces| match faces {
+                Some(faces) => glm::vec4(faces.top, faces.side, faces.bottom, 1),
+                None => glm::UVec4::zeros(),
+            })
+            .collect()
+    }
+}
+
+/// Block textures stacked into a 2D array texture, with the faces of block types.
+///
+/// Meshes sample the texture with the layers of their vertices, and the ray tracer looks
+/// up the layers of the hit block in the `block_faces` buffer.
+#[derive(Debug)]
+pub struct BlockAtlas {
+    texture: Texture,
+    layers: HashMap<String, u32>,
+    textures: Arc<BlockTextures>,
+    faces_buffer: Buffer<glm::UVec4>,
+    dirty: bool,
+}
+
+impl BlockAtlas {
+    /// Loads the PNG images of a directory into the layers of the atlas,
+    /// sorted by their file names.
+    ///
+    /// Images are named by their file stems. Images named after a color identifier are
+    /// assigned to its block type: `<id>` to all faces, and `<id>_top`, `<id>_side` and
+    /// `<id>_bottom` to single faces, e.g. `12_top.png` and `12.png` for the other faces.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `dir` - The directory of block textures of equal size.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `BlockAtlas`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory or an image cannot be read, the images
+    /// have different sizes, the directory has no images or the faces cannot be staged.
+    pub fn load_dir(renderer: &Renderer, dir: impl AsRef<Path>) -> Result<BlockAtlas, RenderError> {
+        let mut atlas = BlockAtlas::from_images(renderer, read_images(dir.as_ref())?)?;
+        atlas.textures = Arc::new(BlockTextures::from_layer_names(&atlas.layers));
+        atlas.faces_buffer.fill_exact(renderer, 0, &atlas.textures.gpu_data())?;
+
+        Ok(atlas)
+    }
+
+    /// Creates an atlas from named images of equal size, in order of the layers.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    /// * `images` - The names and images of the block textures.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `BlockAtlas` without textured block types.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the images have different sizes, there are no images
+    /// or the faces cannot be staged.
+    pub fn from_images(renderer: &Renderer, images: Vec<(String, Image)>) -> Result<BlockAtlas, RenderError> {
+        let (names, images): (Vec<_>, Vec<_>) = images.into_iter().unzip();
+
+        let image = Image::array(&images).map_err(TextureError::from)?;
+        let texture = Texture::from_image(renderer, &image, TextureDescriptor {
+            depth: Some(1),
+            filter: FilterMode::Nearest,
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            mip_level_count: u32::MAX,
+            address_mode: AddressMode::Repeat,
+            label: "Block textures",
+            ..Default::default()
+        })?;
+
+        let layers = names
+            .into_iter()
+            .enumerate()
+            .map(|(layer, name)| (name, layer as u32))
+            .collect();
+
+        let textures = BlockTextures::default();
+        let faces_buffer = Buffer::new(renderer, BlockTextures::BLOCK_TYPES, BufferUsages::STORAGE);
+        faces_buffer.fill_exact(renderer, 0, &textures.gpu_data())?;
+
+        Ok(BlockAtlas {
+            texture,
+            layers,
+            textures: Arc::new(textures),
+            faces_buffer,
+            dirty: false,
+        })
+    }
+
+    /// Creates an atlas with a single white texture, for shaders requiring one when
+    /// no block textures are used.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `BlockAtlas` without textured block types.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the faces cannot be staged.
+    pub fn placeholder(renderer: &Renderer) -> Result<BlockAtlas, RenderError> {
+        let image = Image::from_rgba8(1, 1, vec![255; 4]).expect("Data matches the image size");
+
+        BlockAtlas::from_images(renderer, vec![("white".to_owned(), image)])
+    }
+
+    /// Retrieves the layer of a texture by its name.
+    pub fn layer(&self, name: &str) -> Option<u32> {
+        self.layers.get(name).copied()
+    }
+
+    /// Retrieves the face textures of block types, e.g. to generate textured chunk meshes.
+    pub fn textures(&self) -> &Arc<BlockTextures> {
+        &self.textures
+    }
+
+    /// Sets the face textures of a block type. The `block_faces` buffer is updated with [`BlockAtlas::update`].
+    ///
+    /// # Arguments
+    ///
+    /// * `color_id` - The color identifier of the blocks.
+    /// * `faces` - The face textures, or `None` to use the palette color.
+    pub fn set_faces(&mut self, color_id: u8, faces: Option<BlockFaces>) {
+        Arc::make_mut(&mut self.textures).set(color_id, faces);
+        self.dirty = true;
+    }
+
+    /// Retrieves the 2D array texture of block textures, bound to `block_textures` and `block_textures_sampler`.
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// Retrieves the buffer of block type faces, bound to `block_faces`.
+    pub fn faces_buffer(&self) -> &Buffer<glm::UVec4> {
+        &self.faces_buffer
+    }
+
+    /// Stages the faces of block types changed since the last update.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the faces do not fit into the buffer.
+    pub fn update(&mut self, renderer: &Renderer) -> Result<(), RenderError> {
+        if self.dirty {
+            self.faces_buffer.stage(renderer, 0, &self.textures.gpu_data())?;
+            self.dirty = false;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the PNG images of a directory, named by their file stems and sorted by their file names.
+fn read_images(dir: &Path) -> Result<Vec<(String, Image)>, TextureError> {
+    let io_error = |e| TextureError::Image(ImageError::Io(dir.to_owned(), e));
+
+    let mut paths = std::fs::read_dir(dir)
+        .map_err(io_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io_error)?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "png"));
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+            Ok((name, Image::load(path)?))
+        })
+        .collect::<Result<_, ImageError>>()
+        .map_err(TextureError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layers(names: &[&str]) -> HashMap<String, u32> {
+        names.iter().enumerate().map(|(layer, name)| (name.to_string(), layer as u32)).collect()
+    }
+
+    fn png(color: [u8; 4]) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut encoder = png::Encoder::new(&mut bytes, 1, 1);
+        encoder.set_color(png::ColorType::Rgba);
+
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&color).unwrap();
+        writer.finish().unwrap();
+
+        bytes
+    }
+
+    #[test]
+    fn faces_use_the_top_bottom_or_side_layer() {
+        let faces = BlockFaces { top: 1, side: 2, bottom: 3 };
+
+        assert_eq!(faces.layer(FaceDirection::Top), 1);
+        assert_eq!(faces.layer(FaceDirection::Bottom), 3);
+        for direction in [FaceDirection::Front, FaceDirection::Back, FaceDirection::Left, FaceDirection::Right] {
+            assert_eq!(faces.layer(direction), 2, "{direction:?}");
+        }
+
+        assert_eq!(BlockFaces::all(4), BlockFaces { top: 4, side: 4, bottom: 4 });
+    }
+
+    #[test]
+    fn textures_are_assigned_to_block_types_by_name() {
+        let textures = BlockTextures::from_layer_names(&layers(&["12", "12_top", "13_side", "14_top", "14_side", "14_bottom", "white", "300"]));
+
+        // Single faces override the texture of all faces
+        assert_eq!(textures.get(12), Some(BlockFaces { top: 1, side: 0, bottom: 0 }));
+        assert_eq!(textures.get(14), Some(BlockFaces { top: 3, side: 4, bottom: 5 }));
+        // Block types missing a face stay flat-colored
+        assert_eq!(textures.get(13), None);
+        assert_eq!((0..=u8::MAX).filter(|&id| textures.get(id).is_some()).count(), 2);
+    }
+
+    #[test]
+    fn faces_are_packed_for_the_shader() {
+        let mut textures = BlockTextures::default();
+        textures.set(1, Some(BlockFaces { top: 5, side: 6, bottom: 7 }));
+
+        let data = textures.gpu_data();
+
+        assert_eq!(data.len(), BlockTextures::BLOCK_TYPES);
+        assert_eq!(data[0], glm::UVec4::zeros());
+        assert_eq!(data[1], glm::vec4(5, 6, 7, 1));
+    }
+
+    #[test]
+    fn images_are_read_in_order_of_their_names() {
+        let dir = std::env::temp_dir().join(format!("tracengine_atlas_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("2_top.png"), png([0, 255, 0, 255])).unwrap();
+        std::fs::write(dir.join("1.png"), png([255, 0, 0, 255])).unwrap();
+        std::fs::write(dir.join("notes.txt"), "not an image").unwrap();
+
+        let images = read_images(&dir);
+        std::fs::write(dir.join("3.png"), "corrupt").unwrap();
+        let corrupt = read_images(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let images = images.unwrap();
+        let names = images.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["1", "2_top"]);
+        assert_eq!(images[0].1.data(), &[255, 0, 0, 255]);
+        assert!(corrupt.is_err());
+    }
+
+    #[test]
+    fn shipped_block_textures_load() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets/textures/blocks");
+        let images = read_images(&dir).unwrap();
+        assert!(!images.is_empty());
+
+        let image = Image::array(&images.iter().map(|(_, image)| image.clone()).collect::<Vec<_>>()).unwrap();
+        assert_eq!(*image.layers() as usize, images.len());
+
+        let names = images.into_iter().enumerate().map(|(layer, (name, _))| (name, layer as u32)).collect();
+        assert!(BlockTextures::from_layer_names(&names).get(246).is_some());
+    }
+}
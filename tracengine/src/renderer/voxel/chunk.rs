@@ -6,12 +6,14 @@ use nalgebra_glm as glm;
 use crate::renderer::{
     hal::buffer::{Buffer, BufferId},
     pbr::{
-        mesh::Mesh,
+        frustum::Aabb,
+        mesh::{FaceDirection, Mesh},
         transform::Transform,
         Color,
     },
-    voxel::block::Block,
+    voxel::{atlas::BlockTextures, block::Block, lod::{ChunkLod, LodLevel}},
     types::*,
+    error::RenderError,
     Drawable, Renderer, Texture
 };
 
@@ -43,6 +45,10 @@ pub enum LoadChunkError {
     },
     #[error("Invalid texture depth: `{0}` is not a multiple of `{}`", Chunk::CHUNK_SIZE)]
     InvalidTextureDepth(u32),
+    #[error("Cannot write chunk palette: {0}")]
+    Palette(#[from] RenderError),
+    #[error(transparent)]
+    InvalidBlockCoords(#[from] InvalidBlockCoords),
 }
 
 /// Represents a 3D chunk of blocks in a voxel-based world.
@@ -57,6 +63,18 @@ pub struct Chunk {
     /// Optional buffer ID for the vertex buffer associated with the chunk.
     #[serde(skip)]
     vertex_buffer: Option<BufferId>,
+
+    /// Optional face textures of block types used in the mesh.
+    #[serde(skip)]
+    textures: Option<Arc<BlockTextures>>,
+
+    /// Bounds of the active blocks as of the last mesh update.
+    #[serde(skip)]
+    aabb: Option<Aabb>,
+
+    /// Level of detail of the mesh and of the blocks written to textures.
+    #[serde(skip)]
+    lod: LodLevel,
 }
 
 impl Chunk {
@@ -138,9 +156,25 @@ impl Chunk {
         Ok(())
     }
 
-    // FIXME: throws "Copy of (131072 * chunk_index)..(131072 * (chunk_index + 1)) 
-    // would end up overrunning the bounds of the Source buffer of size 131072", 
-    // if `chunk_index` > 0
+    /// Stages the blocks of the chunk into its layers of the chunks texture, and its
+    /// palette into the palettes buffer. The upload may be deferred to later frames
+    /// by the upload budget of the renderer.
+    ///
+    /// Blocks are written at the level of detail of the chunk, filling the first
+    /// `lod().size()` texels of the layers along each axis. Each texel stores whether the
+    /// block is active, its color id and the level of detail.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer staging the uploads.
+    /// * `chunks_texture` - A 3D texture of `CHUNK_SIZE` wide layers of chunks.
+    /// * `palettes_buffer` - The buffer of palette colors.
+    /// * `chunk_index` - The index of the chunk layer in the texture.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the texture has an invalid size or dimension, the index is out
+    /// of bounds or the palette does not fit into the buffer.
     pub fn write_to_texture(
         &self, 
         renderer: &Renderer, 
@@ -180,50 +214,50 @@ impl Chunk {
             });
         }
 
-        renderer.queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: chunks_texture.texture(),
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &self.data_to_u8_slice(),
-            wgpu::ImageDataLayout {
-                offset: chunk_index * Chunk::CHUNK_SIZE as u64 * Chunk::CHUNK_SIZE as u64 * Chunk::CHUNK_SIZE as u64 * 4,
-                bytes_per_row: Some(4 * descr.width),
-                rows_per_image: Some(descr.height),
+        chunks_texture.stage(
+            renderer,
+            wgpu::Origin3d {
+                x: 0,
+                y: 0,
+                z: chunk_index as u32 * Chunk::CHUNK_SIZE as u32,
             },
             wgpu::Extent3d {
-                width: Chunk::CHUNK_SIZE as u32,
-                height: Chunk::CHUNK_SIZE as u32,
-                depth_or_array_layers: Chunk::CHUNK_SIZE as u32,
+                width: self.lod.size() as u32,
+                height: self.lod.size() as u32,
+                depth_or_array_layers: self.lod.size() as u32,
             },
+            self.data_to_u8_slice(),
         );
 
         // TODO: Chunks 3d texture and palettes buffer to single type
 
-        palettes_buffer.fill_exact(
+        palettes_buffer.stage(
             renderer, 
             chunk_index,
             &self.palette
                 .iter()
                 .map(|c| glm::vec4(c.r, c.g, c.b, 1.0))
                 .collect::<Vec<_>>()
-        ).unwrap();
+        )?;
 
         Ok(())
     }
 
     fn data_to_u8_slice(&self) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(self.blocks.len() * 4);
+        let lod = self.lod_blocks();
+        let size = self.lod.size();
+        let mut bytes = Vec::with_capacity(size * size * size * 4);
         
-        for z in 0..Self::CHUNK_SIZE {
-            for y in 0..Self::CHUNK_SIZE {
-                for x in 0..Self::CHUNK_SIZE {
-                    let block = self.get_block(x, y, z).unwrap();
+        for z in 0..size {
+            for y in 0..size {
+                for x in 0..size {
+                    let block = match &lod {
+                        Some(lod) => lod.get_block(x, y, z),
+                        None => self.get_block(x, y, z),
+                    }.unwrap();
                     bytes.push(block.is_active() as u8);
                     bytes.push(block.color());
-                    bytes.push(0);
+                    bytes.push(self.lod.index() as u8);
                     bytes.push(0);
                 }
             }
@@ -232,18 +266,33 @@ impl Chunk {
         bytes
     }
 
-    /// Generates a `Mesh` for the chunk based on its blocks.
+    /// Generates a `Mesh` for the chunk based on its blocks, at its level of detail.
+    ///
+    /// Faces on the boundaries of the chunk are always generated, so the mesh stays closed
+    /// next to chunks of another level of detail.
     ///
     /// # Returns
     ///
     /// A `Mesh` representing the chunk.
-    pub fn generate_mesh(&self) -> Mesh {
+    ///
+    /// # Errors
+    ///
+    /// Returns `RenderError::InvalidPaletteIndex` if a block refers to a color outside of the palette.
+    pub fn generate_mesh(&self) -> Result<Mesh, RenderError> {
         let mut mesh = Mesh::default();
+        let lod = self.lod_blocks();
+        let (size, scale) = (self.lod.size(), self.lod.scale());
 
-        for x in 0..Self::CHUNK_SIZE {
-            for y in 0..Self::CHUNK_SIZE {
-                for z in 0..Self::CHUNK_SIZE {
-                    let block = self.get_block(x, y, z).unwrap();
+        let get_block = |x: usize, y: usize, z: usize| match &lod {
+            Some(lod) => lod.get_block(x, y, z),
+            None => self.get_block(x, y, z),
+        };
+        let check_block = |x, y, z| get_block(x, y, z).is_some_and(|block| block.is_active());
+
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    let block = get_block(x, y, z).unwrap();
 
                     if !block.is_active() {
                         continue;
@@ -251,48 +300,149 @@ impl Chunk {
 
                     let color = *self.palette
                         .get(block.color() as usize)
-                        .unwrap_or_else(|| {
-                            panic!(
-                                "Wrong palette index `{}` in palette with size `{}`", 
-                                block.color(), 
-                                self.palette.len()
-                            );
-                        });
+                        .ok_or(RenderError::InvalidPaletteIndex {
+                            index: block.color(),
+                            palette_size: self.palette.len(),
+                        })?;
+
+                    let faces = self.textures
+                        .as_ref()
+                        .and_then(|textures| textures.get(block.color()));
+                    let position = [x * scale, y * scale, z * scale];
+                    let mut add_face = |direction| {
+                        let texture = faces.map(|faces| faces.layer(direction));
+                        mesh.add_face(direction, position, scale, color, texture);
+                    };
 
                     // Front face
-                    if z == 0 || !self.check_block(x, y, z - 1) {
-                        mesh.add_front_face(x, y, z, color)
+                    if z == 0 || !check_block(x, y, z - 1) {
+                        add_face(FaceDirection::Front);
                     }
 
                     // Back face
-                    if !self.check_block(x, y, z + 1) {
-                        mesh.add_back_face(x, y, z, color);
+                    if !check_block(x, y, z + 1) {
+                        add_face(FaceDirection::Back);
                     }
 
                     // Left face
-                    if x == 0 || !self.check_block(x - 1, y, z) {
-                        mesh.add_left_face(x, y, z, color);
+                    if x == 0 || !check_block(x - 1, y, z) {
+                        add_face(FaceDirection::Left);
                     }
 
                     // Right face
-                    if !self.check_block(x + 1, y, z) {
-                        mesh.add_right_face(x, y, z, color);
+                    if !check_block(x + 1, y, z) {
+                        add_face(FaceDirection::Right);
                     }
 
                     // Bottom face
-                    if y == 0 || !self.check_block(x, y - 1, z) {
-                        mesh.add_bottom_face(x, y, z, color);
+                    if y == 0 || !check_block(x, y - 1, z) {
+                        add_face(FaceDirection::Bottom);
                     }
 
                     // Top face
-                    if !self.check_block(x, y + 1, z) {
-                        mesh.add_top_face(x, y, z, color);
+                    if !check_block(x, y + 1, z) {
+                        add_face(FaceDirection::Top);
                     }
                 }
             }
         }
 
-        mesh
+        Ok(mesh)
+    }
+
+    /// Sets the face textures of block types, used by meshes generated afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `textures` - The face textures, e.g. of a `BlockAtlas`, or `None` for flat-colored blocks.
+    pub fn set_textures(&mut self, textures: Option<Arc<BlockTextures>>) {
+        self.textures = textures;
+    }
+
+    /// Retrieves the bounds of the mesh in the local space of the chunk, e.g. for frustum culling.
+    /// The bounds are computed by `update()` with the mesh.
+    ///
+    /// # Returns
+    ///
+    /// The bounds of the active blocks, or `None` if the chunk is empty or has not been updated.
+    pub fn aabb(&self) -> Option<Aabb> {
+        self.aabb
+    }
+
+    /// Retrieves the level of detail of the chunk.
+    pub fn lod(&self) -> LodLevel {
+        self.lod
+    }
+
+    /// Sets the level of detail used by meshes generated and textures written afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of detail, e.g. selected by `LodSettings::select`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the level has changed, and the chunk must be updated.
+    pub fn set_lod(&mut self, level: LodLevel) -> bool {
+        let changed = self.lod != level;
+        self.lod = level;
+
+        changed
+    }
+
+    /// Downsamples the blocks to the level of detail, or `None` at full resolution.
+    fn lod_blocks(&self) -> Option<ChunkLod> {
+        (self.lod != LodLevel::Full).then(|| ChunkLod::generate(self, self.lod))
+    }
+
+    /// Computes the bounds of the active blocks at the level of detail, one unit per block.
+    fn compute_aabb(&self) -> Option<Aabb> {
+        let lod = self.lod_blocks();
+        let (size, scale) = (self.lod.size(), self.lod.scale());
+        let mut bounds: Option<Aabb> = None;
+
+        for x in 0..size {
+            for y in 0..size {
+                for z in 0..size {
+                    let active = match &lod {
+                        Some(lod) => lod.check_block(x, y, z),
+                        None => self.check_block(x, y, z),
+                    };
+
+                    if !active {
+                        continue;
+                    }
+
+                    let min = glm::vec3(x as f32, y as f32, z as f32) * scale as f32;
+                    let max = min.add_scalar(scale as f32);
+
+                    bounds = Some(match bounds {
+                        Some(aabb) => Aabb::new(glm::min2(&aabb.min, &min), glm::max2(&aabb.max, &max)),
+                        None => Aabb::new(min, max),
+                    });
+                }
+            }
+        }
+
+        bounds
+    }
+
+    /// Destroys the vertex buffer of the chunk, e.g. when the chunk is unloaded.
+    /// The next `update()` creates a new one.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The `Renderer` instance owning the vertex buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the vertex buffer has already been destroyed.
+    pub fn release_vertex_buffer(&mut self, renderer: &mut Renderer) -> Result<(), RenderError> {
+        if let Some(vertex_buffer) = self.vertex_buffer.take() {
+            renderer.destroy_vertex_buffer(vertex_buffer)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -303,32 +453,30 @@ impl Drawable for Chunk {
     ///
     /// * `renderer` - The `Renderer` instance used to manage rendering resources.
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// Panics if `update()` has not been called and `vertex_buffer` is `None`.
-    fn update(&mut self, renderer: &mut Renderer) {
-        let mesh = self.generate_mesh();
+    /// Returns an error if the mesh cannot be generated or the vertex buffer is invalid.
+    fn update(&mut self, renderer: &mut Renderer) -> Result<(), RenderError> {
+        let mesh = self.generate_mesh()?;
+        self.aabb = self.compute_aabb();
 
-        if self.vertex_buffer.is_none() {
-            self.vertex_buffer = Some(renderer.create_vertex_buffer(mesh.vertex_data.len()));
-        }
+        let vertex_buffer = match self.vertex_buffer {
+            Some(vertex_buffer) => vertex_buffer,
+            None => *self.vertex_buffer.insert(renderer.create_vertex_buffer(mesh.vertex_data.len())?),
+        };
 
-        renderer.update_vertex_buffer(self.vertex_buffer.unwrap(), &mesh.vertex_data)
-            .expect("Cannot call update() on chunk");
+        renderer.update_vertex_buffer(vertex_buffer, &mesh.vertex_data)?;
+
+        Ok(())
     }
 
     /// Retrieves the vertex buffer ID for the chunk.
     ///
     /// # Returns
     ///
-    /// The `BufferId` for the vertex buffer.
-    ///
-    /// # Panics
-    ///
-    /// Panics if `vertex_buffer` is `None`, indicating that `update()` has not been called.
-    fn vertex_buffer(&self) -> BufferId {
+    /// The `BufferId` for the vertex buffer, or `None` if `update()` has not been called.
+    fn vertex_buffer(&self) -> Option<BufferId> {
         self.vertex_buffer
-            .expect("Chunk is not set up with update()")
     }
 }
 
@@ -338,6 +486,9 @@ impl Default for Chunk {
             blocks: [[[Block::default(); Self::CHUNK_SIZE]; Self::CHUNK_SIZE]; Self::CHUNK_SIZE],
             palette: Arc::new([]),
             vertex_buffer: None,
+            textures: None,
+            aabb: None,
+            lod: LodLevel::Full,
         }
     }
 }
@@ -350,4 +501,35 @@ pub struct ChunkBundle {
     
     /// The `Transform` associated with the chunk.
     pub transform: Transform,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_mesh_rejects_colors_outside_of_the_palette() {
+        let mut chunk = Chunk::new(Arc::new([Color::new(1.0, 0.0, 0.0)]));
+        chunk.set_block(Block::new(true, 0), 0, 0, 0).unwrap();
+        assert!(chunk.generate_mesh().is_ok());
+
+        chunk.set_block(Block::new(true, 3), 1, 0, 0).unwrap();
+        let error = chunk.generate_mesh().unwrap_err();
+        assert!(matches!(error, RenderError::InvalidPaletteIndex { index: 3, palette_size: 1 }));
+    }
+
+    #[test]
+    fn inactive_blocks_are_not_checked_against_the_palette() {
+        let mut chunk = Chunk::default();
+        chunk.set_block(Block::new(false, 3), 0, 0, 0).unwrap();
+
+        assert!(chunk.generate_mesh().unwrap().vertex_data.is_empty());
+    }
+
+    #[test]
+    fn set_block_rejects_coordinates_outside_of_the_chunk() {
+        let mut chunk = Chunk::default();
+        let error = chunk.set_block(Block::new(true, 0), Chunk::CHUNK_SIZE, 0, 0).unwrap_err();
+
+        assert_eq!(error.to_string(), "Invalid block coords (32, 0, 0) in chunk");
+    }
+}
@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+
+use crate::renderer::voxel::{block::Block, chunk::Chunk};
+
+/// A level of detail of a chunk, merging cubes of `scale()` blocks into a single block.
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum LodLevel {
+    /// The full resolution of the chunk.
+    #[default]
+    Full,
+    /// Cubes of 2 blocks merged into one.
+    Half,
+    /// Cubes of 4 blocks merged into one.
+    Quarter,
+    /// Cubes of 8 blocks merged into one.
+    Eighth,
+}
+
+impl LodLevel {
+    /// All levels, from the finest to the coarsest.
+    pub const ALL: [LodLevel; 4] = [LodLevel::Full, LodLevel::Half, LodLevel::Quarter, LodLevel::Eighth];
+
+    /// Retrieves the index of the level, `0` being the full resolution.
+    pub fn index(&self) -> u32 {
+        *self as u32
+    }
+
+    /// Retrieves the number of blocks merged along each axis.
+    pub fn scale(&self) -> usize {
+        1 << self.index()
+    }
+
+    /// Retrieves the number of merged blocks along each axis of a chunk.
+    pub fn size(&self) -> usize {
+        Chunk::CHUNK_SIZE / self.scale()
+    }
+}
+
+/// The blocks of a chunk downsampled to a coarser level of detail.
+///
+/// A merged block is active if any of its blocks is active, so the coarse occupancy
+/// always covers the full one and distant surfaces never open holes. Its color is the
+/// most common color among its active blocks, the lowest color id winning ties.
+///
+/// Seams between neighbouring chunks of different levels are not stitched and no skirts
+/// are generated: merged blocks stay within their chunk, so no gaps open along the shared
+/// faces, but the surface of the coarser chunk may step out by up to `scale() - 1` blocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkLod {
+    level: LodLevel,
+    blocks: Vec<Block>,
+}
+
+impl ChunkLod {
+    /// Downsamples the blocks of a chunk.
+    ///
+    /// # Arguments
+    ///
+    /// * `chunk` - The chunk at full resolution.
+    /// * `level` - The level of detail to downsample to.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `ChunkLod` with `level.size()` blocks along each axis.
+    pub fn generate(chunk: &Chunk, level: LodLevel) -> ChunkLod {
+        let (size, scale) = (level.size(), level.scale());
+        let mut blocks = Vec::with_capacity(size * size * size);
+        let mut counts = [0u16; 256];
+
+        for z in 0..size {
+            for y in 0..size {
+                for x in 0..size {
+                    counts.fill(0);
+
+                    for dz in 0..scale {
+                        for dy in 0..scale {
+                            for dx in 0..scale {
+                                if let Some(block) = chunk
+                                    .get_block(x * scale + dx, y * scale + dy, z * scale + dz)
+                                    .filter(|block| block.is_active())
+                                {
+                                    counts[block.color() as usize] += 1;
+                                }
+                            }
+                        }
+                    }
+
+                    // `max_by_key` keeps the last maximum, so colors are visited in reverse
+                    let (color, &count) = counts
+                        .iter()
+                        .enumerate()
+                        .rev()
+                        .max_by_key(|&(_, count)| count)
+                        .unwrap();
+
+                    blocks.push(Block::new(count > 0, color as u8));
+                }
+            }
+        }
+
+        ChunkLod { level, blocks }
+    }
+
+    /// Retrieves the level of detail of the blocks.
+    pub fn level(&self) -> LodLevel {
+        self.level
+    }
+
+    /// Retrieves a reference to a merged block at the specified coordinates.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The x-coordinate of the block, up to `level().size()`.
+    /// * `y` - The y-coordinate of the block.
+    /// * `z` - The z-coordinate of the block.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing a reference to the block if the coordinates are valid, otherwise `None`.
+    pub fn get_block(&self, x: usize, y: usize, z: usize) -> Option<&Block> {
+        let size = self.level.size();
+
+        if x >= size || y >= size || z >= size {
+            return None;
+        }
+
+        self.blocks.get(x + (y + z * size) * size)
+    }
+
+    /// Checks if a merged block is active at the specified coordinates.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The x-coordinate of the block, up to `level().size()`.
+    /// * `y` - The y-coordinate of the block.
+    /// * `z` - The z-coordinate of the block.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the block is active, otherwise `false`.
+    pub fn check_block(&self, x: usize, y: usize, z: usize) -> bool {
+        self.get_block(x, y, z).is_some_and(|block| block.is_active())
+    }
+}
+
+/// Distances at which chunks switch to coarser levels of detail.
+///
+/// Chunks switch back to a finer level only once they are `hysteresis` closer than the
+/// distance of their level, so chunks moving around a distance do not switch every frame.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LodSettings {
+    /// Minimum distances of the `Half`, `Quarter` and `Eighth` levels, in increasing order.
+    pub distances: [f32; 3],
+    /// Margin below the distance of a level before chunks switch back to a finer level,
+    /// smaller than the gaps between the distances.
+    #[serde(default)]
+    pub hysteresis: f32,
+}
+
+impl LodSettings {
+    /// Selects the level of detail of a chunk.
+    ///
+    /// # Arguments
+    ///
+    /// * `distance` - The distance from the camera to the chunk, e.g. to its bounding box.
+    /// * `current` - The current level of the chunk.
+    ///
+    /// # Returns
+    ///
+    /// The coarsest level whose distance has been reached, the distances of levels up to
+    /// `current` being lowered by the hysteresis.
+    pub fn select(&self, distance: f32, current: LodLevel) -> LodLevel {
+        let index = self.distances
+            .iter()
+            .enumerate()
+            .take_while(|&(level, &min)| {
+                let min = if level < current.index() as usize { min - self.hysteresis } else { min };
+                distance >= min
+            })
+            .count();
+
+        LodLevel::ALL[index]
+    }
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        LodSettings {
+            distances: [64.0, 128.0, 256.0],
+            hysteresis: 8.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(blocks: &[(usize, usize, usize, u8)]) -> Chunk {
+        let mut chunk = Chunk::default();
+        for &(x, y, z, color) in blocks {
+            chunk.set_block(Block::new(true, color), x, y, z).unwrap();
+        }
+
+        chunk
+    }
+
+    #[test]
+    fn merged_blocks_cover_every_active_block() {
+        let chunk = chunk(&[(0, 0, 0, 3), (1, 0, 0, 5), (1, 1, 1, 5), (31, 17, 9, 7)]);
+
+        for level in LodLevel::ALL {
+            let lod = ChunkLod::generate(&chunk, level);
+            let (size, scale) = (level.size(), level.scale());
+
+            for x in 0..Chunk::CHUNK_SIZE {
+                for y in 0..Chunk::CHUNK_SIZE {
+                    for z in 0..Chunk::CHUNK_SIZE {
+                        if chunk.check_block(x, y, z) {
+                            assert!(lod.check_block(x / scale, y / scale, z / scale));
+                        }
+                    }
+                }
+            }
+
+            // Merged blocks without any active block stay empty
+            let active = (0..size.pow(3))
+                .filter(|i| lod.check_block(i % size, i / size % size, i / size.pow(2)))
+                .count();
+            assert_eq!(active, if level == LodLevel::Full { 4 } else { 2 });
+            assert_eq!(lod.get_block(size, 0, 0), None);
+        }
+    }
+
+    #[test]
+    fn merged_blocks_take_the_majority_color() {
+        let chunk = chunk(&[(0, 0, 0, 3), (1, 0, 0, 5), (1, 1, 1, 5), (31, 17, 9, 7)]);
+
+        assert_eq!(ChunkLod::generate(&chunk, LodLevel::Half).get_block(0, 0, 0).unwrap().color(), 5);
+        assert_eq!(ChunkLod::generate(&chunk, LodLevel::Eighth).get_block(3, 2, 1).unwrap().color(), 7);
+    }
+
+    #[test]
+    fn color_ties_pick_the_lowest_color() {
+        let chunk = chunk(&[(0, 0, 0, 9), (1, 0, 0, 4), (0, 1, 0, 4), (1, 1, 0, 9), (2, 0, 0, 200), (3, 0, 0, 0)]);
+
+        assert_eq!(ChunkLod::generate(&chunk, LodLevel::Half).get_block(0, 0, 0).unwrap().color(), 4);
+        assert_eq!(ChunkLod::generate(&chunk, LodLevel::Half).get_block(1, 0, 0).unwrap().color(), 0);
+        // Inactive blocks do not count towards the majority
+        assert_eq!(ChunkLod::generate(&chunk, LodLevel::Quarter).get_block(0, 0, 0).unwrap().color(), 4);
+    }
+
+    #[test]
+    fn levels_switch_at_their_distances() {
+        let settings = LodSettings { distances: [10.0, 20.0, 40.0], hysteresis: 2.0 };
+
+        assert_eq!(settings.select(9.99, LodLevel::Full), LodLevel::Full);
+        assert_eq!(settings.select(10.0, LodLevel::Full), LodLevel::Half);
+        assert_eq!(settings.select(20.0, LodLevel::Full), LodLevel::Quarter);
+        assert_eq!(settings.select(39.99, LodLevel::Half), LodLevel::Quarter);
+        assert_eq!(settings.select(40.0, LodLevel::Half), LodLevel::Eighth);
+        assert_eq!(settings.select(1000.0, LodLevel::Full), LodLevel::Eighth);
+    }
+
+    #[test]
+    fn finer_levels_are_selected_past_the_hysteresis() {
+        let settings = LodSettings { distances: [10.0, 20.0, 40.0], hysteresis: 2.0 };
+
+        assert_eq!(settings.select(9.0, LodLevel::Half), LodLevel::Half);
+        assert_eq!(settings.select(8.0, LodLevel::Half), LodLevel::Half);
+        assert_eq!(settings.select(7.99, LodLevel::Half), LodLevel::Full);
+
+        // Chunks switching several levels at once only keep the margin of the levels they leave
+        assert_eq!(settings.select(18.5, LodLevel::Eighth), LodLevel::Quarter);
+        assert_eq!(settings.select(17.99, LodLevel::Eighth), LodLevel::Half);
+        assert_eq!(settings.select(0.0, LodLevel::Eighth), LodLevel::Full);
+    }
+}
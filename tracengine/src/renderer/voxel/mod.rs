@@ -1,3 +1,5 @@
+pub mod atlas;
 pub mod block;
 pub mod chunk;
+pub mod lod;
 pub mod model;
\ No newline at end of file
@@ -1,10 +1,18 @@
 use tracengine::{
-    engine::Engine, 
-    event::{MouseScrollDelta, WindowEvent}, 
-    glm, 
+    engine::{
+        controller::{CameraController, ControllerMode}, 
+        events::{BlockChanged, ChunkLoaded, ChunkUnloaded, EventReader, Events},
+        hot_reload::{ShaderChanged, ShaderWatcher},
+        input::Input, 
+        resources::Resources, 
+        time::Time, 
+        Engine,
+    }, 
+    event::WindowEvent, 
     renderer::{
         error::RenderError, 
-        rt::camera::{RtCamera, RtCameraDescriptor},
+        hal::taa::TaaPass,
+        pbr::camera::CameraProjection,
         InstanceData, Renderer
     }, 
     World
@@ -12,162 +20,207 @@ use tracengine::{
 
 use crate::tracer::Tracer;
 
-#[derive(Default)]
+pub const TOGGLE_ACCUMULATION: &str = "toggle_accumulation";
+pub const TOGGLE_DENOISER: &str = "toggle_denoiser";
+
 pub struct VoxelCraft {
-    tracer: Option<Tracer>,
+    controller: CameraController,
+    chunk_loaded_reader: EventReader<ChunkLoaded>,
+    chunk_unloaded_reader: EventReader<ChunkUnloaded>,
+    block_changed_reader: EventReader<BlockChanged>,
+    shader_changed_reader: EventReader<ShaderChanged>,
 }
 
-impl Engine for VoxelCraft {
-    fn init(&mut self, _: &mut World, renderer: &mut Renderer) {
-        self.tracer = Some(Tracer::new(renderer));
+impl Default for VoxelCraft {
+    fn default() -> Self {
+        VoxelCraft {
+            controller: CameraController::new(ControllerMode::Fly),
+            chunk_loaded_reader: EventReader::default(),
+            chunk_unloaded_reader: EventReader::default(),
+            block_changed_reader: EventReader::default(),
+            shader_changed_reader: EventReader::default(),
+        }
     }
+}
 
-    fn input(&mut self, event: &WindowEvent, _: &mut World) -> bool {
-        let tracer = self.tracer.as_mut().unwrap();
-
-        match event {
-            WindowEvent::CursorMoved { position, .. } => {
-                let (delta_x, delta_y) = {
-                    if tracer.camera_config.latest_pos == glm::Vec2::default() {
-                        (0.0, 0.0)
-                    } else {
-                        (
-                            position.x as f32 - tracer.camera_config.latest_pos.x,
-                            position.y as f32 - tracer.camera_config.latest_pos.y,
-                        )
-                    }
-                };
+impl VoxelCraft {
+    /// Checks if the traced world has been modified since the previous update.
+    fn world_changed(&mut self, resources: &Resources) -> bool {
+        let mut changed = false;
 
-                tracer.camera_config.latest_pos = glm::vec2(position.x as f32, position.y as f32);
+        if let Ok(events) = resources.get::<Events<ChunkLoaded>>() {
+            changed |= self.chunk_loaded_reader.read(&events).count() > 0;
+        }
 
-                let local_x = tracer.tmp_transform.local_x();
-                let (tx, ty) = (tracer.camera_config.target_x, tracer.camera_config.target_y);
+        if let Ok(events) = resources.get::<Events<ChunkUnloaded>>() {
+            changed |= self.chunk_unloaded_reader.read(&events).count() > 0;
+        }
 
-                tracer.camera_config.target_x += delta_y * 0.005;
-                tracer.camera_config.target_y -= delta_x * 0.005;
+        if let Ok(events) = resources.get::<Events<BlockChanged>>() {
+            changed |= self.block_changed_reader.read(&events).count() > 0;
+        }
 
-                tracer.camera_config.target_x = tracer.camera_config.target_x.clamp(
-                    tracer.camera_config.limit.start.to_radians(),
-                    tracer.camera_config.limit.end.to_radians(),
-                );
+        changed
+    }
 
-                tracer.tmp_transform.rotation *=
-                    glm::quat_angle_axis(tracer.camera_config.target_x - tx, &local_x) *
-                    glm::quat_angle_axis(tracer.camera_config.target_y - ty, &glm::Vec3::y());
-            },
-            WindowEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(_, delta), .. } => {
-                tracer.tmp_transform.translation.z += delta * 0.01;
-            },
-            _ => return false,
-        }
+    /// Checks if the watched shaders have been modified since the previous frame.
+    fn shaders_changed(&mut self, resources: &Resources) -> bool {
+        resources.get::<Events<ShaderChanged>>()
+            .is_ok_and(|events| self.shader_changed_reader.read(&events).count() > 0)
+    }
+}
+
+impl Engine for VoxelCraft {
+    fn init(&mut self, _: &mut World, renderer: &mut Renderer, resources: &mut Resources) -> Result<(), RenderError> {
+        resources.insert(Tracer::new(renderer)?);
+
+        Ok(())
+    }
 
+    fn input(&mut self, _: &WindowEvent, _: &mut World, _: &mut Resources) -> bool {
         false
     }
 
-    fn render(&mut self, _: &mut World, renderer: &mut Renderer) -> Result<(), RenderError> {
+    fn render(&mut self, _: &mut World, renderer: &mut Renderer, resources: &mut Resources) -> Result<(), RenderError> {
+        let mut tracer = resources.get_mut::<Tracer>()
+            .map_err(|_| RenderError::SetupError(vec!["Tracer"]))?;
+        let tracer = &mut *tracer;
+
+        if self.shaders_changed(resources) {
+            if let Ok(watcher) = resources.get::<ShaderWatcher>() {
+                tracer.reload_shaders(renderer, watcher.root());
+            }
+        }
+
         let canvas = renderer.canvas()?;
         let mut ctx = renderer.draw_ctx();
 
-        let tracer = self.tracer.as_mut().unwrap();
+        self.controller.update_cursor(&renderer.window());
 
-        tracer.taa.update(renderer);
+        tracer.taa.update(renderer)?;
 
-        let mut rebind_resources = false;
+        let mut rebind_resources = tracer.update_graph(renderer)?;
 
-        let viewport_size = renderer.size().width as usize * renderer.size().height as usize;
-        if *tracer.color_buffer.capacity() != viewport_size {
-            tracer.color_buffer.resize(renderer, viewport_size);
+        if tracer.camera.image_width != renderer.size().width || tracer.camera.image_height != renderer.size().height {
+            tracer.camera.resize(renderer.size().width, renderer.size().height);
             rebind_resources = true;
         }
 
-        if *tracer.background_buffer.capacity() != viewport_size {
-            tracer.background_buffer.resize(renderer, viewport_size);
-            rebind_resources = true;
-        }
+        tracer.camera_buffer.fill_exact(renderer, 0, &[tracer.camera.update(&tracer.camera_transform)])?;
 
-        if *tracer.normal_buffer.capacity() != viewport_size {
-            tracer.normal_buffer.resize(renderer, viewport_size);
-            rebind_resources = true;
-        }
+        let mut chunk_loaded = resources.get_mut::<Events<ChunkLoaded>>()
+            .map_err(|_| RenderError::SetupError(vec!["Events<ChunkLoaded>"]))?;
 
-        if *tracer.depth_buffer.capacity() != viewport_size {
-            tracer.depth_buffer.resize(renderer, viewport_size);
-            rebind_resources = true;
+        if let Err(e) = tracer.update_lod(renderer, &mut chunk_loaded) {
+            eprintln!("Cannot update chunk level of detail: {e}");
         }
 
-        if *tracer.depth2_buffer.capacity() != viewport_size {
-            tracer.depth2_buffer.resize(renderer, viewport_size);
-            rebind_resources = true;
+        if rebind_resources {
+            tracer.rebind_resources(renderer)?;
+            tracer.accumulator.reset();
         }
 
-        if tracer.camera.image_width != renderer.size().width || tracer.camera.image_height != renderer.size().height {
-            tracer.camera = RtCamera::new(&RtCameraDescriptor {
-                image_width: renderer.size().width,
-                image_height: renderer.size().height,
-                scan_depth: 10,
-                jitter: tracer.taa.current_jitter,
-            });
-            tracer.camera_buffer.fill_exact(renderer, 0, &[tracer.camera.uniform_data()]).unwrap();
-            rebind_resources = true;
-        }
+        tracer.denoiser.update(renderer)?;
+
+        tracer.accumulator.track(&[tracer.camera_transform, *tracer.tmp_transform]);
+        tracer.accumulation_buffer.fill_exact(renderer, 0, &[tracer.accumulator.uniform_data()])?;
+
+        let passes = &tracer.passes;
+
+        tracer.graph.execute(renderer, &mut ctx, &canvas, |pass, pass_ctx| {
+            if pass == passes.ray_tracing {
+                pass_ctx.compute_pass().compute(
+                    renderer,
+                    Some(&mut tracer.tmp_transform),
+                    &tracer.rt_pipeline, 
+                    &[
+                        &tracer.taa.shader_resource,
+                        &tracer.shader_resource,
+                    ], 
+                    renderer.size(),
+                )?;
+            } else if pass == passes.denoising {
+                // Reference renders are converged by accumulation and must stay unbiased
+                if tracer.denoiser.enabled && !tracer.accumulator.is_enabled() {
+                    let mut compute_pass = pass_ctx.compute_pass();
+
+                    for mut denoise_pass in tracer.denoiser.passes() {
+                        compute_pass.compute(
+                            renderer,
+                            Some(&mut denoise_pass),
+                            &tracer.denoise_pipeline,
+                            &[
+                                &tracer.taa.shader_resource,
+                                &tracer.shader_resource,
+                                &tracer.denoiser.shader_resource,
+                            ],
+                            renderer.size(),
+                        )?;
+                    }
+                }
+            } else if pass == passes.taa_resolve || pass == passes.taa_present {
+                let mut taa_pass = if pass == passes.taa_resolve { TaaPass::resolve() } else { TaaPass::present() };
+
+                pass_ctx.render_pass()?.draw(
+                    renderer,
+                    None, 
+                    Some(&mut taa_pass), 
+                    &tracer.taa_pipeline, 
+                    &[
+                        &tracer.taa.shader_resource,
+                        &tracer.shader_resource,
+                    ],
+                )?;
+            }
+
+            Ok(())
+        })?;
+
+        tracer.accumulator.advance();
+        tracer.tmp_transform.advance();
 
-        if rebind_resources {
-            tracer.rebind_resources(renderer);
-        }
+        ctx.apply(canvas, renderer);
+        tracer.apply_pending_accumulation_reset(renderer);
 
-        {
-            let mut compute_pass = ctx.compute_pass();
-
-            compute_pass.compute(
-                Some(&mut tracer.tmp_transform),
-                &tracer.rt_pipeline, 
-                &[
-                    &tracer.taa.shader_resource,
-                    &tracer.shader_resource,
-                ], 
-                renderer.size(),
-            );
-        }
+        Ok(())
+    }
 
-        {
-            let mut render_pass = ctx.render_pass(&tracer.taa.render_texture, renderer.depth_texture());
-
-            render_pass.draw::<()>(
-                renderer,
-                None, 
-                None, 
-                &tracer.taa_pipeline, 
-                &[
-                    &tracer.taa.shader_resource,
-                    &tracer.shader_resource,
-                ],
-            );
+    fn update(&mut self, _: &mut World, resources: &mut Resources) {
+        let world_changed = self.world_changed(resources);
+
+        let (Ok(input), Ok(time), Ok(mut tracer)) = (
+            resources.get::<Input>(),
+            resources.get::<Time>(),
+            resources.get_mut::<Tracer>(),
+        ) else {
+            return;
+        };
+        let tracer = &mut *tracer;
+
+        // Accumulated samples are stale once the changed blocks have been uploaded
+        if world_changed {
+            tracer.reset_accumulation_after_uploads();
         }
 
-        ctx.copy_texture(
-            &tracer.taa.render_texture, 
-            &tracer.taa.history_texture, 
-        );
+        if input.action_just_pressed(TOGGLE_ACCUMULATION) {
+            let enabled = tracer.accumulator.is_enabled();
+            tracer.accumulator.set_enabled(!enabled);
+        }
 
-        {
-            let mut render_pass = ctx.render_pass(&canvas, renderer.depth_texture());
-
-            render_pass.draw::<()>(
-                renderer,
-                None, 
-                None, 
-                &tracer.taa_pipeline, 
-                &[
-                    &tracer.taa.shader_resource,
-                    &tracer.shader_resource,
-                ],
-            );
+        if input.action_just_pressed(TOGGLE_DENOISER) {
+            tracer.denoiser.enabled = !tracer.denoiser.enabled;
         }
 
-        ctx.apply(canvas, renderer);
+        // The denoiser history is not updated while it is skipped
+        if input.action_just_pressed(TOGGLE_ACCUMULATION) || input.action_just_pressed(TOGGLE_DENOISER) {
+            tracer.denoiser.reset_history();
+        }
 
-        Ok(())
+        self.controller.update(
+            &input,
+            &tracer.camera, 
+            &mut tracer.camera_transform, 
+            time.unscaled_fixed_delta(),
+        );
     }
-
-    fn update(&mut self, _: &mut World) {}
 }
\ No newline at end of file
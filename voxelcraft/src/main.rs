@@ -1,8 +1,11 @@
 pub mod tracer;
-pub mod camera;
 pub mod app;
 
-use tracengine::{Game, PhysicalSize, WindowBuilder};
+use tracengine::{
+    engine::{controller::CameraController, hot_reload::ShaderHotReloadPlugin, input::InputBindings}, 
+    renderer::config::RendererConfig,
+    Game, PhysicalSize, WindowBuilder,
+};
 use app::VoxelCraft;
 
 fn main() -> anyhow::Result<()> {
@@ -10,7 +13,17 @@ fn main() -> anyhow::Result<()> {
         WindowBuilder::new()
             .with_title("Ray tracing")
             .with_inner_size(PhysicalSize::new(1280, 720)),
+        RendererConfig::default(),
     )?;
+    let mut bindings = InputBindings::load("../assets/config/input.ron")?;
+    CameraController::bind_defaults(&mut bindings);
+
+    game.set_input_bindings(bindings);
+
+    if cfg!(debug_assertions) {
+        game.add_plugin(ShaderHotReloadPlugin::new("../assets/shaders"));
+    }
+
     game.set_engine(VoxelCraft::default());
     game.run()?;
 
@@ -1,88 +1,195 @@
+use tracengine::engine::events::{BlockChanged, ChunkLoaded, Events};
 use tracengine::renderer::{
+    graph::{BufferHandle, GraphBufferDescriptor, GraphTextureDescriptor, PassId, RenderGraph, TextureHandle},
     hal::{
-        buffer::{Buffer, BufferResourceDescriptor},
+        buffer::Buffer,
+        denoiser::{Denoiser, DenoiserDescriptor},
         pipeline::{include_wgsl, Pipeline, ShaderResource}, 
+        reflection::ShaderReflection,
+        shader::ShaderError,
         taa::Taa, 
-        texture::{Texture, TextureDescriptor, TextureResourceDescriptor, TextureResourceUsage}
+        texture::{Texture, TextureDescriptor}
     }, 
+    error::RenderError,
     rt::{
+        accumulation::{AccumulationUniform, Accumulator},
         camera::{RtCamera, RtCameraDescriptor, RtCameraUniform},
         transform::RtTransform,
     }, 
+    pbr::{camera::CameraType, frustum::Aabb, transform::Transform},
     types::*,
     voxel::{
-        chunk::Chunk, 
+        atlas::BlockAtlas,
+        block::Block,
+        chunk::{Chunk, LoadChunkError}, 
+        lod::LodSettings,
         model::VoxelModel,
     }, 
     InstanceData, Renderer
 };
 use tracengine::glm;
 
-use crate::camera::CameraConfiguration;
+use std::path::Path;
 
 const CHUNKS_RENDER_DISTANCE: u32 = 3;
 
+/// Size of a traced chunk in world units, matching `CHUNK_SIZE * VOXEL_SIZE` in the shaders.
+const CHUNK_EXTENT: f32 = 4.0;
+
+/// Directory of block textures, named after color ids, e.g. `246.png` for the light stone
+/// of the model; blocks are flat-colored if the directory is missing.
+const BLOCK_TEXTURES_DIR: &str = "../assets/textures/blocks";
+
+/// Names of the TAA resources imported into the render graph.
+const TAA_COLOR: &str = "TAA color";
+const TAA_HISTORY: &str = "TAA history";
+const TAA_VELOCITY: &str = "TAA velocity";
+const TAA_HISTORY_DEPTH: &str = "TAA history depth";
+
+/// Index of the tracer bind group in the shaders, following the TAA one.
+const TRACER_BIND_GROUP: u32 = 1;
+
 const fn chunks_count() -> u32 {
     let distance = [CHUNKS_RENDER_DISTANCE, 1][(CHUNKS_RENDER_DISTANCE < 1) as usize];
     (2 * distance + 1) * (2 * distance + 1)
 }
 
+/// Passes of the frame, recorded in the order of the render graph.
+pub struct TracerPasses {
+    pub ray_tracing: PassId,
+    pub denoising: PassId,
+    pub taa_resolve: PassId,
+    pub taa_present: PassId,
+}
+
 pub struct Tracer {
     pub taa: Taa,
+    pub denoiser: Denoiser,
 
     pub camera_buffer: Buffer<RtCameraUniform>,
 
-    pub color_buffer: Buffer<glm::Vec4>,
-    pub background_buffer: Buffer<glm::Vec4>,
-    pub depth_buffer: Buffer<f32>,
-    pub depth2_buffer: Buffer<f32>,
-    pub normal_buffer: Buffer<glm::Vec4>,
+    pub graph: RenderGraph,
+    pub passes: TracerPasses,
+    pub color_buffer: BufferHandle,
+    pub background_buffer: BufferHandle,
+    pub depth_buffer: BufferHandle,
+    pub depth2_buffer: BufferHandle,
+    pub normal_buffer: BufferHandle,
+    pub taa_history: TextureHandle,
 
     pub palettes_buffer: Buffer<glm::Vec4>,
     pub chunks_3d_texture: Texture,
+    pub block_atlas: BlockAtlas,
+    pub accumulation_buffer: Buffer<AccumulationUniform>,
     pub shader_resource: ShaderResource,
 
     pub rt_pipeline: Pipeline,
+    pub denoise_pipeline: Pipeline,
     pub taa_pipeline: Pipeline,
 
     pub chunk: Chunk,
+    pub lod_settings: LodSettings,
     pub camera: RtCamera,
+    pub camera_transform: Transform,
     pub tmp_transform: RtTransform,
-    pub camera_config: CameraConfiguration,
+    pub accumulator: Accumulator,
+    /// Whether the accumulator must be reset once the staged world uploads have been copied.
+    accumulation_reset_pending: bool,
 }
 
 impl Tracer {
-    pub fn new(renderer: &mut Renderer) -> Tracer {
+    pub fn new(renderer: &mut Renderer) -> Result<Tracer, RenderError> {
+        // Reflect shaders
+        let rt_shader = include_wgsl!("../../assets/shaders/rt_shader.wgsl");
+        let denoise_shader = include_wgsl!("../../assets/shaders/denoise_shader.wgsl");
+        let taa_shader = include_wgsl!("../../assets/shaders/taa_shader.wgsl");
+
+        let reflections = [
+            &ShaderReflection::new(&rt_shader)?,
+            &ShaderReflection::new(&denoise_shader)?,
+            &ShaderReflection::new(&taa_shader)?,
+        ];
+
         // Init TAA instance
-        let taa = Taa::new(renderer);
+        let mut taa = Taa::new(renderer, &reflections)?;
 
-        // Init color buffer
-        let viewport_size = (renderer.size().width * renderer.size().height) as usize;
-        let color_buffer = Buffer::new(renderer, viewport_size, BufferUsages::STORAGE);
-        let background_buffer = Buffer::new(renderer, viewport_size, BufferUsages::STORAGE);
+        // Init render graph
+        let mut graph = RenderGraph::new();
 
-        // Init depth buffer
-        let depth_buffer = Buffer::new(renderer, viewport_size, BufferUsages::STORAGE);
-        let depth2_buffer = Buffer::new(renderer, viewport_size, BufferUsages::STORAGE);
+        // Init denoiser, whose screen-sized buffers are allocated by the graph
+        let mut denoiser = Denoiser::new(renderer, &mut graph, DenoiserDescriptor::default())?;
 
-        // Init normal buffer
-        let normal_buffer = Buffer::new(renderer, viewport_size, BufferUsages::STORAGE);
+        let color_buffer = graph.create_buffer::<glm::Vec4>("Color", GraphBufferDescriptor::default());
+        let background_buffer = graph.create_buffer::<glm::Vec4>("Background", GraphBufferDescriptor::default());
+        let depth_buffer = graph.create_buffer::<f32>("Depth", GraphBufferDescriptor::default());
+        let depth2_buffer = graph.create_buffer::<f32>("Depth2", GraphBufferDescriptor::default());
+        let normal_buffer = graph.create_buffer::<glm::Vec4>("Normal", GraphBufferDescriptor::default());
 
-        // Init camera
-        let camera_config = CameraConfiguration {
-            limit: -85.0..85.0,
-            ..Default::default()
+        // Resized with the canvas by the graph
+        let taa_color = graph.create_texture(TAA_COLOR, GraphTextureDescriptor::default());
+        let taa_history = graph.create_texture(TAA_HISTORY, GraphTextureDescriptor::default());
+        let velocity_buffer = graph.import_buffer(TAA_VELOCITY, &taa.velocity_buffer);
+        let history_depth_buffer = graph.import_buffer(TAA_HISTORY_DEPTH, &taa.history_depth_buffer);
+
+        // Samples are accumulated into the color of the previous frame
+        let ray_tracing = graph.add_compute_pass("Ray tracing")
+            .read_previous(color_buffer)
+            .read_previous(taa_history)
+            .read_previous(history_depth_buffer)
+            .write(color_buffer)
+            .write(background_buffer)
+            .write(depth_buffer)
+            .write(depth2_buffer)
+            .write(normal_buffer)
+            .write(velocity_buffer)
+            .id();
+
+        // The history of the denoiser is read and written by the same pass
+        let denoising = graph.add_compute_pass("Denoising");
+        denoising
+            .read(color_buffer)
+            .read(depth_buffer)
+            .read(normal_buffer)
+            .read(velocity_buffer)
+            .write(color_buffer);
+        for buffer in denoiser.buffers {
+            denoising.read_previous(buffer).write(buffer);
+        }
+        let denoising = denoising.id();
+
+        let passes = TracerPasses {
+            ray_tracing,
+            denoising,
+            taa_resolve: graph.add_render_pass("TAA resolve", &[taa_color], true)
+                .read(color_buffer)
+                .read(depth_buffer)
+                .read(velocity_buffer)
+                .read_previous(taa_history)
+                .read_previous(history_depth_buffer)
+                .id(),
+            taa_present: graph.add_render_pass("TAA present", &[RenderGraph::SURFACE], true)
+                .read(taa_history)
+                .id(),
         };
 
+        graph.add_copy("TAA history copy", taa_color, taa_history);
+        graph.add_copy("TAA history depth copy", depth_buffer, history_depth_buffer);
+        graph.update(renderer)?;
+        taa.bind_history(renderer, graph.texture(taa_history)?)?;
+        denoiser.bind_buffers(renderer, &graph)?;
+
+        // Init camera
         let mut camera = RtCamera::new(&RtCameraDescriptor {
             image_width: renderer.size().width,
             image_height: renderer.size().height,
             scan_depth: 10,
-            jitter: taa.current_jitter,
+            camera_type: CameraType::FirstPerson,
+            ..Default::default()
         });
+        let camera_transform = Transform::default();
 
         let camera_buffer = Buffer::new(renderer, 1, BufferUsages::UNIFORM | BufferUsages::COPY_DST);
-        camera_buffer.fill_exact(renderer, 0, &[camera.uniform_data()]).unwrap();
+        camera_buffer.fill_exact(renderer, 0, &[camera.update(&camera_transform)]).unwrap();
 
         // Init chunks
         let chunk = VoxelModel::load_vox("../assets/vox/model2.vox")
@@ -100,7 +207,9 @@ impl Tracer {
             dimension: TextureDimension::D3,
             usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
             format: TextureFormat::Rgba8Uint,
+            sample_count: 1,
             label: "Chunks",
+            ..Default::default()
         });
 
         let palettes_buffer = Buffer::new(renderer, 256 * chunks_count() as usize, BufferUsages::STORAGE);
@@ -111,116 +220,258 @@ impl Tracer {
             0,
         ).unwrap();
 
+        // Init block textures
+        let block_atlas = if Path::new(BLOCK_TEXTURES_DIR).is_dir() {
+            BlockAtlas::load_dir(renderer, BLOCK_TEXTURES_DIR)?
+        } else {
+            BlockAtlas::placeholder(renderer)?
+        };
+
+        // Init progressive accumulation
+        let mut accumulator = Accumulator::new(false);
+        let accumulation_buffer = Buffer::new(renderer, 1, BufferUsages::UNIFORM | BufferUsages::COPY_DST);
+        accumulation_buffer.fill_exact(renderer, 0, &[accumulator.uniform_data()]).unwrap();
+
         // TODO: local transformations
         // Init transform
         let tmp_transform = RtTransform::default();
 
         // Init shader resource
-        let shader_resource = ShaderResource::builder()
-            .add_buffer(&camera_buffer, &BufferResourceDescriptor {
-                visibility: ShaderStages::COMPUTE,
-                buffer_type: BufferBindingType::Uniform,
-            })
-            .add_buffer(&color_buffer, &BufferResourceDescriptor {
-                visibility: ShaderStages::COMPUTE | ShaderStages::FRAGMENT,
-                buffer_type: BufferBindingType::Storage { read_only: false },
-            })
-            .add_buffer(&background_buffer, &BufferResourceDescriptor {
-                visibility: ShaderStages::COMPUTE | ShaderStages::FRAGMENT,
-                buffer_type: BufferBindingType::Storage { read_only: false },
-            })
-            .add_buffer(&normal_buffer, &BufferResourceDescriptor {
-                visibility: ShaderStages::COMPUTE | ShaderStages::FRAGMENT,
-                buffer_type: BufferBindingType::Storage { read_only: false },
-            })
-            .add_buffer(&depth_buffer, &BufferResourceDescriptor {
-                visibility: ShaderStages::COMPUTE | ShaderStages::FRAGMENT,
-                buffer_type: BufferBindingType::Storage { read_only: false },
-            })
-            .add_buffer(&depth2_buffer, &BufferResourceDescriptor {
-                visibility: ShaderStages::COMPUTE | ShaderStages::FRAGMENT,
-                buffer_type: BufferBindingType::Storage { read_only: false },
-            })
-            .add_buffer(&palettes_buffer, &BufferResourceDescriptor {
-                visibility: ShaderStages::COMPUTE,
-                buffer_type: BufferBindingType::Storage { read_only: true },
-            })
-            .add_texture(&chunks_3d_texture, &TextureResourceDescriptor {
-                usage: TextureResourceUsage::TEXTURE | TextureResourceUsage::SAMPLER,
-                sample_type: Some(TextureSampleType::Uint),
-            })
-            .build(renderer);
+        let shader_resource = ShaderResource::from_reflection(renderer, &reflections, TRACER_BIND_GROUP, "Tracer")?;
 
         // Init pipelines
         let rt_pipeline = Pipeline::new_compute(
             renderer, 
-            include_wgsl!("../../assets/shaders/rt_shader.wgsl"),
+            rt_shader,
             &[&taa.shader_resource, &shader_resource], 
             "Ray tracing"
         );
 
+        let denoise_pipeline = Pipeline::new_compute(
+            renderer, 
+            denoise_shader,
+            &[&taa.shader_resource, &shader_resource, &denoiser.shader_resource], 
+            "Denoising"
+        );
+
         let taa_pipeline = Pipeline::new_render(
             renderer,
-            include_wgsl!("../../assets/shaders/taa_shader.wgsl"),
+            taa_shader,
             &[&taa.shader_resource, &shader_resource],
             "TAA",
             false,
         );  
 
-        Tracer {
+        let mut tracer = Tracer {
             taa,
+            denoiser,
             camera_buffer,
+            graph,
+            passes,
             color_buffer,
             background_buffer,
             normal_buffer,
             depth_buffer,
             depth2_buffer,
+            taa_history,
             chunks_3d_texture,
+            block_atlas,
             palettes_buffer,
+            accumulation_buffer,
             shader_resource,
             rt_pipeline,
+            denoise_pipeline,
             taa_pipeline,
             chunk,
+            lod_settings: LodSettings {
+                distances: [2.0, 4.0, 8.0].map(|chunks| chunks * CHUNK_EXTENT),
+                hysteresis: 0.25 * CHUNK_EXTENT,
+            },
             camera,
+            camera_transform,
             tmp_transform,
-            camera_config,
+            accumulator,
+            accumulation_reset_pending: false,
+        };
+        tracer.rebind_resources(renderer)?;
+
+        Ok(tracer)
+    }
+
+    /// Writes the chunk into the chunks texture and sends `ChunkLoaded`. The accumulated
+    /// samples of the previous chunk are discarded once the staged upload has been copied.
+    pub fn update_chunk(
+        &mut self, 
+        renderer: &Renderer, 
+        events: &mut Events<ChunkLoaded>, 
+        chunk_index: u64,
+    ) -> Result<(), LoadChunkError> {
+        self.chunk.write_to_texture(
+            renderer, 
+            &self.chunks_3d_texture, 
+            &self.palettes_buffer, 
+            chunk_index,
+        )?;
+
+        events.send(ChunkLoaded { index: chunk_index });
+        self.reset_accumulation_after_uploads();
+
+        Ok(())
+    }
+
+    /// Replaces a block of the chunk, writes the chunk again and sends `BlockChanged`.
+    pub fn set_block(
+        &mut self, 
+        renderer: &Renderer, 
+        events: &mut Events<BlockChanged>, 
+        block: Block, 
+        position: [usize; 3],
+    ) -> Result<(), LoadChunkError> {
+        let [x, y, z] = position;
+        self.chunk.set_block(block, x, y, z)?;
+
+        self.chunk.write_to_texture(
+            renderer, 
+            &self.chunks_3d_texture, 
+            &self.palettes_buffer, 
+            0,
+        )?;
+
+        events.send(BlockChanged { chunk: 0, position, block });
+        self.reset_accumulation_after_uploads();
+
+        Ok(())
+    }
+
+    /// Selects the level of detail of the chunk from its distance to the camera, and
+    /// writes the chunk again if the level has changed.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the chunk has been written at a new level of detail.
+    pub fn update_lod(&mut self, renderer: &Renderer, events: &mut Events<ChunkLoaded>) -> Result<bool, LoadChunkError> {
+        let bounds = Aabb::new(glm::Vec3::zeros(), glm::Vec3::repeat(CHUNK_EXTENT));
+        let level = self.lod_settings.select(bounds.distance(&self.camera_transform.translation), self.chunk.lod());
+
+        if !self.chunk.set_lod(level) {
+            return Ok(false);
         }
+
+        self.update_chunk(renderer, events, 0)?;
+
+        Ok(true)
     }
 
-    pub fn rebind_resources(&mut self, renderer: &mut Renderer) {
-        self.shader_resource = ShaderResource::builder()
-        .add_buffer(&self.camera_buffer, &BufferResourceDescriptor {
-            visibility: ShaderStages::COMPUTE,
-            buffer_type: BufferBindingType::Uniform,
-        })
-        .add_buffer(&self.color_buffer, &BufferResourceDescriptor {
-            visibility: ShaderStages::COMPUTE | ShaderStages::FRAGMENT,
-            buffer_type: BufferBindingType::Storage { read_only: false },
-        })
-        .add_buffer(&self.background_buffer, &BufferResourceDescriptor {
-            visibility: ShaderStages::COMPUTE | ShaderStages::FRAGMENT,
-            buffer_type: BufferBindingType::Storage { read_only: false },
-        })
-        .add_buffer(&self.normal_buffer, &BufferResourceDescriptor {
-            visibility: ShaderStages::COMPUTE | ShaderStages::FRAGMENT,
-            buffer_type: BufferBindingType::Storage { read_only: false },
-        })
-        .add_buffer(&self.depth_buffer, &BufferResourceDescriptor {
-            visibility: ShaderStages::COMPUTE | ShaderStages::FRAGMENT,
-            buffer_type: BufferBindingType::Storage { read_only: false },
-        })
-        .add_buffer(&self.depth2_buffer, &BufferResourceDescriptor {
-            visibility: ShaderStages::COMPUTE | ShaderStages::FRAGMENT,
-            buffer_type: BufferBindingType::Storage { read_only: false },
-        })
-        .add_buffer(&self.palettes_buffer, &BufferResourceDescriptor {
-            visibility: ShaderStages::COMPUTE,
-            buffer_type: BufferBindingType::Storage { read_only: true },
-        })
-        .add_texture(&self.chunks_3d_texture, &TextureResourceDescriptor {
-            usage: TextureResourceUsage::TEXTURE | TextureResourceUsage::SAMPLER,
-            sample_type: Some(TextureSampleType::Uint),
-        })
-        .build(renderer)
+    /// Resets the accumulator once the staged uploads have been copied, as the uploads
+    /// of changed chunks may be deferred to later frames by the upload budget.
+    pub fn reset_accumulation_after_uploads(&mut self) {
+        self.accumulation_reset_pending = true;
     }
-}
\ No newline at end of file
+
+    /// Resets the accumulator if a reset is pending and all staged uploads have been copied.
+    /// Called after the frame has been applied.
+    pub fn apply_pending_accumulation_reset(&mut self, renderer: &Renderer) {
+        if self.accumulation_reset_pending && renderer.pending_upload_bytes() == 0 {
+            self.accumulator.reset();
+            self.accumulation_reset_pending = false;
+        }
+    }
+
+    /// Imports the current TAA buffers into the render graph and reallocates the
+    /// graph resources, including the TAA textures, if the canvas has been resized.
+    ///
+    /// # Returns
+    ///
+    /// `true` if graph resources have been reallocated and must be bound again.
+    pub fn update_graph(&mut self, renderer: &Renderer) -> Result<bool, RenderError> {
+        self.graph.import_buffer(TAA_VELOCITY, &self.taa.velocity_buffer);
+        self.graph.import_buffer(TAA_HISTORY_DEPTH, &self.taa.history_depth_buffer);
+
+        Ok(self.graph.update(renderer)?)
+    }
+
+    /// Binds the current buffers and textures to the shader resource, e.g. after they have been resized.
+    pub fn rebind_resources(&mut self, renderer: &Renderer) -> Result<(), RenderError> {
+        self.taa.bind_history(renderer, self.graph.texture(self.taa_history)?)?;
+        self.denoiser.bind_buffers(renderer, &self.graph)?;
+
+        self.shader_resource
+            .set("camera", &self.camera_buffer)?
+            .set("color_buffer", self.graph.buffer(self.color_buffer)?)?
+            .set("background_buffer", self.graph.buffer(self.background_buffer)?)?
+            .set("normal_buffer", self.graph.buffer(self.normal_buffer)?)?
+            .set("depth_buffer", self.graph.buffer(self.depth_buffer)?)?
+            .set("depth2_buffer", self.graph.buffer(self.depth2_buffer)?)?
+            .set("palettes_buffer", &self.palettes_buffer)?
+            .set("chunks", &self.chunks_3d_texture)?
+            .set("chunks_sampler", &self.chunks_3d_texture)?
+            .set("block_faces", self.block_atlas.faces_buffer())?
+            .set("block_textures", self.block_atlas.texture())?
+            .set("block_textures_sampler", self.block_atlas.texture())?
+            .set("accumulation", &self.accumulation_buffer)?
+            .update(renderer)
+    }
+
+    /// Recompiles the pipelines from the shaders in `dir`, keeping the current pipelines
+    /// if a shader fails to compile.
+    pub fn reload_shaders(&mut self, renderer: &Renderer, dir: &Path) {
+        let rt_pipeline = Pipeline::load_compute(
+            renderer, 
+            dir.join("rt_shader.wgsl"), 
+            &[&self.taa.shader_resource, &self.shader_resource], 
+            "Ray tracing",
+        );
+
+        let denoise_pipeline = Pipeline::load_compute(
+            renderer, 
+            dir.join("denoise_shader.wgsl"), 
+            &[&self.taa.shader_resource, &self.shader_resource, &self.denoiser.shader_resource], 
+            "Denoising",
+        );
+
+        let taa_pipeline = Pipeline::load_builder(dir.join("taa_shader.wgsl")).and_then(|mut builder| {
+            builder
+                .set_label("TAA".to_owned())
+                .add_bindings(&[&self.taa.shader_resource, &self.shader_resource])
+                .try_build(renderer)
+        });
+
+        let swap = |pipeline: &mut Pipeline, new: Result<Pipeline, ShaderError>| match new {
+            Ok(new) => *pipeline = new,
+            Err(e) => eprintln!("{e}"),
+        };
+
+        swap(&mut self.rt_pipeline, rt_pipeline);
+        swap(&mut self.denoise_pipeline, denoise_pipeline);
+        swap(&mut self.taa_pipeline, taa_pipeline);
+
+        self.accumulator.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use tracengine::renderer::hal::shader::load_wgsl;
+
+    use super::*;
+
+    #[test]
+    fn shaders_match_the_tracer_layouts() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets/shaders");
+        let reflect = |name: &str| ShaderReflection::new(&load_wgsl(dir.join(name)).unwrap()).unwrap();
+
+        let (rt, denoise, taa) = (reflect("rt_shader.wgsl"), reflect("denoise_shader.wgsl"), reflect("taa_shader.wgsl"));
+        let reflections = [&rt, &denoise, &taa];
+
+        // The groups are bound to the pipelines as in `Tracer::new`
+        let taa_group = ShaderResource::reflected_layout_entries(&reflections, Taa::BIND_GROUP).unwrap();
+        let tracer_group = ShaderResource::reflected_layout_entries(&reflections, TRACER_BIND_GROUP).unwrap();
+        let denoiser_group = Denoiser::layout_entries();
+
+        rt.check_layouts(&[&taa_group, &tracer_group]).unwrap();
+        denoise.check_layouts(&[&taa_group, &tracer_group, &denoiser_group]).unwrap();
+        taa.check_layouts(&[&taa_group, &tracer_group]).unwrap();
+    }
+}